@@ -1,14 +1,9 @@
-use std::{marker::Send, path::Path, time::Duration};
+use std::{path::Path, sync::Arc};
 
 use cpal::traits::{DeviceTrait, HostTrait, StreamTrait};
 use pxtone::{
-    interface::{
-        io::PxToneServiceIO,
-        moo::{AsMoo, Moo},
-        service::PxTone,
-    },
-    rust_impl::service::RPxTone,
-    util::BoxOrMut,
+    interface::{io::PxToneServiceIO, moo::Moo},
+    rust_impl::{moo::RPxTonePlayer, service::RPxTone},
 };
 
 fn main() {
@@ -23,26 +18,8 @@ fn main() {
     ))
     .unwrap();
     let mut pxtone = RPxTone::new();
+    pxtone.read_bytes(&bytes).expect("read_bytes failed");
 
-    do_stuff(&bytes, &mut pxtone).unwrap();
-}
-
-fn do_stuff<'a, PXTN: PxTone + PxToneServiceIO + AsMoo>(
-    bytes: &[u8],
-    pxtone: &'a mut PXTN,
-) -> Result<(), <PXTN as PxToneServiceIO>::Error>
-where
-    <PXTN as AsMoo>::M<'a>: Send + Sync,
-{
-    pxtone.read_bytes(bytes).expect("read_bytes failed");
-
-    // moo
-    play(pxtone.as_moo()).unwrap();
-
-    Ok(())
-}
-
-fn play<'a, M: Moo<'a> + Send + Sync>(mut moo: BoxOrMut<M>) -> Result<(), M::Error> {
     // set up audio device
     let host = cpal::default_host();
     let device = host
@@ -55,87 +32,48 @@ fn play<'a, M: Moo<'a> + Send + Sync>(mut moo: BoxOrMut<M>) -> Result<(), M::Err
     );
 
     let config = device.default_output_config().unwrap();
+    let channels = config.channels() as usize;
 
-    moo.set_audio_format(config.channels() as u8, config.sample_rate().0)
+    // the player owns the project so it can be moved into cpal's (`'static`) callback
+    let mut player = RPxTonePlayer::new(Arc::new(pxtone));
+    player
+        .set_audio_format(channels as u8, config.sample_rate().0)
         .expect("set_audio_format failed");
 
     // prepare to moo
-    moo.prepare_sample().expect("prepare_sample failed");
-    let total_samples = moo.total_samples();
+    player.prepare_sample().expect("prepare_sample failed");
+    let total_samples = player.total_samples();
     println!("pxtone.get_total_samples() = {}", total_samples);
-    let mut sn = 10000;
-    sn = sn - (sn % 4);
-    println!("sn = {}", sn);
-    let mut mem: Vec<i16> = vec![0; sn as usize * 2];
 
-    moo.sample(&mut mem).expect("sample failed");
+    let mut mem: Vec<i16> = Vec::new();
+    let data_fn = move |data: &mut [f32], _: &cpal::OutputCallbackInfo| {
+        mem.resize(data.len(), 0);
 
-    println!("Mooed to buffer, playing back...");
+        let start = std::time::Instant::now();
+        player.sample(&mut mem).unwrap();
+        println!(
+            "{:.1}ms",
+            std::time::Instant::now().duration_since(start).as_micros() as f32 / 1000.0
+        );
 
-    // cpal requires borrowed data to be 'static for some reason, so we have to do dumb stuff with channels and threads to access the PxTone
-    // Another way would be moving the PxTone into cpal's closure but then you couldn't use it elsewhere...
-    // cpal seems to be in no rush to changing this: https://github.com/RustAudio/cpal/issues/434
-
-    let (sender, reciever) = std::sync::mpsc::channel::<usize>();
-    let (sender2, reciever2) = std::sync::mpsc::channel::<Vec<i16>>();
-
-    let (exit_tx, exit_rx) = std::sync::mpsc::channel::<()>();
-
-    std::thread::scope(|s| {
-        let handle = s.spawn(move || {
-            while exit_rx.try_recv().is_err() {
-                if let Ok(s) = reciever.try_recv() {
-                    let mut mem: Vec<i16> = vec![0; s];
-                    let start = std::time::Instant::now();
-                    moo.sample(&mut mem).unwrap();
-                    println!(
-                        "{:.1}ms",
-                        std::time::Instant::now().duration_since(start).as_micros() as f32 / 1000.0
-                    );
-                    sender2.send(mem).unwrap();
-                } else {
-                    std::thread::sleep(Duration::from_millis(1));
-                }
-            }
+        let mut samples = mem.iter();
+        write_data(data, channels, &mut || {
+            *samples.next().unwrap() as f32 / i16::MAX as f32
         });
+    };
 
-        // play back audio
-        let mut sample_i = 0;
-        // let mut next_value = move ;
-        let data_fn = move |data: &mut [f32], _: &cpal::OutputCallbackInfo| {
-            // let mut mutex = moo;
-            write_data(data, 2, &mut || {
-                if sample_i >= mem.len() {
-                    sender.send(mem.len()).unwrap();
-                    let dat = reciever2.recv().unwrap();
-                    mem = dat;
-                    sample_i -= mem.len();
-                }
-
-                let v = mem[sample_i];
-                sample_i += 1;
-                v as f32 / i16::MAX as f32
-            })
-        };
-
-        let err_fn = |err| eprintln!("an error occurred on stream: {}", err);
-
-        let sample_rate = config.sample_rate().0;
-        let stream = device
-            .build_output_stream(&config.into(), data_fn, err_fn)
-            .expect("Failed to start audio stream");
-        stream.play().expect("Failed to play audio");
-
-        let play_time = total_samples as f64 / sample_rate as f64;
-        println!("Sleeping for {:.2}s", play_time);
-        std::thread::sleep(std::time::Duration::from_secs_f64(play_time));
-        println!("Done!");
+    let err_fn = |err| eprintln!("an error occurred on stream: {}", err);
 
-        exit_tx.send(()).unwrap();
-        handle.join().unwrap();
-    });
+    let sample_rate = config.sample_rate().0;
+    let stream = device
+        .build_output_stream(&config.into(), data_fn, err_fn)
+        .expect("Failed to start audio stream");
+    stream.play().expect("Failed to play audio");
 
-    Ok(())
+    let play_time = total_samples as f64 / sample_rate as f64;
+    println!("Sleeping for {:.2}s", play_time);
+    std::thread::sleep(std::time::Duration::from_secs_f64(play_time));
+    println!("Done!");
 }
 
 fn write_data<T>(output: &mut [T], channels: usize, next_sample: &mut dyn FnMut() -> f32)
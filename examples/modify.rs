@@ -4,10 +4,11 @@ use pxtone::{
     interface::{
         delay::{Delay, DelayUnit, DelaysMut, HasDelays},
         event::{
-            BaseEvent, EventListMut, EventPanVolume, GenericEvent, GenericEventKind, HasEventList,
-            PanValue,
+            BaseEvent, EventList, EventListMut, EventPanVolume, GenericEvent, GenericEventKind,
+            HasEventList, Key, PanValue,
         },
         io::PxToneServiceIO,
+        note::Note,
         overdrive::{HasOverDrives, OverDAmp, OverDCut, OverDrivesMut},
         service::PxTone,
         unit::{HasUnits, Unit, UnitsMut},
@@ -65,7 +66,10 @@ fn do_stuff<
     units.remove(1);
 
     // add a new woice from file
-    pxtone.woices_mut().add_ptv_from_file("examples/sample.ptvoice").unwrap();
+    pxtone
+        .woices_mut()
+        .add_ptv_from_file("examples/sample.ptvoice")
+        .unwrap();
 
     // add a new black woice
     pxtone.woices_mut().add_blank_ptv().unwrap();
@@ -166,6 +170,23 @@ fn do_stuff<
         }
     }
 
+    // add a note to the first unit, then move all of its notes up an octave
+    {
+        let mut events = pxtone.event_list_mut();
+        events
+            .insert_note(&Note {
+                unit_no: 0,
+                clock: 0,
+                length: 480,
+                key: Key::from_midi(60),
+                velocity: ZeroToOneF32::new(0.8),
+            })
+            .unwrap();
+        for note in events.notes().iter().filter(|n| n.unit_no == 0) {
+            events.transpose_note(note, 12).unwrap();
+        }
+    }
+
     // add a couple delay effects
     pxtone
         .delays_mut()
@@ -0,0 +1,447 @@
+//! Controlling a playing [`Moo`] from other threads
+//!
+//! [`ControlledMoo`] wraps any renderer and applies the [`MooCommand`]s sent through its
+//! [`MooHandle`]s at the start of every [`Moo::sample`] call. The queue is lock-free, so neither
+//! senders nor the thread that is sampling (usually the audio callback) ever wait on each other.
+//! Draining it is also wait-free, so the audio thread always gets through it in bounded time.
+//! Sending is only lock-free, since a sender can have to retry while others race it for a slot.
+
+use std::{
+    cell::UnsafeCell,
+    fmt,
+    mem::MaybeUninit,
+    ops::{Deref, DerefMut, Range},
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+
+use crate::{
+    interface::{
+        event::{Key, PanValue},
+        moo::{Fade, Moo, MooLive},
+    },
+    util::ZeroToOneF32,
+};
+
+/// Default number of commands that can be waiting at once
+pub const DEFAULT_QUEUE_CAPACITY: usize = 256;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum MooCommand {
+    /// Also turns on unit muting (see [`Moo::set_unit_mute_enabled`])
+    SetUnitMuted {
+        unit_no: u8,
+        muted: bool,
+    },
+    SetMasterVolume(f32),
+    SetFade(Option<Fade>, Duration),
+    SetLoop(bool),
+    SetLoopMeasures(Option<Range<i32>>),
+    JumpToMeasure(i32),
+}
+
+/// The queue was full, the command is handed back
+#[derive(Debug)]
+pub struct QueueFull(pub MooCommand);
+
+impl fmt::Display for QueueFull {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Moo command queue is full, dropped {:?}", self.0)
+    }
+}
+
+impl std::error::Error for QueueFull {}
+
+/// Fixed size multi producer, single consumer ring buffer.
+///
+/// Each slot has a sequence number saying whose turn it is: senders reserve a slot by moving
+/// `tail` on with a compare-exchange, write it, then hand it to the consumer by bumping its
+/// sequence. The consumer hands it back a lap later once it's read. Neither side ever waits on the
+/// other. Positions only ever increase, the slot is `position % capacity`.
+///
+/// Popping takes a fixed number of steps. Pushing is only lock-free: the compare-exchange can
+/// keep losing to other senders, but every time it does one of them got its command in. Reserving
+/// with a `fetch_add` instead would make it wait-free, but then a sender that finds the queue
+/// full has already claimed a position it can't give back.
+struct CommandQueue {
+    slots: Box<[Slot]>,
+    /// Next position to read, only written by the consumer
+    head: AtomicUsize,
+    /// Next position to reserve for writing
+    tail: AtomicUsize,
+}
+
+struct Slot {
+    /// `position` when it's free to write for that position, `position + 1` once written
+    seq: AtomicUsize,
+    command: UnsafeCell<MaybeUninit<MooCommand>>,
+}
+
+// a slot's command is only touched by whoever its sequence number says owns it, and the
+// acquire/release on `seq` orders the handover between them
+unsafe impl Sync for CommandQueue {}
+
+impl CommandQueue {
+    fn new(capacity: usize) -> Self {
+        assert!(capacity > 0, "command queue capacity must not be 0");
+
+        Self {
+            slots: (0..capacity)
+                .map(|i| Slot {
+                    seq: AtomicUsize::new(i),
+                    command: UnsafeCell::new(MaybeUninit::uninit()),
+                })
+                .collect(),
+            head: AtomicUsize::new(0),
+            tail: AtomicUsize::new(0),
+        }
+    }
+
+    #[allow(clippy::cast_possible_wrap)]
+    fn push(&self, command: MooCommand) -> Result<(), QueueFull> {
+        let mut pos = self.tail.load(Ordering::Relaxed);
+        loop {
+            let slot = &self.slots[pos % self.slots.len()];
+            // how far the slot is from being ready for `pos`, wrapping like the positions do
+            let lag = slot.seq.load(Ordering::Acquire).wrapping_sub(pos) as isize;
+
+            match lag {
+                0 => match self.tail.compare_exchange_weak(
+                    pos,
+                    pos.wrapping_add(1),
+                    Ordering::Relaxed,
+                    Ordering::Relaxed,
+                ) {
+                    Ok(_) => {
+                        unsafe { (*slot.command.get()).write(command) };
+                        slot.seq.store(pos.wrapping_add(1), Ordering::Release);
+                        return Ok(());
+                    },
+                    Err(now) => pos = now,
+                },
+                // the consumer hasn't read this slot's last lap yet
+                lag if lag < 0 => return Err(QueueFull(command)),
+                // another sender got here first
+                _ => pos = self.tail.load(Ordering::Relaxed),
+            }
+        }
+    }
+
+    /// Number of commands waiting, including ones still being written
+    fn len(&self) -> usize {
+        self.tail
+            .load(Ordering::Acquire)
+            .wrapping_sub(self.head.load(Ordering::Relaxed))
+    }
+
+    /// Must only be called by the queue's one consumer
+    fn pop(&self) -> Option<MooCommand> {
+        let head = self.head.load(Ordering::Relaxed);
+        let slot = &self.slots[head % self.slots.len()];
+        // empty, or the sender that reserved it is still writing
+        if slot.seq.load(Ordering::Acquire) != head.wrapping_add(1) {
+            return None;
+        }
+
+        let command = unsafe { (*slot.command.get()).assume_init_read() };
+        slot.seq
+            .store(head.wrapping_add(self.slots.len()), Ordering::Release);
+        self.head.store(head.wrapping_add(1), Ordering::Release);
+        Some(command)
+    }
+}
+
+impl Drop for CommandQueue {
+    fn drop(&mut self) {
+        while self.pop().is_some() {}
+    }
+}
+
+/// Cheap, cloneable handle for sending commands to a [`ControlledMoo`] from any thread
+#[derive(Clone)]
+pub struct MooHandle {
+    queue: Arc<CommandQueue>,
+}
+
+impl MooHandle {
+    pub fn send(&self, command: MooCommand) -> Result<(), QueueFull> {
+        self.queue.push(command)
+    }
+
+    /// Mute or unmute a unit. This also turns on unit muting, so units muted in the project are muted too
+    pub fn set_unit_muted(&self, unit_no: u8, muted: bool) -> Result<(), QueueFull> {
+        self.send(MooCommand::SetUnitMuted { unit_no, muted })
+    }
+
+    pub fn set_master_volume(&self, volume: f32) -> Result<(), QueueFull> {
+        self.send(MooCommand::SetMasterVolume(volume))
+    }
+
+    pub fn set_fade(&self, fade: Option<Fade>, duration: Duration) -> Result<(), QueueFull> {
+        self.send(MooCommand::SetFade(fade, duration))
+    }
+
+    pub fn set_loop(&self, should_loop: bool) -> Result<(), QueueFull> {
+        self.send(MooCommand::SetLoop(should_loop))
+    }
+
+    pub fn set_loop_measures(&self, measures: Option<Range<i32>>) -> Result<(), QueueFull> {
+        self.send(MooCommand::SetLoopMeasures(measures))
+    }
+
+    pub fn jump_to_measure(&self, measure: i32) -> Result<(), QueueFull> {
+        self.send(MooCommand::JumpToMeasure(measure))
+    }
+}
+
+/// A [`Moo`] that can be controlled through [`MooHandle`]s while it plays
+pub struct ControlledMoo<M> {
+    moo: M,
+    queue: Arc<CommandQueue>,
+}
+
+impl<M> ControlledMoo<M> {
+    pub fn new(moo: M) -> (Self, MooHandle) {
+        Self::with_capacity(moo, DEFAULT_QUEUE_CAPACITY)
+    }
+
+    /// `capacity` is how many commands can be waiting before [`MooHandle::send`] fails
+    pub fn with_capacity(moo: M, capacity: usize) -> (Self, MooHandle) {
+        let queue = Arc::new(CommandQueue::new(capacity));
+        let handle = MooHandle { queue: Arc::clone(&queue) };
+        (Self { moo, queue }, handle)
+    }
+
+    #[must_use]
+    pub fn handle(&self) -> MooHandle {
+        MooHandle { queue: Arc::clone(&self.queue) }
+    }
+
+    /// Unwrap the renderer, dropping any commands that haven't been applied
+    pub fn into_inner(self) -> M {
+        self.moo
+    }
+
+    /// Apply the commands that were waiting when this was called
+    pub fn apply_commands<'a>(&mut self) -> Result<(), M::Error>
+    where
+        M: Moo<'a>,
+    {
+        // commands sent while this runs wait for the next call, so a busy sender can't stall it
+        for _ in 0..self.queue.len() {
+            let Some(command) = self.queue.pop() else {
+                break;
+            };
+
+            match command {
+                MooCommand::SetUnitMuted { unit_no, muted } => {
+                    self.moo.set_unit_mute_enabled(true)?;
+                    self.moo.set_unit_muted(unit_no, muted)?;
+                },
+                MooCommand::SetMasterVolume(volume) => self.moo.set_master_volume(volume)?,
+                MooCommand::SetFade(fade, duration) => self.moo.set_fade(fade, duration)?,
+                MooCommand::SetLoop(should_loop) => self.moo.set_loop(should_loop)?,
+                MooCommand::SetLoopMeasures(measures) => self.moo.set_loop_measures(measures)?,
+                MooCommand::JumpToMeasure(measure) => self.moo.jump_to_measure(measure)?,
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl<M> Deref for ControlledMoo<M> {
+    type Target = M;
+
+    fn deref(&self) -> &Self::Target {
+        &self.moo
+    }
+}
+
+impl<M> DerefMut for ControlledMoo<M> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.moo
+    }
+}
+
+impl<'a, M: Moo<'a>> Moo<'a> for ControlledMoo<M> {
+    type Error = M::Error;
+
+    fn set_audio_format(&mut self, channels: u8, sample_rate: u32) -> Result<(), Self::Error> {
+        self.moo.set_audio_format(channels, sample_rate)
+    }
+
+    fn prepare_sample(&mut self) -> Result<(), Self::Error> {
+        self.moo.prepare_sample()
+    }
+
+    fn sample(&mut self, buffer: &mut [i16]) -> Result<(), Self::Error> {
+        self.apply_commands()?;
+        self.moo.sample(buffer)
+    }
+
+    fn sample_f32(&mut self, buffer: &mut [f32]) -> Result<(), Self::Error> {
+        self.apply_commands()?;
+        self.moo.sample_f32(buffer)
+    }
+
+    fn is_done_sampling(&self) -> bool {
+        self.moo.is_done_sampling()
+    }
+
+    fn now_clock(&self) -> u32 {
+        self.moo.now_clock()
+    }
+
+    fn end_clock(&self) -> u32 {
+        self.moo.end_clock()
+    }
+
+    fn set_unit_mute_enabled(&mut self, unit_mute: bool) -> Result<(), Self::Error> {
+        self.moo.set_unit_mute_enabled(unit_mute)
+    }
+
+    fn set_unit_muted(&mut self, unit_no: u8, muted: bool) -> Result<(), Self::Error> {
+        self.moo.set_unit_muted(unit_no, muted)
+    }
+
+    fn set_loop(&mut self, should_loop: bool) -> Result<(), Self::Error> {
+        self.moo.set_loop(should_loop)
+    }
+
+    fn set_loop_measures(&mut self, measures: Option<Range<i32>>) -> Result<(), Self::Error> {
+        self.moo.set_loop_measures(measures)
+    }
+
+    fn jump_to_measure(&mut self, measure: i32) -> Result<(), Self::Error> {
+        self.moo.jump_to_measure(measure)
+    }
+
+    fn set_fade(&mut self, fade: Option<Fade>, duration: Duration) -> Result<(), Self::Error> {
+        self.moo.set_fade(fade, duration)
+    }
+
+    fn sampling_offset(&self) -> u32 {
+        self.moo.sampling_offset()
+    }
+
+    fn sampling_end(&self) -> u32 {
+        self.moo.sampling_end()
+    }
+
+    fn total_samples(&self) -> u32 {
+        self.moo.total_samples()
+    }
+
+    fn set_master_volume(&mut self, volume: f32) -> Result<(), Self::Error> {
+        self.moo.set_master_volume(volume)
+    }
+}
+
+impl<'a, M: MooLive<'a>> MooLive<'a> for ControlledMoo<M> {
+    fn live_note_on(
+        &mut self,
+        unit_no: u8,
+        key: Key,
+        velocity: ZeroToOneF32,
+        pan_volume: PanValue,
+    ) -> Result<(), Self::Error> {
+        self.moo.live_note_on(unit_no, key, velocity, pan_volume)
+    }
+
+    fn live_note_off(&mut self, unit_no: u8) -> Result<(), Self::Error> {
+        self.moo.live_note_off(unit_no)
+    }
+
+    fn live_key(&mut self, unit_no: u8, key: Key) -> Result<(), Self::Error> {
+        self.moo.live_key(unit_no, key)
+    }
+
+    fn live_velocity(&mut self, unit_no: u8, velocity: ZeroToOneF32) -> Result<(), Self::Error> {
+        self.moo.live_velocity(unit_no, velocity)
+    }
+
+    fn live_pan_volume(&mut self, unit_no: u8, pan_volume: PanValue) -> Result<(), Self::Error> {
+        self.moo.live_pan_volume(unit_no, pan_volume)
+    }
+
+    fn live_voice_no(&mut self, unit_no: u8, voice_no: u8) -> Result<(), Self::Error> {
+        self.moo.live_voice_no(unit_no, voice_no)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{sync::Arc, thread};
+
+    use super::{CommandQueue, MooCommand};
+
+    #[test]
+    fn queue_wraps_around() {
+        let queue = CommandQueue::new(3);
+
+        for i in 0..10 {
+            queue.push(MooCommand::JumpToMeasure(i)).unwrap();
+            queue.push(MooCommand::JumpToMeasure(-i)).unwrap();
+            assert_eq!(queue.pop(), Some(MooCommand::JumpToMeasure(i)));
+            assert_eq!(queue.pop(), Some(MooCommand::JumpToMeasure(-i)));
+            assert_eq!(queue.pop(), None);
+        }
+    }
+
+    #[test]
+    fn full_queue_returns_command() {
+        let queue = CommandQueue::new(2);
+        queue.push(MooCommand::SetLoop(true)).unwrap();
+        queue.push(MooCommand::SetLoop(false)).unwrap();
+
+        let full = queue.push(MooCommand::JumpToMeasure(1)).unwrap_err();
+        assert_eq!(full.0, MooCommand::JumpToMeasure(1));
+
+        assert_eq!(queue.pop(), Some(MooCommand::SetLoop(true)));
+        queue.push(MooCommand::JumpToMeasure(1)).unwrap();
+    }
+
+    #[test]
+    fn senders_dont_lose_or_reorder_commands() {
+        const SENDERS: i32 = 4;
+        const PER_SENDER: i32 = 1000;
+        let queue = Arc::new(CommandQueue::new(8));
+
+        let senders: Vec<_> = (0..SENDERS)
+            .map(|sender| {
+                let queue = Arc::clone(&queue);
+                thread::spawn(move || {
+                    for i in 0..PER_SENDER {
+                        let mut command = MooCommand::JumpToMeasure(sender * PER_SENDER + i);
+                        while let Err(full) = queue.push(command) {
+                            command = full.0;
+                            thread::yield_now();
+                        }
+                    }
+                })
+            })
+            .collect();
+
+        let mut next = vec![0; SENDERS as usize];
+        let mut received = 0;
+        while received < SENDERS * PER_SENDER {
+            let Some(MooCommand::JumpToMeasure(n)) = queue.pop() else {
+                thread::yield_now();
+                continue;
+            };
+            let sender = (n / PER_SENDER) as usize;
+            assert_eq!(n % PER_SENDER, next[sender]);
+            next[sender] += 1;
+            received += 1;
+        }
+
+        for sender in senders {
+            sender.join().unwrap();
+        }
+        assert_eq!(queue.pop(), None);
+    }
+}
@@ -0,0 +1,473 @@
+//! Structural comparison of two projects
+//!
+//! [`diff`] works on anything implementing the interface traits, so it can compare projects
+//! loaded by different backends (eg. to check that `RPxTone` reads a file the same way
+//! `PxToneService` does).
+
+use std::fmt;
+
+use crate::interface::{
+    delay::{Delay, Delays, HasDelays},
+    event::{
+        EventGroupNo, EventKey, EventList, EventOn, EventPanTime, EventPanVolume, EventPorta,
+        EventTuning, EventVelocity, EventVoiceNo, EventVolume, GenericEvent, GenericEventKind,
+        HasEventList,
+    },
+    overdrive::{HasOverDrives, OverDrive, OverDrives},
+    service::PxTone,
+    unit::{HasUnits, Unit, Units},
+    woice::{
+        HasWoices, PTNEnvelopePoint, PTNOscillator, PTNUnit, PTVCoordinateWave,
+        PTVCoordinateWavePoint, PTVEnvelope, PTVOvertoneWave, PTVOvertoneWaveTone, PTVWaveType,
+        SingleVoice, Voice, VoiceOGGV, VoicePCM, VoicePTN, VoicePTV, Woice, WoicePTV, WoiceType,
+        Woices,
+    },
+};
+
+/// A single difference between two projects
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Difference {
+    /// Location of the value, eg. `units[2].name` or `woices[0].voices[1].basic_key`
+    pub path: String,
+    /// The value in the first project
+    pub left: String,
+    /// The value in the second project
+    pub right: String,
+}
+
+impl fmt::Display for Difference {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {} != {}", self.path, self.left, self.right)
+    }
+}
+
+/// Every difference found by [`diff`], in the order they were found
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ProjectDiff {
+    pub differences: Vec<Difference>,
+}
+
+impl ProjectDiff {
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.differences.is_empty()
+    }
+
+    // taking owned values keeps the call sites short, most of them are Copy anyway
+    #[allow(clippy::needless_pass_by_value)]
+    fn check<T: PartialEq + fmt::Debug>(
+        &mut self,
+        path: impl FnOnce() -> String,
+        left: T,
+        right: T,
+    ) {
+        if left != right {
+            self.differences.push(Difference {
+                path: path(),
+                left: format!("{left:?}"),
+                right: format!("{right:?}"),
+            });
+        }
+    }
+}
+
+impl fmt::Display for ProjectDiff {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for d in &self.differences {
+            writeln!(f, "{d}")?;
+        }
+        Ok(())
+    }
+}
+
+/// Every trait [`diff`] needs to walk a project
+pub trait Diffable:
+    PxTone + HasUnits + HasWoices + HasEventList + HasDelays + HasOverDrives
+{
+}
+
+impl<T: PxTone + HasUnits + HasWoices + HasEventList + HasDelays + HasOverDrives> Diffable for T {}
+
+/// Walk two projects and collect every difference between them.
+///
+/// This compares master settings, events, unit names, woice/voice parameters and effects.
+/// Events are compared in (clock, unit, kind, value) order, so events on the same clock
+/// can be stored in a different order without being reported.
+pub fn diff<A: Diffable, B: Diffable>(a: &A, b: &B) -> ProjectDiff {
+    let mut d = ProjectDiff::default();
+
+    diff_master(&mut d, a, b);
+    diff_events(&mut d, a, b);
+    diff_units(&mut d, a, b);
+    diff_woices(&mut d, a, b);
+    diff_effects(&mut d, a, b);
+
+    d
+}
+
+fn diff_master<A: PxTone, B: PxTone>(d: &mut ProjectDiff, a: &A, b: &B) {
+    d.check(|| "master.beat_num".into(), a.beat_num(), b.beat_num());
+    d.check(
+        || "master.beat_tempo".into(),
+        a.beat_tempo(),
+        b.beat_tempo(),
+    );
+    d.check(
+        || "master.beat_clock".into(),
+        a.beat_clock(),
+        b.beat_clock(),
+    );
+    d.check(
+        || "master.num_measures".into(),
+        a.num_measures(),
+        b.num_measures(),
+    );
+    d.check(
+        || "master.repeat_measure".into(),
+        a.repeat_measure(),
+        b.repeat_measure(),
+    );
+    d.check(
+        || "master.last_measure".into(),
+        a.last_measure(),
+        b.last_measure(),
+    );
+    d.check(|| "master.name".into(), a.name(), b.name());
+    d.check(|| "master.comment".into(), a.comment(), b.comment());
+}
+
+/// Backend independent view of an event, sorts by clock first
+#[derive(Debug, PartialEq, PartialOrd)]
+struct EventSummary {
+    clock: u32,
+    unit_no: u8,
+    kind: &'static str,
+    value: f64,
+}
+
+fn summarize<E: GenericEvent>(e: &E) -> EventSummary {
+    let (kind, value) = match e.kind() {
+        GenericEventKind::Invalid | GenericEventKind::_Phantom(..) => ("Invalid", 0.0),
+        GenericEventKind::On(e) => ("On", e.length().into()),
+        GenericEventKind::Key(e) => ("Key", (*e.key()).into()),
+        GenericEventKind::PanVolume(e) => ("PanVolume", (*e.pan_volume()).into()),
+        GenericEventKind::Velocity(e) => ("Velocity", (*e.velocity()).into()),
+        GenericEventKind::Volume(e) => ("Volume", (*e.volume()).into()),
+        GenericEventKind::Porta(e) => ("Porta", e.porta().into()),
+        GenericEventKind::VoiceNo(e) => ("VoiceNo", e.voice_no().into()),
+        GenericEventKind::GroupNo(e) => ("GroupNo", e.group_no().into()),
+        GenericEventKind::Tuning(e) => ("Tuning", (*e.tuning()).into()),
+        GenericEventKind::PanTime(e) => ("PanTime", (*e.pan_time()).into()),
+    };
+
+    EventSummary {
+        clock: e.clock(),
+        unit_no: e.unit_no(),
+        kind,
+        value,
+    }
+}
+
+fn summarize_all<L: EventList>(list: &L) -> Vec<EventSummary> {
+    let mut events: Vec<_> = list.iter().map(summarize).collect();
+    events.sort_by(|a, b| a.partial_cmp(b).unwrap_or(std::cmp::Ordering::Equal));
+    events
+}
+
+fn diff_events<A: HasEventList, B: HasEventList>(d: &mut ProjectDiff, a: &A, b: &B) {
+    let a = summarize_all(&*a.event_list());
+    let b = summarize_all(&*b.event_list());
+
+    d.check(|| "events.len".into(), a.len(), b.len());
+    for (i, (a, b)) in a.iter().zip(&b).enumerate() {
+        d.check(|| format!("events[{i}]"), a, b);
+    }
+}
+
+fn diff_units<A: HasUnits, B: HasUnits>(d: &mut ProjectDiff, a: &A, b: &B) {
+    let a: Vec<_> = a.units().iter().map(|u| u.name()).collect();
+    let b: Vec<_> = b.units().iter().map(|u| u.name()).collect();
+
+    d.check(|| "units.len".into(), a.len(), b.len());
+    for (i, (a, b)) in a.iter().zip(&b).enumerate() {
+        d.check(|| format!("units[{i}].name"), a, b);
+    }
+}
+
+fn woice_type_name<W: Woice>(w: &W) -> &'static str {
+    match w.woice_type() {
+        WoiceType::PCM(_) => "PCM",
+        WoiceType::PTV(_) => "PTV",
+        WoiceType::PTN(_) => "PTN",
+        WoiceType::OGGV(_) => "OGGV",
+        _ => "None",
+    }
+}
+
+fn diff_woices<A: HasWoices, B: HasWoices>(d: &mut ProjectDiff, a: &A, b: &B) {
+    let a = a.woices();
+    let b = b.woices();
+    let a: Vec<_> = a.iter().collect();
+    let b: Vec<_> = b.iter().collect();
+
+    d.check(|| "woices.len".into(), a.len(), b.len());
+    for (i, (a, b)) in a.iter().zip(&b).enumerate() {
+        let path = format!("woices[{i}]");
+        diff_woice(d, &path, &**a, &**b);
+    }
+}
+
+fn diff_woice<A: Woice, B: Woice>(d: &mut ProjectDiff, path: &str, a: &A, b: &B) {
+    d.check(|| format!("{path}.name"), a.name(), b.name());
+
+    match (a.woice_type(), b.woice_type()) {
+        (WoiceType::PCM(a), WoiceType::PCM(b)) => {
+            diff_voice_pcm(d, &format!("{path}.voice"), a.voice(), b.voice());
+        },
+        (WoiceType::PTV(a), WoiceType::PTV(b)) => {
+            let a = a.voices();
+            let b = b.voices();
+            d.check(|| format!("{path}.voices.len"), a.len(), b.len());
+            for (i, (a, b)) in a.iter().zip(&b).enumerate() {
+                diff_voice_ptv(d, &format!("{path}.voices[{i}]"), *a, *b);
+            }
+        },
+        (WoiceType::PTN(a), WoiceType::PTN(b)) => {
+            diff_voice_ptn(d, &format!("{path}.voice"), a.voice(), b.voice());
+        },
+        (WoiceType::OGGV(a), WoiceType::OGGV(b)) => {
+            diff_voice_oggv(d, &format!("{path}.voice"), a.voice(), b.voice());
+        },
+        _ => d.check(
+            || format!("{path}.type"),
+            woice_type_name(a),
+            woice_type_name(b),
+        ),
+    }
+}
+
+fn diff_voice<A: Voice + ?Sized, B: Voice + ?Sized>(d: &mut ProjectDiff, path: &str, a: &A, b: &B) {
+    d.check(|| format!("{path}.basic_key"), a.basic_key(), b.basic_key());
+    d.check(|| format!("{path}.volume"), a.volume(), b.volume());
+    d.check(|| format!("{path}.pan"), a.pan(), b.pan());
+    d.check(|| format!("{path}.tuning"), a.tuning(), b.tuning());
+}
+
+fn diff_voice_pcm<A: VoicePCM + ?Sized, B: VoicePCM + ?Sized>(
+    d: &mut ProjectDiff,
+    path: &str,
+    a: &A,
+    b: &B,
+) {
+    diff_voice(d, path, a, b);
+    d.check(|| format!("{path}.channels"), a.channels(), b.channels());
+    d.check(
+        || format!("{path}.samples_per_second"),
+        a.samples_per_second(),
+        b.samples_per_second(),
+    );
+    d.check(
+        || format!("{path}.bits_per_sample"),
+        a.bits_per_sample(),
+        b.bits_per_sample(),
+    );
+}
+
+fn wave_type_name<C: PTVCoordinateWave, O: PTVOvertoneWave>(
+    wave: &PTVWaveType<C, O>,
+) -> &'static str {
+    match wave {
+        PTVWaveType::Coordinate(_) => "Coordinate",
+        PTVWaveType::Overtone(_) => "Overtone",
+    }
+}
+
+fn diff_voice_ptv<A: VoicePTV + ?Sized, B: VoicePTV + ?Sized>(
+    d: &mut ProjectDiff,
+    path: &str,
+    a: &A,
+    b: &B,
+) {
+    diff_voice(d, path, a, b);
+
+    match (a.wave(), b.wave()) {
+        (PTVWaveType::Coordinate(a), PTVWaveType::Coordinate(b)) => {
+            d.check(
+                || format!("{path}.wave.resolution"),
+                a.resolution(),
+                b.resolution(),
+            );
+            let a: Vec<_> = a.points().iter().map(|p| (p.x(), p.y())).collect();
+            let b: Vec<_> = b.points().iter().map(|p| (p.x(), p.y())).collect();
+            d.check(|| format!("{path}.wave.points"), a, b);
+        },
+        (PTVWaveType::Overtone(a), PTVWaveType::Overtone(b)) => {
+            let a: Vec<_> = a
+                .tones()
+                .iter()
+                .map(|t| (t.frequency(), t.amplitude()))
+                .collect();
+            let b: Vec<_> = b
+                .tones()
+                .iter()
+                .map(|t| (t.frequency(), t.amplitude()))
+                .collect();
+            d.check(|| format!("{path}.wave.tones"), a, b);
+        },
+        (a, b) => {
+            d.check(
+                || format!("{path}.wave.type"),
+                wave_type_name(&a),
+                wave_type_name(&b),
+            );
+        },
+    }
+
+    let (a, b) = (a.envelope(), b.envelope());
+    d.check(|| format!("{path}.envelope.fps"), a.fps(), b.fps());
+    d.check(
+        || format!("{path}.envelope.head_num"),
+        a.head_num(),
+        b.head_num(),
+    );
+    d.check(
+        || format!("{path}.envelope.body_num"),
+        a.body_num(),
+        b.body_num(),
+    );
+    d.check(
+        || format!("{path}.envelope.tail_num"),
+        a.tail_num(),
+        b.tail_num(),
+    );
+    let a: Vec<_> = a.points().iter().map(|p| (p.x(), p.y())).collect();
+    let b: Vec<_> = b.points().iter().map(|p| (p.x(), p.y())).collect();
+    d.check(|| format!("{path}.envelope.points"), a, b);
+}
+
+fn diff_oscillator<A: PTNOscillator, B: PTNOscillator>(
+    d: &mut ProjectDiff,
+    path: &str,
+    a: &A,
+    b: &B,
+) {
+    d.check(|| format!("{path}.shape"), a.shape(), b.shape());
+    d.check(|| format!("{path}.frequency"), a.frequency(), b.frequency());
+    d.check(|| format!("{path}.volume"), a.volume(), b.volume());
+    d.check(|| format!("{path}.offset"), a.offset(), b.offset());
+    d.check(|| format!("{path}.reverse"), a.reverse(), b.reverse());
+}
+
+fn diff_voice_ptn<A: VoicePTN + ?Sized, B: VoicePTN + ?Sized>(
+    d: &mut ProjectDiff,
+    path: &str,
+    a: &A,
+    b: &B,
+) {
+    diff_voice(d, path, a, b);
+    d.check(
+        || format!("{path}.ptn_sample_num"),
+        a.ptn_sample_num(),
+        b.ptn_sample_num(),
+    );
+
+    let a = a.units();
+    let b = b.units();
+    d.check(|| format!("{path}.units.len"), a.len(), b.len());
+    for (i, (a, b)) in a.iter().zip(&b).enumerate() {
+        let path = format!("{path}.units[{i}]");
+        d.check(|| format!("{path}.enabled"), a.enabled(), b.enabled());
+        d.check(|| format!("{path}.pan"), a.pan(), b.pan());
+
+        let ea: Vec<_> = a.envelope().iter().map(|p| (p.x(), p.y())).collect();
+        let eb: Vec<_> = b.envelope().iter().map(|p| (p.x(), p.y())).collect();
+        d.check(|| format!("{path}.envelope"), ea, eb);
+
+        diff_oscillator(d, &format!("{path}.osc_main"), a.osc_main(), b.osc_main());
+        diff_oscillator(
+            d,
+            &format!("{path}.osc_frequency"),
+            a.osc_frequency(),
+            b.osc_frequency(),
+        );
+        diff_oscillator(
+            d,
+            &format!("{path}.osc_volume"),
+            a.osc_volume(),
+            b.osc_volume(),
+        );
+    }
+}
+
+fn diff_voice_oggv<A: VoiceOGGV + ?Sized, B: VoiceOGGV + ?Sized>(
+    d: &mut ProjectDiff,
+    path: &str,
+    a: &A,
+    b: &B,
+) {
+    diff_voice(d, path, a, b);
+    d.check(
+        || format!("{path}.ogg_channels"),
+        a.ogg_channels(),
+        b.ogg_channels(),
+    );
+    d.check(
+        || format!("{path}.ogg_samples_per_second"),
+        a.ogg_samples_per_second(),
+        b.ogg_samples_per_second(),
+    );
+    d.check(
+        || format!("{path}.ogg_sample_num"),
+        a.ogg_sample_num(),
+        b.ogg_sample_num(),
+    );
+    // the data itself is too big to print, just report that it's different
+    d.check(
+        || format!("{path}.ogg_data_len"),
+        a.ogg_data().len(),
+        b.ogg_data().len(),
+    );
+    if a.ogg_data() != b.ogg_data() {
+        d.check(|| format!("{path}.ogg_data"), "left", "right");
+    }
+}
+
+fn delay_summary<D: Delay>(delay: &D) -> (u8, String, f32) {
+    (
+        delay.group(),
+        format!("{:?}", delay.frequency()),
+        *delay.rate(),
+    )
+}
+
+fn overdrive_summary<O: OverDrive>(overdrive: &O) -> (u8, f32, f32) {
+    (overdrive.group(), *overdrive.cut(), *overdrive.amp())
+}
+
+fn diff_effects<A: HasDelays + HasOverDrives, B: HasDelays + HasOverDrives>(
+    d: &mut ProjectDiff,
+    a: &A,
+    b: &B,
+) {
+    let da: Vec<_> = a.delays().iter().map(|x| delay_summary(&*x)).collect();
+    let db: Vec<_> = b.delays().iter().map(|x| delay_summary(&*x)).collect();
+    d.check(|| "delays.len".into(), da.len(), db.len());
+    for (i, (a, b)) in da.iter().zip(&db).enumerate() {
+        d.check(|| format!("delays[{i}]"), a, b);
+    }
+
+    let oa: Vec<_> = a
+        .overdrives()
+        .iter()
+        .map(|x| overdrive_summary(&*x))
+        .collect();
+    let ob: Vec<_> = b
+        .overdrives()
+        .iter()
+        .map(|x| overdrive_summary(&*x))
+        .collect();
+    d.check(|| "overdrives.len".into(), oa.len(), ob.len());
+    for (i, (a, b)) in oa.iter().zip(&ob).enumerate() {
+        d.check(|| format!("overdrives[{i}]"), a, b);
+    }
+}
@@ -0,0 +1,483 @@
+//! Exporting a project as a Standard MIDI File
+//!
+//! ```no_run
+//! # #[cfg(feature = "rust-impl")]
+//! # {
+//! use pxtone::{
+//!     export::midi::{MidiExport, MidiExportOptions},
+//!     interface::io::PxToneServiceIO,
+//!     rust_impl::service::RPxTone,
+//! };
+//!
+//! let mut pxtone = RPxTone::new();
+//! pxtone.read_bytes(&std::fs::read("song.ptcop").unwrap()).unwrap();
+//!
+//! let file = std::io::BufWriter::new(std::fs::File::create("song.mid").unwrap());
+//! pxtone.export_midi(file, &MidiExportOptions::default()).unwrap();
+//! # }
+//! ```
+
+use std::{fmt, io};
+
+use crate::{
+    interface::{
+        event::{
+            BaseEvent, EventKey, EventKind, EventList, EventOn, EventPanVolume, EventVolume,
+            GenericEvent, GenericEventKind, HasEventList, Key, UnitState,
+        },
+        service::PxTone,
+        unit::{HasUnits, Unit, Units},
+    },
+    time::{HasTempoMap, Tempo},
+};
+
+/// Every channel but 10 (`9` here), which is reserved for drums in General MIDI
+const CHANNELS: [u8; 15] = [0, 1, 2, 3, 4, 5, 6, 7, 8, 10, 11, 12, 13, 14, 15];
+
+const NOTE_OFF: u8 = 0x80;
+const NOTE_ON: u8 = 0x90;
+const CONTROL_CHANGE: u8 = 0xB0;
+const PITCH_BEND: u8 = 0xE0;
+
+const CC_DATA_ENTRY: u8 = 6;
+const CC_VOLUME: u8 = 7;
+const CC_PAN: u8 = 10;
+const CC_DATA_ENTRY_LSB: u8 = 38;
+const CC_RPN_LSB: u8 = 100;
+const CC_RPN_MSB: u8 = 101;
+
+const META_TRACK_NAME: u8 = 0x03;
+const META_END_OF_TRACK: u8 = 0x2F;
+const META_TEMPO: u8 = 0x51;
+const META_TIME_SIGNATURE: u8 = 0x58;
+
+const BEND_CENTER: i32 = 0x2000;
+
+#[derive(Debug, Clone)]
+pub struct MidiExportOptions {
+    /// Pitch bend range in semitones, set on every track with RPN 0.
+    ///
+    /// Microtuning only needs half a semitone, but portamento slides and key changes during a note
+    /// are clamped to this range.
+    pub pitch_bend_range: u8,
+    /// Porta slides are written as one pitch bend every this many clocks
+    pub glide_step: u32,
+}
+
+impl Default for MidiExportOptions {
+    fn default() -> Self {
+        Self { pitch_bend_range: 12, glide_step: 10 }
+    }
+}
+
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum MidiExportError {
+    InvalidOptions(&'static str),
+    /// The project's clocks per beat can't be used as the MIDI time division (1 to 32767)
+    UnsupportedBeatClock(i32),
+    Io(io::Error),
+}
+
+impl fmt::Display for MidiExportError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::InvalidOptions(reason) => write!(f, "Invalid MIDI export options: {reason}"),
+            Self::UnsupportedBeatClock(beat_clock) => {
+                write!(f, "Beat clock {beat_clock} can't be used in a MIDI file")
+            },
+            Self::Io(e) => write!(f, "Failed to write MIDI: {e}"),
+        }
+    }
+}
+
+impl std::error::Error for MidiExportError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Io(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for MidiExportError {
+    fn from(e: io::Error) -> Self {
+        Self::Io(e)
+    }
+}
+
+/// Write `pxtone` to `writer` as a Type-1 MIDI file.
+///
+/// The first track holds the name, tempo and time signature, followed by one track per unit. A
+/// MIDI tick is one pxtone clock, so nothing gets quantized. Units share the 15 melodic channels,
+/// so with more than 15 units some share pitch bends and controllers.
+pub fn export_midi<P: PxTone + HasEventList + HasUnits + ?Sized, W: io::Write>(
+    pxtone: &P,
+    mut writer: W,
+    options: &MidiExportOptions,
+) -> Result<(), MidiExportError> {
+    if options.pitch_bend_range == 0 {
+        return Err(MidiExportError::InvalidOptions(
+            "pitch_bend_range must not be 0",
+        ));
+    }
+    if options.glide_step == 0 {
+        return Err(MidiExportError::InvalidOptions("glide_step must not be 0"));
+    }
+
+    let tempo = Tempo::of(pxtone);
+    let division = u16::try_from(tempo.beat_clock)
+        .ok()
+        .filter(|d| (1..=0x7FFF).contains(d))
+        .ok_or(MidiExportError::UnsupportedBeatClock(tempo.beat_clock))?;
+
+    let unit_names: Vec<String> = Units::iter(&*pxtone.units()).map(|u| u.name()).collect();
+    let end_clock = pxtone.tempo_map().end_clock();
+
+    let mut conductor = Track::default();
+    conductor.meta(0, META_TRACK_NAME, pxtone.name().as_bytes());
+    let us_per_beat = (60_000_000.0 / f64::from(tempo.beat_tempo)).round() as u32;
+    conductor.meta(0, META_TEMPO, &us_per_beat.to_be_bytes()[1..]);
+    // beat_num/4, 24 clocks per metronome click, 8 32nd notes per beat
+    let beat_num = tempo.beat_num.clamp(1, 255) as u8;
+    conductor.meta(0, META_TIME_SIGNATURE, &[beat_num, 2, 24, 8]);
+
+    let mut tracks = vec![conductor];
+    let events = pxtone.event_list();
+    for (unit_no, name) in unit_names.iter().enumerate() {
+        let channel = CHANNELS[unit_no % CHANNELS.len()];
+        let mut writer = UnitTrackWriter::new(channel, options);
+        writer.track.meta(0, META_TRACK_NAME, name.as_bytes());
+
+        let unit_events: Vec<_> = events.events_for_unit(unit_no as u8).collect();
+        // like in pxtone, the other events on a clock apply before its `On`s
+        for group in unit_events.chunk_by(|a, b| a.clock() == b.clock()) {
+            let clock = group[0].clock();
+            writer.advance_glide(clock);
+            for &e in group.iter().filter(|e| EventKind::of(**e) != EventKind::On) {
+                writer.event(e);
+            }
+            for &e in group.iter().filter(|e| EventKind::of(**e) == EventKind::On) {
+                writer.event(e);
+            }
+        }
+        writer.finish_glide();
+
+        tracks.push(writer.finish());
+    }
+
+    let mut header = Vec::with_capacity(14);
+    header.extend_from_slice(b"MThd");
+    header.extend_from_slice(&6_u32.to_be_bytes());
+    header.extend_from_slice(&1_u16.to_be_bytes());
+    header.extend_from_slice(&(tracks.len() as u16).to_be_bytes());
+    header.extend_from_slice(&division.to_be_bytes());
+    writer.write_all(&header)?;
+
+    for track in tracks {
+        writer.write_all(&track.into_bytes(end_clock))?;
+    }
+    writer.flush()?;
+
+    Ok(())
+}
+
+#[derive(Default)]
+struct Track {
+    /// `(clock, order, message)`, sorted by clock and then order before writing
+    events: Vec<(u32, u8, Vec<u8>)>,
+}
+
+impl Track {
+    /// Note offs go before everything else on the same clock, and note ons after
+    const ORDER_OFF: u8 = 0;
+    const ORDER_CONTROL: u8 = 1;
+    const ORDER_ON: u8 = 2;
+
+    fn push(&mut self, clock: u32, order: u8, message: Vec<u8>) {
+        self.events.push((clock, order, message));
+    }
+
+    fn meta(&mut self, clock: u32, kind: u8, data: &[u8]) {
+        let mut message = vec![0xFF, kind];
+        write_vlq(&mut message, data.len() as u32);
+        message.extend_from_slice(data);
+        self.push(clock, Self::ORDER_CONTROL, message);
+    }
+
+    /// Encode the track chunk, ending no earlier than `end_clock`
+    fn into_bytes(mut self, end_clock: u32) -> Vec<u8> {
+        self.events.sort_by_key(|&(clock, order, _)| (clock, order));
+
+        let mut data = Vec::new();
+        let mut last = 0;
+        for (clock, _, message) in &self.events {
+            write_vlq(&mut data, clock - last);
+            data.extend_from_slice(message);
+            last = *clock;
+        }
+        write_vlq(&mut data, end_clock.saturating_sub(last));
+        data.extend_from_slice(&[0xFF, META_END_OF_TRACK, 0]);
+
+        let mut bytes = Vec::with_capacity(data.len() + 8);
+        bytes.extend_from_slice(b"MTrk");
+        bytes.extend_from_slice(&(data.len() as u32).to_be_bytes());
+        bytes.extend_from_slice(&data);
+        bytes
+    }
+}
+
+fn write_vlq(out: &mut Vec<u8>, value: u32) {
+    let mut bytes = vec![(value & 0x7F) as u8];
+    let mut value = value >> 7;
+    while value > 0 {
+        bytes.push((value & 0x7F) as u8 | 0x80);
+        value >>= 7;
+    }
+    out.extend(bytes.iter().rev());
+}
+
+/// Porta slide of the unit's key, see `UnitData::key` in the rust impl's moo
+struct Glide {
+    start: u32,
+    from: f64,
+    to: f64,
+    length: u32,
+    /// Clock of the next pitch bend to write
+    next_step: u32,
+}
+
+impl Glide {
+    fn key_at(&self, clock: u32) -> f64 {
+        let progress = f64::from(clock.saturating_sub(self.start)) / f64::from(self.length);
+        self.from + (self.to - self.from) * progress.min(1.0)
+    }
+
+    fn end(&self) -> u32 {
+        self.start + self.length
+    }
+}
+
+struct PlayingNote {
+    note: u8,
+    end: u32,
+}
+
+/// Turns one unit's events into a track
+struct UnitTrackWriter<'a> {
+    track: Track,
+    channel: u8,
+    options: &'a MidiExportOptions,
+    state: UnitState,
+    /// Key the unit is sounding at, in pxtone key units
+    key_now: f64,
+    glide: Option<Glide>,
+    /// Notes that have been started, with the newest last
+    playing: Vec<PlayingNote>,
+    last_bend: Option<u16>,
+}
+
+impl<'a> UnitTrackWriter<'a> {
+    fn new(channel: u8, options: &'a MidiExportOptions) -> Self {
+        let mut writer = Self {
+            track: Track::default(),
+            channel,
+            options,
+            state: UnitState::default(),
+            key_now: f64::from(*Key::DEFAULT),
+            glide: None,
+            playing: Vec::new(),
+            last_bend: None,
+        };
+
+        // pitch bend range through RPN 0
+        for (cc, value) in [
+            (CC_RPN_MSB, 0),
+            (CC_RPN_LSB, 0),
+            (CC_DATA_ENTRY, options.pitch_bend_range.min(127)),
+            (CC_DATA_ENTRY_LSB, 0),
+        ] {
+            writer.control(0, cc, value);
+        }
+        // pxtone's defaults, which aren't the same as MIDI's
+        writer.control(0, CC_VOLUME, to_midi_value(*writer.state.volume));
+        writer.control(0, CC_PAN, pan_to_midi(*writer.state.pan_volume));
+
+        writer
+    }
+
+    fn control(&mut self, clock: u32, cc: u8, value: u8) {
+        self.track.push(
+            clock,
+            Track::ORDER_CONTROL,
+            vec![CONTROL_CHANGE | self.channel, cc, value],
+        );
+    }
+
+    /// Offset of the unit's tuning, in key units
+    fn tuning_offset(&self) -> f64 {
+        let tuning = f64::from(*self.state.tuning);
+        if tuning > 0.0 {
+            12.0 * tuning.log2() * f64::from(Key::SEMITONE)
+        } else {
+            0.0
+        }
+    }
+
+    /// Bend the newest note to `key`, if it's still playing at `clock`
+    fn bend_to(&mut self, clock: u32, key: f64) {
+        let Some(note) = self.playing.last().filter(|n| n.end > clock) else {
+            return;
+        };
+
+        let base = f64::from(*Key::from_midi(note.note));
+        let semitones = (key + self.tuning_offset() - base) / f64::from(Key::SEMITONE);
+        let range = f64::from(self.options.pitch_bend_range);
+        let bend = (f64::from(BEND_CENTER) * (1.0 + semitones / range))
+            .round()
+            .clamp(0.0, 0x3FFF as f64) as u16;
+
+        if self.last_bend != Some(bend) {
+            self.last_bend = Some(bend);
+            self.track.push(
+                clock,
+                Track::ORDER_CONTROL,
+                vec![
+                    PITCH_BEND | self.channel,
+                    (bend & 0x7F) as u8,
+                    (bend >> 7) as u8,
+                ],
+            );
+        }
+    }
+
+    /// Write the steps of the current porta up to (but not including) `clock`
+    fn advance_glide(&mut self, clock: u32) {
+        let Some(mut glide) = self.glide.take() else {
+            return;
+        };
+
+        let until = clock.min(glide.end());
+        while glide.next_step < until {
+            self.bend_to(glide.next_step, glide.key_at(glide.next_step));
+            glide.next_step += self.options.glide_step;
+        }
+        if clock >= glide.end() {
+            self.key_now = glide.to;
+            self.bend_to(glide.end(), glide.to);
+        } else {
+            self.key_now = glide.key_at(clock);
+            self.glide = Some(glide);
+        }
+    }
+
+    fn finish_glide(&mut self) {
+        self.advance_glide(u32::MAX);
+    }
+
+    fn end_notes(&mut self, clock: u32, mut f: impl FnMut(&PlayingNote) -> bool) {
+        for note in self.playing.iter_mut().filter(|n| n.end > clock && f(n)) {
+            note.end = clock;
+        }
+    }
+
+    fn event<E: GenericEvent + ?Sized>(&mut self, event: &E) {
+        let clock = event.clock();
+        self.state.apply(event);
+
+        match event.kind() {
+            GenericEventKind::On(on) => {
+                // `On` finishes any porta in progress
+                self.glide = None;
+                self.key_now = f64::from(*self.state.key);
+
+                let key = Key::new((self.key_now + self.tuning_offset()).round() as i32);
+                let Some(note) = key.to_midi() else {
+                    return;
+                };
+
+                // the same note can't overlap itself
+                self.end_notes(clock, |n| n.note == note);
+                self.playing
+                    .push(PlayingNote { note, end: clock + on.length() });
+                self.bend_to(clock, self.key_now);
+
+                let velocity = to_midi_value(*self.state.velocity).max(1);
+                self.track.push(
+                    clock,
+                    Track::ORDER_ON,
+                    vec![NOTE_ON | self.channel, note, velocity],
+                );
+            },
+            GenericEventKind::Key(e) => {
+                let to = f64::from(*e.key());
+                if self.state.porta == 0 {
+                    self.glide = None;
+                    self.key_now = to;
+                    self.bend_to(clock, to);
+                } else {
+                    self.glide = Some(Glide {
+                        start: clock,
+                        from: self.key_now,
+                        to,
+                        length: self.state.porta,
+                        next_step: clock + self.options.glide_step,
+                    });
+                }
+            },
+            GenericEventKind::Tuning(_) => self.bend_to(clock, self.key_now),
+            GenericEventKind::Volume(e) => {
+                self.control(clock, CC_VOLUME, to_midi_value(*e.volume()));
+            },
+            GenericEventKind::PanVolume(e) => {
+                self.control(clock, CC_PAN, pan_to_midi(*e.pan_volume()));
+            },
+            GenericEventKind::VoiceNo(_) => {
+                // changing the woice cuts every note and resets the key
+                self.end_notes(clock, |_| true);
+                self.glide = None;
+                self.key_now = f64::from(*Key::DEFAULT);
+            },
+            _ => {},
+        }
+    }
+
+    fn finish(mut self) -> Track {
+        for note in std::mem::take(&mut self.playing) {
+            self.track.push(
+                note.end,
+                Track::ORDER_OFF,
+                vec![NOTE_OFF | self.channel, note.note, 0],
+            );
+        }
+        self.track
+    }
+}
+
+/// 0.0..=1.0 of pxtone's 0..=128 to MIDI's 0..=127
+fn to_midi_value(value: f32) -> u8 {
+    (value * 128.0).round().clamp(0.0, 127.0) as u8
+}
+
+fn pan_to_midi(pan: f32) -> u8 {
+    ((pan + 1.0) * 64.0).round().clamp(0.0, 127.0) as u8
+}
+
+/// Adds [`export_midi`] as a method on every backend
+pub trait MidiExport {
+    fn export_midi<W: io::Write>(
+        &self,
+        writer: W,
+        options: &MidiExportOptions,
+    ) -> Result<(), MidiExportError>;
+}
+
+impl<T: PxTone + HasEventList + HasUnits> MidiExport for T {
+    fn export_midi<W: io::Write>(
+        &self,
+        writer: W,
+        options: &MidiExportOptions,
+    ) -> Result<(), MidiExportError> {
+        export_midi(self, writer, options)
+    }
+}
@@ -0,0 +1,5 @@
+//! Rendering/exporting projects to other formats
+
+pub mod midi;
+pub mod sfz;
+pub mod wav;
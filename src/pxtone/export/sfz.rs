@@ -0,0 +1,335 @@
+//! Exporting a project's woices as SFZ instruments, so they can be played in other samplers
+//!
+//! ```no_run
+//! # #[cfg(feature = "rust-impl")]
+//! # {
+//! use pxtone::{
+//!     export::sfz::{SfzExport, SfzExportOptions},
+//!     interface::io::PxToneServiceIO,
+//!     rust_impl::service::RPxTone,
+//! };
+//!
+//! let mut pxtone = RPxTone::new();
+//! pxtone.read_bytes(&std::fs::read("song.ptcop").unwrap()).unwrap();
+//!
+//! for path in pxtone.export_sfz("song instruments", &SfzExportOptions::default()).unwrap() {
+//!     println!("{}", path.display());
+//! }
+//! # }
+//! ```
+
+use std::{
+    fmt,
+    fs::{self, File},
+    io::{self, BufWriter},
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use crate::interface::{
+    event::Key,
+    woice::{HasWoices, SingleVoice, Voice, VoiceOGGV, VoicePCM, Woice, WoiceType, Woices},
+};
+#[cfg(feature = "rust-impl")]
+use crate::rust_impl::woice::{NoteExportOptions, RPxToneWoice};
+
+/// Folder the samples are written to, next to the `.sfz` files
+const SAMPLE_DIR: &str = "samples";
+
+/// Basic key of a woice that plays its sample at the recorded rate on [`Key::DEFAULT`]
+const BASIC_KEY: i32 = 0x4500;
+
+/// pxtone fades sample woices in over 4ms when smoothing is on
+const SMOOTH_SECS: f32 = 0.004;
+
+#[derive(Debug, Clone)]
+pub struct SfzExportOptions {
+    /// MIDI notes PTV and PTN woices are rendered at. Each one is played for the keys up to
+    /// halfway to its neighbours.
+    pub render_notes: Vec<u8>,
+    /// How long each rendered note is held. Envelope releases play out after it.
+    pub note_length: Duration,
+    /// Sample rate of rendered notes
+    pub sample_rate: u32,
+}
+
+impl Default for SfzExportOptions {
+    fn default() -> Self {
+        Self {
+            // every C from C1 to C7
+            render_notes: (24..=96).step_by(12).collect(),
+            note_length: Duration::from_secs(2),
+            sample_rate: 44100,
+        }
+    }
+}
+
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum SfzExportError {
+    InvalidOptions(&'static str),
+    Io(io::Error),
+}
+
+impl fmt::Display for SfzExportError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::InvalidOptions(reason) => write!(f, "Invalid SFZ export options: {reason}"),
+            Self::Io(e) => write!(f, "Failed to write SFZ instrument: {e}"),
+        }
+    }
+}
+
+impl std::error::Error for SfzExportError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Io(e) => Some(e),
+            Self::InvalidOptions(_) => None,
+        }
+    }
+}
+
+impl From<io::Error> for SfzExportError {
+    fn from(e: io::Error) -> Self {
+        Self::Io(e)
+    }
+}
+
+/// Write every woice in `pxtone` to `dir` as its own `.sfz` instrument, returning their paths
+///
+/// Samples go in a `samples` folder beside them. PCM woices are written as `.wav` and OGGV woices
+/// as their original `.ogg`, mapped so they play at the same pitch as in pxtone. PTV and PTN
+/// woices are rendered at [`SfzExportOptions::render_notes`] into a multisample. Files are named
+/// after the woice's index and name, and `dir` is created if it doesn't exist.
+pub fn export_sfz<P: HasWoices + ?Sized, D: AsRef<Path>>(
+    pxtone: &P,
+    dir: D,
+    options: &SfzExportOptions,
+) -> Result<Vec<PathBuf>, SfzExportError>
+where
+    <P::Woices as Woices>::W: RenderNote,
+{
+    if options.render_notes.is_empty() {
+        return Err(SfzExportError::InvalidOptions(
+            "render_notes must not be empty",
+        ));
+    }
+    if options.render_notes.iter().any(|&n| n > 127) {
+        return Err(SfzExportError::InvalidOptions(
+            "render_notes must be MIDI notes (0 to 127)",
+        ));
+    }
+    if options.note_length.is_zero() {
+        return Err(SfzExportError::InvalidOptions("note_length must not be 0"));
+    }
+    if options.sample_rate == 0 {
+        return Err(SfzExportError::InvalidOptions("sample_rate must not be 0"));
+    }
+
+    let mut notes = options.render_notes.clone();
+    notes.sort_unstable();
+    notes.dedup();
+
+    let dir = dir.as_ref();
+    fs::create_dir_all(dir.join(SAMPLE_DIR))?;
+
+    let woices = pxtone.woices();
+    let mut written = vec![];
+    for (i, woice) in woices.iter().enumerate() {
+        let name = woice.name();
+        let stem = format!("{i:02} {}", file_name(&name));
+        let mut sfz = format!("// {}, exported from pxtone\n", name.trim());
+
+        match woice.woice_type() {
+            WoiceType::PCM(pcm) => {
+                let voice = pcm.voice();
+                let sample = format!("{SAMPLE_DIR}/{stem}.wav");
+                voice.export_wav(BufWriter::new(File::create(dir.join(&sample))?))?;
+
+                sample_region(&mut sfz, &sample, voice, voice.sample_num());
+            },
+            WoiceType::OGGV(oggv) => {
+                let voice = oggv.voice();
+                let sample = format!("{SAMPLE_DIR}/{stem}.ogg");
+                voice.export_ogg(BufWriter::new(File::create(dir.join(&sample))?))?;
+
+                sample_region(&mut sfz, &sample, voice, voice.ogg_sample_num());
+            },
+            WoiceType::PTV(_) | WoiceType::PTN(_) => {
+                multisample(&mut sfz, &*woice, dir, &stem, &notes, options)?;
+            },
+            _ => continue,
+        }
+
+        let path = dir.join(format!("{stem}.sfz"));
+        fs::write(&path, sfz)?;
+        written.push(path);
+    }
+
+    Ok(written)
+}
+
+/// Renders single notes of woices for [`export_sfz`], which turns PTV and PTN woices into
+/// multisamples since they don't have a sample of their own
+pub trait RenderNote {
+    /// Write `key` held for `length` to `writer` as a 16 bit stereo `.wav` file
+    fn render_note<W: io::Write>(
+        &self,
+        writer: W,
+        key: Key,
+        length: Duration,
+        sample_rate: u32,
+    ) -> io::Result<()>;
+}
+
+#[cfg(feature = "rust-impl")]
+impl RenderNote for RPxToneWoice {
+    fn render_note<W: io::Write>(
+        &self,
+        writer: W,
+        key: Key,
+        length: Duration,
+        sample_rate: u32,
+    ) -> io::Result<()> {
+        self.export_note_wav(
+            writer,
+            &NoteExportOptions { key, length, channels: 2, sample_rate },
+        )
+    }
+}
+
+/// One region covering every key, playing `sample` at its recorded rate on the voice's key
+fn sample_region<V: Voice + ?Sized>(sfz: &mut String, sample: &str, voice: &V, frames: u32) {
+    let (note, tune) = keycenter(root_key(voice.basic_key(), voice.tuning()));
+
+    let mut region = vec![
+        ("sample", sample.to_owned()),
+        ("pitch_keycenter", note.to_string()),
+    ];
+    if tune != 0 {
+        region.push(("tune", tune.to_string()));
+    }
+    if voice.flag_loop() && frames > 0 {
+        region.push(("loop_mode", "loop_continuous".into()));
+        region.push(("loop_start", "0".into()));
+        region.push(("loop_end", (frames - 1).to_string()));
+    } else {
+        region.push(("loop_mode", "no_loop".into()));
+    }
+    if voice.flag_smooth() {
+        region.push(("ampeg_attack", SMOOTH_SECS.to_string()));
+    }
+    push_region(sfz, &region);
+}
+
+/// Render `woice` at each of `notes` and add a region for each, splitting the keys between them
+fn multisample<W: RenderNote + ?Sized>(
+    sfz: &mut String,
+    woice: &W,
+    dir: &Path,
+    stem: &str,
+    notes: &[u8],
+    options: &SfzExportOptions,
+) -> Result<(), SfzExportError> {
+    for (i, &note) in notes.iter().enumerate() {
+        let sample = format!("{SAMPLE_DIR}/{stem} {note:03}.wav");
+        woice.render_note(
+            BufWriter::new(File::create(dir.join(&sample))?),
+            Key::from_midi(note),
+            options.note_length,
+            options.sample_rate,
+        )?;
+
+        let lokey = i
+            .checked_sub(1)
+            .map_or(0, |prev| midpoint(notes[prev], note) + 1);
+        let hikey = notes.get(i + 1).map_or(127, |&next| midpoint(note, next));
+
+        push_region(
+            sfz,
+            &[
+                ("sample", sample),
+                ("lokey", lokey.to_string()),
+                ("hikey", hikey.to_string()),
+                ("pitch_keycenter", note.to_string()),
+                ("loop_mode", "no_loop".into()),
+            ],
+        );
+    }
+
+    Ok(())
+}
+
+/// Opcodes go on their own lines, since sample paths can have spaces in them
+fn push_region(sfz: &mut String, opcodes: &[(&str, String)]) {
+    sfz.push_str("\n<region>\n");
+    for (opcode, value) in opcodes {
+        sfz.push_str(opcode);
+        sfz.push('=');
+        sfz.push_str(value);
+        sfz.push('\n');
+    }
+}
+
+fn midpoint(low: u8, high: u8) -> u8 {
+    low + (high - low) / 2
+}
+
+/// Key the voice plays its sample at the recorded rate on
+#[allow(clippy::cast_possible_truncation)]
+fn root_key(basic_key: i32, tuning: f32) -> Key {
+    // tuning speeds playback up, so the sample sounds at its recorded rate on a lower key
+    let detune = (f64::from(tuning).log2() * 12.0 * f64::from(Key::SEMITONE)).round() as i32;
+    Key::new(*Key::DEFAULT + basic_key - BASIC_KEY - detune)
+}
+
+/// `pitch_keycenter` and `tune` (in cents) that play a sample at its recorded rate on `root`
+#[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+fn keycenter(root: Key) -> (u8, i32) {
+    let semitones = f64::from(*root - *Key::from_midi(0)) / f64::from(Key::SEMITONE);
+    let note = semitones.round().clamp(0.0, 127.0);
+    (note as u8, ((note - semitones) * 100.0).round() as i32)
+}
+
+/// `name` with anything that isn't safe in a file name replaced
+fn file_name(name: &str) -> String {
+    let name: String = name
+        .trim()
+        .chars()
+        .map(|c| {
+            if c.is_alphanumeric() || " -_".contains(c) {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect();
+
+    if name.is_empty() {
+        "woice".into()
+    } else {
+        name
+    }
+}
+
+/// Adds [`export_sfz`] as a method on every backend whose woices can render notes
+pub trait SfzExport {
+    fn export_sfz<D: AsRef<Path>>(
+        &self,
+        dir: D,
+        options: &SfzExportOptions,
+    ) -> Result<Vec<PathBuf>, SfzExportError>;
+}
+
+impl<T: HasWoices> SfzExport for T
+where
+    <T::Woices as Woices>::W: RenderNote,
+{
+    fn export_sfz<D: AsRef<Path>>(
+        &self,
+        dir: D,
+        options: &SfzExportOptions,
+    ) -> Result<Vec<PathBuf>, SfzExportError> {
+        export_sfz(self, dir, options)
+    }
+}
@@ -0,0 +1,376 @@
+//! Offline rendering of a project to a WAV file
+//!
+//! ```no_run
+//! # #[cfg(feature = "rust-impl")]
+//! # {
+//! use pxtone::{
+//!     export::wav::{WavExport, WavExportOptions},
+//!     interface::io::PxToneServiceIO,
+//!     rust_impl::service::RPxTone,
+//! };
+//!
+//! let mut pxtone = RPxTone::new();
+//! pxtone.read_bytes(&std::fs::read("song.ptcop").unwrap()).unwrap();
+//!
+//! let file = std::io::BufWriter::new(std::fs::File::create("song.wav").unwrap());
+//! let summary = pxtone.export_wav(file, &WavExportOptions::default()).unwrap();
+//! println!("{:?} (peak {})", summary.duration, summary.peak);
+//! # }
+//! ```
+
+use std::{fmt, io, time::Duration};
+
+use crate::{
+    interface::{
+        event::HasEventList,
+        moo::{AsMoo, Fade, Moo},
+        service::PxTone,
+    },
+    time::HasTempoMap,
+};
+
+/// Number of frames rendered per call to [`Moo::sample`]
+const BLOCK_FRAMES: usize = 4096;
+
+const WAVE_FORMAT_PCM: u16 = 1;
+const WAVE_FORMAT_IEEE_FLOAT: u16 = 3;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WavSampleFormat {
+    /// 16-bit signed integer
+    Int16,
+    /// 32-bit IEEE float, rendered with [`Moo::sample_f32`] so it isn't clipped
+    Float32,
+}
+
+impl WavSampleFormat {
+    fn bytes_per_sample(self) -> u16 {
+        match self {
+            Self::Int16 => 2,
+            Self::Float32 => 4,
+        }
+    }
+
+    fn format_tag(self) -> u16 {
+        match self {
+            Self::Int16 => WAVE_FORMAT_PCM,
+            Self::Float32 => WAVE_FORMAT_IEEE_FLOAT,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct WavExportOptions {
+    /// 1 (mono) or 2 (stereo)
+    pub channels: u8,
+    pub sample_rate: u32,
+    pub format: WavSampleFormat,
+    /// Number of times to play the song, `1` plays it once.
+    ///
+    /// Every play after the first starts from the repeat measure.
+    pub loops: u32,
+    /// If set, keep playing (looping if needed) after the last loop and fade out over this duration
+    pub fade_out: Option<Duration>,
+}
+
+impl Default for WavExportOptions {
+    fn default() -> Self {
+        Self {
+            channels: 2,
+            sample_rate: 44100,
+            format: WavSampleFormat::Int16,
+            loops: 1,
+            fade_out: None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct WavExportSummary {
+    pub duration: Duration,
+    /// Number of frames (samples per channel) written
+    pub frames: u64,
+    /// Highest absolute sample value, where 1.0 is full scale. Only float output can go past it.
+    pub peak: f32,
+    /// Number of samples that hit the limits of 16-bit output. Float output isn't clipped, but
+    /// these would be if it was converted to 16-bit.
+    pub clipped_samples: u64,
+}
+
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum WavExportError {
+    InvalidOptions(&'static str),
+    /// The song would not fit in a WAV file (4 GiB max)
+    TooLong,
+    Moo(String),
+    Io(io::Error),
+}
+
+impl fmt::Display for WavExportError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::InvalidOptions(reason) => write!(f, "Invalid WAV export options: {reason}"),
+            Self::TooLong => write!(f, "Song is too long to fit in a WAV file"),
+            Self::Moo(e) => write!(f, "Failed to render song: {e}"),
+            Self::Io(e) => write!(f, "Failed to write WAV: {e}"),
+        }
+    }
+}
+
+impl std::error::Error for WavExportError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Io(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for WavExportError {
+    fn from(e: io::Error) -> Self {
+        Self::Io(e)
+    }
+}
+
+/// Render `pxtone` to `writer` as a WAV file.
+///
+/// The length is worked out before rendering so the header can be written up front, which means
+/// `writer` doesn't need to be seekable. Once the song finishes any remaining frames are silence.
+#[allow(clippy::cast_possible_truncation, clippy::cast_precision_loss)]
+pub fn export_wav<P: PxTone + HasEventList + AsMoo + ?Sized, W: io::Write>(
+    pxtone: &mut P,
+    mut writer: W,
+    options: &WavExportOptions,
+) -> Result<WavExportSummary, WavExportError> {
+    if !(1..=2).contains(&options.channels) {
+        return Err(WavExportError::InvalidOptions("channels must be 1 or 2"));
+    }
+    if options.sample_rate == 0 {
+        return Err(WavExportError::InvalidOptions("sample_rate must not be 0"));
+    }
+    if options.loops == 0 {
+        return Err(WavExportError::InvalidOptions("loops must be at least 1"));
+    }
+
+    let song_frames = pxtone
+        .tempo_map()
+        .song_samples(options.sample_rate, options.loops);
+    let fade_frames = options.fade_out.map_or(0, |fade| {
+        (fade.as_secs_f64() * f64::from(options.sample_rate)) as u64
+    });
+
+    let total_frames = song_frames + fade_frames;
+
+    let channels = u16::from(options.channels);
+    let bytes_per_sample = options.format.bytes_per_sample();
+    let data_len = u32::try_from(total_frames * u64::from(channels * bytes_per_sample))
+        .ok()
+        .filter(|len| len.checked_add(64).is_some())
+        .ok_or(WavExportError::TooLong)?;
+
+    write_header(
+        &mut writer,
+        options.format.format_tag(),
+        channels,
+        options.sample_rate,
+        bytes_per_sample * 8,
+        data_len,
+        total_frames as u32,
+    )?;
+
+    let mut moo = pxtone.as_moo();
+    let moo_err = |e| WavExportError::Moo(format!("{e:?}"));
+    moo.set_audio_format(options.channels, options.sample_rate)
+        .map_err(moo_err)?;
+    moo.prepare_sample().map_err(moo_err)?;
+    moo.set_loop(options.loops > 1 || options.fade_out.is_some())
+        .map_err(moo_err)?;
+
+    let body_frames = total_frames - fade_frames;
+    let mut summary = WavExportSummary {
+        duration: Duration::from_secs_f64(total_frames as f64 / f64::from(options.sample_rate)),
+        frames: total_frames,
+        peak: 0.0,
+        clipped_samples: 0,
+    };
+    let samples_per_block = BLOCK_FRAMES * usize::from(options.channels);
+    let mut ints = Vec::new();
+    let mut floats = Vec::new();
+    match options.format {
+        WavSampleFormat::Int16 => ints.resize(samples_per_block, 0_i16),
+        WavSampleFormat::Float32 => floats.resize(samples_per_block, 0.0_f32),
+    }
+    let mut bytes = Vec::with_capacity(samples_per_block * usize::from(bytes_per_sample));
+    let mut written = 0;
+
+    while written < total_frames {
+        if written == body_frames {
+            if let Some(fade) = options.fade_out {
+                moo.set_fade(Some(Fade::Out), fade).map_err(moo_err)?;
+            }
+        }
+
+        // don't let a block cross the start of the fade
+        let until = if written < body_frames {
+            body_frames
+        } else {
+            total_frames
+        };
+        let frames = (until - written).min(BLOCK_FRAMES as u64) as usize;
+        let len = frames * usize::from(options.channels);
+        // og pxtone errors if asked to sample once it's done
+        let done = moo.is_done_sampling();
+
+        bytes.clear();
+        match options.format {
+            WavSampleFormat::Int16 => {
+                let block = &mut ints[..len];
+                if done {
+                    block.fill(0);
+                } else {
+                    moo.sample(block).map_err(moo_err)?;
+                }
+                encode_i16(block, &mut bytes, &mut summary);
+            },
+            WavSampleFormat::Float32 => {
+                let block = &mut floats[..len];
+                if done {
+                    block.fill(0.0);
+                } else {
+                    moo.sample_f32(block).map_err(moo_err)?;
+                }
+                encode_f32(block, &mut bytes, &mut summary);
+            },
+        }
+        writer.write_all(&bytes)?;
+
+        written += frames as u64;
+    }
+
+    writer.flush()?;
+
+    Ok(summary)
+}
+
+/// Append `block` to `bytes`, keeping track of its peak and clipping
+fn encode_i16(block: &[i16], bytes: &mut Vec<u8>, summary: &mut WavExportSummary) {
+    for &s in block {
+        summary.peak = summary.peak.max(f32::from(s.unsigned_abs()) / 32768.0);
+        if s == i16::MIN || s == i16::MAX {
+            summary.clipped_samples += 1;
+        }
+        bytes.extend_from_slice(&s.to_le_bytes());
+    }
+}
+
+/// Like [`encode_i16`], counting samples as clipped if they would be at 16-bit
+fn encode_f32(block: &[f32], bytes: &mut Vec<u8>, summary: &mut WavExportSummary) {
+    for &s in block {
+        summary.peak = summary.peak.max(s.abs());
+        if !(-1.0..f32::from(i16::MAX) / 32768.0).contains(&s) {
+            summary.clipped_samples += 1;
+        }
+        bytes.extend_from_slice(&s.to_le_bytes());
+    }
+}
+
+/// Write a plain integer PCM `.wav` file, as the woice exports do
+///
+/// `data` is laid out like the file's `data` chunk: interleaved, unsigned if 8 bit and signed
+/// little endian otherwise.
+pub fn write_pcm<W: io::Write>(
+    mut writer: W,
+    channels: u8,
+    sample_rate: u32,
+    bits_per_sample: u8,
+    data: &[u8],
+) -> io::Result<()> {
+    let data_len = u32::try_from(data.len())
+        .ok()
+        .filter(|len| len.checked_add(64).is_some())
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "too long for a WAV file"))?;
+
+    write_header(
+        &mut writer,
+        WAVE_FORMAT_PCM,
+        channels.into(),
+        sample_rate,
+        bits_per_sample.into(),
+        data_len,
+        0,
+    )?;
+    writer.write_all(data)?;
+    // chunks are padded to an even length
+    if data_len % 2 == 1 {
+        writer.write_all(&[0])?;
+    }
+    writer.flush()
+}
+
+fn write_header<W: io::Write>(
+    writer: &mut W,
+    format_tag: u16,
+    channels: u16,
+    sample_rate: u32,
+    bits_per_sample: u16,
+    data_len: u32,
+    frames: u32,
+) -> io::Result<()> {
+    let block_align = channels * bits_per_sample / 8;
+
+    // non-PCM formats need the cbSize field and a fact chunk
+    let (fmt_len, fact_len) = if format_tag == WAVE_FORMAT_PCM {
+        (16, 0)
+    } else {
+        (18, 12)
+    };
+    let riff_len = 4 + (8 + fmt_len) + fact_len + (8 + data_len + data_len % 2);
+
+    let mut header = Vec::with_capacity(64);
+    header.extend_from_slice(b"RIFF");
+    header.extend_from_slice(&riff_len.to_le_bytes());
+    header.extend_from_slice(b"WAVE");
+
+    header.extend_from_slice(b"fmt ");
+    header.extend_from_slice(&fmt_len.to_le_bytes());
+    header.extend_from_slice(&format_tag.to_le_bytes());
+    header.extend_from_slice(&channels.to_le_bytes());
+    header.extend_from_slice(&sample_rate.to_le_bytes());
+    header.extend_from_slice(&(sample_rate * u32::from(block_align)).to_le_bytes());
+    header.extend_from_slice(&block_align.to_le_bytes());
+    header.extend_from_slice(&bits_per_sample.to_le_bytes());
+    if fmt_len == 18 {
+        header.extend_from_slice(&0_u16.to_le_bytes());
+    }
+
+    if fact_len > 0 {
+        header.extend_from_slice(b"fact");
+        header.extend_from_slice(&4_u32.to_le_bytes());
+        header.extend_from_slice(&frames.to_le_bytes());
+    }
+
+    header.extend_from_slice(b"data");
+    header.extend_from_slice(&data_len.to_le_bytes());
+
+    writer.write_all(&header)
+}
+
+/// Adds [`export_wav`] as a method on every backend
+pub trait WavExport {
+    fn export_wav<W: io::Write>(
+        &mut self,
+        writer: W,
+        options: &WavExportOptions,
+    ) -> Result<WavExportSummary, WavExportError>;
+}
+
+impl<T: PxTone + HasEventList + AsMoo> WavExport for T {
+    fn export_wav<W: io::Write>(
+        &mut self,
+        writer: W,
+        options: &WavExportOptions,
+    ) -> Result<WavExportSummary, WavExportError> {
+        export_wav(self, writer, options)
+    }
+}
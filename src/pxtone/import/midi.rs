@@ -0,0 +1,647 @@
+//! Importing a Standard MIDI File into a project
+//!
+//! ```no_run
+//! # #[cfg(feature = "rust-impl")]
+//! # {
+//! use pxtone::{import::midi::MidiImport, rust_impl::service::RPxTone};
+//!
+//! let mut pxtone = RPxTone::new();
+//! let warnings = pxtone.import_midi(&std::fs::read("song.mid").unwrap()).unwrap();
+//! for warning in warnings {
+//!     println!("{warning}");
+//! }
+//! # }
+//! ```
+
+use std::{collections::BTreeMap, fmt};
+
+use crate::{
+    interface::{
+        event::{AddEventError, EventListMut, HasEventList, Key, PanValue, UnitState},
+        event_impl::EventImpl,
+        service::PxTone,
+        unit::{HasUnits, Unit, UnitsMut},
+    },
+    time::Tempo,
+    util::ZeroToOneF32,
+};
+
+/// Longest unit name og pxtone can store, in bytes
+const MAX_UNIT_NAME: usize = 16;
+
+const CC_DATA_ENTRY: u8 = 6;
+const CC_VOLUME: u8 = 7;
+const CC_PAN: u8 = 10;
+const CC_DATA_ENTRY_LSB: u8 = 38;
+const CC_RPN_LSB: u8 = 100;
+const CC_RPN_MSB: u8 = 101;
+
+const META_TRACK_NAME: u8 = 0x03;
+const META_TEMPO: u8 = 0x51;
+const META_TIME_SIGNATURE: u8 = 0x58;
+
+/// General MIDI's default pitch bend range, in semitones
+const DEFAULT_BEND_RANGE: u8 = 2;
+/// General MIDI's percussion channel, channel 10 counting from zero
+const PERCUSSION_CHANNEL: u8 = 9;
+
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum MidiImportError {
+    InvalidFile(&'static str),
+    /// Files timed in SMPTE frames instead of ticks per beat aren't supported
+    SmpteTiming,
+    AddEvent(AddEventError),
+}
+
+impl fmt::Display for MidiImportError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::InvalidFile(reason) => write!(f, "Invalid MIDI file: {reason}"),
+            Self::SmpteTiming => write!(f, "MIDI files with SMPTE timing aren't supported"),
+            Self::AddEvent(e) => write!(f, "{e}"),
+        }
+    }
+}
+
+impl std::error::Error for MidiImportError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::AddEvent(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<AddEventError> for MidiImportError {
+    fn from(e: AddEventError) -> Self {
+        Self::AddEvent(e)
+    }
+}
+
+/// Something in the MIDI file that couldn't be brought over exactly
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum MidiImportWarning {
+    /// Notes of a unit overlapped `overlaps` times. They're all kept, but only the newest note of
+    /// a unit follows key changes.
+    Polyphony { unit_no: u8, overlaps: usize },
+    /// The unit came from MIDI channel 10, which is percussion in General MIDI. Its notes pick
+    /// drums rather than pitches, so it won't sound right until it's given drum woices.
+    Percussion { unit_no: u8 },
+    /// The project only has one tempo, so only the first one was used
+    TempoChanges { ignored: usize },
+    /// The project only has one time signature, so only the first one was used
+    TimeSignatureChanges { ignored: usize },
+    /// Measures in this time signature aren't a whole number of beats, the numerator was used as
+    /// the number of beats
+    TimeSignature { numerator: u8, denominator: u32 },
+    /// There were more tracks and channels than the project can have units
+    TooManyUnits { dropped: usize },
+    /// Messages pxtone has nothing like, such as program changes or sysex
+    Unmapped { message: &'static str, count: usize },
+}
+
+impl fmt::Display for MidiImportWarning {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Polyphony { unit_no, overlaps } => {
+                write!(f, "Unit {unit_no} has {overlaps} overlapping note(s)")
+            },
+            Self::Percussion { unit_no } => {
+                write!(f, "Unit {unit_no} is from percussion channel 10")
+            },
+            Self::TempoChanges { ignored } => write!(f, "Ignored {ignored} tempo change(s)"),
+            Self::TimeSignatureChanges { ignored } => {
+                write!(f, "Ignored {ignored} time signature change(s)")
+            },
+            Self::TimeSignature { numerator, denominator } => {
+                write!(
+                    f,
+                    "Time signature {numerator}/{denominator} doesn't fit in whole beats"
+                )
+            },
+            Self::TooManyUnits { dropped } => {
+                write!(
+                    f,
+                    "Dropped {dropped} track(s)/channel(s) past the unit limit"
+                )
+            },
+            Self::Unmapped { message, count } => write!(f, "Ignored {count} {message} message(s)"),
+        }
+    }
+}
+
+enum Message {
+    NoteOn { channel: u8, note: u8, velocity: u8 },
+    NoteOff { channel: u8, note: u8 },
+    Control { channel: u8, cc: u8, value: u8 },
+    PitchBend { channel: u8, value: u16 },
+    TrackName(String),
+    Tempo(u32),
+    TimeSignature { numerator: u8, denominator: u32 },
+    Unmapped(&'static str),
+}
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+    at: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, len: usize) -> Result<&'a [u8], MidiImportError> {
+        let bytes = self
+            .bytes
+            .get(self.at..self.at + len)
+            .ok_or(MidiImportError::InvalidFile("unexpected end of data"))?;
+        self.at += len;
+        Ok(bytes)
+    }
+
+    fn u8(&mut self) -> Result<u8, MidiImportError> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn u16(&mut self) -> Result<u16, MidiImportError> {
+        let b = self.take(2)?;
+        Ok(u16::from_be_bytes([b[0], b[1]]))
+    }
+
+    fn u32(&mut self) -> Result<u32, MidiImportError> {
+        let b = self.take(4)?;
+        Ok(u32::from_be_bytes([b[0], b[1], b[2], b[3]]))
+    }
+
+    fn vlq(&mut self) -> Result<u32, MidiImportError> {
+        let mut value = 0_u32;
+        for _ in 0..4 {
+            let b = self.u8()?;
+            value = (value << 7) | u32::from(b & 0x7F);
+            if b & 0x80 == 0 {
+                return Ok(value);
+            }
+        }
+        Err(MidiImportError::InvalidFile(
+            "variable length number is too long",
+        ))
+    }
+
+    fn is_empty(&self) -> bool {
+        self.at >= self.bytes.len()
+    }
+}
+
+/// `(tick, message)`s of one track
+fn read_track(data: &[u8]) -> Result<Vec<(u64, Message)>, MidiImportError> {
+    let mut reader = Reader { bytes: data, at: 0 };
+    let mut messages = Vec::new();
+    let mut tick = 0_u64;
+    let mut running_status = None;
+
+    while !reader.is_empty() {
+        tick += u64::from(reader.vlq()?);
+
+        let mut status = reader.u8()?;
+        let mut first = None;
+        if status < 0x80 {
+            // running status, this byte was already data
+            first = Some(status);
+            status = running_status.ok_or(MidiImportError::InvalidFile("missing status byte"))?;
+        }
+
+        let message = match status {
+            0xFF => {
+                let kind = reader.u8()?;
+                let len = reader.vlq()? as usize;
+                let data = reader.take(len)?;
+                match kind {
+                    META_TRACK_NAME => {
+                        Message::TrackName(String::from_utf8_lossy(data).into_owned())
+                    },
+                    META_TEMPO if len == 3 => {
+                        Message::Tempo(u32::from_be_bytes([0, data[0], data[1], data[2]]))
+                    },
+                    META_TIME_SIGNATURE if len >= 2 => Message::TimeSignature {
+                        numerator: data[0],
+                        denominator: 1_u32.checked_shl(u32::from(data[1])).unwrap_or(0),
+                    },
+                    // end of track and the others just don't matter
+                    _ => continue,
+                }
+            },
+            0xF0 | 0xF7 => {
+                let len = reader.vlq()? as usize;
+                reader.take(len)?;
+                Message::Unmapped("sysex")
+            },
+            0x80..=0xEF => {
+                running_status = Some(status);
+                let channel = status & 0x0F;
+                let mut data = || first.take().map_or_else(|| reader.u8(), Ok);
+                match status & 0xF0 {
+                    0x80 => {
+                        let note = data()?;
+                        data()?;
+                        Message::NoteOff { channel, note }
+                    },
+                    0x90 => {
+                        let note = data()?;
+                        let velocity = data()?;
+                        if velocity == 0 {
+                            Message::NoteOff { channel, note }
+                        } else {
+                            Message::NoteOn { channel, note, velocity }
+                        }
+                    },
+                    0xA0 => {
+                        data()?;
+                        data()?;
+                        Message::Unmapped("polyphonic aftertouch")
+                    },
+                    0xB0 => Message::Control { channel, cc: data()?, value: data()? },
+                    0xC0 => {
+                        data()?;
+                        Message::Unmapped("program change")
+                    },
+                    0xD0 => {
+                        data()?;
+                        Message::Unmapped("channel aftertouch")
+                    },
+                    _ => {
+                        let lsb = data()?;
+                        let msb = data()?;
+                        Message::PitchBend {
+                            channel,
+                            value: u16::from(lsb & 0x7F) | u16::from(msb & 0x7F) << 7,
+                        }
+                    },
+                }
+            },
+            _ => return Err(MidiImportError::InvalidFile("unknown status byte")),
+        };
+        messages.push((tick, message));
+    }
+
+    Ok(messages)
+}
+
+/// What a unit does at some tick, built from the messages of one track and channel
+enum Action {
+    Note { note: u8, velocity: u8, end: u64 },
+    Control { cc: u8, value: u8 },
+    PitchBend(u16),
+}
+
+impl Action {
+    /// Controllers and bends on a tick apply to the notes starting on it
+    fn order(&self) -> u8 {
+        match self {
+            Self::Note { .. } => 1,
+            _ => 0,
+        }
+    }
+}
+
+struct Source {
+    track: usize,
+    channel: u8,
+    actions: Vec<(u64, Action)>,
+}
+
+fn actions(
+    sources: &mut BTreeMap<u8, Source>,
+    track: usize,
+    channel: u8,
+) -> &mut Vec<(u64, Action)> {
+    &mut sources
+        .entry(channel)
+        .or_insert_with(|| Source { track, channel, actions: Vec::new() })
+        .actions
+}
+
+/// Everything that matters in a MIDI file, grouped by unit
+#[derive(Default)]
+struct Song {
+    division: u16,
+    sources: Vec<Source>,
+    track_names: Vec<Option<String>>,
+    tempos: Vec<u32>,
+    time_signatures: Vec<(u8, u32)>,
+    unmapped: BTreeMap<&'static str, usize>,
+}
+
+impl Song {
+    fn read(bytes: &[u8]) -> Result<Self, MidiImportError> {
+        let mut reader = Reader { bytes, at: 0 };
+        if reader.take(4)? != b"MThd" {
+            return Err(MidiImportError::InvalidFile("missing MThd header"));
+        }
+        let header_len = reader.u32()? as usize;
+        if header_len < 6 {
+            return Err(MidiImportError::InvalidFile("header is too short"));
+        }
+        // format 0 only has one track, and 1 and 2 are read the same
+        let _format = reader.u16()?;
+        let track_num = usize::from(reader.u16()?);
+        let division = reader.u16()?;
+        reader.take(header_len - 6)?;
+        if division & 0x8000 != 0 {
+            return Err(MidiImportError::SmpteTiming);
+        }
+        if division == 0 {
+            return Err(MidiImportError::InvalidFile("division is 0"));
+        }
+
+        let mut song = Self { division, ..Self::default() };
+        while song.track_names.len() < track_num && !reader.is_empty() {
+            let kind = reader.take(4)?;
+            let len = reader.u32()? as usize;
+            let data = reader.take(len)?;
+            // unknown chunks are to be skipped
+            if kind == b"MTrk" {
+                song.track_names.push(None);
+                song.add_track(&read_track(data)?);
+            }
+        }
+
+        Ok(song)
+    }
+
+    fn add_track(&mut self, track: &[(u64, Message)]) {
+        let track_no = self.track_names.len() - 1;
+        let end = track.last().map_or(0, |(tick, _)| *tick);
+        let mut channels = BTreeMap::<u8, Source>::new();
+        // indices of the notes waiting for their note off, oldest first
+        let mut open = BTreeMap::<(u8, u8), Vec<usize>>::new();
+
+        for &(tick, ref message) in track {
+            match *message {
+                Message::NoteOn { channel, note, velocity } => {
+                    let actions = actions(&mut channels, track_no, channel);
+                    open.entry((channel, note)).or_default().push(actions.len());
+                    // the end is filled in by the note off
+                    actions.push((tick, Action::Note { note, velocity, end: end.max(tick) }));
+                },
+                Message::NoteOff { channel, note } => {
+                    let Some(index) = open
+                        .get_mut(&(channel, note))
+                        .filter(|notes| !notes.is_empty())
+                        .map(|notes| notes.remove(0))
+                    else {
+                        continue;
+                    };
+                    if let Some((_, Action::Note { end, .. })) =
+                        actions(&mut channels, track_no, channel).get_mut(index)
+                    {
+                        *end = tick;
+                    }
+                },
+                Message::Control { channel, cc, value } => {
+                    actions(&mut channels, track_no, channel)
+                        .push((tick, Action::Control { cc, value }));
+                },
+                Message::PitchBend { channel, value } => {
+                    actions(&mut channels, track_no, channel)
+                        .push((tick, Action::PitchBend(value)));
+                },
+                Message::TrackName(ref name) => self.track_names[track_no] = Some(name.clone()),
+                Message::Tempo(us_per_beat) => self.tempos.push(us_per_beat),
+                Message::TimeSignature { numerator, denominator } => {
+                    self.time_signatures.push((numerator, denominator));
+                },
+                Message::Unmapped(message) => *self.unmapped.entry(message).or_default() += 1,
+            }
+        }
+
+        self.sources.extend(channels.into_values().filter(|s| {
+            s.actions
+                .iter()
+                .any(|(_, a)| matches!(a, Action::Note { .. }))
+        }));
+    }
+
+    /// Set the project's tempo and time signature from the first ones in the song
+    fn set_master<P: PxTone + ?Sized>(
+        &self,
+        pxtone: &mut P,
+        warnings: &mut Vec<MidiImportWarning>,
+    ) {
+        if let Some(&us_per_beat) = self.tempos.first() {
+            if us_per_beat > 0 {
+                pxtone.set_beat_tempo((60_000_000.0 / f64::from(us_per_beat)) as f32);
+            }
+            let ignored = self.tempos.iter().filter(|&&t| t != us_per_beat).count();
+            if ignored > 0 {
+                warnings.push(MidiImportWarning::TempoChanges { ignored });
+            }
+        }
+
+        if let Some(&(numerator, denominator)) = self.time_signatures.first() {
+            let quarters = u32::from(numerator) * 4;
+            if denominator > 0 && quarters % denominator == 0 {
+                pxtone.set_beat_num((quarters / denominator) as i32);
+            } else {
+                pxtone.set_beat_num(i32::from(numerator.max(1)));
+                warnings.push(MidiImportWarning::TimeSignature { numerator, denominator });
+            }
+            let ignored = self
+                .time_signatures
+                .iter()
+                .filter(|&&ts| ts != (numerator, denominator))
+                .count();
+            if ignored > 0 {
+                warnings.push(MidiImportWarning::TimeSignatureChanges { ignored });
+            }
+        }
+    }
+
+    /// Replace the project's units with one per source, returning how many fit
+    fn set_units<P: HasUnits + ?Sized>(&self, pxtone: &mut P) -> usize {
+        let mut units = pxtone.units_mut();
+        while UnitsMut::remove(&mut *units, 0) {}
+
+        let mut unit_num = 0;
+        for source in &self.sources {
+            let Some(mut unit) = units.add_new() else {
+                break;
+            };
+            let name = self.track_names[source.track]
+                .clone()
+                .unwrap_or_else(|| format!("track {}", source.track));
+            let split = self
+                .sources
+                .iter()
+                .filter(|s| s.track == source.track)
+                .count()
+                > 1;
+            let name = if split {
+                format!("{name} ch{}", source.channel + 1)
+            } else {
+                name
+            };
+            // not all backends can store long names
+            let _ = unit.set_name(truncate(&name, MAX_UNIT_NAME).into());
+            unit_num += 1;
+        }
+
+        unit_num
+    }
+}
+
+/// Adds one source's events as `unit_no`, returning the clock after its last note and the
+/// number of times its notes overlapped
+fn add_unit_events<L: EventListMut + ?Sized>(
+    events: &mut L,
+    unit_no: u8,
+    source: &mut Source,
+    to_clock: impl Fn(u64) -> u32,
+    unmapped: &mut BTreeMap<&'static str, usize>,
+) -> Result<(u32, usize), MidiImportError> {
+    let mut state = UnitState::default();
+    let mut bend_range = DEFAULT_BEND_RANGE;
+    let mut rpn = (0x7F, 0x7F);
+    // key offset from pitch bend
+    let mut bend = 0;
+    let mut newest: Option<(u8, u64)> = None;
+    let mut sounding_until = 0;
+    let mut overlaps = 0;
+    let mut last_clock = 0;
+
+    let set_key = |events: &mut L, state: &mut UnitState, clock, key| {
+        if key == state.key {
+            return Ok(());
+        }
+        state.key = key;
+        events.add(&EventImpl::key(clock, unit_no, key))
+    };
+
+    source
+        .actions
+        .sort_by_key(|(tick, action)| (*tick, action.order()));
+    for &(tick, ref action) in &source.actions {
+        let clock = to_clock(tick);
+        match *action {
+            Action::Note { note, velocity, end } => {
+                if tick < sounding_until {
+                    overlaps += 1;
+                }
+                sounding_until = sounding_until.max(end);
+                newest = Some((note, end));
+
+                let key = Key::new(*Key::from_midi(note) + bend);
+                set_key(events, &mut state, clock, key)?;
+                let velocity = ZeroToOneF32::new(f32::from(velocity) / 128.0);
+                if velocity != state.velocity {
+                    state.velocity = velocity;
+                    events.add(&EventImpl::velocity(clock, unit_no, velocity))?;
+                }
+                let length = (to_clock(end) - clock).max(1);
+                events.add(&EventImpl::on(clock, unit_no, length))?;
+                last_clock = last_clock.max(clock + length);
+            },
+            Action::PitchBend(value) => {
+                let semitones = (f64::from(value) - f64::from(0x2000)) / f64::from(0x2000)
+                    * f64::from(bend_range);
+                bend = (semitones * f64::from(Key::SEMITONE)).round() as i32;
+
+                // bend the note that's playing
+                if let Some((note, _)) = newest.filter(|&(_, end)| end > tick) {
+                    let key = Key::new(*Key::from_midi(note) + bend);
+                    set_key(events, &mut state, clock, key)?;
+                }
+            },
+            Action::Control { cc, value } => match cc {
+                CC_VOLUME => {
+                    let volume = ZeroToOneF32::new(f32::from(value) / 128.0);
+                    events.add(&EventImpl::volume(clock, unit_no, volume))?;
+                },
+                CC_PAN => {
+                    let pan = PanValue::new((f32::from(value) - 64.0) / 64.0);
+                    events.add(&EventImpl::pan_volume(clock, unit_no, pan))?;
+                },
+                CC_RPN_MSB => rpn.0 = value,
+                CC_RPN_LSB => rpn.1 = value,
+                CC_DATA_ENTRY if rpn == (0, 0) => bend_range = value,
+                CC_DATA_ENTRY | CC_DATA_ENTRY_LSB => {},
+                _ => *unmapped.entry("control change").or_default() += 1,
+            },
+        }
+    }
+
+    Ok((last_clock, overlaps))
+}
+
+/// Replace `pxtone`'s units and events with the contents of a MIDI file.
+///
+/// Every track and channel that has notes becomes a unit, named after its track. Notes, velocity,
+/// volume (CC7), pan (CC10) and pitch bend are converted to events at the project's `beat_clock`,
+/// and the first tempo and time signature become the project's. Woices are left alone, so every
+/// unit plays woice 0.
+///
+/// Returns what couldn't be converted exactly.
+pub fn import_midi<P: PxTone + HasEventList + HasUnits + ?Sized>(
+    pxtone: &mut P,
+    bytes: &[u8],
+) -> Result<Vec<MidiImportWarning>, MidiImportError> {
+    let mut song = Song::read(bytes)?;
+    let mut warnings = Vec::new();
+
+    song.set_master(pxtone, &mut warnings);
+    let unit_num = song.set_units(pxtone);
+    if unit_num < song.sources.len() {
+        warnings.push(MidiImportWarning::TooManyUnits { dropped: song.sources.len() - unit_num });
+    }
+
+    let beat_clock = u64::try_from(pxtone.beat_clock()).unwrap_or(0).max(1);
+    let division = u64::from(song.division);
+    let to_clock = |tick: u64| ((tick * beat_clock + division / 2) / division) as u32;
+
+    let mut events = pxtone.event_list_mut();
+    events.clear();
+    let mut last_clock = 0;
+    for (unit_no, source) in song.sources.iter_mut().take(unit_num).enumerate() {
+        let unit_no = unit_no as u8;
+        let (end, overlaps) =
+            add_unit_events(&mut *events, unit_no, source, to_clock, &mut song.unmapped)?;
+        last_clock = last_clock.max(end);
+        if overlaps > 0 {
+            warnings.push(MidiImportWarning::Polyphony { unit_no, overlaps });
+        }
+        if source.channel == PERCUSSION_CHANNEL {
+            warnings.push(MidiImportWarning::Percussion { unit_no });
+        }
+    }
+    drop(events);
+
+    let clocks_per_measure = Tempo::of(&*pxtone).clocks_per_measure().max(1);
+    pxtone.set_num_measures(last_clock.div_ceil(clocks_per_measure).max(1) as i32);
+    pxtone.set_repeat_measure(0);
+    pxtone.set_last_measure(0);
+
+    warnings.extend(
+        song.unmapped
+            .into_iter()
+            .map(|(message, count)| MidiImportWarning::Unmapped { message, count }),
+    );
+    Ok(warnings)
+}
+
+/// Longest prefix of `s` that's at most `len` bytes
+fn truncate(s: &str, len: usize) -> &str {
+    let mut end = s.len().min(len);
+    while !s.is_char_boundary(end) {
+        end -= 1;
+    }
+    &s[..end]
+}
+
+/// Adds [`import_midi`] as a method on every backend
+pub trait MidiImport {
+    fn import_midi(&mut self, bytes: &[u8]) -> Result<Vec<MidiImportWarning>, MidiImportError>;
+}
+
+impl<T: PxTone + HasEventList + HasUnits> MidiImport for T {
+    fn import_midi(&mut self, bytes: &[u8]) -> Result<Vec<MidiImportWarning>, MidiImportError> {
+        import_midi(self, bytes)
+    }
+}
@@ -0,0 +1,4 @@
+//! Importing projects from other formats
+
+pub mod midi;
+pub mod organya;
@@ -0,0 +1,512 @@
+//! Importing an Organya (`.org`) song into a project
+//!
+//! Organya files don't contain any sound data, songs are played with the waveforms and drum
+//! samples of whatever game they come from, so those have to be passed in.
+//!
+//! ```no_run
+//! # #[cfg(feature = "rust-impl")]
+//! # {
+//! use pxtone::{
+//!     import::organya::{OrganyaImport, OrganyaSamples},
+//!     rust_impl::service::RPxTone,
+//! };
+//!
+//! let waves = std::fs::read("Wave.dat").unwrap();
+//! let drums: Vec<Vec<u8>> = (0..12)
+//!     .map(|i| std::fs::read(format!("drum{i}.raw")).unwrap())
+//!     .collect();
+//! let drums: Vec<&[u8]> = drums.iter().map(Vec::as_slice).collect();
+//!
+//! let mut pxtone = RPxTone::new();
+//! let samples = OrganyaSamples { waves: &waves, drums: &drums };
+//! pxtone
+//!     .import_organya(&std::fs::read("song.org").unwrap(), &samples)
+//!     .unwrap();
+//! # }
+//! ```
+
+use std::fmt;
+
+use crate::{
+    interface::{
+        event::{AddEventError, EventListMut, HasEventList, Key, PanValue},
+        event_impl::EventImpl,
+        service::PxTone,
+        unit::{HasUnits, Unit, UnitsMut},
+        woice::{HasWoices, Woice, WoicesMut},
+    },
+    time::Tempo,
+    util::ZeroToOneF32,
+};
+
+const MELODY_TRACKS: usize = 8;
+const TRACKS: usize = MELODY_TRACKS * 2;
+
+/// Samples in one melody waveform
+pub const WAVE_LEN: usize = 256;
+/// Number of melody waveforms
+pub const WAVE_NUM: usize = 100;
+/// Rate drum samples are stored at
+pub const DRUM_SAMPLES_PER_SECOND: u32 = 22050;
+
+/// Rate melody waveforms are stored at in the project. Any rate works since they loop.
+const WAVE_SAMPLES_PER_SECOND: u32 = 44100;
+
+/// Key, volume or pan value meaning "keep the previous one"
+const NO_CHANGE: u8 = 255;
+const PAN_CENTER: u8 = 6;
+const FREQ_DEFAULT: u16 = 1000;
+
+/// PCM woices play at their own rate on the key this far above their basic key
+const BASIC_KEY_OFFSET: i32 = 27 * Key::SEMITONE;
+
+/// The sounds an Organya song is played with
+pub struct OrganyaSamples<'a> {
+    /// The 100 melody waveforms of 256 signed 8 bit samples each, back to back (Cave Story's
+    /// `Wave.dat`)
+    pub waves: &'a [u8],
+    /// Drum samples by wave number, as unsigned 8 bit mono at [`DRUM_SAMPLES_PER_SECOND`]
+    pub drums: &'a [&'a [u8]],
+}
+
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum OrganyaImportError {
+    InvalidFile(&'static str),
+    /// [`OrganyaSamples::waves`] isn't 100 waveforms of 256 samples
+    InvalidWaves {
+        len: usize,
+    },
+    /// A drum track uses a wave number with no sample in [`OrganyaSamples::drums`]
+    MissingDrum {
+        wave_no: u8,
+    },
+    /// The project couldn't take another unit or woice
+    ProjectFull,
+    AddEvent(AddEventError),
+}
+
+impl fmt::Display for OrganyaImportError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::InvalidFile(reason) => write!(f, "Invalid Organya file: {reason}"),
+            Self::InvalidWaves { len } => {
+                write!(
+                    f,
+                    "Expected {} bytes of waveforms, got {len}",
+                    WAVE_NUM * WAVE_LEN
+                )
+            },
+            Self::MissingDrum { wave_no } => write!(f, "No sample for drum {wave_no}"),
+            Self::ProjectFull => write!(f, "Couldn't add a unit or woice to the project"),
+            Self::AddEvent(e) => write!(f, "{e}"),
+        }
+    }
+}
+
+impl std::error::Error for OrganyaImportError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::AddEvent(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<AddEventError> for OrganyaImportError {
+    fn from(e: AddEventError) -> Self {
+        Self::AddEvent(e)
+    }
+}
+
+/// Something in the Organya file that couldn't be brought over exactly
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum OrganyaImportWarning {
+    /// The track's frequency fine-tune was ignored
+    FineTune { track: u8, freq: u16 },
+    /// The track's "pi" setting, which plays each note's waveform a fixed number of times, was
+    /// ignored
+    PlayOnce { track: u8 },
+    /// The project can only loop whole measures, so the loop was rounded out to them
+    LoopPoints { repeat_step: u32, end_step: u32 },
+}
+
+impl fmt::Display for OrganyaImportWarning {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::FineTune { track, freq } => {
+                write!(f, "Ignored fine-tune {freq} of track {track}")
+            },
+            Self::PlayOnce { track } => write!(f, "Ignored the \"pi\" setting of track {track}"),
+            Self::LoopPoints { repeat_step, end_step } => {
+                write!(
+                    f,
+                    "Loop from step {repeat_step} to {end_step} was rounded to whole measures"
+                )
+            },
+        }
+    }
+}
+
+struct OrgNote {
+    x: u32,
+    y: u8,
+    length: u8,
+    volume: u8,
+    pan: u8,
+}
+
+struct Track {
+    freq: u16,
+    wave_no: u8,
+    pipi: u8,
+    notes: Vec<OrgNote>,
+}
+
+struct Song {
+    /// Milliseconds per step
+    wait: u16,
+    /// Beats per measure
+    line: u8,
+    /// Steps per beat
+    dot: u8,
+    repeat_x: u32,
+    end_x: u32,
+    tracks: Vec<Track>,
+}
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+    at: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, len: usize) -> Result<&'a [u8], OrganyaImportError> {
+        let bytes = self
+            .bytes
+            .get(self.at..self.at + len)
+            .ok_or(OrganyaImportError::InvalidFile("unexpected end of file"))?;
+        self.at += len;
+        Ok(bytes)
+    }
+
+    fn u8(&mut self) -> Result<u8, OrganyaImportError> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn u16(&mut self) -> Result<u16, OrganyaImportError> {
+        let b = self.take(2)?;
+        Ok(u16::from_le_bytes([b[0], b[1]]))
+    }
+
+    fn u32(&mut self) -> Result<u32, OrganyaImportError> {
+        let b = self.take(4)?;
+        Ok(u32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+    }
+}
+
+impl Song {
+    fn read(bytes: &[u8]) -> Result<Self, OrganyaImportError> {
+        let mut reader = Reader { bytes, at: 0 };
+        if !matches!(reader.take(6)?, b"Org-02" | b"Org-03") {
+            return Err(OrganyaImportError::InvalidFile(
+                "not an Org-02 or Org-03 file",
+            ));
+        }
+
+        let wait = reader.u16()?;
+        let line = reader.u8()?;
+        let dot = reader.u8()?;
+        let repeat_x = reader.u32()?;
+        let end_x = reader.u32()?;
+        if wait == 0 || line == 0 || dot == 0 {
+            return Err(OrganyaImportError::InvalidFile(
+                "zero tempo or time signature",
+            ));
+        }
+
+        let mut headers = Vec::with_capacity(TRACKS);
+        for _ in 0..TRACKS {
+            headers.push((reader.u16()?, reader.u8()?, reader.u8()?, reader.u16()?));
+        }
+
+        // each track's notes are stored a field at a time
+        let mut tracks = Vec::with_capacity(TRACKS);
+        for (freq, wave_no, pipi, note_num) in headers {
+            let note_num = usize::from(note_num);
+            let mut xs = Vec::with_capacity(note_num);
+            for _ in 0..note_num {
+                xs.push(reader.u32()?);
+            }
+            let ys = reader.take(note_num)?;
+            let lengths = reader.take(note_num)?;
+            let volumes = reader.take(note_num)?;
+            let pans = reader.take(note_num)?;
+
+            let mut notes: Vec<_> = (0..note_num)
+                .map(|i| OrgNote {
+                    x: xs[i],
+                    y: ys[i],
+                    length: lengths[i],
+                    volume: volumes[i],
+                    pan: pans[i],
+                })
+                .collect();
+            notes.sort_by_key(|n| n.x);
+            tracks.push(Track { freq, wave_no, pipi, notes });
+        }
+
+        Ok(Self { wait, line, dot, repeat_x, end_x, tracks })
+    }
+
+    fn set_master<P: PxTone + ?Sized>(
+        &self,
+        pxtone: &mut P,
+        warnings: &mut Vec<OrganyaImportWarning>,
+    ) {
+        let step_ms = f64::from(self.wait) * f64::from(self.dot);
+        pxtone.set_beat_tempo((60_000.0 / step_ms) as f32);
+        pxtone.set_beat_num(i32::from(self.line));
+
+        let steps_per_measure = u32::from(self.line) * u32::from(self.dot);
+        let last_measure = self.end_x.div_ceil(steps_per_measure);
+        pxtone.set_repeat_measure((self.repeat_x / steps_per_measure) as i32);
+        pxtone.set_last_measure(last_measure as i32);
+        pxtone.set_num_measures(last_measure.max(1) as i32);
+        if !self.repeat_x.is_multiple_of(steps_per_measure)
+            || !self.end_x.is_multiple_of(steps_per_measure)
+        {
+            warnings.push(OrganyaImportWarning::LoopPoints {
+                repeat_step: self.repeat_x,
+                end_step: self.end_x,
+            });
+        }
+    }
+
+    /// Replace the project's units and woices with one of each per track, returning the woice
+    /// of each track. Tracks with no notes don't get a woice.
+    #[allow(clippy::cast_precision_loss)]
+    fn set_units<P: HasUnits + HasWoices + ?Sized>(
+        &self,
+        pxtone: &mut P,
+        samples: &OrganyaSamples,
+    ) -> Result<Vec<Option<u8>>, OrganyaImportError> {
+        if samples.waves.len() != WAVE_NUM * WAVE_LEN {
+            return Err(OrganyaImportError::InvalidWaves { len: samples.waves.len() });
+        }
+
+        let mut units = pxtone.units_mut();
+        while UnitsMut::remove(&mut *units, 0) {}
+        for (i, _) in self.tracks.iter().enumerate() {
+            let mut unit = units.add_new().ok_or(OrganyaImportError::ProjectFull)?;
+            let name = if i < MELODY_TRACKS {
+                format!("melody {}", i + 1)
+            } else {
+                format!("drum {}", i - MELODY_TRACKS + 1)
+            };
+            let _ = unit.set_name(name);
+        }
+        drop(units);
+
+        let mut woices = pxtone.woices_mut();
+        while WoicesMut::remove(&mut *woices, 0) {}
+        let mut voice_nos = Vec::with_capacity(TRACKS);
+        let mut woice_num = 0;
+        for (i, track) in self.tracks.iter().enumerate() {
+            if track.notes.is_empty() {
+                voice_nos.push(None);
+                continue;
+            }
+
+            let wave_no = usize::from(track.wave_no);
+            let (added, name) = if i < MELODY_TRACKS {
+                let wave = samples
+                    .waves
+                    .get(wave_no * WAVE_LEN..(wave_no + 1) * WAVE_LEN)
+                    .ok_or(OrganyaImportError::InvalidFile("wave number out of range"))?;
+                // signed to unsigned
+                let data = wave.iter().map(|s| s ^ 0x80).collect();
+                let basic_key = *Key::from_hz(WAVE_SAMPLES_PER_SECOND as f32 / WAVE_LEN as f32)
+                    - BASIC_KEY_OFFSET;
+                let added = woices.add_pcm(1, WAVE_SAMPLES_PER_SECOND, 8, data, basic_key, true);
+                (added.is_some(), format!("wave {wave_no}"))
+            } else {
+                let drum = samples
+                    .drums
+                    .get(wave_no)
+                    .filter(|d| !d.is_empty())
+                    .ok_or(OrganyaImportError::MissingDrum { wave_no: track.wave_no })?;
+                let added = woices.add_pcm(
+                    1,
+                    DRUM_SAMPLES_PER_SECOND,
+                    8,
+                    drum.to_vec(),
+                    *Key::DEFAULT - BASIC_KEY_OFFSET,
+                    false,
+                );
+                (added.is_some(), format!("drum {wave_no}"))
+            };
+            if !added {
+                return Err(OrganyaImportError::ProjectFull);
+            }
+            if let Some(mut woice) = woices.iter_mut().last() {
+                let _ = woice.set_name(name);
+            }
+
+            voice_nos.push(Some(woice_num));
+            woice_num += 1;
+        }
+
+        Ok(voice_nos)
+    }
+}
+
+/// Organya plays drums at `y * 800 + 100` Hz
+fn drum_rate(y: u8) -> f64 {
+    f64::from(y) * 800.0 + 100.0
+}
+
+fn drum_key(y: u8) -> Key {
+    let semitones = 12.0 * (drum_rate(y) / f64::from(DRUM_SAMPLES_PER_SECOND)).log2();
+    Key::new(*Key::DEFAULT + (semitones * f64::from(Key::SEMITONE)).round() as i32)
+}
+
+/// Organya volume is attenuation in hundredths of a decibel, `(volume - 255) * 8`
+fn volume(volume: u8) -> ZeroToOneF32 {
+    let db = (f32::from(volume) - 255.0) * 8.0 / 100.0;
+    ZeroToOneF32::new(10_f32.powf(db / 20.0))
+}
+
+fn pan(pan: u8) -> PanValue {
+    PanValue::new((f32::from(pan.min(PAN_CENTER * 2)) - f32::from(PAN_CENTER)) / 6.0)
+}
+
+/// Adds one track's events as `unit_no`, returning the clock after its last note
+#[allow(clippy::cast_precision_loss)]
+fn add_track_events<L: EventListMut + ?Sized>(
+    events: &mut L,
+    unit_no: u8,
+    track: &Track,
+    drum: Option<&[u8]>,
+    to_clock: impl Fn(u32) -> u32,
+    clocks_per_sec: f64,
+) -> Result<u32, OrganyaImportError> {
+    let (mut key, mut vol, mut pan_value) = (None, None, None);
+    let mut end = 0;
+    for (i, note) in track.notes.iter().enumerate() {
+        let clock = to_clock(note.x);
+        if note.volume != NO_CHANGE && vol != Some(note.volume) {
+            events.add(&EventImpl::volume(clock, unit_no, volume(note.volume)))?;
+            vol = Some(note.volume);
+        }
+        if note.pan != NO_CHANGE && pan_value != Some(note.pan) {
+            events.add(&EventImpl::pan_volume(clock, unit_no, pan(note.pan)))?;
+            pan_value = Some(note.pan);
+        }
+        if note.y == NO_CHANGE {
+            continue;
+        }
+
+        let (note_key, length) = if let Some(sample) = drum {
+            // drums ignore the note length and play the whole sample
+            let secs = sample.len() as f64 / drum_rate(note.y);
+            (drum_key(note.y), (secs * clocks_per_sec).ceil() as u32)
+        } else {
+            let length = to_clock(note.x + u32::from(note.length)) - clock;
+            (Key::from_midi(note.y.saturating_add(24)), length)
+        };
+        // a track's next note cuts it off
+        let next = track.notes[i + 1..]
+            .iter()
+            .find(|n| n.y != NO_CHANGE)
+            .map_or(u32::MAX, |n| to_clock(n.x));
+        let length = length.min(next - clock).max(1);
+
+        if key != Some(note_key) {
+            events.add(&EventImpl::key(clock, unit_no, note_key))?;
+            key = Some(note_key);
+        }
+        events.add(&EventImpl::on(clock, unit_no, length))?;
+        end = end.max(clock + length);
+    }
+
+    Ok(end)
+}
+
+/// Replace `pxtone`'s units, woices and events with an Organya song from `bytes`.
+///
+/// Tracks become 8 melody units and 8 drum units, each with a PCM woice built from `samples`.
+/// Returns anything that couldn't be imported exactly.
+pub fn import_organya<P: PxTone + HasEventList + HasUnits + HasWoices + ?Sized>(
+    pxtone: &mut P,
+    bytes: &[u8],
+    samples: &OrganyaSamples,
+) -> Result<Vec<OrganyaImportWarning>, OrganyaImportError> {
+    let song = Song::read(bytes)?;
+    let mut warnings = Vec::new();
+
+    for (i, track) in song.tracks.iter().enumerate() {
+        if track.notes.is_empty() {
+            continue;
+        }
+        let track_no = i as u8;
+        if track.freq != FREQ_DEFAULT {
+            warnings.push(OrganyaImportWarning::FineTune { track: track_no, freq: track.freq });
+        }
+        if track.pipi != 0 && i < MELODY_TRACKS {
+            warnings.push(OrganyaImportWarning::PlayOnce { track: track_no });
+        }
+    }
+
+    song.set_master(pxtone, &mut warnings);
+    let voice_nos = song.set_units(pxtone, samples)?;
+
+    let beat_clock = u64::try_from(pxtone.beat_clock()).unwrap_or(0).max(1);
+    let dot = u64::from(song.dot);
+    let to_clock = |step: u32| ((u64::from(step) * beat_clock + dot / 2) / dot) as u32;
+    let clocks_per_sec = Tempo::of(&*pxtone).ticks_per_sec();
+
+    let mut events = pxtone.event_list_mut();
+    events.clear();
+    let mut last_clock = 0;
+    for (i, (track, voice_no)) in song.tracks.iter().zip(voice_nos).enumerate() {
+        let Some(voice_no) = voice_no else {
+            continue;
+        };
+        let unit_no = i as u8;
+        events.add(&EventImpl::voice_no(0, unit_no, voice_no))?;
+
+        let drum = (i >= MELODY_TRACKS).then(|| samples.drums[usize::from(track.wave_no)]);
+        let end = add_track_events(&mut *events, unit_no, track, drum, to_clock, clocks_per_sec)?;
+        last_clock = last_clock.max(end);
+    }
+    drop(events);
+
+    let clocks_per_measure = Tempo::of(&*pxtone).clocks_per_measure().max(1);
+    let num_measures = last_clock.div_ceil(clocks_per_measure) as i32;
+    if num_measures > pxtone.num_measures() {
+        pxtone.set_num_measures(num_measures);
+    }
+
+    Ok(warnings)
+}
+
+/// Adds [`import_organya`] as a method on every backend
+pub trait OrganyaImport {
+    fn import_organya(
+        &mut self,
+        bytes: &[u8],
+        samples: &OrganyaSamples,
+    ) -> Result<Vec<OrganyaImportWarning>, OrganyaImportError>;
+}
+
+impl<T: PxTone + HasEventList + HasUnits + HasWoices> OrganyaImport for T {
+    fn import_organya(
+        &mut self,
+        bytes: &[u8],
+        samples: &OrganyaSamples,
+    ) -> Result<Vec<OrganyaImportWarning>, OrganyaImportError> {
+        import_organya(self, bytes, samples)
+    }
+}
@@ -1,16 +1,27 @@
 use std::{
     borrow::Borrow,
+    collections::HashMap,
     convert::Infallible,
     fmt::{self, Debug},
     marker::PhantomData,
-    ops::Deref,
+    ops::{Bound, Deref, Range, RangeBounds},
+    str::FromStr,
 };
 
-use crate::pxtone::util::{BoxOrMut, BoxOrRef, ZeroToOneF32};
+use crate::pxtone::{
+    time::TempoChange,
+    util::{BoxOrMut, BoxOrRef, ZeroToOneF32},
+};
+
+use super::{
+    event_impl::EventImpl,
+    note::{Note, NoteEditError},
+};
 
 #[repr(u8)]
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 #[allow(dead_code)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum EventKind {
     Null,
     On,
@@ -103,6 +114,11 @@ pub trait GenericEvent: BaseEvent {
         Self::Tuning,
         Self::PanTime,
     >;
+    /// The change a raw tempo event (which [`GenericEvent::kind`] has no variant for) makes.
+    /// These are only found in old projects.
+    fn tempo_change(&self) -> Option<TempoChange> {
+        None
+    }
 }
 
 pub enum GenericEventKind<
@@ -250,14 +266,149 @@ pub trait EventOn: BaseEvent {
 }
 
 pub trait EventKey: BaseEvent {
-    fn key(&self) -> i32;
-    fn set_key(&mut self, key: i32);
+    fn key(&self) -> Key;
+    fn set_key(&mut self, key: Key);
+}
+
+/// Wrapper for an i32 representing a pitch in pxtone's key units.
+///
+/// There are 256 key units per semitone, and the default key (`0x6000`) is A3, 220Hz.
+/// Note names use scientific pitch notation, where middle C (MIDI note 60) is C4.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Key(i32);
+
+#[allow(clippy::cast_precision_loss)]
+impl Key {
+    /// Key units per semitone
+    pub const SEMITONE: i32 = 256;
+    /// Key of units that haven't had a key event yet
+    pub const DEFAULT: Self = Self(0x6000);
+
+    /// Key of MIDI note 0
+    const MIDI_ZERO: i32 = 13056 - 12 * Self::SEMITONE;
+    /// Frequency of C0, which is key 13056
+    const C0_HZ: f32 = 16.3515;
+    const NOTE_NAMES: [&'static str; 12] = [
+        "C", "C#", "D", "D#", "E", "F", "F#", "G", "G#", "A", "A#", "B",
+    ];
+
+    #[must_use]
+    pub const fn new(key: i32) -> Self {
+        Self(key)
+    }
+
+    #[must_use]
+    pub const fn from_midi(note: u8) -> Self {
+        Self(Self::MIDI_ZERO + note as i32 * Self::SEMITONE)
+    }
+
+    /// Key of MIDI note `note`, detuned by `cents` (100 cents per semitone)
+    #[must_use]
+    pub fn from_midi_cents(note: u8, cents: f32) -> Self {
+        let offset = (cents * Self::SEMITONE as f32 / 100.0).round() as i32;
+        Self(Self::from_midi(note).0 + offset)
+    }
+
+    /// Nearest MIDI note, along with how far off this key is from it in cents (-50 to 50).
+    ///
+    /// Returns `None` if the nearest note is outside of MIDI's range.
+    #[must_use]
+    pub fn to_midi_cents(self) -> Option<(u8, f32)> {
+        let semitones = (self.0 - Self::MIDI_ZERO) as f32 / Self::SEMITONE as f32;
+        let note = semitones.round();
+        let cents = (semitones - note) * 100.0;
+        u8::try_from(note as i32)
+            .ok()
+            .filter(|&n| n < 128)
+            .map(|n| (n, cents))
+    }
+
+    /// Nearest MIDI note, `None` if it's outside of MIDI's range
+    #[must_use]
+    pub fn to_midi(self) -> Option<u8> {
+        self.to_midi_cents().map(|(note, _)| note)
+    }
+
+    /// Nearest key to the frequency `hz`
+    #[must_use]
+    pub fn from_hz(hz: f32) -> Self {
+        let semitones = 12.0 * (hz / Self::C0_HZ).log2();
+        Self(13056 + (semitones * Self::SEMITONE as f32).round() as i32)
+    }
+
+    #[must_use]
+    pub fn to_hz(self) -> f32 {
+        // 1.059463 is 2^(1/12), one semitone
+        Self::C0_HZ * 1.059_463_1_f32.powf((self.0 as f32 - 13056.0) / Self::SEMITONE as f32)
+    }
+
+    /// Name of the nearest note, like `C#4`
+    #[must_use]
+    pub fn note_name(self) -> String {
+        let semitones = ((self.0 - Self::MIDI_ZERO) as f32 / Self::SEMITONE as f32).round() as i32;
+        let name = Self::NOTE_NAMES[semitones.rem_euclid(12) as usize];
+        format!("{name}{}", semitones.div_euclid(12) - 1)
+    }
+}
+
+impl Default for Key {
+    fn default() -> Self {
+        Self::DEFAULT
+    }
+}
+
+impl Deref for Key {
+    type Target = i32;
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+#[derive(Debug)]
+pub struct InvalidNoteName;
+
+impl fmt::Display for InvalidNoteName {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Invalid note name")
+    }
+}
+
+impl std::error::Error for InvalidNoteName {}
+
+impl FromStr for Key {
+    type Err = InvalidNoteName;
+
+    /// Parse a note name like `C4`, `F#2` or `Bb-1`
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut chars = s.chars();
+        let letter = chars.next().ok_or(InvalidNoteName)?.to_ascii_uppercase();
+        let mut semitone = Self::NOTE_NAMES
+            .iter()
+            .position(|n| n.len() == 1 && n.starts_with(letter))
+            .ok_or(InvalidNoteName)? as i32;
+
+        let rest = chars.as_str();
+        let octave = if let Some(rest) = rest.strip_prefix('#') {
+            semitone += 1;
+            rest
+        } else if let Some(rest) = rest.strip_prefix('b') {
+            semitone -= 1;
+            rest
+        } else {
+            rest
+        };
+        let octave: i32 = octave.parse().map_err(|_| InvalidNoteName)?;
+
+        let note = (octave + 1) * 12 + semitone;
+        Ok(Self(Self::MIDI_ZERO + note * Self::SEMITONE))
+    }
 }
 
 /// Wrapper for an f32 representing a pan value.
 ///
 /// 0.0 means centered, -1.0 means full left, and 1.0 means full right.
-#[derive(Clone, Copy, Debug)]
+#[derive(Clone, Copy, Debug, PartialEq)]
 pub struct PanValue(f32);
 
 impl PanValue {
@@ -324,13 +475,16 @@ pub trait EventVoiceNo: BaseEvent {
     fn set_voice_no(&mut self, voice_no: u8);
 }
 
+/// Number of groups units and effects can be assigned to (`pxtnMAX_TUNEGROUPNUM`)
+pub const MAX_GROUPS: u8 = 7;
+
 pub trait EventGroupNo: BaseEvent {
     fn group_no(&self) -> u8;
     fn set_group_no(&mut self, group_no: u8);
 }
 
 /// Wrapper for an f32 representing a value from 0.0 to 9.99999 (inclusive).
-#[derive(Clone, Copy, Debug)]
+#[derive(Clone, Copy, Debug, PartialEq)]
 pub struct TuningValue(f32);
 
 impl TuningValue {
@@ -439,10 +593,167 @@ pub type GenericEventKindMut<
     BoxOrMut<'a, PanTime>,
 >;
 
+impl EventKind {
+    /// Kind of a generic event, [`EventKind::Null`] for invalid events
+    pub fn of<E: GenericEvent + ?Sized>(event: &E) -> Self {
+        match event.kind() {
+            GenericEventKind::On(_) => Self::On,
+            GenericEventKind::Key(_) => Self::Key,
+            GenericEventKind::PanVolume(_) => Self::PanVolume,
+            GenericEventKind::Velocity(_) => Self::Velocity,
+            GenericEventKind::Volume(_) => Self::Volume,
+            GenericEventKind::Porta(_) => Self::Portament,
+            GenericEventKind::VoiceNo(_) => Self::VoiceNo,
+            GenericEventKind::GroupNo(_) => Self::GroupNo,
+            GenericEventKind::Tuning(_) => Self::Tuning,
+            GenericEventKind::PanTime(_) => Self::PanTime,
+            GenericEventKind::Invalid | GenericEventKind::_Phantom(..) => {
+                match event.tempo_change() {
+                    Some(TempoChange::BeatClock(_)) => Self::BeatClock,
+                    Some(TempoChange::BeatTempo(_)) => Self::BeatTempo,
+                    Some(TempoChange::BeatNum(_)) => Self::BeatNum,
+                    None => Self::Null,
+                }
+            },
+        }
+    }
+}
+
+/// The values a unit's events have set by some clock, see [`EventList::unit_state_at`]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct UnitState {
+    pub key: Key,
+    pub pan_volume: PanValue,
+    pub velocity: ZeroToOneF32,
+    pub volume: ZeroToOneF32,
+    pub porta: u32,
+    pub voice_no: u8,
+    pub group_no: u8,
+    pub tuning: TuningValue,
+    pub pan_time: PanValue,
+}
+
+impl Default for UnitState {
+    /// Same as og pxtone's `EVENTDEFAULT_*`
+    fn default() -> Self {
+        Self {
+            key: Key::DEFAULT,
+            pan_volume: PanValue::center(),
+            velocity: ZeroToOneF32::new(104.0 / 128.0),
+            volume: ZeroToOneF32::new(104.0 / 128.0),
+            porta: 0,
+            voice_no: 0,
+            group_no: 0,
+            tuning: TuningValue::default(),
+            pan_time: PanValue::center(),
+        }
+    }
+}
+
+impl UnitState {
+    /// Update the state with `event`, which is expected to be for this unit
+    pub fn apply<E: GenericEvent + ?Sized>(&mut self, event: &E) {
+        match event.kind() {
+            GenericEventKind::Key(e) => self.key = e.key(),
+            GenericEventKind::PanVolume(e) => self.pan_volume = e.pan_volume(),
+            GenericEventKind::Velocity(e) => self.velocity = e.velocity(),
+            GenericEventKind::Volume(e) => self.volume = e.volume(),
+            GenericEventKind::Porta(e) => self.porta = e.porta(),
+            GenericEventKind::VoiceNo(e) => self.voice_no = e.voice_no(),
+            GenericEventKind::GroupNo(e) => self.group_no = e.group_no(),
+            GenericEventKind::Tuning(e) => self.tuning = e.tuning(),
+            GenericEventKind::PanTime(e) => self.pan_time = e.pan_time(),
+            _ => {},
+        }
+    }
+}
+
+/// Events are always sorted by clock.
 pub trait EventList {
     type Event: GenericEvent;
 
     fn iter(&self) -> Box<dyn Iterator<Item = &Self::Event> + '_>;
+
+    /// Events with a clock in `clocks`
+    fn events_in<R: RangeBounds<u32>>(
+        &self,
+        clocks: R,
+    ) -> Box<dyn Iterator<Item = &Self::Event> + '_> {
+        let start = clocks.start_bound().cloned();
+        let end = clocks.end_bound().cloned();
+        Box::new(
+            self.iter()
+                .skip_while(move |e| !(start, Bound::Unbounded).contains(&e.clock()))
+                .take_while(move |e| (Bound::Unbounded, end).contains(&e.clock())),
+        )
+    }
+
+    fn events_for_unit(&self, unit_no: u8) -> Box<dyn Iterator<Item = &Self::Event> + '_> {
+        Box::new(self.iter().filter(move |e| e.unit_no() == unit_no))
+    }
+
+    fn events_of_kind(&self, kind: EventKind) -> Box<dyn Iterator<Item = &Self::Event> + '_> {
+        Box::new(self.iter().filter(move |e| EventKind::of(*e) == kind))
+    }
+
+    /// State of `unit_no` once every event up to and including `clock` has played.
+    ///
+    /// Values that slide (like the key with a porta set) are given as their target.
+    fn unit_state_at(&self, unit_no: u8, clock: u32) -> UnitState {
+        let mut state = UnitState::default();
+        for e in self.events_in(..=clock).filter(|e| e.unit_no() == unit_no) {
+            state.apply(e);
+        }
+        state
+    }
+
+    /// Every `On` event as a [`Note`], with the key and velocity its unit had when it started
+    fn notes(&self) -> Vec<Note> {
+        let mut states = HashMap::<u8, UnitState>::new();
+        let mut notes = Vec::new();
+        // notes starting on the current clock, events on the same clock still apply to them
+        let mut pending: Vec<(u8, u32, u32)> = Vec::new();
+
+        let flush = |pending: &mut Vec<(u8, u32, u32)>,
+                     states: &HashMap<u8, UnitState>,
+                     notes: &mut Vec<Note>| {
+            for (unit_no, clock, length) in pending.drain(..) {
+                let state = states.get(&unit_no).copied().unwrap_or_default();
+                notes.push(Note {
+                    unit_no,
+                    clock,
+                    length,
+                    key: state.key,
+                    velocity: state.velocity,
+                });
+            }
+        };
+
+        for e in self.iter() {
+            if pending
+                .first()
+                .is_some_and(|&(_, clock, _)| clock != e.clock())
+            {
+                flush(&mut pending, &states, &mut notes);
+            }
+
+            if let GenericEventKind::On(on) = e.kind() {
+                pending.push((e.unit_no(), e.clock(), on.length()));
+            } else {
+                states.entry(e.unit_no()).or_default().apply(e);
+            }
+        }
+        flush(&mut pending, &states, &mut notes);
+
+        notes
+    }
+
+    /// Notes that are playing at some point in `clocks`
+    fn notes_in(&self, clocks: Range<u32>) -> Vec<Note> {
+        let mut notes = self.notes();
+        notes.retain(|n| n.clock < clocks.end && n.end() > clocks.start);
+        notes
+    }
 }
 
 #[derive(Debug)]
@@ -457,9 +768,215 @@ impl fmt::Display for AddEventError {
 impl std::error::Error for AddEventError {}
 
 pub trait EventListMut: EventList {
+    /// Changing clocks through here breaks the sort order, use [`EventListMut::shift_clocks`]
+    /// instead.
     fn iter_mut(&mut self) -> Box<dyn Iterator<Item = &mut Self::Event> + '_>;
 
     fn add<E: GenericEvent>(&mut self, event: &E) -> Result<(), AddEventError>;
+
+    /// Keep only the events `f` returns `true` for
+    fn retain<F: FnMut(&Self::Event) -> bool>(&mut self, f: F);
+
+    /// Move the events in `clocks` (of `unit_no`, or of every unit if `None`) by `shift` ticks,
+    /// keeping the list sorted. Events can't be moved before clock 0.
+    ///
+    /// Returns the number of events moved.
+    fn shift_clocks(&mut self, clocks: Range<u32>, unit_no: Option<u8>, shift: i32) -> usize;
+
+    fn clear(&mut self) {
+        self.retain(|_| false);
+    }
+
+    /// Remove every `kind` event of `unit_no` at `clock`, returning how many were removed
+    fn remove(&mut self, clock: u32, unit_no: u8, kind: EventKind) -> usize {
+        let mut removed = 0;
+        self.retain(|e| {
+            let matches = e.clock() == clock && e.unit_no() == unit_no && EventKind::of(e) == kind;
+            removed += usize::from(matches);
+            !matches
+        });
+        removed
+    }
+
+    /// Remove the events in `clocks` (of `unit_no`, or of every unit if `None`).
+    ///
+    /// Like `pxtnEvelist::Record_Delete`, notes that started earlier but are still playing at
+    /// `clocks.start` are cut short there. Returns the number of events removed or shortened.
+    fn remove_range(&mut self, clocks: Range<u32>, unit_no: Option<u8>) -> usize {
+        let of_unit = move |e: &Self::Event| unit_no.is_none_or(|u| e.unit_no() == u);
+
+        let mut count = 0;
+        self.retain(|e| {
+            let matches = clocks.contains(&e.clock()) && of_unit(e);
+            count += usize::from(matches);
+            !matches
+        });
+
+        for e in self.iter_mut() {
+            if e.clock() >= clocks.start || !of_unit(e) {
+                continue;
+            }
+
+            let clock = e.clock();
+            if let GenericEventKind::On(mut on) = e.kind_mut() {
+                if clock + on.length() > clocks.start {
+                    on.set_length(clocks.start - clock);
+                    count += 1;
+                }
+            }
+        }
+
+        count
+    }
+
+    /// Add `note`'s `On` event, plus `Key` and `Velocity` events where its unit isn't already at
+    /// the note's key and velocity. Later notes of the unit keep playing as they did.
+    fn insert_note(&mut self, note: &Note) -> Result<(), AddEventError> {
+        let (unit_no, clock) = (note.unit_no, note.clock);
+        set_unit_value(
+            self,
+            unit_no,
+            clock,
+            EventKind::Key,
+            note.key,
+            |s| s.key,
+            EventImpl::key,
+        )?;
+        set_unit_value(
+            self,
+            unit_no,
+            clock,
+            EventKind::Velocity,
+            note.velocity,
+            |s| s.velocity,
+            EventImpl::velocity,
+        )?;
+        self.add(&EventImpl::on(clock, unit_no, note.length))
+    }
+
+    /// Remove `note`'s `On` event.
+    ///
+    /// Its `Key` and `Velocity` events are kept, since later notes of the unit can rely on them.
+    fn delete_note(&mut self, note: &Note) -> Result<(), NoteEditError> {
+        let mut found = false;
+        self.retain(|e| {
+            let matches = !found && is_note(e, note);
+            found |= matches;
+            !matches
+        });
+        found.then_some(()).ok_or(NoteEditError::NotFound)
+    }
+
+    /// Replace `note` with `new`, as if it was deleted and `new` inserted
+    fn replace_note(&mut self, note: &Note, new: &Note) -> Result<(), NoteEditError> {
+        self.delete_note(note)?;
+        Ok(self.insert_note(new)?)
+    }
+
+    /// Move `note` up by `semitones` (or down if negative), returning the moved note
+    fn transpose_note(&mut self, note: &Note, semitones: i32) -> Result<Note, NoteEditError> {
+        let new = Note {
+            key: Key::new(*note.key + semitones * Key::SEMITONE),
+            ..*note
+        };
+        self.replace_note(note, &new)?;
+        Ok(new)
+    }
+
+    /// Move the start of `note` to the closest multiple of `grid` clocks, keeping its length.
+    /// Returns the moved note.
+    fn quantize_note(&mut self, note: &Note, grid: u32) -> Result<Note, NoteEditError> {
+        let grid = grid.max(1);
+        let new = Note {
+            clock: (note.clock + grid / 2) / grid * grid,
+            ..*note
+        };
+        self.replace_note(note, &new)?;
+        Ok(new)
+    }
+
+    /// Change the length of `note`, returning the resized note
+    fn resize_note(&mut self, note: &Note, length: u32) -> Result<Note, NoteEditError> {
+        let on = self
+            .iter_mut()
+            .find(|e| is_note(&**e, note))
+            .ok_or(NoteEditError::NotFound)?;
+        if let GenericEventKind::On(mut on) = on.kind_mut() {
+            on.set_length(length);
+        }
+        Ok(Note { length, ..*note })
+    }
+
+    /// Split `note` in two at `clock`, which has to be strictly inside of it.
+    ///
+    /// The second half is a new note on, so it doesn't sound quite like the original: the woice
+    /// starts over from its attack, and a key slide that's still going at `clock` jumps straight to
+    /// its target there. Returns both halves.
+    fn split_note(&mut self, note: &Note, clock: u32) -> Result<(Note, Note), NoteEditError> {
+        if clock <= note.clock || clock >= note.end() {
+            return Err(NoteEditError::SplitOutside { clock });
+        }
+
+        let first = self.resize_note(note, clock - note.clock)?;
+        self.add(&EventImpl::on(clock, note.unit_no, note.end() - clock))?;
+
+        let state = self.unit_state_at(note.unit_no, clock);
+        let second = Note {
+            clock,
+            length: note.end() - clock,
+            key: state.key,
+            velocity: state.velocity,
+            ..*note
+        };
+        Ok((first, second))
+    }
+}
+
+fn is_note<E: GenericEvent + ?Sized>(event: &E, note: &Note) -> bool {
+    event.clock() == note.clock
+        && event.unit_no() == note.unit_no
+        && matches!(event.kind(), GenericEventKind::On(on) if on.length() == note.length)
+}
+
+/// Make `unit_no`'s value (read with `get`) be `value` from `clock` on, putting the old value back
+/// for the unit's next note if it relied on it
+fn set_unit_value<L: EventListMut + ?Sized, T: PartialEq + Copy>(
+    list: &mut L,
+    unit_no: u8,
+    clock: u32,
+    kind: EventKind,
+    value: T,
+    get: impl Fn(&UnitState) -> T,
+    make: impl Fn(u32, u8, T) -> EventImpl<'static>,
+) -> Result<(), AddEventError> {
+    let old = get(&list.unit_state_at(unit_no, clock));
+    if old == value {
+        return Ok(());
+    }
+
+    let (mut next_on, mut next_set) = (None, None);
+    for e in list
+        .events_in(clock + 1..)
+        .filter(|e| e.unit_no() == unit_no)
+    {
+        match EventKind::of(e) {
+            EventKind::On => next_on = next_on.or(Some(e.clock())),
+            k if k == kind => next_set = next_set.or(Some(e.clock())),
+            _ => {},
+        }
+        if next_on.is_some() && next_set.is_some() {
+            break;
+        }
+    }
+
+    list.remove(clock, unit_no, kind);
+    list.add(&make(clock, unit_no, value))?;
+    if let Some(next_on) = next_on {
+        if next_set.is_none_or(|c| c > next_on) {
+            list.add(&make(next_on, unit_no, old))?;
+        }
+    }
+    Ok(())
 }
 
 pub trait HasEventList {
@@ -473,3 +990,32 @@ pub trait HasEventList {
     fn event_list(&self) -> BoxOrRef<Self::EventList<'_>>;
     fn event_list_mut(&mut self) -> BoxOrMut<Self::EventListMut<'_>>;
 }
+
+#[cfg(test)]
+mod tests {
+    use super::Key;
+
+    #[test]
+    fn key_conversions() {
+        assert_eq!(Key::DEFAULT.to_midi(), Some(57));
+        assert_eq!(Key::DEFAULT.note_name(), "A3");
+        assert!((Key::DEFAULT.to_hz() - 220.0).abs() < 0.01);
+        assert_eq!(Key::from_hz(440.0), Key::from_midi(69));
+
+        let detuned = Key::from_midi_cents(60, -25.0);
+        assert_eq!(*detuned, *Key::from_midi(60) - 64);
+        assert_eq!(detuned.to_midi_cents(), Some((60, -25.0)));
+        assert_eq!(Key::new(0).to_midi(), None);
+    }
+
+    #[test]
+    fn parses_note_names() {
+        assert_eq!("C4".parse::<Key>().unwrap(), Key::from_midi(60));
+        assert_eq!("c#4".parse::<Key>().unwrap(), Key::from_midi(61));
+        assert_eq!("Db4".parse::<Key>().unwrap(), Key::from_midi(61));
+        assert_eq!("C-1".parse::<Key>().unwrap(), Key::from_midi(0));
+        assert_eq!(Key::from_midi(70).note_name(), "A#4");
+        assert!("H4".parse::<Key>().is_err());
+        assert!("C".parse::<Key>().is_err());
+    }
+}
@@ -5,13 +5,13 @@ use crate::pxtone::util::{BoxOrMut, BoxOrRef, ZeroToOneF32};
 use super::event::{
     BaseEvent, EventGroupNo, EventKey, EventKind, EventOn, EventPanTime, EventPanVolume,
     EventPorta, EventTuning, EventVelocity, EventVoiceNo, EventVolume, GenericEvent,
-    GenericEventKind, GenericEventKindMut, GenericEventKindRef, PanValue, TuningValue,
+    GenericEventKind, GenericEventKindMut, GenericEventKindRef, Key, PanValue, TuningValue,
 };
 
 pub type EventKindImpl<'a> = GenericEventKind<
     'a,
     (BaseEventImpl, u32),
-    (BaseEventImpl, i32),
+    (BaseEventImpl, Key),
     (BaseEventImpl, PanValue),
     (BaseEventImpl, ZeroToOneF32),
     (BaseEventImpl, ZeroToOneF32),
@@ -21,7 +21,7 @@ pub type EventKindImpl<'a> = GenericEventKind<
     (BaseEventImpl, TuningValue),
     (BaseEventImpl, PanValue),
     (BaseEventImpl, u32),
-    (BaseEventImpl, i32),
+    (BaseEventImpl, Key),
     (BaseEventImpl, PanValue),
     (BaseEventImpl, ZeroToOneF32),
     (BaseEventImpl, ZeroToOneF32),
@@ -65,7 +65,7 @@ impl EventImpl<'_> {
     pub fn from_raw(clock: u32, unit_no: u8, kind: EventKind, value: u32) -> Option<Self> {
         match kind {
             EventKind::On => Some(Self::on(clock, unit_no, value as _)),
-            EventKind::Key => Some(Self::key(clock, unit_no, value as _)),
+            EventKind::Key => Some(Self::key(clock, unit_no, Key::new(value as _))),
             EventKind::PanVolume => Some(Self::pan_volume(
                 clock,
                 unit_no,
@@ -106,7 +106,7 @@ impl EventImpl<'_> {
     }
 
     #[must_use]
-    pub fn key(clock: u32, unit_no: u8, key: i32) -> Self {
+    pub fn key(clock: u32, unit_no: u8, key: Key) -> Self {
         Self {
             kind: EventKindImpl::Key((BaseEventImpl { clock, unit_no }, key)),
         }
@@ -231,12 +231,12 @@ impl EventOn for (BaseEventImpl, u32) {
     }
 }
 
-impl EventKey for (BaseEventImpl, i32) {
-    fn key(&self) -> i32 {
+impl EventKey for (BaseEventImpl, Key) {
+    fn key(&self) -> Key {
         self.1
     }
 
-    fn set_key(&mut self, key: i32) {
+    fn set_key(&mut self, key: Key) {
         self.1 = key;
     }
 }
@@ -345,7 +345,7 @@ impl BaseEvent for EventImpl<'_> {
 
 impl GenericEvent for EventImpl<'_> {
     type On = (BaseEventImpl, u32);
-    type Key = (BaseEventImpl, i32);
+    type Key = (BaseEventImpl, Key);
     type PanVolume = (BaseEventImpl, PanValue);
     type Velocity = (BaseEventImpl, ZeroToOneF32);
     type Volume = (BaseEventImpl, ZeroToOneF32);
@@ -3,6 +3,7 @@ pub mod event;
 pub mod event_impl;
 pub mod io;
 pub mod moo;
+pub mod note;
 pub mod overdrive;
 pub mod service;
 pub mod unit;
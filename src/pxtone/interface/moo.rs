@@ -1,7 +1,10 @@
-use std::fmt::Debug;
+use std::{fmt::Debug, ops::Range};
 
-use crate::util::BoxOrMut;
+use crate::util::{BoxOrMut, ZeroToOneF32};
 
+use super::event::{Key, PanValue};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum Fade {
     In,
     Out,
@@ -18,13 +21,32 @@ pub trait Moo<'a> {
     // TODO: consider enforcing `prepare_sample` being called before `sample`, eg with a MutexGuard type thing
     fn prepare_sample(&mut self) -> Result<(), Self::Error>;
     fn sample(&mut self, buffer: &mut [i16]) -> Result<(), Self::Error>;
+    /// Like [`Moo::sample`], but with full scale at -1.0 to 1.0 and nothing clipped.
+    ///
+    /// Backends that only render 16 bit audio (like og pxtone) convert what [`Moo::sample`] gives.
+    fn sample_f32(&mut self, buffer: &mut [f32]) -> Result<(), Self::Error> {
+        let mut ints = vec![0; buffer.len()];
+        self.sample(&mut ints)?;
+        for (f, i) in buffer.iter_mut().zip(ints) {
+            *f = f32::from(i) / 32768.0;
+        }
+        Ok(())
+    }
 
     fn is_done_sampling(&self) -> bool;
     fn now_clock(&self) -> u32;
     fn end_clock(&self) -> u32;
 
     fn set_unit_mute_enabled(&mut self, unit_mute: bool) -> Result<(), Self::Error>;
+    /// Mute or unmute a single unit. Only has an effect while unit muting is enabled (see [`Moo::set_unit_mute_enabled`])
+    ///
+    /// This only affects playback, the units' own mute flags in the project are left alone.
+    fn set_unit_muted(&mut self, unit_no: u8, muted: bool) -> Result<(), Self::Error>;
     fn set_loop(&mut self, should_loop: bool) -> Result<(), Self::Error>;
+    /// Loop between these measures instead of the song's repeat and last measures (`None` goes back to those)
+    fn set_loop_measures(&mut self, measures: Option<Range<i32>>) -> Result<(), Self::Error>;
+    /// Continue playing from the start of `measure`, keeping the loop, fade and volume settings
+    fn jump_to_measure(&mut self, measure: i32) -> Result<(), Self::Error>;
     fn set_fade(
         &mut self,
         fade: Option<Fade>,
@@ -38,6 +60,34 @@ pub trait Moo<'a> {
     fn set_master_volume(&mut self, volume: f32) -> Result<(), Self::Error>;
 }
 
+/// Playing units live, like an instrument, without adding events to the song
+///
+/// These act like the matching events would if they were at the current position: live notes use
+/// the unit's woice, volume, tuning etc, and song events on the same unit affect them too.
+/// Like song notes, live notes are cut off when playback loops or jumps.
+pub trait MooLive<'a>: Moo<'a> {
+    /// Start a note on `unit_no` at `key`, it keeps playing until [`MooLive::live_note_off`].
+    ///
+    /// A note that is already playing on the unit is released.
+    fn live_note_on(
+        &mut self,
+        unit_no: u8,
+        key: Key,
+        velocity: ZeroToOneF32,
+        pan_volume: PanValue,
+    ) -> Result<(), Self::Error>;
+    /// Release whatever is playing on `unit_no`
+    fn live_note_off(&mut self, unit_no: u8) -> Result<(), Self::Error>;
+
+    /// Change the key of `unit_no`, sliding if the unit has a porta set
+    fn live_key(&mut self, unit_no: u8, key: Key) -> Result<(), Self::Error>;
+    fn live_velocity(&mut self, unit_no: u8, velocity: ZeroToOneF32) -> Result<(), Self::Error>;
+    fn live_pan_volume(&mut self, unit_no: u8, pan_volume: PanValue) -> Result<(), Self::Error>;
+    /// Switch `unit_no` to another woice. Like a `VoiceNo` event, this cuts off anything playing
+    /// on it (release tails included) and resets its key.
+    fn live_voice_no(&mut self, unit_no: u8, voice_no: u8) -> Result<(), Self::Error>;
+}
+
 pub trait AsMoo {
     type M<'a>: Moo<'a>
     where
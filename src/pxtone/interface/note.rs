@@ -0,0 +1,60 @@
+use std::fmt;
+
+use crate::util::ZeroToOneF32;
+
+use super::event::{AddEventError, Key};
+
+/// An `On` event together with the key and velocity it plays at
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Note {
+    pub unit_no: u8,
+    pub clock: u32,
+    pub length: u32,
+    pub key: Key,
+    pub velocity: ZeroToOneF32,
+}
+
+impl Note {
+    /// Clock right after the note ends
+    #[must_use]
+    pub fn end(&self) -> u32 {
+        self.clock + self.length
+    }
+}
+
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum NoteEditError {
+    /// No `On` event matches the note
+    NotFound,
+    /// A note can only be split at a clock strictly inside of it
+    SplitOutside {
+        clock: u32,
+    },
+    AddEvent(AddEventError),
+}
+
+impl fmt::Display for NoteEditError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::NotFound => write!(f, "Note not found"),
+            Self::SplitOutside { clock } => write!(f, "Can't split note at clock {clock}"),
+            Self::AddEvent(e) => write!(f, "{e}"),
+        }
+    }
+}
+
+impl std::error::Error for NoteEditError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::AddEvent(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<AddEventError> for NoteEditError {
+    fn from(e: AddEventError) -> Self {
+        Self::AddEvent(e)
+    }
+}
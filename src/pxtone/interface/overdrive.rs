@@ -4,6 +4,11 @@ use crate::util::{BoxOrMut, BoxOrRef};
 
 /// Wrapper for an f32 representing an overdrive cut value, 0.5 to 0.999 (inclusive).
 #[derive(Clone, Copy, Debug)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(transparent)
+)]
 pub struct OverDCut(f32);
 
 impl OverDCut {
@@ -26,6 +31,11 @@ impl Deref for OverDCut {
 
 /// Wrapper for an f32 representing an overdrive amp value, 0.1 to 8.0 (inclusive).
 #[derive(Clone, Copy, Debug)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(transparent)
+)]
 pub struct OverDAmp(f32);
 
 impl OverDAmp {
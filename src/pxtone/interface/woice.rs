@@ -1,4 +1,4 @@
-use std::{borrow::Borrow, convert::Infallible, marker::PhantomData, path::Path};
+use std::{borrow::Borrow, convert::Infallible, fmt::Debug, io, marker::PhantomData, path::Path};
 
 use crate::pxtone::util::{BoxOrMut, BoxOrRef};
 
@@ -132,6 +132,12 @@ pub trait Voice {
 
     fn tuning(&self) -> f32;
     fn set_tuning(&mut self, tuning: f32);
+
+    /// `true` if the sample repeats for as long as a note is held
+    fn flag_loop(&self) -> bool;
+
+    /// `true` if notes fade in and out quickly so they don't click
+    fn flag_smooth(&self) -> bool;
 }
 
 pub trait VoicePCM: Voice {
@@ -143,9 +149,20 @@ pub trait VoicePCM: Voice {
     /// Should only be 8 or 16
     fn bits_per_sample(&self) -> u8;
 
+    /// Number of frames in the sample data
+    fn sample_num(&self) -> u32;
+
     /// cycle is >= 0.0 and each 1.0 represents 1Hz passing
     fn sample(&self, cycle: f32, channel: u8) -> f32;
 
+    /// Write the sample data this voice plays from as a `.wav` file
+    ///
+    /// For PCM woices that's the data exactly as it was loaded, which can be added back with
+    /// [`WoicesMut::add_pcm_from_file`]. Other woice types write what they were decoded or built
+    /// into for playback, if the backend keeps it, which isn't necessarily what a note of the
+    /// woice sounds like.
+    fn export_wav<W: io::Write>(&self, writer: W) -> io::Result<()>;
+
     fn select_channel_index(&self, index: usize, channel: u8) -> usize {
         if self.channels() == 2 {
             // floor down to even and add 0 or 1 for channel
@@ -193,15 +210,15 @@ pub enum PTVWaveType<'a, C: PTVCoordinateWave, O: PTVOvertoneWave> {
 
 pub trait PTVEnvelope {
     type EnvelopePoint: PTNEnvelopePoint;
-    
-	fn fps(&self) -> u32;
-    
-	fn head_num(&self) -> u32;
-    
-	fn body_num(&self) -> u32;
-    
-	fn tail_num(&self) -> u32;
-    
+
+    fn fps(&self) -> u32;
+
+    fn head_num(&self) -> u32;
+
+    fn body_num(&self) -> u32;
+
+    fn tail_num(&self) -> u32;
+
     fn points(&self) -> Vec<&Self::EnvelopePoint>;
 }
 
@@ -217,6 +234,7 @@ pub trait VoicePTV: Voice {
 #[repr(u8)]
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 #[allow(dead_code)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum PTNWaveType {
     None,
     Sine,
@@ -352,6 +370,13 @@ pub trait VoiceOGGV: VoicePCM {
     fn ogg_sample_num(&self) -> u32;
 
     fn ogg_data(&self) -> &[u8];
+
+    /// Write the original Ogg Vorbis file back out, which can be added again with
+    /// [`WoicesMut::add_oggv_from_file`]
+    fn export_ogg<W: io::Write>(&self, mut writer: W) -> io::Result<()> {
+        writer.write_all(self.ogg_data())?;
+        writer.flush()
+    }
 }
 
 pub trait SingleVoice<V: Voice + ?Sized> {
@@ -363,9 +388,19 @@ pub trait WoicePCM<V: VoicePCM + ?Sized>: SingleVoice<V> {}
 
 pub trait WoicePTV<V: VoicePTV + ?Sized> {
     fn voices(&self) -> Vec<&V>;
+
+    /// Write this woice out as a standalone ptVoice (`.ptvoice`) file,
+    /// which can be loaded again with [`WoicesMut::add_ptv_from_file`].
+    fn export_ptv<W: io::Write>(&self, writer: W) -> io::Result<()>;
 }
 
-pub trait WoicePTN<V: VoicePTN + ?Sized>: SingleVoice<V> {}
+pub trait WoicePTN<V: VoicePTN + ?Sized>: SingleVoice<V> {
+    /// Write this woice out as a standalone ptNoise (`.ptnoise`) file,
+    /// which can be loaded again with [`WoicesMut::add_ptn_from_file`].
+    ///
+    /// The file only holds the noise design, so the key, tuning and flags aren't included.
+    fn export_ptn<W: io::Write>(&self, writer: W) -> io::Result<()>;
+}
 
 pub trait WoiceOGGV<V: VoiceOGGV + ?Sized>: SingleVoice<V> {}
 
@@ -375,14 +410,60 @@ pub trait Woices {
 }
 
 pub trait WoicesMut: Woices {
+    /// Why [`WoicesMut::add_pcm_from_bytes`] couldn't read a WAV file
+    type WavError: Debug;
+    /// Why [`WoicesMut::add_oggv_from_bytes`] couldn't read an Ogg Vorbis file
+    type OggError: Debug;
+
     fn iter_mut<'a>(&'a mut self) -> Box<dyn Iterator<Item = BoxOrMut<Self::W>> + 'a>;
-    
+
     fn add_blank_ptv(&mut self) -> Option<BoxOrMut<<Self::W as Woice>::PTV>>;
     fn add_blank_ptn(&mut self) -> Option<BoxOrMut<<Self::W as Woice>::PTN>>;
-    fn add_pcm_from_file<P: AsRef<Path>>(&mut self, path: P) -> Option<BoxOrMut<<Self::W as Woice>::PCM>>;
-    fn add_ptv_from_file<P: AsRef<Path>>(&mut self, path: P) -> Option<BoxOrMut<<Self::W as Woice>::PTV>>;
-    fn add_ptn_from_file<P: AsRef<Path>>(&mut self, path: P) -> Option<BoxOrMut<<Self::W as Woice>::PTN>>;
-    fn add_oggv_from_file<P: AsRef<Path>>(&mut self, path: P) -> Option<BoxOrMut<<Self::W as Woice>::OGGV>>;
+
+    /// Add a PCM woice from raw sample data, laid out like a WAV file's `data` chunk:
+    /// interleaved, unsigned if 8 bit and signed little endian if 16 bit.
+    ///
+    /// The samples play at their own rate on a key 27 semitones above `basic_key`, so the usual
+    /// `0x4500` plays them as recorded on the default key. Returns `None` if the format isn't 1 or
+    /// 2 channels of 8 or 16 bit.
+    fn add_pcm(
+        &mut self,
+        channels: u8,
+        samples_per_second: u32,
+        bits_per_sample: u8,
+        data: Vec<u8>,
+        basic_key: i32,
+        looped: bool,
+    ) -> Option<BoxOrMut<'_, <Self::W as Woice>::PCM>>;
+    /// Like [`WoicesMut::add_pcm_from_bytes`], but also fails if the file can't be read
+    fn add_pcm_from_file<P: AsRef<Path>>(
+        &mut self,
+        path: P,
+    ) -> Result<BoxOrMut<'_, <Self::W as Woice>::PCM>, Self::WavError>;
+    /// Add a PCM woice from the bytes of a WAV file, which must be 1 or 2 channels of 8 or 16 bit
+    /// samples like [`WoicesMut::add_pcm_from_file`] takes.
+    fn add_pcm_from_bytes(
+        &mut self,
+        bytes: &[u8],
+    ) -> Result<BoxOrMut<'_, <Self::W as Woice>::PCM>, Self::WavError>;
+    fn add_ptv_from_file<P: AsRef<Path>>(
+        &mut self,
+        path: P,
+    ) -> Option<BoxOrMut<<Self::W as Woice>::PTV>>;
+    fn add_ptn_from_file<P: AsRef<Path>>(
+        &mut self,
+        path: P,
+    ) -> Option<BoxOrMut<<Self::W as Woice>::PTN>>;
+    /// Like [`WoicesMut::add_oggv_from_bytes`], but also fails if the file can't be read
+    fn add_oggv_from_file<P: AsRef<Path>>(
+        &mut self,
+        path: P,
+    ) -> Result<BoxOrMut<'_, <Self::W as Woice>::OGGV>, Self::OggError>;
+    /// Add an OGGV woice from the bytes of an Ogg Vorbis file
+    fn add_oggv_from_bytes(
+        &mut self,
+        bytes: &[u8],
+    ) -> Result<BoxOrMut<'_, <Self::W as Woice>::OGGV>, Self::OggError>;
     fn remove(&mut self, index: usize) -> bool;
 }
 
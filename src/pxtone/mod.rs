@@ -1,4 +1,9 @@
+pub mod control;
+pub mod diff;
+pub mod export;
+pub mod import;
 pub mod interface;
+pub mod time;
 pub mod util;
 
 #[cfg(feature = "og-impl")]
@@ -1,5 +1,6 @@
 use std::{
     borrow::{Borrow, BorrowMut},
+    ops::Range,
     ptr::addr_of,
 };
 
@@ -10,9 +11,10 @@ use crate::{
         AddEventError, BaseEvent, EventGroupNo, EventKey, EventKind, EventList, EventListMut,
         EventOn, EventPanTime, EventPanVolume, EventPorta, EventTuning, EventVelocity,
         EventVoiceNo, EventVolume, GenericEvent, GenericEventKind, GenericEventKindMut,
-        GenericEventKindRef, HasEventList, PanValue, TuningValue,
+        GenericEventKindRef, HasEventList, Key, PanValue, TuningValue,
     },
     pxtone::util::{BoxOrMut, BoxOrRef, ZeroToOneF32},
+    time::TempoChange,
 };
 
 use super::service::PxToneService;
@@ -186,6 +188,10 @@ impl GenericEvent for EVERECORD {
             _ => GenericEventKind::Invalid,
         }
     }
+
+    fn tempo_change(&self) -> Option<TempoChange> {
+        TempoChange::from_raw(EventKind::from(self.kind), self.value as u32)
+    }
 }
 
 impl EventOn for EVERECORD {
@@ -199,12 +205,12 @@ impl EventOn for EVERECORD {
 }
 
 impl EventKey for EVERECORD {
-    fn key(&self) -> i32 {
-        self.value
+    fn key(&self) -> Key {
+        Key::new(self.value)
     }
 
-    fn set_key(&mut self, key: i32) {
-        self.value = key;
+    fn set_key(&mut self, key: Key) {
+        self.value = *key;
     }
 }
 
@@ -305,7 +311,7 @@ impl<T: BorrowMut<pxtnEvelist>> EventListMut for PxToneEventList<T> {
         unsafe {
             let (kind, value) = match event.kind() {
                 GenericEventKind::On(e) => (EventKind::On, e.length() as _),
-                GenericEventKind::Key(e) => (EventKind::Key, e.key()),
+                GenericEventKind::Key(e) => (EventKind::Key, *e.key()),
                 GenericEventKind::PanVolume(e) => (
                     EventKind::PanVolume,
                     ((*e.pan_volume() / 2.0 + 0.5) * 128.0) as _,
@@ -337,6 +343,64 @@ impl<T: BorrowMut<pxtnEvelist>> EventListMut for PxToneEventList<T> {
             }
         }
     }
+
+    fn retain<F: FnMut(&Self::Event) -> bool>(&mut self, mut f: F) {
+        let evelist = self.evelist.borrow_mut();
+        let mut p = evelist._start;
+        unsafe {
+            while let Some(record) = p.as_mut() {
+                p = record.next;
+                if !f(record) {
+                    cut_record(evelist, record);
+                }
+            }
+        }
+    }
+
+    fn shift_clocks(&mut self, clocks: Range<u32>, unit_no: Option<u8>, shift: i32) -> usize {
+        let evelist = self.evelist.borrow_mut();
+        let mut moved = Vec::new();
+
+        let mut p = evelist._start;
+        unsafe {
+            while let Some(record) = p.as_mut() {
+                p = record.next;
+                if clocks.contains(&record.clock()) && unit_no.is_none_or(|u| record.unit_no == u) {
+                    moved.push((
+                        record.clock().saturating_add_signed(shift),
+                        record.unit_no,
+                        record.kind,
+                        record.value,
+                    ));
+                    cut_record(evelist, record);
+                }
+            }
+
+            // re-adding puts them back in order
+            for &(clock, unit_no, kind, value) in &moved {
+                evelist.Record_Add_i(clock as _, unit_no, kind, value);
+            }
+        }
+
+        moved.len()
+    }
+
+    fn clear(&mut self) {
+        unsafe { self.evelist.borrow_mut().Clear() };
+    }
+}
+
+/// Unlink `record` from the list and return it to the record pool, like `pxtnEvelist::_rec_cut`
+unsafe fn cut_record(evelist: &mut pxtnEvelist, record: &mut EVERECORD) {
+    if let Some(prev) = record.prev.as_mut() {
+        prev.next = record.next;
+    } else {
+        evelist._start = record.next;
+    }
+    if let Some(next) = record.next.as_mut() {
+        next.prev = record.prev;
+    }
+    record.kind = EventKind::Null as u8;
 }
 
 impl<T: Borrow<pxtnEvelist>> EventList for PxToneEventList<T> {
@@ -344,11 +408,9 @@ impl<T: Borrow<pxtnEvelist>> EventList for PxToneEventList<T> {
 
     fn iter(&self) -> Box<dyn Iterator<Item = &Self::Event>> {
         Box::new(
-            EventLinkedList {
-                raw: self.evelist.borrow()._start.cast_const(),
-            }
-            .into_iter()
-            .map(|e| e as &'static EVERECORD),
+            EventLinkedList { raw: self.evelist.borrow()._start.cast_const() }
+                .into_iter()
+                .map(|e| e as &'static EVERECORD),
         )
     }
 }
@@ -1,25 +1,34 @@
 use std::{
+    collections::HashMap,
     convert::TryInto,
     ffi::{CStr, CString},
     fs::File,
+    ops::Range,
     path::PathBuf,
     slice,
 };
 
-use pxtone_sys::{fclose, fopen, pxtnDescriptor, pxtnService, pxtnVOMITPREPARATION};
+use pxtone_sys::{fclose, fopen, pxtnDescriptor, pxtnService, pxtnUnit, pxtnVOMITPREPARATION};
 
 use crate::{
     interface::{
+        event::{Key, PanValue},
         io::PxToneServiceIO,
-        moo::{AsMoo, Fade, Moo},
+        moo::{AsMoo, Fade, Moo, MooLive},
         service::{InvalidText, PxTone},
+        unit::{Unit, UnitsMut},
     },
     og_impl::error::Error,
-    util::BoxOrMut,
+    util::{BoxOrMut, ZeroToOneF32},
 };
 
+/// `on_count` of a live note that hasn't been released yet
+const HELD_ON_COUNT: i32 = i32::MAX / 2;
+
 pub struct PxToneService<'p> {
     service: BoxOrMut<'p, pxtnService>,
+    /// Units' own mute flags, kept while [`Moo::set_unit_muted`] overrides them for playback
+    saved_mutes: HashMap<u8, bool>,
 }
 
 impl<'p> PxToneService<'p> {
@@ -27,7 +36,7 @@ impl<'p> PxToneService<'p> {
         let mut serv = unsafe { pxtnService::new() };
         Error::from_raw(unsafe { serv.init_collage(pxtone_sys::pxtnMAX_EVENTNUM as _) })?;
 
-        Ok(Self { service: serv.into() })
+        Ok(Self { service: serv.into(), saved_mutes: HashMap::new() })
     }
 
     #[must_use]
@@ -44,35 +53,27 @@ impl<'p> PxToneService<'p> {
     pub fn is_valid(&self) -> bool {
         unsafe { self.service.moo_is_valid_data() }
     }
-}
-
-impl<'p, T: Into<BoxOrMut<'p, pxtnService>>> From<T> for PxToneService<'p> {
-    fn from(service: T) -> Self {
-        Self { service: service.into() }
-    }
-}
-
-impl<'p> PxToneServiceIO for PxToneService<'p> {
-    type Error = Error;
 
-    fn read_bytes(&mut self, bytes: &[u8]) -> Result<(), Self::Error> {
-        let mut descriptor = unsafe { pxtnDescriptor::new() };
-        if !unsafe {
-            descriptor.set_memory_r(
-                bytes as *const _ as *mut std::ffi::c_void,
-                bytes.len() as i32,
-            )
-        } {
-            return Err(Error::DescR);
+    /// Swap the units' playback mute overrides with their own flags
+    fn swap_unit_mutes(&mut self) {
+        let mut saved = std::mem::take(&mut self.saved_mutes);
+        for (unit_no, muted) in &mut saved {
+            if let Some(mut unit) = UnitsMut::iter_mut(self).nth(usize::from(*unit_no)) {
+                let overridden = unit.muted();
+                unit.set_muted(*muted);
+                *muted = overridden;
+            }
         }
+        self.saved_mutes = saved;
+    }
 
-        Error::from_raw(unsafe { self.service.read(&mut descriptor) })?;
-
-        Ok(())
+    /// Give the units back their own mute flags, dropping the playback overrides
+    fn restore_unit_mutes(&mut self) {
+        self.swap_unit_mutes();
+        self.saved_mutes.clear();
     }
 
-    fn write_file(&mut self, path: impl Into<PathBuf>) -> Result<Vec<u8>, Self::Error> {
-        let path = path.into();
+    fn write_project(&mut self, path: PathBuf) -> Result<Vec<u8>, Error> {
         File::create(&path).map_err(|_| Error::DescW)?;
 
         let path: PathBuf = path.canonicalize().map_err(|_| Error::DescW)?;
@@ -102,6 +103,45 @@ impl<'p> PxToneServiceIO for PxToneService<'p> {
     }
 }
 
+impl<'p, T: Into<BoxOrMut<'p, pxtnService>>> From<T> for PxToneService<'p> {
+    fn from(service: T) -> Self {
+        Self {
+            service: service.into(),
+            saved_mutes: HashMap::new(),
+        }
+    }
+}
+
+impl<'p> PxToneServiceIO for PxToneService<'p> {
+    type Error = Error;
+
+    fn read_bytes(&mut self, bytes: &[u8]) -> Result<(), Self::Error> {
+        let mut descriptor = unsafe { pxtnDescriptor::new() };
+        if !unsafe {
+            descriptor.set_memory_r(
+                bytes as *const _ as *mut std::ffi::c_void,
+                bytes.len() as i32,
+            )
+        } {
+            return Err(Error::DescR);
+        }
+
+        Error::from_raw(unsafe { self.service.read(&mut descriptor) })?;
+        // the units they were saved from are gone
+        self.saved_mutes.clear();
+
+        Ok(())
+    }
+
+    fn write_file(&mut self, path: impl Into<PathBuf>) -> Result<Vec<u8>, Self::Error> {
+        // save the units' own mute flags, not the ones overridden for playback
+        self.swap_unit_mutes();
+        let written = self.write_project(path.into());
+        self.swap_unit_mutes();
+        written
+    }
+}
+
 impl<'p> PxTone for PxToneService<'p> {
     fn beat_num(&self) -> i32 {
         unsafe { (*self.service.master)._beat_num }
@@ -264,6 +304,8 @@ impl<'p> Moo<'_> for PxToneService<'p> {
         };
 
         if unsafe { self.service.moo_preparation(&prep) } {
+            // preparing turns unit muting off
+            self.restore_unit_mutes();
             Ok(())
         } else {
             Err(Error::VOID)
@@ -299,12 +341,28 @@ impl<'p> Moo<'_> for PxToneService<'p> {
 
     fn set_unit_mute_enabled(&mut self, unit_mute: bool) -> Result<(), Error> {
         if unsafe { self.service.moo_set_mute_by_unit(unit_mute) } {
+            if !unit_mute {
+                self.restore_unit_mutes();
+            }
             Ok(())
         } else {
             Err(Error::INIT)
         }
     }
 
+    fn set_unit_muted(&mut self, unit_no: u8, muted: bool) -> Result<(), Error> {
+        // pxtone plays from the mute flag on the unit itself, so override it until playback is
+        // prepared again or unit muting is turned off, keeping the project's own flag for saving
+        let mut unit = UnitsMut::iter_mut(self)
+            .nth(unit_no as usize)
+            .ok_or(Error::Param)?;
+        let own = unit.muted();
+        unit.set_muted(muted);
+        drop(unit);
+        self.saved_mutes.entry(unit_no).or_insert(own);
+        Ok(())
+    }
+
     fn set_loop(&mut self, should_loop: bool) -> Result<(), Error> {
         if unsafe { self.service.moo_set_loop(should_loop) } {
             Ok(())
@@ -313,6 +371,71 @@ impl<'p> Moo<'_> for PxToneService<'p> {
         }
     }
 
+    fn set_loop_measures(&mut self, measures: Option<Range<i32>>) -> Result<(), Error> {
+        let (repeat, end) = measures.map_or_else(
+            || {
+                let last = self.last_measure();
+                let end = if last > 0 { last } else { self.num_measures() };
+                (self.repeat_measure(), end)
+            },
+            |m| (m.start, m.end),
+        );
+
+        // same as `moo_preparation`
+        let service = &mut *self.service;
+        let measure_to_sample = |measure: i32| {
+            (f64::from(measure)
+                * f64::from(service._moo_bt_num)
+                * f64::from(service._moo_bt_clock)
+                * f64::from(service._moo_clock_rate)) as i32
+        };
+        let (repeat, end) = (measure_to_sample(repeat), measure_to_sample(end));
+        service._moo_smp_repeat = repeat;
+        service._moo_smp_end = end;
+        Ok(())
+    }
+
+    fn jump_to_measure(&mut self, measure: i32) -> Result<(), Error> {
+        let service = &mut *self.service;
+
+        let mut flags = 0;
+        if service._moo_b_loop {
+            flags |= pxtone_sys::pxtnVOMITPREPFLAG_loop;
+        }
+        if service._moo_b_mute_by_unit {
+            flags |= pxtone_sys::pxtnVOMITPREPFLAG_unit_mute;
+        }
+
+        let prep = pxtnVOMITPREPARATION {
+            start_pos_meas: measure,
+            start_pos_sample: 0,
+            start_pos_float: 0.0,
+            meas_end: 0,
+            meas_repeat: 0,
+            fadein_sec: 0.0,
+            flags,
+            master_volume: service._moo_master_vol,
+        };
+
+        // `moo_preparation` resets these, so put them back afterwards
+        let smp_end = service._moo_smp_end;
+        let smp_repeat = service._moo_smp_repeat;
+        let fade_fade = service._moo_fade_fade;
+        let fade_count = service._moo_fade_count;
+        let fade_max = service._moo_fade_max;
+
+        if !unsafe { service.moo_preparation(&prep) } {
+            return Err(Error::VOID);
+        }
+
+        service._moo_smp_end = smp_end;
+        service._moo_smp_repeat = smp_repeat;
+        service._moo_fade_fade = fade_fade;
+        service._moo_fade_count = fade_count;
+        service._moo_fade_max = fade_max;
+        Ok(())
+    }
+
     fn set_fade(&mut self, fade: Option<Fade>, duration: std::time::Duration) -> Result<(), Error> {
         if unsafe {
             self.service.moo_set_fade(
@@ -353,3 +476,106 @@ impl<'p> Moo<'_> for PxToneService<'p> {
         }
     }
 }
+
+impl PxToneService<'_> {
+    fn unit_ptr(&mut self, unit_no: u8) -> Result<*mut pxtnUnit, Error> {
+        if i32::from(unit_no) >= self.service._unit_num {
+            return Err(Error::Param);
+        }
+
+        Ok(unsafe { *self.service._units.add(unit_no as usize) })
+    }
+}
+
+// This does what `_moo_PXTONE_SAMPLE` does for events, but with notes that last until released
+impl MooLive<'_> for PxToneService<'_> {
+    fn live_note_on(
+        &mut self,
+        unit_no: u8,
+        key: Key,
+        velocity: ZeroToOneF32,
+        pan_volume: PanValue,
+    ) -> Result<(), Error> {
+        let ch_num = self.service._dst_ch_num;
+        let unit = unsafe { &mut *self.unit_ptr(unit_no)? };
+
+        unsafe {
+            unit.Tone_Key(*key);
+            unit.Tone_KeyOn();
+            unit.Tone_Velocity((*velocity * 128.0) as _);
+            unit.Tone_Pan_Volume(ch_num, ((*pan_volume / 2.0 + 0.5) * 128.0) as _);
+
+            let woice = unit.get_woice();
+            if woice.is_null() {
+                return Ok(());
+            }
+
+            for v in 0..(*woice).get_voice_num() {
+                let tone = &mut *unit.get_tone(v);
+                let inst = &*(*woice).get_instance(v);
+
+                tone.on_count = HELD_ON_COUNT;
+                tone.life_count = HELD_ON_COUNT + inst.env_release;
+                tone.smp_pos = 0.0;
+                tone.env_pos = 0;
+                let env_start = if inst.env_size > 0 { 0 } else { 128 };
+                tone.env_volume = env_start;
+                tone.env_start = env_start;
+            }
+        }
+
+        Ok(())
+    }
+
+    fn live_note_off(&mut self, unit_no: u8) -> Result<(), Error> {
+        let unit = unsafe { &mut *self.unit_ptr(unit_no)? };
+
+        unsafe {
+            let woice = unit.get_woice();
+            if woice.is_null() {
+                return Ok(());
+            }
+
+            for v in 0..(*woice).get_voice_num() {
+                let tone = &mut *unit.get_tone(v);
+                let inst = &*(*woice).get_instance(v);
+
+                // `Tone_Increment_Sample` starts the release once `on_count` hits 0
+                if tone.on_count > 1 {
+                    tone.on_count = 1;
+                    tone.life_count = tone.life_count.min(1 + inst.env_release);
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    fn live_key(&mut self, unit_no: u8, key: Key) -> Result<(), Error> {
+        unsafe { (*self.unit_ptr(unit_no)?).Tone_Key(*key) };
+        Ok(())
+    }
+
+    fn live_velocity(&mut self, unit_no: u8, velocity: ZeroToOneF32) -> Result<(), Error> {
+        unsafe { (*self.unit_ptr(unit_no)?).Tone_Velocity((*velocity * 128.0) as _) };
+        Ok(())
+    }
+
+    fn live_pan_volume(&mut self, unit_no: u8, pan_volume: PanValue) -> Result<(), Error> {
+        let ch_num = self.service._dst_ch_num;
+        unsafe {
+            (*self.unit_ptr(unit_no)?)
+                .Tone_Pan_Volume(ch_num, ((*pan_volume / 2.0 + 0.5) * 128.0) as _);
+        };
+        Ok(())
+    }
+
+    fn live_voice_no(&mut self, unit_no: u8, voice_no: u8) -> Result<(), Error> {
+        let unit = self.unit_ptr(unit_no)?;
+        if unsafe { self.service._moo_ResetVoiceOn(unit, voice_no.into()) } {
+            Ok(())
+        } else {
+            Err(Error::VOID)
+        }
+    }
+}
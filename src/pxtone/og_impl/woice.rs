@@ -1,20 +1,35 @@
-use std::{ffi::CString, slice};
+use std::{
+    ffi::{CStr, CString},
+    io, slice,
+};
 
 use pxtone_sys::{
-    pxNOISEDESIGN_OSCILLATOR, pxNOISEDESIGN_UNIT, pxtnDescriptor, pxtnPOINT, pxtnVOICEENVELOPE, pxtnVOICEUNIT, pxtnVOICEWAVE, pxtnWOICETYPE, pxtnWOICETYPE_pxtnWOICE_OGGV, pxtnWOICETYPE_pxtnWOICE_PCM, pxtnWOICETYPE_pxtnWOICE_PTN, pxtnWOICETYPE_pxtnWOICE_PTV, pxtnWoice
+    fclose, fopen, pxNOISEDESIGN_OSCILLATOR, pxNOISEDESIGN_UNIT, pxtnDescriptor, pxtnPOINT,
+    pxtnVOICEENVELOPE, pxtnVOICEUNIT, pxtnVOICEWAVE, pxtnWOICETYPE, pxtnWOICETYPE_pxtnWOICE_OGGV,
+    pxtnWOICETYPE_pxtnWOICE_PCM, pxtnWOICETYPE_pxtnWOICE_PTN, pxtnWOICETYPE_pxtnWOICE_PTV,
+    pxtnWoice,
 };
 
 use crate::{
+    export::wav::write_pcm,
     interface::{
         service::InvalidText,
         woice::{
-            HasWoices, PTNEnvelopePoint, PTNOscillator, PTNUnit, PTNWaveType, PTVCoordinateWave, PTVCoordinateWavePoint, PTVEnvelope, PTVOvertoneWave, PTVOvertoneWaveTone, PTVWaveType, SingleVoice, Voice, VoiceOGGV, VoicePCM, VoicePTN, VoicePTV, Woice, WoiceOGGV, WoicePCM, WoicePTN, WoicePTV, WoiceType, WoiceTypeMut, WoiceTypeRef, Woices, WoicesMut
+            HasWoices, PTNEnvelopePoint, PTNOscillator, PTNUnit, PTNWaveType, PTVCoordinateWave,
+            PTVCoordinateWavePoint, PTVEnvelope, PTVOvertoneWave, PTVOvertoneWaveTone, PTVWaveType,
+            SingleVoice, Voice, VoiceOGGV, VoicePCM, VoicePTN, VoicePTV, Woice, WoiceOGGV,
+            WoicePCM, WoicePTN, WoicePTV, WoiceType, WoiceTypeMut, WoiceTypeRef, Woices, WoicesMut,
         },
     },
     pxtone::util::{BoxOrMut, BoxOrRef},
 };
 
-use super::{service::PxToneService, error::Error};
+use super::{error::Error, service::PxToneService};
+
+/// `PTV_VOICEFLAG_WAVELOOP` in og pxtone
+const VOICEFLAG_WAVELOOP: u32 = 0x0000_0001;
+/// `PTV_VOICEFLAG_SMOOTH` in og pxtone
+const VOICEFLAG_SMOOTH: u32 = 0x0000_0002;
 
 impl Woice for pxtnWoice {
     type VPCM = pxtnVOICEUNIT;
@@ -147,6 +162,14 @@ impl Voice for pxtnVOICEUNIT {
     fn set_tuning(&mut self, tuning: f32) {
         self.tuning = tuning;
     }
+
+    fn flag_loop(&self) -> bool {
+        self.voice_flags & VOICEFLAG_WAVELOOP != 0
+    }
+
+    fn flag_smooth(&self) -> bool {
+        self.voice_flags & VOICEFLAG_SMOOTH != 0
+    }
 }
 
 impl VoicePCM for pxtnVOICEUNIT {
@@ -162,6 +185,11 @@ impl VoicePCM for pxtnVOICEUNIT {
         unsafe { (*self.p_pcm)._bps as u8 }
     }
 
+    fn sample_num(&self) -> u32 {
+        let pcm = unsafe { &*self.p_pcm };
+        (pcm._smp_head + pcm._smp_body + pcm._smp_tail) as u32
+    }
+
     #[allow(clippy::cast_precision_loss)]
     fn sample(&self, cycle: f32, channel: u8) -> f32 {
         // TODO: check that this handles stereo correctly
@@ -173,6 +201,18 @@ impl VoicePCM for pxtnVOICEUNIT {
             / 256.0
             - 0.5
     }
+
+    /// og only keeps sample data for PCM voices, others write an empty file
+    fn export_wav<W: io::Write>(&self, writer: W) -> io::Result<()> {
+        let pcm = unsafe { &*self.p_pcm };
+        let size = (pcm._smp_head + pcm._smp_body + pcm._smp_tail) * pcm._ch * pcm._bps / 8;
+        let data = if pcm._p_smp.is_null() {
+            &[][..]
+        } else {
+            unsafe { slice::from_raw_parts(pcm._p_smp, size as usize) }
+        };
+        write_pcm(writer, pcm._ch as u8, pcm._sps as u32, pcm._bps as u8, data)
+    }
 }
 
 impl PTVCoordinateWavePoint for pxtnPOINT {
@@ -258,9 +298,9 @@ impl VoicePTV for pxtnVOICEUNIT {
             PTVWaveType::Overtone(&self.wave)
         }
     }
-    
+
     fn envelope(&self) -> &Self::Envelope {
-        todo!()
+        &self.envelope
     }
 }
 
@@ -404,9 +444,24 @@ impl WoicePTV<pxtnVOICEUNIT> for pxtnWoice {
         }
         v
     }
+
+    fn export_ptv<W: io::Write>(&self, mut writer: W) -> io::Result<()> {
+        let bytes = write_through_file(|descriptor| unsafe {
+            self.PTV_Write(descriptor, std::ptr::null_mut())
+        })?;
+        writer.write_all(&bytes)
+    }
 }
 
-impl WoicePTN<pxtnVOICEUNIT> for pxtnWoice {}
+impl WoicePTN<pxtnVOICEUNIT> for pxtnWoice {
+    fn export_ptn<W: io::Write>(&self, mut writer: W) -> io::Result<()> {
+        let ptn = self.voice().p_ptn;
+        let bytes = write_through_file(|descriptor| unsafe {
+            (*ptn).write(descriptor, std::ptr::null_mut())
+        })?;
+        writer.write_all(&bytes)
+    }
+}
 
 impl WoiceOGGV<pxtnVOICEUNIT> for pxtnWoice {}
 
@@ -425,6 +480,9 @@ impl Woices for PxToneService<'_> {
 }
 
 impl WoicesMut for PxToneService<'_> {
+    type WavError = Error;
+    type OggError = Error;
+
     fn iter_mut<'a>(&'a mut self) -> Box<dyn Iterator<Item = BoxOrMut<Self::W>> + 'a> {
         let slice = unsafe {
             slice::from_raw_parts_mut(self.raw_mut()._woices, self.raw_mut()._woice_num as usize)
@@ -439,42 +497,122 @@ impl WoicesMut for PxToneService<'_> {
     /// Add a blank ptvoice woice to the project.
     fn add_blank_ptv(&mut self) -> Option<BoxOrMut<<Self::W as Woice>::PTV>> {
         self.add_from_bytes(include_bytes!("blank.ptvoice"), pxtnWOICETYPE_pxtnWOICE_PTV)
+            .ok()
     }
 
     /// Add a blank ptnoise woice to the project.
     fn add_blank_ptn(&mut self) -> Option<BoxOrMut<<Self::W as Woice>::PTN>> {
         self.add_from_bytes(include_bytes!("blank.ptnoise"), pxtnWOICETYPE_pxtnWOICE_PTN)
+            .ok()
     }
 
-    fn add_pcm_from_file<P: AsRef<std::path::Path>>(&mut self, path: P) -> Option<BoxOrMut<<Self::W as Woice>::PCM>> {
+    fn add_pcm(
+        &mut self,
+        channels: u8,
+        samples_per_second: u32,
+        bits_per_sample: u8,
+        data: Vec<u8>,
+        basic_key: i32,
+        looped: bool,
+    ) -> Option<BoxOrMut<'_, <Self::W as Woice>::PCM>> {
+        if !matches!((channels, bits_per_sample), (1 | 2, 8 | 16)) {
+            return None;
+        }
+
+        // og pxtone only takes PCM as a WAV file
+        let block_align = u16::from(channels) * u16::from(bits_per_sample / 8);
+        let mut wav = Vec::with_capacity(44 + data.len());
+        wav.extend_from_slice(b"RIFF");
+        wav.extend_from_slice(&(36 + data.len() as u32).to_le_bytes());
+        wav.extend_from_slice(b"WAVEfmt ");
+        wav.extend_from_slice(&16_u32.to_le_bytes());
+        wav.extend_from_slice(&1_u16.to_le_bytes());
+        wav.extend_from_slice(&u16::from(channels).to_le_bytes());
+        wav.extend_from_slice(&samples_per_second.to_le_bytes());
+        wav.extend_from_slice(&(samples_per_second * u32::from(block_align)).to_le_bytes());
+        wav.extend_from_slice(&block_align.to_le_bytes());
+        wav.extend_from_slice(&u16::from(bits_per_sample).to_le_bytes());
+        wav.extend_from_slice(b"data");
+        wav.extend_from_slice(&(data.len() as u32).to_le_bytes());
+        wav.extend_from_slice(&data);
+
+        let mut woice = self
+            .add_from_bytes(&wav, pxtnWOICETYPE_pxtnWOICE_PCM)
+            .ok()?;
+        // og decides looping by the sample's length, so set it explicitly
+        let voice = woice.voice_mut();
+        voice.basic_key = basic_key;
+        if looped {
+            voice.voice_flags |= VOICEFLAG_WAVELOOP;
+        } else {
+            voice.voice_flags &= !VOICEFLAG_WAVELOOP;
+        }
+        Some(woice)
+    }
+
+    fn add_pcm_from_file<P: AsRef<std::path::Path>>(
+        &mut self,
+        path: P,
+    ) -> Result<BoxOrMut<'_, <Self::W as Woice>::PCM>, Error> {
         self.add_from_file(path, pxtnWOICETYPE_pxtnWOICE_PCM)
     }
 
-    fn add_ptv_from_file<P: AsRef<std::path::Path>>(&mut self, path: P) -> Option<BoxOrMut<<Self::W as Woice>::PTV>> {
-        self.add_from_file(path, pxtnWOICETYPE_pxtnWOICE_PTV)
+    fn add_pcm_from_bytes(
+        &mut self,
+        bytes: &[u8],
+    ) -> Result<BoxOrMut<'_, <Self::W as Woice>::PCM>, Error> {
+        self.add_from_bytes(bytes, pxtnWOICETYPE_pxtnWOICE_PCM)
     }
 
-    fn add_ptn_from_file<P: AsRef<std::path::Path>>(&mut self, path: P) -> Option<BoxOrMut<<Self::W as Woice>::PTN>> {
-        self.add_from_file(path, pxtnWOICETYPE_pxtnWOICE_PTN)
+    fn add_ptv_from_file<P: AsRef<std::path::Path>>(
+        &mut self,
+        path: P,
+    ) -> Option<BoxOrMut<<Self::W as Woice>::PTV>> {
+        self.add_from_file(path, pxtnWOICETYPE_pxtnWOICE_PTV).ok()
+    }
+
+    fn add_ptn_from_file<P: AsRef<std::path::Path>>(
+        &mut self,
+        path: P,
+    ) -> Option<BoxOrMut<<Self::W as Woice>::PTN>> {
+        self.add_from_file(path, pxtnWOICETYPE_pxtnWOICE_PTN).ok()
     }
 
-    fn add_oggv_from_file<P: AsRef<std::path::Path>>(&mut self, path: P) -> Option<BoxOrMut<<Self::W as Woice>::OGGV>> {
+    fn add_oggv_from_file<P: AsRef<std::path::Path>>(
+        &mut self,
+        path: P,
+    ) -> Result<BoxOrMut<'_, <Self::W as Woice>::OGGV>, Error> {
         self.add_from_file(path, pxtnWOICETYPE_pxtnWOICE_OGGV)
     }
 
+    fn add_oggv_from_bytes(
+        &mut self,
+        bytes: &[u8],
+    ) -> Result<BoxOrMut<'_, <Self::W as Woice>::OGGV>, Error> {
+        self.add_from_bytes(bytes, pxtnWOICETYPE_pxtnWOICE_OGGV)
+    }
+
     fn remove(&mut self, index: usize) -> bool {
-        unsafe {
-            self.raw_mut().Woice_Remove(index as _)
-        }
+        unsafe { self.raw_mut().Woice_Remove(index as _) }
     }
 }
 
 impl PxToneService<'_> {
-    fn add_from_file<P: AsRef<std::path::Path>>(&mut self, path: P, typ: pxtnWOICETYPE) -> Option<BoxOrMut<<Self as Woices>::W>> {
-        self.add_from_bytes(&std::fs::read(path.as_ref()).unwrap(), typ)
+    fn add_from_file<P: AsRef<std::path::Path>>(
+        &mut self,
+        path: P,
+        typ: pxtnWOICETYPE,
+    ) -> Result<BoxOrMut<<Self as Woices>::W>, Error> {
+        // og pxtone reports files it can't open as a descriptor read error too
+        let bytes = std::fs::read(path.as_ref()).map_err(|_| Error::DescR)?;
+        self.add_from_bytes(&bytes, typ)
     }
 
-    fn add_from_bytes(&mut self, bytes: &[u8], typ: pxtnWOICETYPE) -> Option<BoxOrMut<<Self as Woices>::W>> {
+    fn add_from_bytes(
+        &mut self,
+        bytes: &[u8],
+        typ: pxtnWOICETYPE,
+    ) -> Result<BoxOrMut<<Self as Woices>::W>, Error> {
         let svc = self.raw_mut();
 
         let mut descriptor = unsafe { pxtnDescriptor::new() };
@@ -484,18 +622,13 @@ impl PxToneService<'_> {
                 bytes.len() as i32,
             )
         } {
-            // return Err(Error::DescR);
-            return None;
+            return Err(Error::DescR);
         }
 
         let idx = svc._woice_num;
-        let res = Error::from_raw(unsafe { svc.Woice_read(idx, &mut descriptor, typ) });
-        if res.is_ok() {
-            let w = unsafe { svc.Woice_Get_variable(idx) };
-            Some(BoxOrMut::Ref(unsafe { &mut *w } as &mut <Self as Woices>::W))
-        } else {
-            None
-        }
+        Error::from_raw(unsafe { svc.Woice_read(idx, &mut descriptor, typ) })?;
+        let w = unsafe { svc.Woice_Get_variable(idx) };
+        Ok(BoxOrMut::Ref(unsafe { &mut *w } as &mut <Self as Woices>::W))
     }
 }
 
@@ -511,3 +644,36 @@ impl HasWoices for PxToneService<'_> {
         self.into()
     }
 }
+
+/// og pxtone can only write to a `FILE`, so go through a temporary one
+fn write_through_file(write: impl FnOnce(&mut pxtnDescriptor) -> bool) -> io::Result<Vec<u8>> {
+    let path = std::env::temp_dir().join(format!(
+        "pxtone-{}-{:?}.tmp",
+        std::process::id(),
+        std::thread::current().id()
+    ));
+    let fpath = CString::new(path.to_string_lossy().as_bytes()).unwrap();
+
+    let file = unsafe {
+        fopen(
+            fpath.as_ptr(),
+            CStr::from_bytes_with_nul_unchecked(b"wb\0").as_ptr(),
+        )
+    };
+
+    if file.is_null() {
+        return Err(io::Error::other("failed to create temporary file"));
+    }
+
+    let mut descriptor = unsafe { pxtnDescriptor::new() };
+    let written = unsafe { descriptor.set_file_w(file) } && write(&mut descriptor);
+    unsafe { fclose(file) };
+
+    let bytes = std::fs::read(&path);
+    let _ = std::fs::remove_file(&path);
+
+    if !written {
+        return Err(io::Error::other("pxtone failed to write the woice"));
+    }
+    bytes
+}
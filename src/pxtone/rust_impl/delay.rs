@@ -0,0 +1,95 @@
+pub use crate::interface::event::MAX_GROUPS;
+
+use crate::{
+    interface::delay::{AddDelayError, Delay, DelayUnit, Delays, DelaysMut, HasDelays},
+    util::{BoxOrMut, BoxOrRef, ZeroToOneF32},
+};
+
+use super::service::RPxTone;
+
+/// Maximum number of delays in a project (`pxtnMAX_TUNEDELAYSTRUCT`)
+pub const MAX_DELAYS: usize = 4;
+
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct RPxToneDelay {
+    pub(crate) group: u8,
+    pub(crate) frequency: DelayUnit,
+    /// Percent, kept as project files store it so they save back exactly
+    pub(crate) rate: f32,
+}
+
+impl Delay for RPxToneDelay {
+    fn group(&self) -> u8 {
+        self.group
+    }
+
+    fn set_group(&mut self, group: u8) {
+        self.group = group;
+    }
+
+    fn frequency(&self) -> DelayUnit {
+        self.frequency
+    }
+
+    fn set_frequency(&mut self, frequency: DelayUnit) {
+        self.frequency = frequency;
+    }
+
+    fn rate(&self) -> ZeroToOneF32 {
+        ZeroToOneF32::new(self.rate / 100.0)
+    }
+
+    fn set_rate(&mut self, rate: ZeroToOneF32) {
+        self.rate = *rate * 100.0;
+    }
+}
+
+impl Delays for RPxTone {
+    type D = RPxToneDelay;
+
+    fn iter<'a>(&'a self) -> Box<dyn Iterator<Item = BoxOrRef<'a, Self::D>> + 'a> {
+        Box::new(self.delays.iter().map(BoxOrRef::Ref))
+    }
+}
+
+impl DelaysMut for RPxTone {
+    fn iter_mut<'a>(&'a mut self) -> Box<dyn Iterator<Item = BoxOrMut<'a, Self::D>> + 'a> {
+        Box::new(self.delays.iter_mut().map(BoxOrMut::Ref))
+    }
+
+    fn add(
+        &mut self,
+        group: u8,
+        frequency: DelayUnit,
+        rate: ZeroToOneF32,
+    ) -> Result<BoxOrMut<'_, Self::D>, AddDelayError> {
+        if self.delays.len() >= MAX_DELAYS {
+            return Err(AddDelayError { group, frequency, rate });
+        }
+
+        self.delays
+            .push(RPxToneDelay { group, frequency, rate: *rate * 100.0 });
+        Ok(self.delays.last_mut().unwrap().into())
+    }
+
+    fn remove(&mut self, index: usize) -> bool {
+        if index >= self.delays.len() {
+            return false;
+        }
+        self.delays.remove(index);
+        true
+    }
+}
+
+impl HasDelays for RPxTone {
+    type Delays = Self;
+    type DelaysMut = Self;
+
+    fn delays(&self) -> BoxOrRef<'_, Self::Delays> {
+        BoxOrRef::Ref(self)
+    }
+
+    fn delays_mut(&mut self) -> BoxOrMut<'_, Self::DelaysMut> {
+        BoxOrMut::Ref(self)
+    }
+}
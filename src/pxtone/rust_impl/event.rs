@@ -1,23 +1,43 @@
-use std::cmp::Ordering;
+use std::{
+    cmp::Ordering,
+    ops::{Bound, Range, RangeBounds},
+};
 
 use crate::{
     interface::event::{
         BaseEvent, EventGroupNo, EventKey, EventKind, EventList, EventListMut, EventOn,
         EventPanTime, EventPanVolume, EventPorta, EventTuning, EventVelocity, EventVoiceNo,
         EventVolume, GenericEvent, GenericEventKind, GenericEventKindMut, GenericEventKindRef,
-        HasEventList, PanValue, TuningValue,
+        HasEventList, Key, PanValue, TuningValue,
     },
+    time::TempoChange,
     util::{BoxOrMut, BoxOrRef, ZeroToOneF32},
 };
 
 use super::service::RPxTone;
 
 #[derive(Debug, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct RPxToneEventList {
     pub(crate) events: Vec<RPxToneEvent>,
 }
 
 impl RPxToneEventList {
+    /// Add an event as stored in project files, for kinds that [`EventImpl`] can't hold (like
+    /// tempo changes)
+    ///
+    /// [`EventImpl`]: crate::interface::event_impl::EventImpl
+    pub(crate) fn push_raw(&mut self, clock: u32, unit_no: u8, kind: EventKind, value: i32) {
+        self.insert(RPxToneEvent { clock, unit_no, kind, value });
+    }
+
+    /// Put `event` after every event up to its clock, which is where [`Self::sort_events`] would
+    /// move it, without sorting the whole list again for every event a project loads
+    fn insert(&mut self, event: RPxToneEvent) {
+        let at = self.events.partition_point(|e| e.clock <= event.clock);
+        self.events.insert(at, event);
+    }
+
     pub fn sort_events(&mut self) {
         self.events.sort_by(|a, b| {
             match a.clock().cmp(&b.clock()) {
@@ -34,11 +54,12 @@ impl RPxToneEventList {
 }
 
 #[derive(Debug, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct RPxToneEvent {
-    clock: u32,
-    unit_no: u8,
-    kind: EventKind,
-    value: i32,
+    pub(crate) clock: u32,
+    pub(crate) unit_no: u8,
+    pub(crate) kind: EventKind,
+    pub(crate) value: i32,
 }
 
 impl BaseEvent for RPxToneEvent {
@@ -124,9 +145,14 @@ impl GenericEvent for RPxToneEvent {
             EventKind::GroupNo => GenericEventKind::GroupNo(BoxOrMut::Ref(self)),
             EventKind::Tuning => GenericEventKind::Tuning(BoxOrMut::Ref(self)),
             EventKind::PanTime => GenericEventKind::PanTime(BoxOrMut::Ref(self)),
+            EventKind::Portament => GenericEventKind::Porta(BoxOrMut::Ref(self)),
             _ => GenericEventKind::Invalid,
         }
     }
+
+    fn tempo_change(&self) -> Option<TempoChange> {
+        TempoChange::from_raw(self.kind, self.value as u32)
+    }
 }
 
 impl EventOn for RPxToneEvent {
@@ -140,12 +166,12 @@ impl EventOn for RPxToneEvent {
 }
 
 impl EventKey for RPxToneEvent {
-    fn key(&self) -> i32 {
-        self.value
+    fn key(&self) -> Key {
+        Key::new(self.value)
     }
 
-    fn set_key(&mut self, key: i32) {
-        self.value = key;
+    fn set_key(&mut self, key: Key) {
+        self.value = *key;
     }
 }
 
@@ -239,6 +265,23 @@ impl EventList for RPxToneEventList {
     fn iter(&self) -> Box<dyn Iterator<Item = &Self::Event> + '_> {
         Box::new(self.events.iter())
     }
+
+    fn events_in<R: RangeBounds<u32>>(
+        &self,
+        clocks: R,
+    ) -> Box<dyn Iterator<Item = &Self::Event> + '_> {
+        let start = match clocks.start_bound() {
+            Bound::Included(&c) => self.events.partition_point(|e| e.clock < c),
+            Bound::Excluded(&c) => self.events.partition_point(|e| e.clock <= c),
+            Bound::Unbounded => 0,
+        };
+        let end = match clocks.end_bound() {
+            Bound::Included(&c) => self.events.partition_point(|e| e.clock <= c),
+            Bound::Excluded(&c) => self.events.partition_point(|e| e.clock < c),
+            Bound::Unbounded => self.events.len(),
+        };
+        Box::new(self.events[start..end.max(start)].iter())
+    }
 }
 
 impl EventListMut for RPxToneEventList {
@@ -252,7 +295,7 @@ impl EventListMut for RPxToneEventList {
     ) -> Result<(), crate::interface::event::AddEventError> {
         let (kind, value) = match event.kind() {
             GenericEventKind::On(e) => (EventKind::On, e.length() as _),
-            GenericEventKind::Key(e) => (EventKind::Key, e.key()),
+            GenericEventKind::Key(e) => (EventKind::Key, *e.key()),
             GenericEventKind::PanVolume(e) => (
                 EventKind::PanVolume,
                 ((*e.pan_volume() / 2.0 + 0.5) * 128.0) as _,
@@ -280,11 +323,32 @@ impl EventListMut for RPxToneEventList {
             value,
         };
 
-        self.events.push(ne);
-        self.sort_events();
+        self.insert(ne);
 
         Ok(())
     }
+
+    fn retain<F: FnMut(&Self::Event) -> bool>(&mut self, f: F) {
+        self.events.retain(f);
+    }
+
+    fn shift_clocks(&mut self, clocks: Range<u32>, unit_no: Option<u8>, shift: i32) -> usize {
+        let mut count = 0;
+        for e in &mut self.events {
+            if clocks.contains(&e.clock) && unit_no.is_none_or(|u| e.unit_no == u) {
+                e.clock = e.clock.saturating_add_signed(shift);
+                count += 1;
+            }
+        }
+
+        // the sort is stable, so events on the same clock keep their order
+        self.sort_events();
+        count
+    }
+
+    fn clear(&mut self) {
+        self.events.clear();
+    }
 }
 
 impl HasEventList for RPxTone {
@@ -4,23 +4,30 @@ use byteorder::{LittleEndian, ReadBytesExt};
 
 use crate::{
     interface::{
-        event::{EventKind, EventListMut, HasEventList},
+        delay::DelayUnit,
+        event::{EventKind, EventListMut, HasEventList, MAX_GROUPS},
         event_impl::EventImpl,
         io::PxToneServiceIO,
+        overdrive::OverDAmp,
         service::PxTone,
     },
     rust_impl::{
+        delay::RPxToneDelay,
+        overdrive::RPxToneOverDrive,
+        ptnoise::{self, PTNoiseError},
+        ptvoice::{self, PTVoiceError},
         unit::RPxToneUnit,
         woice::{
-            RPXTonePTVEnvelope, RPxTonePTNEnvelopePoint, RPxTonePTVCoordinatePoint, RPxTonePTVCoordinateWave, RPxTonePTVOvertoneWave, RPxTonePTVOvertoneWaveTone, RPxTonePTVWaveType, RPxToneVoicePCM, RPxToneVoicePCMError, RPxToneVoicePTV, RPxToneWoice, RPxToneWoicePCM, RPxToneWoiceType
+            RPxToneVoicePCM, RPxToneVoicePCMError, RPxToneWoice, RPxToneWoicePCM, RPxToneWoiceType,
         },
     },
+    time::Tempo,
 };
 
 use super::{
     event::RPxToneEventList,
     service::RPxTone,
-    woice::{RPxToneVoiceOGGV, RPxToneVoiceOGGVError, RPxToneWoiceOGGV, RPxToneWoicePTV},
+    woice::{RPxToneVoiceOGGV, RPxToneVoiceOGGVError, RPxToneWoiceOGGV, RPxToneWoicePTN},
 };
 
 pub struct RPxToneIO {}
@@ -45,6 +52,34 @@ pub enum RPxToneIOError {
         channels: u8,
     },
     VorbisError(lewton::VorbisError),
+    /// A `mateOGGV` block holding an Ogg stream that isn't Vorbis
+    NotVorbis,
+    InvalidDelayUnit(u16),
+    InvalidOverDriveConfig {
+        cut: f32,
+        amp: f32,
+    },
+    PTVoice(PTVoiceError),
+    PTNoise(PTNoiseError),
+    Io(std::io::Error),
+}
+
+impl From<std::io::Error> for RPxToneIOError {
+    fn from(e: std::io::Error) -> Self {
+        Self::Io(e)
+    }
+}
+
+impl From<PTVoiceError> for RPxToneIOError {
+    fn from(e: PTVoiceError) -> Self {
+        Self::PTVoice(e)
+    }
+}
+
+impl From<PTNoiseError> for RPxToneIOError {
+    fn from(e: PTNoiseError) -> Self {
+        Self::PTNoise(e)
+    }
 }
 
 impl PxToneServiceIO for RPxTone {
@@ -55,6 +90,8 @@ impl PxToneServiceIO for RPxTone {
         self.event_list = RPxToneEventList::default();
         self.woices.clear();
         self.units.clear();
+        self.delays.clear();
+        self.overdrives.clear();
 
         let mut c = Cursor::new(bytes);
 
@@ -94,8 +131,14 @@ impl PxToneServiceIO for RPxTone {
                     let clock_repeat = c.read_i32::<LittleEndian>().unwrap();
                     let clock_last = c.read_i32::<LittleEndian>().unwrap();
 
-                    let repeat_measure = clock_repeat / (beat_num as i32 * beat_clock as i32);
-                    let last_measure = clock_last / (beat_num as i32 * beat_clock as i32);
+                    let clocks_per_measure = Tempo {
+                        beat_num: beat_num as _,
+                        beat_tempo,
+                        beat_clock: beat_clock as _,
+                    }
+                    .clocks_per_measure() as i32;
+                    let repeat_measure = clock_repeat / clocks_per_measure;
+                    let last_measure = clock_last / clocks_per_measure;
 
                     let mut num_measures = 1;
 
@@ -138,17 +181,19 @@ impl PxToneServiceIO for RPxTone {
 
                         last_eve_pos = last_eve_pos.max(abs_position);
 
-                        self.event_list_mut()
-                            .add(
-                                &EventImpl::from_raw(
-                                    abs_position,
-                                    unit_no,
-                                    event_kind,
-                                    event_value,
-                                )
-                                .unwrap(),
-                            )
-                            .unwrap();
+                        if let Some(event) =
+                            EventImpl::from_raw(abs_position, unit_no, event_kind, event_value)
+                        {
+                            self.event_list_mut().add(&event).unwrap();
+                        } else {
+                            // tempo changes and such, which are kept as they are
+                            self.event_list.push_raw(
+                                abs_position,
+                                unit_no,
+                                event_kind,
+                                event_value as i32,
+                            );
+                        }
                     }
                 },
                 b"matePCM " => {
@@ -238,184 +283,128 @@ impl PxToneServiceIO for RPxTone {
                                 RPxToneVoiceOGGVError::VorbisError(e) => {
                                     RPxToneIOError::VorbisError(e)
                                 },
+                                RPxToneVoiceOGGVError::NotVorbis => RPxToneIOError::NotVorbis,
+                                // only reading a file to decode can fail like this
+                                RPxToneVoiceOGGVError::Io(_) => unreachable!(),
                             })?,
                         }),
                     });
                 },
-                #[allow(clippy::unreadable_literal)]
                 b"matePTV " => {
                     let _x3x_unit_no = c.read_u16::<LittleEndian>().unwrap();
                     let rrr = c.read_u16::<LittleEndian>().unwrap();
-                    let tuning = c.read_f32::<LittleEndian>().unwrap();
-                    let _tuning = f32::from_le_bytes(tuning.to_le_bytes());
+                    let _x3x_tuning = c.read_f32::<LittleEndian>().unwrap();
                     let _size = c.read_u32::<LittleEndian>().unwrap();
 
                     assert_eq!(rrr, 0);
 
-                    let mut code = [0_u8; 8];
-                    c.read_exact(&mut code).unwrap();
-                    assert_eq!(&code, b"PTVOICE-");
-
-                    let version = c.read_u32::<LittleEndian>().unwrap();
-                    assert!(version <= 20060111);
-
-                    let _total = c.read_u32::<LittleEndian>().unwrap();
-
-                    let _x3x_basic_key = v_r(&mut c).unwrap();
-
-                    let work1 = v_r(&mut c).unwrap();
-                    let work2 = v_r(&mut c).unwrap();
-                    assert!(work1 == 0 && work2 == 0);
-
-                    let voice_num = v_r(&mut c).unwrap();
-
-                    let voices = (0..voice_num).filter_map(|_| {
-                        let basic_key = v_r(&mut c).unwrap();
-                        let volume = v_r(&mut c).unwrap();
-                        let pan = v_r(&mut c).unwrap();
-                        let tuning = v_r(&mut c).unwrap();
-                        let tuning = f32::from_le_bytes(tuning.to_le_bytes());
-                        let voice_flags = v_r(&mut c).unwrap();
-                        let data_flags = v_r(&mut c).unwrap();
-
-                        println!("{basic_key}, {volume}, {pan}, {tuning}, {voice_flags}, {data_flags}");
-
-                        assert_eq!(voice_flags & 0xffff_fff8, 0); // only flags 0x1, 0x2, and 0x4 are used
-                        assert_eq!(data_flags & 0xffff_fffc, 0); // only flags 0x1 and 0x2 are used
-
-                        if data_flags & 0x1 != 0 {
-                            // wave
-
-                            let wave_type = v_r(&mut c).unwrap();
-
-                            println!("wave_type {wave_type}");
-
-                            let wave = match wave_type {
-                                0 => {
-                                    let num_points = v_r(&mut c).unwrap();
-                                    let resolution = v_r(&mut c).unwrap();
-
-                                    let points = (0..num_points).map(|_| {
-                                        let x = c.read_u8().unwrap();
-                                        let y = c.read_i8().unwrap();
-
-                                        RPxTonePTVCoordinatePoint {
-                                            x: x as _,
-                                            y: y as _,
-                                        }
-                                    }).collect();
-
-                                    RPxTonePTVWaveType::Coordinate(RPxTonePTVCoordinateWave {
-                                        resolution,
-                                        points,
-                                    })
-                                },
-                                1 => {
-                                    let num_tones = v_r(&mut c).unwrap();
-
-                                    println!("num_tones {num_tones}");
-
-                                    let tones = (0..num_tones).map(|_| {
-                                        let x = v_r(&mut c).unwrap();
-                                        let y = v_r(&mut c).unwrap();
-
-                                        println!("{x} {y}");
-
-                                        RPxTonePTVOvertoneWaveTone {
-                                            frequency: x as _,
-                                            amplitude: y as _,
-                                        }
-                                    }).collect();
-
-                                    RPxTonePTVWaveType::Overtone(RPxTonePTVOvertoneWave {
-                                        tones,
-                                    })
-                                }
-                                _ => panic!("Invalid wave type"),
-                            };
-
-                            #[allow(clippy::if_not_else)]
-                            let envelope = if data_flags & 0x2 != 0 {
-                                let fps = v_r(&mut c).unwrap();
-                                let head_num = v_r(&mut c).unwrap();
-                                let body_num = v_r(&mut c).unwrap();
-                                let tail_num = v_r(&mut c).unwrap();
-
-                                println!("{fps}, {head_num}, {body_num}, {tail_num}");
-
-                                assert_eq!(body_num, 0);
-                                assert_eq!(tail_num, 1);
-
-                                let num = head_num + body_num + tail_num;
-
-                                let points: Vec<_> = (0..num).map(|_| {
-                                    let x = v_r(&mut c).unwrap();
-                                    let y = v_r(&mut c).unwrap();
-
-                                    RPxTonePTNEnvelopePoint {
-                                        x,
-                                        y: y as _,
-                                    }
-                                }).collect();
-
-                                println!("points = {points:?}");
-                                let env_release = if tail_num > 0 {
-                                    points[head_num as usize].x * 44100 / fps
-                                } else {
-                                    0
-                                };
-                                println!("env_release = {env_release}");
-
-                                RPXTonePTVEnvelope {
-                                    fps,
-                                    head_num,
-                                    body_num,
-                                    tail_num,
-                                    points,
-                                }
-                            } else {
-                                RPXTonePTVEnvelope::default()
-                            };
-
-                            let voice = RPxToneVoicePTV::new(
-                                basic_key as _,
-                                volume as _,
-                                pan as _,
-                                f32::from_le_bytes(tuning.to_le_bytes()),
-                                wave,
-                                envelope
-                            );
-
-                            return Some(voice);
-                        }
-
-                        None
-                    }).collect();
-
                     self.woices.push(RPxToneWoice {
                         name: String::new(),
-                        woice_type: RPxToneWoiceType::PTV(RPxToneWoicePTV { voices }),
+                        woice_type: RPxToneWoiceType::PTV(ptvoice::read_from(&mut c)?),
                     });
                 },
                 b"matePTN " => {
+                    let _x3x_unit_no = c.read_u16::<LittleEndian>().unwrap();
+                    let basic_key = c.read_u16::<LittleEndian>().unwrap();
+                    let voice_flags = c.read_u32::<LittleEndian>().unwrap();
+                    let tuning = c.read_f32::<LittleEndian>().unwrap();
+                    let rrr = c.read_i32::<LittleEndian>().unwrap();
+
+                    assert!((0..=1).contains(&rrr));
+                    assert_eq!(voice_flags & 0xffff_fff8, 0); // only flags 0x1, 0x2, and 0x4 are used
+
+                    let mut voice = ptnoise::read_from(&mut c)?;
+                    voice.basic_key = basic_key.into();
+                    voice.tuning = tuning;
+                    voice.flag_loop = voice_flags & 0x1 != 0;
+                    voice.flag_smooth = voice_flags & 0x2 != 0;
+                    voice.flag_beat_fit = voice_flags & 0x4 != 0;
+
                     self.woices.push(RPxToneWoice {
                         name: String::new(),
-                        woice_type: RPxToneWoiceType::PTV(RPxToneWoicePTV { voices: vec![] }),
+                        woice_type: RPxToneWoiceType::PTN(RPxToneWoicePTN { voice }),
                     });
+                },
+                b"effeDELA" => {
+                    if block_size != 12 {
+                        return Err(RPxToneIOError::IncorrectBlockSize {
+                            block: block_name,
+                            expected: 12,
+                            actual: block_size,
+                        });
+                    }
 
-                    // TODO: placeholder
-                    c.set_position(c.position() + block_size as u64);
+                    let unit = c.read_u16::<LittleEndian>().unwrap();
+                    let group = c.read_u16::<LittleEndian>().unwrap();
+                    let rate = c.read_f32::<LittleEndian>().unwrap();
+                    let freq = c.read_f32::<LittleEndian>().unwrap();
+
+                    let frequency = match unit {
+                        0 => DelayUnit::Beat(freq),
+                        1 => DelayUnit::Measure(freq),
+                        2 => DelayUnit::Second(freq),
+                        _ => return Err(RPxToneIOError::InvalidDelayUnit(unit)),
+                    };
+
+                    self.delays.push(RPxToneDelay {
+                        // out of range groups get reset to 0, same as og pxtone
+                        group: if group < MAX_GROUPS as u16 {
+                            group as u8
+                        } else {
+                            0
+                        },
+                        frequency,
+                        rate,
+                    });
+                },
+                b"effeOVER" => {
+                    if block_size != 16 {
+                        return Err(RPxToneIOError::IncorrectBlockSize {
+                            block: block_name,
+                            expected: 16,
+                            actual: block_size,
+                        });
+                    }
+
+                    let _xxx = c.read_u16::<LittleEndian>().unwrap();
+                    let group = c.read_u16::<LittleEndian>().unwrap();
+                    let cut = c.read_f32::<LittleEndian>().unwrap();
+                    let amp = c.read_f32::<LittleEndian>().unwrap();
+                    let _yyy = c.read_f32::<LittleEndian>().unwrap();
+
+                    if !(50.0..=99.9).contains(&cut) || !(0.1..=8.0).contains(&amp) {
+                        return Err(RPxToneIOError::InvalidOverDriveConfig { cut, amp });
+                    }
+
+                    self.overdrives.push(RPxToneOverDrive {
+                        // out of range groups get reset to 0, same as og pxtone
+                        group: if group < MAX_GROUPS as u16 {
+                            group as u8
+                        } else {
+                            0
+                        },
+                        cut,
+                        amp: OverDAmp::new(amp),
+                    });
                 },
                 b"num UNIT" => {
                     if block_size != 4 {
                         return Err(RPxToneIOError::IncorrectBlockSize {
                             block: block_name,
-                            expected: 15,
+                            expected: 4,
                             actual: block_size,
                         });
                     }
 
-                    let _num_unit = c.read_u32::<LittleEndian>().unwrap();
+                    let num_unit = c.read_u16::<LittleEndian>().unwrap();
+                    let _rrr = c.read_u16::<LittleEndian>().unwrap();
+
+                    // units only get an assiUNIT block if they have a name
+                    self.units.resize_with(num_unit as usize, || RPxToneUnit {
+                        selected: false,
+                        muted: false,
+                        name: String::new(),
+                    });
                 },
                 b"textNAME" => {
                     let mut name_buf = vec![0_u8; block_size as usize];
@@ -429,25 +418,27 @@ impl PxToneServiceIO for RPxTone {
                     let comment = String::from_utf8(comment_buf).unwrap();
                     self.set_comment(comment).unwrap();
                 },
-                b"assiUNIT" => {
+                b"assiWOIC" => {
                     let index = c.read_u16::<LittleEndian>().unwrap();
-                    assert_eq!(
-                        index,
-                        self.units.len() as u16,
-                        "assiUNIT block out of order, this is unsupported for now"
-                    );
-
                     let rrr = c.read_u16::<LittleEndian>().unwrap();
                     assert_eq!(rrr, 0);
 
-                    let mut name_buf = [0_u8; 16];
-                    c.read_exact(&mut name_buf).unwrap();
-                    let name =
-                        String::from_utf8(name_buf.into_iter().take_while(|c| *c != 0).collect())
-                            .unwrap();
+                    let name = read_assist_name(&mut c);
+                    self.woices
+                        .get_mut(index as usize)
+                        .expect("assiWOIC block for a woice that doesn't exist")
+                        .name = name;
+                },
+                b"assiUNIT" => {
+                    let index = c.read_u16::<LittleEndian>().unwrap();
+                    let rrr = c.read_u16::<LittleEndian>().unwrap();
+                    assert_eq!(rrr, 0);
 
+                    let name = read_assist_name(&mut c);
                     self.units
-                        .push(RPxToneUnit { selected: false, muted: false, name });
+                        .get_mut(index as usize)
+                        .expect("assiUNIT block for a unit that doesn't exist")
+                        .name = name;
                 },
                 b"pxtoneND" => {
                     break;
@@ -462,20 +453,209 @@ impl PxToneServiceIO for RPxTone {
             }
         }
 
-        let num_measures = self.num_measures().max(
-            (last_eve_pos as f64 / self.beat_num() as f64 / self.beat_clock() as f64).ceil() as _,
-        );
+        let num_measures = self
+            .num_measures()
+            .max(last_eve_pos.div_ceil(Tempo::of(self).clocks_per_measure()) as _);
         self.set_num_measures(num_measures);
 
         Ok(())
     }
 
-    fn write_file(&mut self, _path: impl Into<std::path::PathBuf>) -> Result<Vec<u8>, Self::Error> {
-        todo!()
+    fn write_file(&mut self, path: impl Into<std::path::PathBuf>) -> Result<Vec<u8>, Self::Error> {
+        let bytes = self.write_bytes()?;
+        std::fs::write(path.into(), &bytes)?;
+        Ok(bytes)
+    }
+}
+
+impl RPxTone {
+    /// The project in the same layout og pxtone saves it, block for block
+    #[allow(clippy::too_many_lines)]
+    fn write_bytes(&self) -> Result<Vec<u8>, std::io::Error> {
+        let mut out = Vec::new();
+        out.extend_from_slice(b"PTCOLLAGE-071119");
+        // exe version and a reserved value, og_impl saves both as 0 too
+        out.extend_from_slice(&0_u16.to_le_bytes());
+        out.extend_from_slice(&0_u16.to_le_bytes());
+
+        let clocks_per_measure = Tempo::of(self).clocks_per_measure() as i32;
+        let mut master = Vec::with_capacity(15);
+        master.extend_from_slice(&(self.beat_clock() as i16).to_le_bytes());
+        master.push(self.beat_num() as u8);
+        master.extend_from_slice(&self.beat_tempo().to_le_bytes());
+        master.extend_from_slice(&(self.repeat_measure() * clocks_per_measure).to_le_bytes());
+        master.extend_from_slice(&(self.last_measure() * clocks_per_measure).to_le_bytes());
+        write_block(&mut out, b"MasterV5", &master);
+
+        // the list is sorted already unless clocks were edited in place, the sort is stable so
+        // events on the same clock keep their order
+        let mut events: Vec<_> = self.event_list.events.iter().collect();
+        events.sort_by_key(|e| e.clock);
+
+        // og pxtone sizes this block with each event's absolute clock rather than the relative one
+        // it writes, so the size is usually too big. Nothing reads it, but it's kept the same.
+        let mut size = 4;
+        let mut body = (events.len() as u32).to_le_bytes().to_vec();
+        let mut last_clock = 0;
+        for e in events {
+            v_w(&mut body, e.clock - last_clock);
+            body.push(e.unit_no);
+            body.push(e.kind as u8);
+            v_w(&mut body, e.value as u32);
+            size += v_size(e.clock) + 2 + v_size(e.value as u32);
+            last_clock = e.clock;
+        }
+        out.extend_from_slice(b"Event V5");
+        out.extend_from_slice(&size.to_le_bytes());
+        out.extend_from_slice(&body);
+
+        let name = self.name();
+        if !name.is_empty() {
+            write_block(&mut out, b"textNAME", name.as_bytes());
+        }
+        let comment = self.comment();
+        if !comment.is_empty() {
+            write_block(&mut out, b"textCOMM", comment.as_bytes());
+        }
+
+        for delay in &self.delays {
+            let (unit, freq) = match delay.frequency {
+                DelayUnit::Beat(freq) => (0_u16, freq),
+                DelayUnit::Measure(freq) => (1, freq),
+                DelayUnit::Second(freq) => (2, freq),
+            };
+
+            let mut body = Vec::with_capacity(12);
+            body.extend_from_slice(&unit.to_le_bytes());
+            body.extend_from_slice(&u16::from(delay.group).to_le_bytes());
+            body.extend_from_slice(&delay.rate.to_le_bytes());
+            body.extend_from_slice(&freq.to_le_bytes());
+            write_block(&mut out, b"effeDELA", &body);
+        }
+
+        for overdrive in &self.overdrives {
+            let mut body = Vec::with_capacity(16);
+            body.extend_from_slice(&0_u16.to_le_bytes());
+            body.extend_from_slice(&u16::from(overdrive.group).to_le_bytes());
+            body.extend_from_slice(&overdrive.cut.to_le_bytes());
+            body.extend_from_slice(&overdrive.amp.to_le_bytes());
+            body.extend_from_slice(&0_f32.to_le_bytes());
+            write_block(&mut out, b"effeOVER", &body);
+        }
+
+        for (index, woice) in self.woices.iter().enumerate() {
+            let mut body = Vec::new();
+            match &woice.woice_type {
+                RPxToneWoiceType::PCM(woice) => {
+                    let voice = &woice.voice;
+                    let data = voice.data();
+                    body.extend_from_slice(&0_u16.to_le_bytes());
+                    body.extend_from_slice(&(voice.basic_key as u16).to_le_bytes());
+                    body.extend_from_slice(
+                        &voice_flags(voice.flag_loop, voice.flag_smooth, voice.flag_beat_fit)
+                            .to_le_bytes(),
+                    );
+                    body.extend_from_slice(&u16::from(voice.channels).to_le_bytes());
+                    body.extend_from_slice(&u16::from(voice.bits_per_sample).to_le_bytes());
+                    body.extend_from_slice(&voice.samples_per_second.to_le_bytes());
+                    body.extend_from_slice(&voice.tuning.to_le_bytes());
+                    body.extend_from_slice(&(data.len() as u32).to_le_bytes());
+                    body.extend_from_slice(&data);
+                    write_block(&mut out, b"matePCM ", &body);
+                },
+                RPxToneWoiceType::PTV(woice) => {
+                    let mut ptv = Vec::new();
+                    ptvoice::write(woice, &mut ptv)?;
+                    body.extend_from_slice(&0_u16.to_le_bytes());
+                    body.extend_from_slice(&0_u16.to_le_bytes());
+                    body.extend_from_slice(&0_f32.to_le_bytes());
+                    body.extend_from_slice(&(ptv.len() as u32).to_le_bytes());
+                    body.extend_from_slice(&ptv);
+                    write_block(&mut out, b"matePTV ", &body);
+                },
+                RPxToneWoiceType::PTN(woice) => {
+                    let voice = &woice.voice;
+                    body.extend_from_slice(&0_u16.to_le_bytes());
+                    body.extend_from_slice(&(voice.basic_key as u16).to_le_bytes());
+                    body.extend_from_slice(
+                        &voice_flags(voice.flag_loop, voice.flag_smooth, voice.flag_beat_fit)
+                            .to_le_bytes(),
+                    );
+                    body.extend_from_slice(&voice.tuning.to_le_bytes());
+                    body.extend_from_slice(&1_i32.to_le_bytes());
+                    ptnoise::write(voice, &mut body)?;
+                    write_block(&mut out, b"matePTN ", &body);
+                },
+                RPxToneWoiceType::OGGV(woice) => {
+                    let voice = &woice.voice;
+                    body.extend_from_slice(&0_u16.to_le_bytes());
+                    body.extend_from_slice(&(voice.basic_key as u16).to_le_bytes());
+                    body.extend_from_slice(
+                        &voice_flags(voice.flag_loop, voice.flag_smooth, voice.flag_beat_fit)
+                            .to_le_bytes(),
+                    );
+                    body.extend_from_slice(&voice.tuning.to_le_bytes());
+                    body.extend_from_slice(&u32::from(voice.channels).to_le_bytes());
+                    body.extend_from_slice(&voice.samples_per_second.to_le_bytes());
+                    body.extend_from_slice(&voice.ogg_sample_num.to_le_bytes());
+                    body.extend_from_slice(&(voice.ogg_data.len() as u32).to_le_bytes());
+                    body.extend_from_slice(&voice.ogg_data);
+                    write_block(&mut out, b"mateOGGV", &body);
+                },
+            }
+
+            if !woice.name.is_empty() {
+                write_block(&mut out, b"assiWOIC", &assist(index, &woice.name));
+            }
+        }
+
+        let mut num_unit = (self.units.len() as u16).to_le_bytes().to_vec();
+        num_unit.extend_from_slice(&0_u16.to_le_bytes());
+        write_block(&mut out, b"num UNIT", &num_unit);
+        for (index, unit) in self.units.iter().enumerate() {
+            if !unit.name.is_empty() {
+                write_block(&mut out, b"assiUNIT", &assist(index, &unit.name));
+            }
+        }
+
+        write_block(&mut out, b"pxtoneND", &[]);
+        Ok(out)
+    }
+}
+
+fn write_block(out: &mut Vec<u8>, code: &[u8], body: &[u8]) {
+    out.extend_from_slice(code);
+    out.extend_from_slice(&(body.len() as u32).to_le_bytes());
+    out.extend_from_slice(body);
+}
+
+fn voice_flags(flag_loop: bool, flag_smooth: bool, flag_beat_fit: bool) -> u32 {
+    u32::from(flag_loop) | u32::from(flag_smooth) << 1 | u32::from(flag_beat_fit) << 2
+}
+
+/// Body of an assiWOIC/assiUNIT block, the name cut to 16 bytes and padded with NULs
+fn assist(index: usize, name: &str) -> Vec<u8> {
+    let mut end = name.len().min(16);
+    while !name.is_char_boundary(end) {
+        end -= 1;
     }
+
+    let mut body = Vec::with_capacity(20);
+    body.extend_from_slice(&(index as u16).to_le_bytes());
+    body.extend_from_slice(&0_u16.to_le_bytes());
+    body.extend_from_slice(&name.as_bytes()[..end]);
+    body.resize(20, 0);
+    body
 }
 
-fn v_r(c: &mut Cursor<&[u8]>) -> Result<u32, std::io::Error> {
+/// Name in an assiWOIC/assiUNIT block, up to the first NUL
+fn read_assist_name(c: &mut Cursor<&[u8]>) -> String {
+    let mut name_buf = [0_u8; 16];
+    c.read_exact(&mut name_buf).unwrap();
+    String::from_utf8(name_buf.into_iter().take_while(|c| *c != 0).collect()).unwrap()
+}
+
+pub(crate) fn v_r(c: &mut Cursor<&[u8]>) -> Result<u32, std::io::Error> {
     let mut a = [0_u8; 5];
     let mut b = [0_u8; 5];
     let mut i = 0;
@@ -520,3 +700,53 @@ fn v_r(c: &mut Cursor<&[u8]>) -> Result<u32, std::io::Error> {
 
     Ok(u32::from_le_bytes([b[0], b[1], b[2], b[3]]))
 }
+
+/// How many bytes [`v_w`] writes `value` in
+fn v_size(value: u32) -> u32 {
+    (32 - value.leading_zeros()).max(1).div_ceil(7)
+}
+
+/// The inverse of [`v_r`]: 7 bits per byte, low bits first, high bit set if more bytes follow
+pub(crate) fn v_w(out: &mut Vec<u8>, mut value: u32) {
+    while value >= 0x80 {
+        out.push((value & 0x7F) as u8 | 0x80);
+        value >>= 7;
+    }
+    out.push(value as u8);
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        interface::{
+            event::{EventKind, EventList, HasEventList},
+            io::PxToneServiceIO,
+            service::PxTone,
+        },
+        rust_impl::service::RPxTone,
+        time::HasTempoMap,
+    };
+
+    #[test]
+    fn writes_tempo_events() {
+        let mut pxtone = RPxTone::new();
+        pxtone.set_num_measures(2);
+        pxtone
+            .event_list
+            .push_raw(1920, 0, EventKind::BeatTempo, 240.0_f32.to_bits() as i32);
+
+        let bytes = pxtone.write_bytes().unwrap();
+        let mut read = RPxTone::new();
+        read.read_bytes(&bytes).unwrap();
+
+        let tempo: Vec<_> = read
+            .event_list()
+            .iter()
+            .filter(|e| EventKind::of(*e) == EventKind::BeatTempo)
+            .map(|e| (e.clock, e.value))
+            .collect();
+        assert_eq!(tempo, [(1920, 240.0_f32.to_bits() as i32)]);
+        assert_eq!(read.tempo_map().clock_to_secs(2880), 2.5);
+        assert_eq!(read.write_bytes().unwrap(), bytes);
+    }
+}
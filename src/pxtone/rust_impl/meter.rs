@@ -0,0 +1,185 @@
+use std::sync::{
+    atomic::{AtomicU32, AtomicUsize, Ordering},
+    Arc,
+};
+
+use crate::interface::event::MAX_GROUPS;
+
+const GROUP_NUM: usize = MAX_GROUPS as usize;
+
+/// Units are addressed with a `u8`
+const UNIT_SLOTS: usize = u8::MAX as usize + 1;
+
+/// Renderer output is scaled by a half on the way to 16-bit samples
+const FULL_SCALE: f32 = 2.0 * 32768.0;
+
+/// Signal level over one rendered block, from 0.0 to 1.0 of 16-bit full scale
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Level {
+    pub peak: f32,
+    pub rms: f32,
+}
+
+/// Levels of every unit and group over the last rendered block
+#[derive(Debug, Clone, PartialEq)]
+pub struct MeterSnapshot {
+    /// Indexed by unit number, one entry per unit in the project
+    pub units: Vec<Level>,
+    /// Indexed by group number
+    pub groups: [Level; GROUP_NUM],
+}
+
+#[derive(Default)]
+struct AtomicLevel {
+    peak: AtomicU32,
+    rms: AtomicU32,
+}
+
+impl AtomicLevel {
+    fn load(&self) -> Level {
+        Level {
+            peak: f32::from_bits(self.peak.load(Ordering::Relaxed)),
+            rms: f32::from_bits(self.rms.load(Ordering::Relaxed)),
+        }
+    }
+
+    fn store(&self, level: Level) {
+        self.peak.store(level.peak.to_bits(), Ordering::Relaxed);
+        self.rms.store(level.rms.to_bits(), Ordering::Relaxed);
+    }
+}
+
+struct SharedMeters {
+    units: Box<[AtomicLevel]>,
+    groups: [AtomicLevel; GROUP_NUM],
+    unit_num: AtomicUsize,
+}
+
+/// Reads the levels measured by a [`RPxToneMoo`](super::moo::RPxToneMoo), from any thread.
+///
+/// Reading never locks or waits on the renderer. Each value is read atomically, but a snapshot
+/// taken while a block is being published can mix levels from two consecutive blocks.
+///
+/// Levels are measured after unit volume, velocity and muting, but before master volume and fades.
+#[derive(Clone)]
+pub struct MeterHandle {
+    shared: Arc<SharedMeters>,
+}
+
+impl MeterHandle {
+    /// Levels of `unit_no` over the last rendered block, silent if it doesn't exist
+    #[must_use]
+    pub fn unit(&self, unit_no: u8) -> Level {
+        self.shared.units[usize::from(unit_no)].load()
+    }
+
+    /// Levels of every unit in `group_no` mixed together, `None` if it's not a valid group
+    #[must_use]
+    pub fn group(&self, group_no: u8) -> Option<Level> {
+        self.shared
+            .groups
+            .get(usize::from(group_no))
+            .map(AtomicLevel::load)
+    }
+
+    #[must_use]
+    pub fn snapshot(&self) -> MeterSnapshot {
+        let unit_num = self.shared.unit_num.load(Ordering::Relaxed);
+        MeterSnapshot {
+            units: self.shared.units[..unit_num]
+                .iter()
+                .map(AtomicLevel::load)
+                .collect(),
+            groups: std::array::from_fn(|g| self.shared.groups[g].load()),
+        }
+    }
+}
+
+#[derive(Default, Clone, Copy)]
+struct Accumulator {
+    peak: f32,
+    sum_sq: f32,
+}
+
+impl Accumulator {
+    fn add(&mut self, samples: &[f32]) {
+        for s in samples {
+            self.peak = self.peak.max(s.abs());
+            self.sum_sq += s * s;
+        }
+    }
+
+    #[allow(clippy::cast_precision_loss)]
+    fn take(&mut self, samples: usize) -> Level {
+        let level = Level {
+            peak: (self.peak / FULL_SCALE).min(1.0),
+            rms: ((self.sum_sq / samples as f32).sqrt() / FULL_SCALE).min(1.0),
+        };
+        *self = Self::default();
+        level
+    }
+}
+
+/// Renderer side of the meters: accumulates levels while sampling and publishes them per block
+pub(crate) struct Meters {
+    shared: Arc<SharedMeters>,
+    units: Box<[Accumulator]>,
+    groups: [Accumulator; GROUP_NUM],
+    /// Mix of each group for the current frame
+    group_frame: [[f32; 2]; GROUP_NUM],
+}
+
+impl Meters {
+    pub(crate) fn new() -> Self {
+        Self {
+            shared: Arc::new(SharedMeters {
+                units: (0..UNIT_SLOTS).map(|_| AtomicLevel::default()).collect(),
+                groups: Default::default(),
+                unit_num: AtomicUsize::new(0),
+            }),
+            units: vec![Accumulator::default(); UNIT_SLOTS].into_boxed_slice(),
+            groups: [Accumulator::default(); GROUP_NUM],
+            group_frame: [[0.0; 2]; GROUP_NUM],
+        }
+    }
+
+    pub(crate) fn handle(&self) -> MeterHandle {
+        MeterHandle { shared: Arc::clone(&self.shared) }
+    }
+
+    /// Add one frame of a unit's output
+    pub(crate) fn add_unit(&mut self, unit_no: u8, group_no: u8, frame: &[f32]) {
+        self.units[usize::from(unit_no)].add(frame);
+
+        let group = &mut self.group_frame[usize::from(group_no).min(GROUP_NUM - 1)];
+        for (g, s) in group.iter_mut().zip(frame) {
+            *g += s;
+        }
+    }
+
+    /// Finish the current frame, once every unit was added
+    pub(crate) fn end_frame(&mut self, channels: usize) {
+        for (acc, frame) in self.groups.iter_mut().zip(&mut self.group_frame) {
+            acc.add(&frame[..channels]);
+            *frame = [0.0; 2];
+        }
+    }
+
+    /// Make the levels of the block that was just rendered (`samples` long, over all channels)
+    /// visible to the [`MeterHandle`]s
+    pub(crate) fn publish(&mut self, samples: usize, unit_num: usize) {
+        if samples == 0 {
+            return;
+        }
+
+        for (acc, shared) in self.units.iter_mut().zip(self.shared.units.iter()) {
+            shared.store(acc.take(samples));
+        }
+        for (acc, shared) in self.groups.iter_mut().zip(&self.shared.groups) {
+            shared.store(acc.take(samples));
+        }
+        self.shared
+            .unit_num
+            .store(unit_num.min(UNIT_SLOTS), Ordering::Relaxed);
+    }
+}
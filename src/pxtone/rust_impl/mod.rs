@@ -1,6 +1,16 @@
+pub mod delay;
 pub mod event;
 pub mod io;
+pub mod meter;
 pub mod moo;
+mod noise_builder;
+pub mod observer;
+pub mod overdrive;
+pub mod ptnoise;
+pub mod ptvoice;
+#[cfg(feature = "serde")]
+mod serde_impl;
 pub mod service;
 pub mod unit;
+pub mod wav;
 pub mod woice;
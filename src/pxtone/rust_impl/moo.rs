@@ -1,36 +1,180 @@
-use std::{collections::HashMap, ops::Deref};
+use std::{
+    collections::{BTreeMap, HashMap},
+    fmt,
+    ops::{Deref, Range},
+    sync::{Arc, Mutex},
+    time::Duration,
+};
 
 use crate::{
     interface::{
         event::{
-            BaseEvent, EventKey, EventOn, EventPanVolume, EventPorta, EventTuning, EventVelocity,
-            EventVoiceNo, EventVolume, GenericEvent, GenericEventKind, PanValue, TuningValue,
+            BaseEvent, EventGroupNo, EventKey, EventOn, EventPanVolume, EventPorta, EventTuning,
+            EventVelocity, EventVoiceNo, EventVolume, GenericEvent, GenericEventKind, Key,
+            PanValue, TuningValue,
         },
-        moo::{AsMooRef, Moo},
+        moo::{AsMooRef, Fade, Moo, MooLive},
         service::PxTone,
-        woice::{VoicePCM, Woice, WoiceType},
+        woice::VoicePCM,
     },
+    time::{HasTempoMap, MusicalTime, TempoMap},
     util::{BoxOrMut, ZeroToOneF32},
 };
 
-use super::service::RPxTone;
+use super::{
+    meter::{MeterHandle, Meters},
+    observer::{MooObserver, MooPosition},
+    service::RPxTone,
+    woice::{RPxToneVoicePTV, RPxToneWoice, RPxToneWoiceType},
+};
 
-pub struct RPxToneMoo<'a> {
-    pxtone: &'a RPxTone,
+#[allow(clippy::struct_excessive_bools)]
+pub struct RPxToneMoo<'a, O = ()> {
+    pxtone: ProjectRef<'a>,
+    /// Set when this is a [`RPxTonePlayer`] with a [`ProjectSwap`] attached
+    swap: Option<Arc<Mutex<Option<Arc<RPxTone>>>>>,
     channels: u8,
     sample_rate: u32,
 
     smp: u32,
     last_clock: f32,
     last_sample_clock_secs: f32,
+    /// Index of the next event to be processed
+    next_event: usize,
+    /// Set from a jump until the next sample, which replays every event before the new position
+    replaying: bool,
+    /// Built once per project so sampling doesn't have to
+    tempo_map: TempoMap,
 
-    unit_data: HashMap<u8, UnitData>,
+    /// Ordered so units are always mixed in the same order, keeping renders reproducible
+    unit_data: BTreeMap<u8, UnitData>,
 
     master_volume: f32,
+    looping: bool,
+    /// Overrides the song's repeat and last measures
+    loop_measures: Option<Range<i32>>,
+    fade: Option<FadeState>,
+    done: bool,
+
+    unit_mute: bool,
+    /// Mute flags set through [`Moo::set_unit_muted`], these take priority over the units' own flags
+    muted_units: HashMap<u8, bool>,
+    /// Set once [`RPxToneMoo::meter_handle`] was called
+    meters: Option<Meters>,
+    /// Scratch buffer one frame is mixed into, kept so sampling doesn't allocate
+    mix: Vec<f32>,
+
+    observer: O,
+    /// Measure and beat the last sample was on, for [`MooObserver::beat`]
+    last_beat: Option<(u32, u32)>,
+    /// Notes cut off along with their unit's state, waiting to be reported to the observer
+    cut_notes: Vec<(u8, i32)>,
+    /// Set when playback looped, waiting to be reported to the observer
+    wrapped: bool,
+}
+
+/// A [`RPxToneMoo`] that owns (a share of) its project, so it's `Send + 'static` and can be moved
+/// into an audio callback.
+///
+/// Use [`RPxTonePlayer::with_observer`] to get one with a [`MooObserver`].
+///
+/// ```no_run
+/// use std::sync::Arc;
+/// use pxtone::{interface::{io::PxToneServiceIO, moo::Moo}, rust_impl::{moo::RPxTonePlayer, service::RPxTone}};
+///
+/// let mut pxtone = RPxTone::new();
+/// pxtone.read_bytes(&std::fs::read("song.ptcop").unwrap()).unwrap();
+///
+/// let mut player = RPxTonePlayer::new(Arc::new(pxtone));
+/// player.set_audio_format(2, 44100).unwrap();
+/// player.prepare_sample().unwrap();
+/// std::thread::spawn(move || {
+///     let mut buf = vec![0; 1024];
+///     player.sample(&mut buf).unwrap();
+/// });
+/// ```
+pub type RPxTonePlayer<O = ()> = RPxToneMoo<'static, O>;
+
+enum ProjectRef<'a> {
+    Borrowed(&'a RPxTone),
+    Shared(Arc<RPxTone>),
+}
+
+impl Clone for ProjectRef<'_> {
+    fn clone(&self) -> Self {
+        match self {
+            Self::Borrowed(pxtone) => Self::Borrowed(pxtone),
+            Self::Shared(pxtone) => Self::Shared(Arc::clone(pxtone)),
+        }
+    }
+}
+
+impl Deref for ProjectRef<'_> {
+    type Target = RPxTone;
+
+    fn deref(&self) -> &Self::Target {
+        match self {
+            Self::Borrowed(pxtone) => pxtone,
+            Self::Shared(pxtone) => pxtone,
+        }
+    }
+}
+
+/// Handle for replacing the project of a running [`RPxTonePlayer`] from another thread.
+///
+/// The new project is picked up at the start of the next [`Moo::sample`] call. The audio thread
+/// never waits on this; if the handle is mid-update the swap just happens a call later.
+#[derive(Clone)]
+pub struct ProjectSwap {
+    pending: Arc<Mutex<Option<Arc<RPxTone>>>>,
+}
+
+impl ProjectSwap {
+    /// Queue `pxtone` to replace the player's project. Replaces any swap that hasn't happened yet.
+    pub fn swap(&self, pxtone: Arc<RPxTone>) {
+        // a poisoned lock only means a panic while holding it, the slot itself is still fine
+        *self
+            .pending
+            .lock()
+            .unwrap_or_else(std::sync::PoisonError::into_inner) = Some(pxtone);
+    }
+}
+
+/// Progress of a fade in/out, in frames
+struct FadeState {
+    out: bool,
+    pos: u32,
+    len: u32,
+}
+
+impl FadeState {
+    #[allow(clippy::cast_precision_loss)]
+    fn gain(&self) -> f32 {
+        if self.len == 0 {
+            return if self.out { 0.0 } else { 1.0 };
+        }
+
+        let t = self.pos as f32 / self.len as f32;
+        if self.out {
+            1.0 - t
+        } else {
+            t
+        }
+    }
+
+    /// Returns `true` once the fade is finished
+    fn advance(&mut self) -> bool {
+        self.pos = (self.pos + 1).min(self.len);
+        self.pos >= self.len
+    }
 }
 
 struct UnitData {
-    on: Option<UnitOnData>,
+    /// Pool of ringing tones, oldest first.
+    ///
+    /// The last tone is the one started by the most recent `On` event; the others are
+    /// overlapping notes or release tails that are still fading out.
+    tones: Vec<UnitOnData>,
     /// current output key value (including porta)
     key_now: i32,
     /// key at the start of the note (porta)
@@ -41,347 +185,697 @@ struct UnitData {
     velocity: ZeroToOneF32,
     woice: u8,
     tuning: TuningValue,
-    porta: u32,
-    porta_start: u32,
+    /// length of the porta in samples
+    porta_sample_num: u32,
+    /// progress through the current porta in samples
+    porta_sample_pos: u32,
     pan_volume: PanValue,
+    group: u8,
+    /// Notes that got cut off, waiting to be reported to the observer
+    cut_keys: Vec<i32>,
 }
 
-pub const DEFAULT_KEY: i32 = 24576;
+/// Maximum number of tones a single unit can have ringing at once.
+///
+/// When a new `On` event arrives with the pool full, the oldest tone is cut off.
+pub const MAX_UNIT_TONES: usize = 8;
+
+/// Key of units that haven't had a key event yet, the same as [`Key::DEFAULT`]
+#[deprecated(note = "use `Key::DEFAULT` instead")]
+pub const DEFAULT_KEY: i32 = 0x6000;
 
 #[allow(clippy::derivable_impls)]
 impl Default for UnitData {
     fn default() -> Self {
         Self {
-            on: None,
-            key_now: DEFAULT_KEY,
-            key_start: DEFAULT_KEY,
+            tones: Vec::with_capacity(MAX_UNIT_TONES),
+            key_now: *Key::DEFAULT,
+            key_start: *Key::DEFAULT,
             key_margin: 0,
             volume: ZeroToOneF32::new(104.0 / 128.0),
             velocity: ZeroToOneF32::new(104.0 / 128.0),
             woice: 0,
             tuning: TuningValue::new(1.0),
-            porta: 0,
-            porta_start: 0,
+            porta_sample_num: 0,
+            porta_sample_pos: 0,
             pan_volume: PanValue::center(),
+            group: 0,
+            cut_keys: Vec::new(),
         }
     }
 }
 
+// The key/porta handling here mirrors `pxtnUnit::Tone_*` in the original pxtone
+impl UnitData {
+    /// Start a new tone, retiring the oldest one if the pool is full.
+    fn push_tone(&mut self, tone: UnitOnData) {
+        if self.tones.len() >= MAX_UNIT_TONES {
+            let oldest = self.tones.remove(0);
+            if oldest.observed_on && !oldest.observed_off {
+                self.cut_keys.push(oldest.key);
+            }
+        }
+        self.tones.push(tone);
+    }
+
+    /// Stop every tone immediately.
+    fn cut_tones(&mut self) {
+        self.cut_keys.extend(
+            self.tones
+                .drain(..)
+                .filter(|t| t.observed_on && !t.observed_off)
+                .map(|t| t.key),
+        );
+    }
+
+    /// Mix one sample of every tone into `out`, retiring tones that have finished or whose woice
+    /// doesn't exist.
+    fn sample_tones(&mut self, woices: &[RPxToneWoice], ctx: &ToneContext, out: &mut [f32]) {
+        let cut_keys = &mut self.cut_keys;
+        self.tones.retain_mut(|tone| {
+            if let Some(woice) = woices.get(usize::from(tone.woice)) {
+                sample_tone(woice, tone, ctx, out)
+            } else {
+                if tone.observed_on && !tone.observed_off {
+                    cut_keys.push(tone.key);
+                }
+                false
+            }
+        });
+    }
+
+    /// Called on an `On` event, finishes any porta in progress.
+    fn key_on(&mut self) {
+        self.key_now = self.key_start + self.key_margin;
+        self.key_start = self.key_now;
+        self.key_margin = 0;
+    }
+
+    /// Called on a `Key` event, starts a porta from the current key towards `key`.
+    fn key(&mut self, key: i32) {
+        self.key_start = self.key_now;
+        self.key_margin = key - self.key_start;
+        self.porta_sample_pos = 0;
+    }
+
+    /// Release any held live notes at `clock` (in ticks).
+    fn release_held(&mut self, clock: u32) {
+        for tone in self.tones.iter_mut().filter(|t| t.held) {
+            tone.length = clock.saturating_sub(tone.start);
+            tone.held = false;
+        }
+    }
+
+    /// Called on a `VoiceNo` event or [`MooLive::live_voice_no`].
+    ///
+    /// Like og pxtone's `_moo_ResetVoiceOn`, this cuts off the unit's tones, release tails
+    /// included, and resets the key (but not the porta length).
+    fn set_woice(&mut self, woice: u8) {
+        self.woice = woice;
+        self.cut_tones();
+        self.key_now = *Key::DEFAULT;
+        self.key_start = *Key::DEFAULT;
+        self.key_margin = 0;
+    }
+
+    /// Advance the porta by one sample, returning the new `key_now`.
+    #[allow(clippy::cast_precision_loss)]
+    fn increment_key(&mut self) -> i32 {
+        if self.porta_sample_num > 0 && self.key_margin != 0 {
+            if self.porta_sample_pos < self.porta_sample_num {
+                self.porta_sample_pos += 1;
+                self.key_now = (self.key_start as f64
+                    + self.key_margin as f64 * self.porta_sample_pos as f64
+                        / self.porta_sample_num as f64) as i32;
+            } else {
+                self.key_now = self.key_start + self.key_margin;
+                self.key_start = self.key_now;
+                self.key_margin = 0;
+            }
+        } else {
+            self.key_now = self.key_start + self.key_margin;
+        }
+
+        self.key_now
+    }
+}
+
+/// `length` of a live note that hasn't been released yet
+const HELD_LENGTH: u32 = u32::MAX / 2;
+
 struct UnitOnData {
     start: u32,
     length: u32,
+    /// Live note that's still held down (see [`MooLive`])
+    held: bool,
+    /// Whether the observer has been told about the note starting
+    observed_on: bool,
+    /// Whether the observer has been told about the note ending
+    observed_off: bool,
+    /// Key the tone is playing at. Only the newest tone follows the unit's key (and porta),
+    /// older tones keep the key they had when they were superseded.
+    key: i32,
+    /// Woice the tone started with
+    woice: u8,
     /// Needs to be double precision to prevent artifacts
     /// TODO: see if this impacts performance
     cycle: f64,
 }
 
-impl Deref for RPxToneMoo<'_> {
+impl UnitOnData {
+    fn end(&self) -> u32 {
+        self.start + self.length
+    }
+}
+
+/// Per-sample values shared by every tone in a unit
+struct ToneContext {
+    clock_ticks: f32,
+    delta: f32,
+    ticks_per_sec: f32,
+    sample_rate: u32,
+    smooth_smps: u32,
+    volume: f32,
+    tuning: f32,
+    pan_volumes: [f32; 2],
+}
+
+impl<O> Deref for RPxToneMoo<'_, O> {
     type Target = RPxTone;
 
     fn deref(&self) -> &Self::Target {
-        self.pxtone
+        &self.pxtone
     }
 }
 
 #[derive(Debug)]
-pub enum RPxToneMooError {}
+pub enum RPxToneMooError {
+    /// Only mono and stereo can be rendered
+    UnsupportedChannels(u8),
+}
+
+impl fmt::Display for RPxToneMooError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::UnsupportedChannels(channels) => {
+                write!(f, "Can't render {channels} channels, only 1 or 2")
+            },
+        }
+    }
+}
+
+impl std::error::Error for RPxToneMooError {}
 
 impl AsMooRef for RPxTone {
     type M<'a> = RPxToneMoo<'a> where Self: 'a;
 
     fn as_moo_ref(&self) -> BoxOrMut<Self::M<'_>> {
-        BoxOrMut::Box(Box::new(RPxToneMoo {
-            pxtone: self,
+        BoxOrMut::Box(Box::new(RPxToneMoo::with_project(
+            ProjectRef::Borrowed(self),
+            (),
+        )))
+    }
+}
+
+impl RPxTone {
+    /// Like [`AsMooRef::as_moo_ref`], but calling `observer` while sampling
+    pub fn moo_with_observer<O: MooObserver>(&self, observer: O) -> RPxToneMoo<'_, O> {
+        RPxToneMoo::with_project(ProjectRef::Borrowed(self), observer)
+    }
+}
+
+impl RPxTonePlayer {
+    #[must_use]
+    pub fn new(pxtone: Arc<RPxTone>) -> Self {
+        Self::with_project(ProjectRef::Shared(pxtone), ())
+    }
+}
+
+impl<O: MooObserver> RPxTonePlayer<O> {
+    pub fn with_observer(pxtone: Arc<RPxTone>, observer: O) -> Self {
+        Self::with_project(ProjectRef::Shared(pxtone), observer)
+    }
+
+    /// Get a handle that can replace the project from another thread, see [`ProjectSwap`]
+    pub fn swap_handle(&mut self) -> ProjectSwap {
+        ProjectSwap {
+            pending: Arc::clone(self.swap.get_or_insert_with(Arc::default)),
+        }
+    }
+
+    /// Replace the project without interrupting playback.
+    ///
+    /// Playback continues from the same sample, and ringing notes keep playing.
+    pub fn set_project(&mut self, pxtone: Arc<RPxTone>) {
+        self.replace_project(ProjectRef::Shared(pxtone));
+    }
+}
+
+impl<'a, O: MooObserver> RPxToneMoo<'a, O> {
+    fn with_project(pxtone: ProjectRef<'a>, observer: O) -> Self {
+        RPxToneMoo {
+            tempo_map: pxtone.tempo_map(),
+            pxtone,
+            swap: None,
             channels: 2,
             sample_rate: 44100,
 
             smp: 0,
             last_clock: 0.0,
             last_sample_clock_secs: 0.0,
-            unit_data: HashMap::new(),
+            next_event: 0,
+            replaying: true,
+            unit_data: BTreeMap::new(),
 
             master_volume: 1.0,
-        }))
+            looping: true,
+            loop_measures: None,
+            fade: None,
+            done: false,
+
+            unit_mute: false,
+            muted_units: HashMap::new(),
+            meters: None,
+            mix: vec![0.0; 2],
+
+            observer,
+            last_beat: None,
+            cut_notes: Vec::new(),
+            wrapped: false,
+        }
     }
-}
 
-impl<'a> Moo<'a> for RPxToneMoo<'a> {
-    type Error = RPxToneMooError;
+    pub fn observer(&self) -> &O {
+        &self.observer
+    }
 
-    fn set_audio_format(&mut self, channels: u8, sample_rate: u32) -> Result<(), RPxToneMooError> {
-        self.channels = channels;
-        self.sample_rate = sample_rate;
-        Ok(())
+    pub fn observer_mut(&mut self) -> &mut O {
+        &mut self.observer
     }
 
-    fn prepare_sample(&mut self) -> Result<(), RPxToneMooError> {
-        Ok(())
+    /// Start measuring the level of every unit and group, and get a handle to read them from
+    /// another thread. Metering costs nothing until this is called.
+    pub fn meter_handle(&mut self) -> MeterHandle {
+        self.meters.get_or_insert_with(Meters::new).handle()
+    }
+
+    #[allow(clippy::cast_precision_loss)]
+    fn replace_project(&mut self, pxtone: ProjectRef<'a>) {
+        self.pxtone = pxtone;
+        self.tempo_map = self.pxtone.tempo_map();
+
+        // pick up the new project's events from where the old ones were, unless they're about to
+        // be replayed from the start anyway
+        if !self.replaying {
+            let last_clock = self.last_clock;
+            self.next_event = self
+                .pxtone
+                .event_list
+                .events
+                .partition_point(|e| e.clock() as f32 <= last_clock);
+        }
+    }
+
+    /// Swap in the project from the [`ProjectSwap`] handle, if there is one waiting
+    fn take_pending_project(&mut self) {
+        let pending = self
+            .swap
+            .as_ref()
+            .and_then(|swap| swap.try_lock().ok()?.take());
+        if let Some(pxtone) = pending {
+            self.replace_project(ProjectRef::Shared(pxtone));
+        }
+    }
+
+    /// Report everything that happened since the last sample to the observer
+    fn observe(&mut self, pos: MooPosition) {
+        if self.wrapped {
+            self.wrapped = false;
+            self.observer.loop_wrap(pos);
+        }
+
+        for (unit_no, key) in self.cut_notes.drain(..) {
+            self.observer.note_off(pos, unit_no, Key::new(key));
+        }
+
+        let MusicalTime { measure, beat, .. } = self.tempo_map.clock_to_musical(pos.clock);
+        if self.last_beat != Some((measure, beat)) {
+            self.last_beat = Some((measure, beat));
+            self.observer.beat(pos, measure, beat);
+        }
+
+        for (&unit_no, data) in &mut self.unit_data {
+            for key in data.cut_keys.drain(..) {
+                self.observer.note_off(pos, unit_no, Key::new(key));
+            }
+
+            for tone in &mut data.tones {
+                if !tone.observed_on {
+                    tone.observed_on = true;
+                    self.observer
+                        .note_on(pos, unit_no, Key::new(tone.key), data.velocity);
+                }
+
+                if !tone.observed_off && tone.end() <= pos.clock {
+                    tone.observed_off = true;
+                    self.observer.note_off(pos, unit_no, Key::new(tone.key));
+                }
+            }
+        }
+    }
+
+    /// Tell the observer that playback stopped, along with every note that was still on
+    fn observe_end(&mut self, pos: MooPosition) {
+        for (&unit_no, data) in &mut self.unit_data {
+            for tone in &mut data.tones {
+                if tone.observed_on && !tone.observed_off {
+                    tone.observed_off = true;
+                    self.observer.note_off(pos, unit_no, Key::new(tone.key));
+                }
+            }
+        }
+
+        self.observer.end(pos);
+    }
+
+    /// Clock (in ticks) of the next sample
+    fn now_ticks(&self) -> u32 {
+        self.tempo_map
+            .sample_to_clock(self.smp.into(), self.sample_rate)
+    }
+
+    fn measure_to_sample(&self, measure: i32) -> u32 {
+        let clock = self.tempo_map.measure_to_clock(measure.max(0) as u32);
+        self.tempo_map.clock_to_sample(clock, self.sample_rate) as u32
+    }
+
+    /// Measure at which playback ends (or loops)
+    fn end_measure(&self) -> i32 {
+        if let Some(measures) = &self.loop_measures {
+            return measures.end;
+        }
+
+        let last_measure = self.last_measure();
+        if last_measure > 0 {
+            last_measure
+        } else {
+            self.num_measures()
+        }
+    }
+
+    /// Sample at which playback ends (or loops)
+    fn end_sample(&self) -> u32 {
+        self.measure_to_sample(self.end_measure())
+    }
+
+    /// Sample that playback jumps back to when looping
+    fn repeat_sample(&self) -> u32 {
+        self.measure_to_sample(
+            self.loop_measures
+                .as_ref()
+                .map_or_else(|| self.repeat_measure(), |m| m.start),
+        )
+    }
+
+    /// Restart from `smp`, replaying every event before it (like `_moo_InitUnitTone` in og pxtone)
+    #[allow(clippy::cast_precision_loss)]
+    fn jump_to_sample(&mut self, smp: u32) {
+        self.smp = smp;
+        self.last_sample_clock_secs = (smp as f32 - 1.0) / self.sample_rate as f32;
+        self.next_event = 0;
+        self.replaying = true;
+        self.last_beat = None;
+
+        for (unit_no, data) in &mut self.unit_data {
+            data.cut_tones();
+            self.cut_notes
+                .extend(data.cut_keys.drain(..).map(|key| (*unit_no, key)));
+        }
+        self.unit_data.clear();
     }
 
+    /// Shared by [`Moo::sample`] and [`Moo::sample_f32`]
     #[allow(clippy::cast_precision_loss)]
     #[allow(clippy::unreadable_literal)]
     #[allow(clippy::too_many_lines)]
-    fn sample(&mut self, buffer: &mut [i16]) -> Result<(), RPxToneMooError> {
+    fn render<S: MooSample>(&mut self, buffer: &mut [S]) {
         profiling::scope!("sample");
-        // println!("buf {}", buffer.len());
-        let evs = self.pxtone.event_list.events.iter().collect::<Vec<_>>();
+        self.take_pending_project();
+
+        // keep the project alive separately so `self` can be borrowed mutably while sampling
+        let pxtone = self.pxtone.clone();
+        let evs = &pxtone.event_list.events;
+        let mut mix = std::mem::take(&mut self.mix);
 
         let smooth_smps = (self.sample_rate as f32 / 250.0) as u32;
-        // println!("evs {}", evs.len());
 
-        let ticks_per_sec = (self.pxtone.beat_clock() as f32 * self.pxtone.beat_tempo()) / 60.0;
+        let end_sample = self.end_sample();
+        let repeat_sample = self.repeat_sample();
+
+        for (frame, bsmp) in buffer.chunks_mut(self.channels as _).enumerate() {
+            profiling::scope!("one sample");
+            if self.done {
+                bsmp.fill(S::SILENCE);
+                continue;
+            }
 
-        let mut skip = 0;
-        // only check events every 100 samples, minor performance boost
-        for ch in buffer.chunks_mut(100) {
-            profiling::scope!("chunk");
             let clock_secs = self.smp as f32 / self.sample_rate as f32;
-            let clock_ticks = clock_secs * ticks_per_sec;
+            let delta = clock_secs - self.last_sample_clock_secs;
+            let clock_ticks = self.tempo_map.secs_to_ticks(clock_secs.into()) as f32;
+            let ticks_per_sec = self.tempo_map.tempo_at(clock_ticks as u32).ticks_per_sec() as f32;
+            let samples_per_tick = self.sample_rate as f32 / ticks_per_sec;
+            let pos = MooPosition { frame, sample: self.smp, clock: clock_ticks as u32 };
+
             {
                 profiling::scope!("events");
-                for (i, e) in evs.iter().enumerate().skip(skip) {
-                    // if e.unit_no() != 0 { continue; }
-
-                    let e_clock = e.clock() as f32;
-
-                    if e_clock < self.last_clock {
-                        continue;
-                    }
-                    if e_clock > clock_ticks {
-                        skip = i - 1;
+                while let Some(e) = evs.get(self.next_event) {
+                    if e.clock() as f32 > clock_ticks {
                         break;
                     }
+                    self.next_event += 1;
+
+                    let data = self.unit_data.entry(e.unit_no()).or_default();
 
                     match e.kind() {
                         GenericEventKind::On(on) => {
-                            let data = self.unit_data.entry(e.unit_no()).or_default();
-                            data.key_now = data.key_start + data.key_margin;
-                            data.key_start = data.key_now;
-                            data.key_margin = 0;
-                            data.on = Some(UnitOnData {
+                            // notes that are already over (eg. skipped by a loop) cut the unit off
+                            if (on.clock() + on.length()) as f32 <= clock_ticks {
+                                data.cut_tones();
+                                continue;
+                            }
+
+                            data.key_on();
+                            let key = data.key_now;
+                            data.push_tone(UnitOnData {
                                 start: on.clock(),
                                 length: on.length(),
+                                held: false,
+                                observed_on: false,
+                                observed_off: false,
+                                key,
+                                woice: data.woice,
                                 cycle: 0.0,
                             });
                         },
                         GenericEventKind::Key(key) => {
-                            let key_v = key.key();
-
-                            let data = self.unit_data.entry(e.unit_no()).or_default();
-
-                            data.key_start = data.key_now;
-                            data.key_margin = key_v - data.key_start;
-                            data.porta_start = e.clock();
+                            data.key(*key.key());
                         },
                         GenericEventKind::Velocity(vel) => {
-                            self.unit_data.entry(e.unit_no()).or_default().velocity =
-                                vel.velocity();
+                            data.velocity = vel.velocity();
                         },
                         GenericEventKind::Volume(vol) => {
-                            self.unit_data.entry(e.unit_no()).or_default().volume = vol.volume();
+                            data.volume = vol.volume();
                         },
                         GenericEventKind::VoiceNo(voice) => {
-                            // TODO: I think voice no is supposed to reset porta
-                            self.unit_data.entry(e.unit_no()).or_default().woice = voice.voice_no();
+                            data.set_woice(voice.voice_no());
                         },
                         GenericEventKind::Tuning(tuning) => {
-                            self.unit_data.entry(e.unit_no()).or_default().tuning = tuning.tuning();
+                            data.tuning = tuning.tuning();
                         },
                         GenericEventKind::Porta(porta) => {
-                            self.unit_data.entry(e.unit_no()).or_default().porta = porta.porta();
+                            data.porta_sample_num = (porta.porta() as f32 * samples_per_tick) as _;
                         },
                         GenericEventKind::PanVolume(pan_volume) => {
-                            self.unit_data.entry(e.unit_no()).or_default().pan_volume =
-                                pan_volume.pan_volume();
+                            data.pan_volume = pan_volume.pan_volume();
+                        },
+                        GenericEventKind::GroupNo(group_no) => {
+                            data.group = group_no.group_no();
                         },
                         _ => {},
                     }
                 }
+                self.replaying = false;
             }
 
-            {
-                profiling::scope!("sample chunk");
-                for bsmp in ch.chunks_mut(self.channels as _) {
-                    profiling::scope!("one sample");
-                    let clock_secs = self.smp as f32 / self.sample_rate as f32;
-                    let delta = clock_secs - self.last_sample_clock_secs;
-                    let clock_ticks = clock_secs * ticks_per_sec;
-
-                    // println!("skip {skip}");
-                    let mut v: Box<[f32]> = (0..bsmp.len()).map(|_| 0.0).collect();
-
-                    #[allow(clippy::for_kv_map)]
-                    for (_unit, data) in &mut self.unit_data {
-                        if let Some(on) = &mut data.on {
-
-                            // let on_ticks = clock_ticks - on.start as f32;
-                            // let on_secs = on_ticks / ticks_per_sec;
-
-                            // porta
-                            if data.porta > 0 && data.key_margin != 0 {
-                                let thru =
-                                    (clock_ticks - data.porta_start as f32) / data.porta as f32;
-                                let thru = thru.clamp(0.0, 1.0);
-                                data.key_now =
-                                    (data.key_start as f32 + data.key_margin as f32 * thru) as _;
-                            } else {
-                                data.key_now = data.key_start + data.key_margin;
-                            }
+            if O::ENABLED {
+                self.observe(pos);
+            }
 
-                            // TODO: make this not witchcraft
-                            // 16.3515 is C0 in Hz
-                            // 13056 is the "note unit" for C0
-                            // 256 "note units" per real semitone
-                            // 1.05946^x == 2^(x/12)
-                            // 1.05946 == 2^(1/12)
-                            #[allow(clippy::excessive_precision)]
-                            let key_freq = 16.3515
-                                * (1.0594630943592953_f32)
-                                    .powf((data.key_now as f32 - 13056.0) / 256.0);
-
-                            on.cycle += (delta * key_freq * *data.tuning) as f64;
-                            // on.cycle = (on_secs * key_freq * *data.tuning) as f64;
-                            let cycle = on.cycle as f32;
-
-                            // println!("{delta} {key_freq} {} {} {}", *data.tuning, delta * key_freq * *data.tuning, data.cycle);
-
-                            let woice = &self.pxtone.woices.get(data.woice as usize);
-
-                            if let Some(woice) = woice {
-                                let pan_volumes = if self.channels == 2 {
-                                    [
-                                        (1.0 - *data.pan_volume).clamp(0.0, 1.0),
-                                        (*data.pan_volume + 1.0).clamp(0.0, 1.0),
-                                    ]
-                                } else {
-                                    [1.0, 1.0]
-                                };
-
-                                #[allow(clippy::single_match)]
-                                match woice.woice_type() {
-                                    WoiceType::PCM(pcm) => {
-
-                                        if clock_ticks > (on.start + on.length) as f32 {
-                                            data.on = None;
-                                            continue;
-                                        }
-
-                                        for (ch, v) in v.iter_mut().enumerate() {
-                                            let mut val = pcm.voice.sample(cycle, ch as _);
-
-                                            if pcm.voice.flag_smooth
-                                                && cycle * 44100.0 < smooth_smps as f32
-                                            {
-                                                val *= (cycle * 44100.0) / smooth_smps as f32;
-                                            }
-
-                                            *v += val
-                                                * *data.volume
-                                                * *data.velocity
-                                                * pan_volumes[ch]
-                                                * i16::MAX as f32;
-                                        }
-                                    },
-                                    WoiceType::OGGV(oggv) => {
-
-                                        if clock_ticks > (on.start + on.length) as f32 {
-                                            data.on = None;
-                                            continue;
-                                        }
-
-                                        for (ch, v) in v.iter_mut().enumerate() {
-                                            let mut val = oggv.voice.sample(cycle, ch as _);
-
-                                            if oggv.voice.flag_smooth
-                                                && cycle * 44100.0 < smooth_smps as f32
-                                            {
-                                                val *= (cycle * 44100.0) / smooth_smps as f32;
-                                            }
-
-                                            *v += val
-                                                * *data.volume
-                                                * *data.velocity
-                                                * pan_volumes[ch]
-                                                * i16::MAX as f32;
-                                        }
-                                    },
-                                    WoiceType::PTV(ptv) => {
-
-                                        let max_env_release_samples = ptv.voices.iter().map(|v| {
-                                            if v.envelope.tail_num > 0 {
-                                                v.envelope.points[v.envelope.head_num as usize].x * self.sample_rate / v.envelope.fps
-                                            } else {
-                                                0
-                                            }
-                                        }).max().unwrap_or(0);
-
-                                        // samples / samples/sec * ticks/seconds = ticks
-                                        let max_env_release_ticks = (max_env_release_samples as f32 / self.sample_rate as f32 * ticks_per_sec) as u32;
-
-                                        if clock_ticks > (on.start + on.length + max_env_release_ticks) as f32 {
-                                            data.on = None;
-                                            continue;
-                                        }
-
-                                        for voice in &ptv.voices {
-
-                                            let env_release_samples = if voice.envelope.tail_num > 0 {
-                                                voice.envelope.points[voice.envelope.head_num as usize].x * self.sample_rate / voice.envelope.fps
-                                            } else {
-                                                0
-                                            };
-
-                                            // samples / samples/sec * ticks/seconds = ticks
-                                            let env_release_ticks = env_release_samples as f32 / self.sample_rate as f32 * ticks_per_sec;
-
-                                            for (ch, v) in v.iter_mut().enumerate() {
-                                                let mut val = voice.sample(cycle, ch as _);
-
-                                                let flag_smooth = true;
-                                                if flag_smooth
-                                                    && cycle * 44100.0 < smooth_smps as f32
-                                                {
-                                                    val *= (cycle * 44100.0) / smooth_smps as f32;
-                                                }
-
-                                                if clock_ticks > (on.start + on.length) as f32 {
-                                                    val *= (1.0 - (clock_ticks - (on.start + on.length) as f32) / env_release_ticks).clamp(0.0, 1.0);
-                                                }
-
-                                                *v += val
-                                                    * *data.volume
-                                                    * *data.velocity
-                                                    * pan_volumes[ch]
-                                                    * i16::MAX as f32;
-                                            }
-                                        }
-                                    },
-                                    _ => {},
-                                };
-                            }
-                        }
-                    }
+            let v = &mut mix[..bsmp.len()];
+            v.fill(0.0);
 
-                    for (ch, v) in v.iter().enumerate() {
-                        bsmp[ch] = (v / 2.0 * self.master_volume)
-                            .clamp(i16::MIN as f32, i16::MAX as f32)
-                            as _;
+            for (unit, data) in &mut self.unit_data {
+                let muted = self.unit_mute
+                    && self.muted_units.get(unit).copied().unwrap_or_else(|| {
+                        pxtone.units.get(*unit as usize).is_some_and(|u| u.muted)
+                    });
+
+                // porta keeps moving even while nothing is playing
+                let key_now = data.increment_key();
+
+                if data.tones.is_empty() {
+                    continue;
+                }
+
+                // only the most recent note follows key changes
+                if let Some(newest) = data.tones.last_mut() {
+                    newest.key = key_now;
+                }
+
+                let ctx = ToneContext {
+                    clock_ticks,
+                    delta,
+                    ticks_per_sec,
+                    sample_rate: self.sample_rate,
+                    smooth_smps,
+                    // muted units keep playing, just silently
+                    volume: if muted {
+                        0.0
+                    } else {
+                        *data.volume * *data.velocity
+                    },
+                    tuning: *data.tuning,
+                    pan_volumes: if self.channels == 2 {
+                        [
+                            (1.0 - *data.pan_volume).clamp(0.0, 1.0),
+                            (*data.pan_volume + 1.0).clamp(0.0, 1.0),
+                        ]
+                    } else {
+                        [1.0, 1.0]
+                    },
+                };
+
+                if let Some(meters) = &mut self.meters {
+                    // render the unit on its own so it can be measured
+                    let mut unit_v = [0.0; 2];
+                    let unit_v = &mut unit_v[..v.len()];
+                    data.sample_tones(&pxtone.woices, &ctx, unit_v);
+                    for (v, u) in v.iter_mut().zip(unit_v.iter()) {
+                        *v += u;
                     }
-                    self.smp += 1;
-                    self.last_sample_clock_secs = clock_secs;
+                    meters.add_unit(*unit, data.group, unit_v);
+                } else {
+                    data.sample_tones(&pxtone.woices, &ctx, v);
                 }
             }
 
+            if let Some(meters) = &mut self.meters {
+                meters.end_frame(v.len());
+            }
+
+            let gain = self.master_volume * self.fade.as_ref().map_or(1.0, FadeState::gain);
+            for (ch, v) in v.iter().enumerate() {
+                bsmp[ch] = S::from_mix(v / 2.0 * gain);
+            }
+            self.smp += 1;
+            self.last_sample_clock_secs = clock_secs;
             self.last_clock = clock_ticks;
+
+            if let Some(fade) = &mut self.fade {
+                if fade.advance() {
+                    if fade.out {
+                        self.done = true;
+                    }
+                    self.fade = None;
+                }
+            }
+
+            if self.smp >= end_sample {
+                if self.looping {
+                    self.jump_to_sample(repeat_sample);
+                    self.wrapped = true;
+                } else {
+                    self.done = true;
+                }
+            }
+
+            if O::ENABLED && self.done {
+                self.observe_end(pos);
+            }
         }
 
+        if let Some(meters) = &mut self.meters {
+            meters.publish(buffer.len(), pxtone.units.len());
+        }
+
+        self.mix = mix;
         profiling::finish_frame!();
-        // println!("done");
+    }
+}
+
+/// Sample formats [`RPxToneMoo`] can render to
+trait MooSample: Copy {
+    const SILENCE: Self;
+
+    /// Convert from the mixer's level, where the range of an `i16` is full scale
+    fn from_mix(v: f32) -> Self;
+}
+
+impl MooSample for i16 {
+    const SILENCE: Self = 0;
+
+    #[allow(clippy::cast_possible_truncation)]
+    fn from_mix(v: f32) -> Self {
+        v.clamp(f32::from(i16::MIN), f32::from(i16::MAX)) as _
+    }
+}
+
+impl MooSample for f32 {
+    const SILENCE: Self = 0.0;
+
+    fn from_mix(v: f32) -> Self {
+        v / 32768.0
+    }
+}
+
+impl<'a, O: MooObserver> Moo<'a> for RPxToneMoo<'a, O> {
+    type Error = RPxToneMooError;
+
+    fn set_audio_format(&mut self, channels: u8, sample_rate: u32) -> Result<(), RPxToneMooError> {
+        if !(1..=2).contains(&channels) {
+            return Err(RPxToneMooError::UnsupportedChannels(channels));
+        }
+
+        self.channels = channels;
+        self.sample_rate = sample_rate;
+        self.mix.resize(channels.into(), 0.0);
+        Ok(())
+    }
+
+    fn prepare_sample(&mut self) -> Result<(), RPxToneMooError> {
+        self.jump_to_sample(0);
+        self.last_clock = 0.0;
+        // og pxtone always prepares with looping on and unit muting off
+        self.looping = true;
+        self.loop_measures = None;
+        self.unit_mute = false;
+        self.fade = None;
+        self.done = false;
+        Ok(())
+    }
+
+    fn sample(&mut self, buffer: &mut [i16]) -> Result<(), RPxToneMooError> {
+        self.render(buffer);
+        Ok(())
+    }
+
+    fn sample_f32(&mut self, buffer: &mut [f32]) -> Result<(), RPxToneMooError> {
+        self.render(buffer);
         Ok(())
     }
 
     fn is_done_sampling(&self) -> bool {
-        todo!()
+        self.done
     }
 
     fn now_clock(&self) -> u32 {
@@ -389,38 +883,63 @@ impl<'a> Moo<'a> for RPxToneMoo<'a> {
     }
 
     fn end_clock(&self) -> u32 {
-        todo!()
+        self.tempo_map
+            .measure_to_clock(self.end_measure().max(0) as u32)
     }
 
-    fn set_unit_mute_enabled(&mut self, _unit_mute: bool) -> Result<(), RPxToneMooError> {
-        todo!()
+    fn set_unit_mute_enabled(&mut self, unit_mute: bool) -> Result<(), RPxToneMooError> {
+        self.unit_mute = unit_mute;
+        Ok(())
     }
 
-    fn set_loop(&mut self, _should_loop: bool) -> Result<(), RPxToneMooError> {
-        todo!()
+    fn set_unit_muted(&mut self, unit_no: u8, muted: bool) -> Result<(), RPxToneMooError> {
+        self.muted_units.insert(unit_no, muted);
+        Ok(())
     }
 
-    fn set_fade(
-        &mut self,
-        _fade: Option<crate::interface::moo::Fade>,
-        _duration: std::time::Duration,
-    ) -> Result<(), RPxToneMooError> {
-        todo!()
+    fn set_loop(&mut self, should_loop: bool) -> Result<(), RPxToneMooError> {
+        self.looping = should_loop;
+        Ok(())
+    }
+
+    fn set_loop_measures(&mut self, measures: Option<Range<i32>>) -> Result<(), RPxToneMooError> {
+        self.loop_measures = measures;
+        Ok(())
+    }
+
+    fn jump_to_measure(&mut self, measure: i32) -> Result<(), RPxToneMooError> {
+        self.jump_to_sample(self.measure_to_sample(measure));
+        self.done = false;
+        Ok(())
+    }
+
+    fn set_fade(&mut self, fade: Option<Fade>, duration: Duration) -> Result<(), RPxToneMooError> {
+        let len = (duration.as_secs_f64() * f64::from(self.sample_rate)) as u32;
+        self.fade = fade.map(|fade| FadeState { out: matches!(fade, Fade::Out), pos: 0, len });
+        Ok(())
     }
 
     fn sampling_offset(&self) -> u32 {
-        todo!()
+        if self.done {
+            0
+        } else {
+            self.smp
+        }
     }
 
     fn sampling_end(&self) -> u32 {
-        todo!()
+        if self.done {
+            0
+        } else {
+            self.end_sample()
+        }
     }
 
-    #[allow(clippy::cast_precision_loss)]
     fn total_samples(&self) -> u32 {
-        let total_beats = (self.beat_num() * self.num_measures()) as u32;
-        println!("{} {} {}", self.sample_rate, total_beats, self.beat_tempo());
-        (self.sample_rate as f32 * 60.0 * total_beats as f32 / self.beat_tempo()) as u32
+        let clock = self
+            .tempo_map
+            .measure_to_clock(self.num_measures().max(0) as u32);
+        self.tempo_map.clock_to_sample(clock, self.sample_rate) as u32
     }
 
     fn set_master_volume(&mut self, volume: f32) -> Result<(), RPxToneMooError> {
@@ -429,3 +948,647 @@ impl<'a> Moo<'a> for RPxToneMoo<'a> {
         Ok(())
     }
 }
+
+impl<'a, O: MooObserver> MooLive<'a> for RPxToneMoo<'a, O> {
+    fn live_note_on(
+        &mut self,
+        unit_no: u8,
+        key: Key,
+        velocity: ZeroToOneF32,
+        pan_volume: PanValue,
+    ) -> Result<(), RPxToneMooError> {
+        let now = self.now_ticks();
+        let data = self.unit_data.entry(unit_no).or_default();
+
+        data.release_held(now);
+        data.key(*key);
+        data.key_on();
+        data.velocity = velocity;
+        data.pan_volume = pan_volume;
+
+        let key = data.key_now;
+        data.push_tone(UnitOnData {
+            start: now,
+            length: HELD_LENGTH,
+            held: true,
+            observed_on: false,
+            observed_off: false,
+            key,
+            woice: data.woice,
+            cycle: 0.0,
+        });
+        Ok(())
+    }
+
+    fn live_note_off(&mut self, unit_no: u8) -> Result<(), RPxToneMooError> {
+        let now = self.now_ticks();
+        if let Some(data) = self.unit_data.get_mut(&unit_no) {
+            data.release_held(now);
+        }
+        Ok(())
+    }
+
+    fn live_key(&mut self, unit_no: u8, key: Key) -> Result<(), RPxToneMooError> {
+        self.unit_data.entry(unit_no).or_default().key(*key);
+        Ok(())
+    }
+
+    fn live_velocity(
+        &mut self,
+        unit_no: u8,
+        velocity: ZeroToOneF32,
+    ) -> Result<(), RPxToneMooError> {
+        self.unit_data.entry(unit_no).or_default().velocity = velocity;
+        Ok(())
+    }
+
+    fn live_pan_volume(
+        &mut self,
+        unit_no: u8,
+        pan_volume: PanValue,
+    ) -> Result<(), RPxToneMooError> {
+        self.unit_data.entry(unit_no).or_default().pan_volume = pan_volume;
+        Ok(())
+    }
+
+    fn live_voice_no(&mut self, unit_no: u8, voice_no: u8) -> Result<(), RPxToneMooError> {
+        self.unit_data
+            .entry(unit_no)
+            .or_default()
+            .set_woice(voice_no);
+        Ok(())
+    }
+}
+
+/// Render a single note of `woice` on its own, as interleaved 16 bit samples
+///
+/// The note is `length` frames long, followed by any envelope release. It's played like a unit
+/// at full volume with no effects would, except twice as loud so that PCM woices on their own key
+/// come out at the level they were recorded at.
+#[allow(clippy::cast_precision_loss)]
+pub(crate) fn render_note(
+    woice: &RPxToneWoice,
+    key: i32,
+    length: u32,
+    channels: u8,
+    sample_rate: u32,
+) -> Vec<i16> {
+    // one tick per frame
+    let mut tone = UnitOnData {
+        start: 0,
+        length,
+        held: false,
+        observed_on: true,
+        observed_off: true,
+        key,
+        woice: 0,
+        cycle: 0.0,
+    };
+    let mut ctx = ToneContext {
+        clock_ticks: 0.0,
+        delta: 0.0,
+        ticks_per_sec: sample_rate as f32,
+        sample_rate,
+        smooth_smps: (sample_rate as f32 / 250.0) as u32,
+        volume: 2.0,
+        tuning: 1.0,
+        pan_volumes: [1.0, 1.0],
+    };
+
+    let mut out = Vec::new();
+    let mut frame = vec![0.0; usize::from(channels)];
+    for smp in 0_u32.. {
+        ctx.clock_ticks = smp as f32;
+        frame.fill(0.0);
+        if !sample_tone(woice, &mut tone, &ctx, &mut frame) {
+            break;
+        }
+        out.extend(
+            frame
+                .iter()
+                .map(|v| v.clamp(i16::MIN as f32, i16::MAX as f32) as i16),
+        );
+        ctx.delta = 1.0 / sample_rate as f32;
+    }
+    out
+}
+
+/// Mix one sample of `tone` into `out`.
+///
+/// Returns `false` once the tone has finished (including any envelope release) and should be retired.
+#[allow(clippy::cast_precision_loss)]
+#[allow(clippy::unreadable_literal)]
+fn sample_tone(
+    woice: &RPxToneWoice,
+    tone: &mut UnitOnData,
+    ctx: &ToneContext,
+    out: &mut [f32],
+) -> bool {
+    let key_freq = Key::new(tone.key).to_hz();
+
+    tone.cycle += (ctx.delta * key_freq * ctx.tuning) as f64;
+    let cycle = tone.cycle as f32;
+
+    let smooth = |val: f32| {
+        if cycle * 44100.0 < ctx.smooth_smps as f32 {
+            val * (cycle * 44100.0) / ctx.smooth_smps as f32
+        } else {
+            val
+        }
+    };
+
+    match &woice.woice_type {
+        RPxToneWoiceType::PCM(pcm) => sample_voice(&pcm.voice, tone, cycle, ctx, &smooth, out),
+        RPxToneWoiceType::PTN(ptn) => sample_voice(&ptn.voice, tone, cycle, ctx, &smooth, out),
+        RPxToneWoiceType::OGGV(oggv) => sample_voice(&oggv.voice, tone, cycle, ctx, &smooth, out),
+        RPxToneWoiceType::PTV(ptv) => {
+            // samples / samples/sec * ticks/seconds = ticks
+            let release_ticks = |voice: &RPxToneVoicePTV| {
+                let env_release_samples = if voice.envelope.tail_num > 0 {
+                    voice.envelope.points[voice.envelope.head_num as usize].x * ctx.sample_rate
+                        / voice.envelope.fps
+                } else {
+                    0
+                };
+
+                env_release_samples as f32 / ctx.sample_rate as f32 * ctx.ticks_per_sec
+            };
+
+            let max_env_release_ticks = ptv
+                .voices
+                .iter()
+                .map(|v| release_ticks(v) as u32)
+                .max()
+                .unwrap_or(0);
+
+            if ctx.clock_ticks > (tone.end() + max_env_release_ticks) as f32 {
+                return false;
+            }
+
+            for voice in &ptv.voices {
+                let env_release_ticks = release_ticks(voice);
+
+                for (ch, v) in out.iter_mut().enumerate() {
+                    let mut val = smooth(voice.sample(cycle, ch as _));
+
+                    if ctx.clock_ticks > tone.end() as f32 {
+                        val *= (1.0 - (ctx.clock_ticks - tone.end() as f32) / env_release_ticks)
+                            .clamp(0.0, 1.0);
+                    }
+
+                    *v += val * ctx.volume * ctx.pan_volumes[ch] * i16::MAX as f32;
+                }
+            }
+
+            true
+        },
+    }
+}
+
+/// [`sample_tone`] for the woice types that play their sample as is, which is all but PTV.
+///
+/// These have no envelope, so like in og pxtone there's no release and the tone stops right at
+/// the end of its note.
+#[allow(clippy::cast_precision_loss)]
+fn sample_voice<V: VoicePCM>(
+    voice: &V,
+    tone: &UnitOnData,
+    cycle: f32,
+    ctx: &ToneContext,
+    smooth: &impl Fn(f32) -> f32,
+    out: &mut [f32],
+) -> bool {
+    if ctx.clock_ticks > tone.end() as f32 {
+        return false;
+    }
+
+    for (ch, v) in out.iter_mut().enumerate() {
+        let mut val = voice.sample(cycle, ch as _);
+
+        if voice.flag_smooth() {
+            val = smooth(val);
+        }
+
+        *v += val * ctx.volume * ctx.pan_volumes[ch] * i16::MAX as f32;
+    }
+
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use crate::{
+        interface::{
+            event::{EventKind, EventListMut, HasEventList, Key, PanValue},
+            event_impl::EventImpl,
+            io::PxToneServiceIO,
+            moo::{AsMooRef, Moo, MooLive},
+            service::PxTone,
+            woice::{HasWoices, WoicesMut},
+        },
+        rust_impl::{
+            observer::{MooObserver, MooPosition},
+            service::RPxTone,
+        },
+        time::HasTempoMap,
+        util::ZeroToOneF32,
+    };
+
+    use super::{RPxToneMoo, RPxToneMooError, RPxTonePlayer};
+
+    // 120bpm at 480 ticks per beat is 960 ticks/sec, so at 44100hz one tick is 45.9375 samples
+
+    fn project(events: &[EventImpl]) -> RPxTone {
+        let mut pxtone = RPxTone::new();
+        for e in events {
+            pxtone.event_list_mut().add(e).unwrap();
+        }
+        pxtone
+    }
+
+    /// Like [`project`], but with the sample project's woices to play
+    fn sample_project(events: &[EventImpl]) -> RPxTone {
+        let mut pxtone = RPxTone::new();
+        pxtone
+            .read_bytes(include_bytes!("../../../examples/sample.ptcop"))
+            .unwrap();
+        pxtone.event_list.events.clear();
+        pxtone.set_beat_tempo(120.0);
+        pxtone.set_beat_clock(480);
+        for e in events {
+            pxtone.event_list_mut().add(e).unwrap();
+        }
+        pxtone
+    }
+
+    fn render(moo: &mut RPxToneMoo, frames: usize) {
+        let mut buf = vec![0; frames * 2];
+        moo.sample(&mut buf).unwrap();
+    }
+
+    #[test]
+    fn porta_slides_between_keys() {
+        let target = *Key::DEFAULT + 12 * Key::SEMITONE;
+        let pxtone = project(&[
+            EventImpl::porta(0, 0, 480),
+            EventImpl::on(0, 0, 3840),
+            EventImpl::key(960, 0, Key::new(target)),
+        ]);
+        let mut moo = pxtone.as_moo_ref();
+
+        // the key event lands on sample 44100, nothing moves before it
+        render(&mut moo, 44100);
+        assert_eq!(moo.unit_data[&0].key_now, *Key::DEFAULT);
+
+        // 480 ticks of porta is 22050 samples, so this is halfway
+        render(&mut moo, 11025);
+        let data = &moo.unit_data[&0];
+        assert_eq!(data.key_now, *Key::DEFAULT + 6 * Key::SEMITONE);
+        assert_eq!(data.key_start, *Key::DEFAULT);
+        assert_eq!(data.key_margin, 12 * Key::SEMITONE);
+
+        // once the porta is over the key is committed
+        render(&mut moo, 11026);
+        let data = &moo.unit_data[&0];
+        assert_eq!(data.key_now, target);
+        assert_eq!(data.key_start, target);
+        assert_eq!(data.key_margin, 0);
+    }
+
+    #[test]
+    fn porta_restarts_from_current_key() {
+        let pxtone = project(&[
+            EventImpl::porta(0, 0, 480),
+            EventImpl::on(0, 0, 3840),
+            EventImpl::key(0, 0, Key::new(*Key::DEFAULT + 12 * Key::SEMITONE)),
+            EventImpl::key(240, 0, Key::DEFAULT),
+        ]);
+        let mut moo = pxtone.as_moo_ref();
+
+        // the second key arrives halfway up the first slide
+        render(&mut moo, 11025 + 1);
+        let data = &moo.unit_data[&0];
+        assert_eq!(data.key_start, *Key::DEFAULT + 6 * Key::SEMITONE);
+        assert_eq!(data.key_margin, -6 * Key::SEMITONE);
+
+        render(&mut moo, 22050);
+        let data = &moo.unit_data[&0];
+        assert_eq!(data.key_now, *Key::DEFAULT);
+        assert_eq!(data.key_margin, 0);
+    }
+
+    #[test]
+    fn key_without_porta_is_immediate() {
+        let target = *Key::DEFAULT - 5 * Key::SEMITONE;
+        let pxtone = project(&[
+            EventImpl::on(0, 0, 480),
+            EventImpl::key(0, 0, Key::new(target)),
+        ]);
+        let mut moo = pxtone.as_moo_ref();
+
+        render(&mut moo, 1);
+        assert_eq!(moo.unit_data[&0].key_now, target);
+    }
+
+    #[test]
+    fn on_finishes_porta() {
+        let target = *Key::DEFAULT + 7 * Key::SEMITONE;
+        let pxtone = project(&[
+            EventImpl::porta(0, 0, 960),
+            EventImpl::key(0, 0, Key::new(target)),
+            EventImpl::on(480, 0, 480),
+        ]);
+        let mut moo = pxtone.as_moo_ref();
+
+        render(&mut moo, 22050 + 1);
+        let data = &moo.unit_data[&0];
+        assert_eq!(data.key_now, target);
+        assert_eq!(data.key_start, target);
+        assert_eq!(data.key_margin, 0);
+    }
+
+    #[test]
+    fn voice_no_resets_key() {
+        let pxtone = sample_project(&[
+            EventImpl::porta(0, 0, 480),
+            EventImpl::key(0, 0, Key::new(*Key::DEFAULT + 12 * Key::SEMITONE)),
+            EventImpl::on(0, 0, 3840),
+            EventImpl::voice_no(240, 0, 1),
+        ]);
+        let mut moo = pxtone.as_moo_ref();
+
+        render(&mut moo, 11025 + 1);
+        let data = &moo.unit_data[&0];
+        assert_eq!(data.woice, 1);
+        assert_eq!(data.key_now, *Key::DEFAULT);
+        assert_eq!(data.key_start, *Key::DEFAULT);
+        assert_eq!(data.key_margin, 0);
+        // the note that was playing is cut off
+        assert!(data.tones.is_empty());
+        // porta length is a unit setting and survives the voice change
+        assert_eq!(data.porta_sample_num, 22050);
+    }
+
+    #[test]
+    fn voice_no_cuts_release_tails() {
+        // a PTV note whose release is still ringing when the unit switches to a PCM woice
+        let mut pxtone = sample_project(&[
+            EventImpl::voice_no(0, 0, 8),
+            EventImpl::on(0, 0, 480),
+            EventImpl::voice_no(480, 0, 0),
+            EventImpl::on(480, 0, 480),
+        ]);
+        pxtone
+            .woices_mut()
+            .add_ptv_from_file("examples/sample.ptvoice")
+            .unwrap();
+
+        let mut moo = pxtone.as_moo_ref();
+        render(&mut moo, 22050 + 100);
+        let woices: Vec<_> = moo.unit_data[&0].tones.iter().map(|t| t.woice).collect();
+        assert_eq!(woices, [0]);
+    }
+
+    #[test]
+    fn rejects_unsupported_channels() {
+        let pxtone = project(&[]);
+        let mut moo = pxtone.as_moo_ref();
+        for channels in [0, 3] {
+            assert!(matches!(
+                moo.set_audio_format(channels, 44100),
+                Err(RPxToneMooError::UnsupportedChannels(c)) if c == channels
+            ));
+        }
+        moo.set_audio_format(1, 44100).unwrap();
+    }
+
+    #[test]
+    fn follows_tempo_events() {
+        let target = *Key::DEFAULT + Key::SEMITONE;
+        let mut pxtone = project(&[EventImpl::key(2880, 0, Key::new(target))]);
+        pxtone.set_num_measures(4);
+        // double speed from the second measure
+        pxtone
+            .event_list
+            .push_raw(1920, 0, EventKind::BeatTempo, 240.0_f32.to_bits() as i32);
+        assert!((pxtone.tempo_map().clock_to_secs(2880) - 2.5).abs() < 1e-9);
+
+        // two seconds for the first measure, then half a second for two beats
+        let mut moo = pxtone.as_moo_ref();
+        render(&mut moo, 110_240);
+        assert_eq!(moo.unit_data[&0].key_now, *Key::DEFAULT);
+        render(&mut moo, 20);
+        assert_eq!(moo.unit_data[&0].key_now, target);
+        assert_eq!(moo.end_clock(), 4 * 1920);
+        assert_eq!(moo.total_samples(), 2 * 44100 + 3 * 44100);
+    }
+
+    #[test]
+    #[allow(deprecated)]
+    fn default_key_alias_matches() {
+        assert_eq!(super::DEFAULT_KEY, *Key::DEFAULT);
+    }
+
+    #[test]
+    fn player_is_send_and_static() {
+        fn assert_send<T: Send + 'static>() {}
+        assert_send::<RPxTonePlayer>();
+    }
+
+    #[test]
+    fn swapped_project_continues_from_same_clock() {
+        let mut player = RPxTonePlayer::new(Arc::new(project(&[EventImpl::key(
+            0,
+            0,
+            Key::new(*Key::DEFAULT + Key::SEMITONE),
+        )])));
+        let swap = player.swap_handle();
+
+        render(&mut player, 100);
+        swap.swap(Arc::new(project(&[
+            EventImpl::key(0, 0, Key::new(*Key::DEFAULT + 2 * Key::SEMITONE)),
+            EventImpl::key(480, 0, Key::new(*Key::DEFAULT + 3 * Key::SEMITONE)),
+        ])));
+
+        // events from before the swap point aren't replayed
+        render(&mut player, 20000);
+        assert_eq!(player.unit_data[&0].key_now, *Key::DEFAULT + Key::SEMITONE);
+
+        // but later ones are picked up
+        render(&mut player, 2000);
+        assert_eq!(
+            player.unit_data[&0].key_now,
+            *Key::DEFAULT + 3 * Key::SEMITONE
+        );
+    }
+
+    #[test]
+    fn swap_from_empty_project_skips_past_events() {
+        let mut player = RPxTonePlayer::new(Arc::new(project(&[])));
+        let swap = player.swap_handle();
+
+        render(&mut player, 20000);
+        swap.swap(Arc::new(project(&[
+            EventImpl::key(0, 0, Key::new(*Key::DEFAULT + 2 * Key::SEMITONE)),
+            EventImpl::key(480, 0, Key::new(*Key::DEFAULT + 3 * Key::SEMITONE)),
+        ])));
+
+        // the old project had no events to move past, but the new one's earlier ones still
+        // aren't replayed
+        render(&mut player, 100);
+        assert!(!player.unit_data.contains_key(&0));
+
+        render(&mut player, 2000);
+        assert_eq!(
+            player.unit_data[&0].key_now,
+            *Key::DEFAULT + 3 * Key::SEMITONE
+        );
+    }
+
+    #[test]
+    fn live_notes_play_until_released() {
+        let mut pxtone = RPxTone::new();
+        pxtone
+            .read_bytes(include_bytes!("../../../examples/sample.ptcop"))
+            .unwrap();
+        pxtone.event_list.events.clear();
+        let mut moo = pxtone.as_moo_ref();
+        moo.prepare_sample().unwrap();
+
+        let peak = |moo: &mut RPxToneMoo, frames: usize| {
+            let mut buf = vec![0_i16; frames * 2];
+            moo.sample(&mut buf).unwrap();
+            buf.iter().map(|s| s.unsigned_abs()).max().unwrap()
+        };
+
+        assert_eq!(peak(&mut moo, 4410), 0);
+
+        moo.live_note_on(0, Key::DEFAULT, ZeroToOneF32::new(1.0), PanValue::center())
+            .unwrap();
+        assert!(peak(&mut moo, 4410) > 0);
+        assert!(moo.unit_data[&0].tones[0].held);
+
+        moo.live_note_off(0).unwrap();
+        assert!(!moo.unit_data[&0].tones[0].held);
+        peak(&mut moo, 44100);
+        assert_eq!(peak(&mut moo, 4410), 0);
+        assert!(moo.unit_data[&0].tones.is_empty());
+    }
+
+    #[test]
+    fn live_voice_no_cuts_playing_notes() {
+        let mut pxtone = RPxTone::new();
+        pxtone
+            .read_bytes(include_bytes!("../../../examples/sample.ptcop"))
+            .unwrap();
+        pxtone.event_list.events.clear();
+        let mut moo = pxtone.as_moo_ref();
+        moo.prepare_sample().unwrap();
+
+        let peak = |moo: &mut RPxToneMoo, frames: usize| {
+            let mut buf = vec![0_i16; frames * 2];
+            moo.sample(&mut buf).unwrap();
+            buf.iter().map(|s| s.unsigned_abs()).max().unwrap()
+        };
+
+        moo.live_note_on(0, Key::DEFAULT, ZeroToOneF32::new(1.0), PanValue::center())
+            .unwrap();
+        assert!(peak(&mut moo, 4410) > 0);
+
+        moo.live_voice_no(0, 1).unwrap();
+        assert_eq!(peak(&mut moo, 4410), 0);
+        assert!(moo.unit_data[&0].tones.is_empty());
+        assert_eq!(moo.unit_data[&0].woice, 1);
+    }
+
+    #[derive(Debug, PartialEq)]
+    enum Seen {
+        On(Key),
+        Off(Key),
+        Beat(u32, u32),
+        Wrap,
+        End,
+    }
+
+    impl MooObserver for Vec<(MooPosition, Seen)> {
+        fn note_on(&mut self, pos: MooPosition, _unit_no: u8, key: Key, _velocity: ZeroToOneF32) {
+            self.push((pos, Seen::On(key)));
+        }
+
+        fn note_off(&mut self, pos: MooPosition, _unit_no: u8, key: Key) {
+            self.push((pos, Seen::Off(key)));
+        }
+
+        fn beat(&mut self, pos: MooPosition, measure: u32, beat: u32) {
+            self.push((pos, Seen::Beat(measure, beat)));
+        }
+
+        fn loop_wrap(&mut self, pos: MooPosition) {
+            self.push((pos, Seen::Wrap));
+        }
+
+        fn end(&mut self, pos: MooPosition) {
+            self.push((pos, Seen::End));
+        }
+    }
+
+    #[test]
+    fn observer_sees_notes_beats_and_loops() {
+        let mut pxtone = RPxTone::new();
+        pxtone
+            .read_bytes(include_bytes!("../../../examples/sample.ptcop"))
+            .unwrap();
+        pxtone.event_list.events.clear();
+        pxtone
+            .event_list_mut()
+            .add(&EventImpl::on(0, 0, 480))
+            .unwrap();
+        pxtone.set_num_measures(1);
+        pxtone.set_last_measure(0);
+        pxtone.set_repeat_measure(0);
+        let beat_num = pxtone.beat_num() as u32;
+
+        let mut moo = pxtone.moo_with_observer(Vec::new());
+        moo.prepare_sample().unwrap();
+        let end = moo.sampling_end() as usize;
+
+        let mut buf = vec![0; (end + 10) * 2];
+        moo.sample(&mut buf).unwrap();
+
+        let seen = std::mem::take(moo.observer_mut());
+        let notes: Vec<_> = seen
+            .iter()
+            .filter(|(_, s)| matches!(s, Seen::On(_) | Seen::Off(_)))
+            .map(|(pos, s)| (pos.clock, s))
+            .collect();
+        assert_eq!(
+            notes,
+            [
+                (0, &Seen::On(Key::DEFAULT)),
+                (480, &Seen::Off(Key::DEFAULT)),
+                (0, &Seen::On(Key::DEFAULT)),
+            ]
+        );
+
+        let beats: Vec<_> = seen
+            .iter()
+            .filter_map(|(_, s)| match s {
+                Seen::Beat(measure, beat) => Some((*measure, *beat)),
+                _ => None,
+            })
+            .collect();
+        let expected: Vec<_> = (0..beat_num).map(|b| (0, b)).chain([(0, 0)]).collect();
+        assert_eq!(beats, expected);
+
+        let (wrap, _) = seen.iter().find(|(_, s)| *s == Seen::Wrap).unwrap();
+        assert_eq!((wrap.frame, wrap.sample, wrap.clock), (end, 0, 0));
+
+        moo.set_loop(false).unwrap();
+        moo.sample(&mut buf).unwrap();
+        let seen = moo.observer();
+        assert_eq!(seen.last().unwrap().1, Seen::End);
+        assert_eq!(seen.last().unwrap().0.sample as usize, end - 1);
+    }
+}
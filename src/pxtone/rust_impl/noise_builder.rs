@@ -0,0 +1,412 @@
+//! Synthesis of ptNoise designs, a port of og pxtone's `pxtnPulse_NoiseBuilder`
+//!
+//! og pxtone builds every noise as 16 bit stereo at 44100Hz when a project is loaded, and then
+//! plays it back like a PCM woice. The arithmetic here follows og step for step (including where
+//! it's done in integers) so the results match sample for sample.
+
+use std::{f64::consts::PI, sync::OnceLock};
+
+use crate::interface::woice::PTNWaveType;
+
+use super::woice::{RPxTonePTNOscillator, RPxTonePTNUnit};
+
+const BASIC_SPS: f64 = 44100.0;
+const BASIC_FREQUENCY: f64 = 100.0;
+const SAMPLING_TOP: i32 = 32767;
+const KEY_TOP: i32 = 0x3200;
+
+/// Length of one cycle of every wave table, 100Hz at 44100Hz
+const SMP_NUM: usize = 441;
+const SMP_NUM_RAND: usize = 44100;
+
+const LIMIT_SMP_NUM: u32 = 48000 * 10;
+const LIMIT_ENVELOPE_X: u32 = 1000 * 10;
+const LIMIT_ENVELOPE_Y: u8 = 100;
+const LIMIT_OSC_FREQUENCY: f32 = 44100.0;
+const LIMIT_OSC_VOLUME: f32 = 200.0;
+const LIMIT_OSC_OFFSET: f32 = 100.0;
+
+const OCTAVE_NUM: usize = 16;
+const FREQUENCY_PER_OCTAVE: usize = 12 * 0x10;
+
+struct Tables {
+    /// One cycle of each [`PTNWaveType`], indexed by its discriminant. `Random2` has no table.
+    waves: Vec<Vec<i16>>,
+    frequency: Vec<f32>,
+}
+
+impl Tables {
+    fn get() -> &'static Self {
+        static TABLES: OnceLock<Tables> = OnceLock::new();
+        TABLES.get_or_init(Self::new)
+    }
+
+    #[allow(clippy::cast_precision_loss)]
+    fn new() -> Self {
+        const TOP: i32 = SAMPLING_TOP;
+        let n = SMP_NUM as i32;
+
+        let overtones = |tones: &[i32]| -> Vec<i16> {
+            (0..SMP_NUM)
+                .map(|s| {
+                    let work: f64 = tones
+                        .iter()
+                        .map(|&x| {
+                            let sss = 2.0 * PI * f64::from(x) * s as f64 / SMP_NUM as f64;
+                            sss.sin() * 128.0 / f64::from(x) / 128.0
+                        })
+                        .sum();
+                    clamp_sample(work)
+                })
+                .collect()
+        };
+        // holds each value until the sample before the index next to it
+        let steps = |steps: &[(i32, i32)]| -> Vec<i16> {
+            let mut table = Vec::with_capacity(SMP_NUM);
+            for &(until, v) in steps {
+                table.resize(until as usize, v as i16);
+            }
+            table
+        };
+
+        let mut waves = vec![Vec::new(); PTNWaveType::Saw8 as usize + 1];
+        waves[PTNWaveType::None as usize] = vec![0; SMP_NUM];
+        waves[PTNWaveType::Sine as usize] = overtones(&[1]);
+        waves[PTNWaveType::Saw as usize] = (0..SMP_NUM)
+            .map(|s| (f64::from(TOP) - f64::from(TOP + TOP) * s as f64 / SMP_NUM as f64) as i16)
+            .collect();
+        waves[PTNWaveType::Rect as usize] = steps(&[(n / 2, TOP), (n, -TOP)]);
+        waves[PTNWaveType::Random as usize] = random_table();
+        waves[PTNWaveType::Saw2 as usize] = overtones(&(1..=16).collect::<Vec<_>>());
+        waves[PTNWaveType::Rect2 as usize] = overtones(&[1, 3, 5, 7, 9, 11, 13, 15]);
+        waves[PTNWaveType::Tri as usize] = triangle();
+        waves[PTNWaveType::Rect3 as usize] = steps(&[(n / 3, TOP), (n, -TOP)]);
+        waves[PTNWaveType::Rect4 as usize] = steps(&[(n / 4, TOP), (n, -TOP)]);
+        waves[PTNWaveType::Rect8 as usize] = steps(&[(n / 8, TOP), (n, -TOP)]);
+        waves[PTNWaveType::Rect16 as usize] = steps(&[(n / 16, TOP), (n, -TOP)]);
+        waves[PTNWaveType::Saw3 as usize] = steps(&[(n / 3, TOP), (n * 2 / 3, 0), (n, -TOP)]);
+        waves[PTNWaveType::Saw4 as usize] = steps(&[
+            (n / 4, TOP),
+            (n * 2 / 4, TOP / 3),
+            (n * 3 / 4, -TOP / 3),
+            (n, -TOP),
+        ]);
+        waves[PTNWaveType::Saw6 as usize] = steps(&[
+            (n / 6, TOP),
+            (n * 2 / 6, TOP - TOP * 2 / 5),
+            (n * 3 / 6, TOP / 5),
+            (n * 4 / 6, -TOP / 5),
+            (n * 5 / 6, -TOP + TOP * 2 / 5),
+            (n, -TOP),
+        ]);
+        waves[PTNWaveType::Saw8 as usize] = steps(&[
+            (n / 8, TOP),
+            (n * 2 / 8, TOP - TOP * 2 / 7),
+            (n * 3 / 8, TOP - TOP * 4 / 7),
+            (n * 4 / 8, TOP / 7),
+            (n * 5 / 8, -TOP / 7),
+            (n * 6 / 8, -TOP + TOP * 4 / 7),
+            (n * 7 / 8, -TOP + TOP * 2 / 7),
+            (n, -TOP),
+        ]);
+
+        Self { waves, frequency: frequency_table() }
+    }
+
+    /// Frequency ratio for a key offset, `0` being 1.0 (`pxtnPulse_Frequency::Get`)
+    fn frequency(&self, key: i32) -> f32 {
+        let i = (key + 0x6000) * 0x10 / 0x100;
+        self.frequency[(i.max(0) as usize).min(self.frequency.len() - 1)]
+    }
+}
+
+fn clamp_sample(work: f64) -> i16 {
+    (work.clamp(-1.0, 1.0) * f64::from(SAMPLING_TOP)) as i16
+}
+
+fn triangle() -> Vec<i16> {
+    let n = SMP_NUM as i32;
+    let points = [(0, 0), (n / 4, 128), (n * 3 / 4, -128), (n, 0)];
+
+    (0..n)
+        .map(|i| {
+            let c = points.iter().position(|p| p.0 > i).unwrap_or(points.len());
+            let ((x1, y1), (x2, y2)) = match c {
+                0 => (points[0], points[0]),
+                c if c == points.len() => (points[c - 1], (n, points[0].1)),
+                c => (points[c - 1], points[c]),
+            };
+            let i = i - x1;
+            let work = if i == 0 {
+                f64::from(y1)
+            } else {
+                f64::from(y1) + f64::from(y2 - y1) * f64::from(i) / f64::from(x2 - x1)
+            };
+            clamp_sample(work * 128.0 / 128.0 / 128.0)
+        })
+        .collect()
+}
+
+/// og's fixed seed random generator, which swaps the bytes of a running sum
+fn random_table() -> Vec<i16> {
+    let mut buf = [0x4444_i32, 0x8888];
+    (0..SMP_NUM_RAND)
+        .map(|_| {
+            let w1 = i32::from(buf[0] as i16) + buf[1];
+            let w2 = (w1 as u16).swap_bytes() as i16;
+            buf[1] = i32::from(buf[0] as i16);
+            buf[0] = i32::from(w2);
+            w2
+        })
+        .collect()
+}
+
+/// The ratio of one twelfth of an octave, found digit by digit like og does
+fn divide_octave_rate(divi: usize) -> f64 {
+    let mut parameter = 1.0;
+    for i in 0..17 {
+        let add = (0..i).fold(1.0, |add, _| add * 0.1);
+        let mut j = 0;
+        while j < 10 {
+            let work = parameter + add * f64::from(j);
+            let mut result = 1.0;
+            let mut k = 0;
+            while k < divi {
+                result *= work;
+                if result >= 2.0 {
+                    break;
+                }
+                k += 1;
+            }
+            if k != divi {
+                break;
+            }
+            j += 1;
+        }
+        parameter += add * f64::from(j - 1);
+    }
+    parameter
+}
+
+fn frequency_table() -> Vec<f32> {
+    let oct_x24 = divide_octave_rate(FREQUENCY_PER_OCTAVE);
+    (0..OCTAVE_NUM * FREQUENCY_PER_OCTAVE)
+        .map(|f| {
+            let octave = 2_f64.powi(f as i32 / FREQUENCY_PER_OCTAVE as i32 - 8);
+            (0..f % FREQUENCY_PER_OCTAVE).fold(octave, |work, _| work * oct_x24) as f32
+        })
+        .collect()
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum RandomType {
+    None,
+    Saw,
+    Rect,
+}
+
+struct Oscillator {
+    increment: f64,
+    offset: f64,
+    volume: f64,
+    table: &'static [i16],
+    reverse: bool,
+    random: RandomType,
+    rdm_start: i32,
+    rdm_margin: i32,
+    rdm_index: usize,
+}
+
+impl Oscillator {
+    #[allow(clippy::cast_precision_loss)]
+    fn new(osc: &RPxTonePTNOscillator, tables: &'static Tables) -> Self {
+        let frequency = osc.frequency.clamp(0.0, LIMIT_OSC_FREQUENCY);
+        let volume = osc.volume.clamp(0.0, LIMIT_OSC_VOLUME);
+        let offset = osc.offset.clamp(0.0, LIMIT_OSC_OFFSET);
+
+        let random = match osc.shape {
+            PTNWaveType::Random => RandomType::Saw,
+            PTNWaveType::Random2 => RandomType::Rect,
+            _ => RandomType::None,
+        };
+        let rdm_index = (SMP_NUM_RAND as f64 * f64::from(offset / 100.0)) as usize % SMP_NUM_RAND;
+
+        Self {
+            // og scales this by 44100 over the build rate, which is always 44100
+            increment: f64::from(frequency) / BASIC_FREQUENCY,
+            offset: if random == RandomType::None {
+                SMP_NUM as f64 * f64::from(offset / 100.0)
+            } else {
+                0.0
+            },
+            volume: f64::from(volume / 100.0),
+            table: &tables.waves[osc.shape as usize],
+            reverse: osc.reverse,
+            random,
+            rdm_start: 0,
+            rdm_index,
+            rdm_margin: tables.waves[PTNWaveType::Random as usize][rdm_index].into(),
+        }
+    }
+
+    /// Current value of the oscillator, `scale` converting table values like og does for each use
+    fn value(&self, scale: impl Fn(i32) -> i32) -> f64 {
+        let offset = self.offset as i32;
+        let value = match self.random {
+            RandomType::None => scale(self.table[offset as usize % SMP_NUM].into()),
+            RandomType::Saw => self.rdm_start + self.rdm_margin * offset / SMP_NUM as i32,
+            RandomType::Rect => self.rdm_start,
+        };
+        let value = f64::from(value);
+        if self.reverse {
+            -value * self.volume
+        } else {
+            value * self.volume
+        }
+    }
+
+    #[allow(clippy::cast_precision_loss)]
+    fn increment(&mut self, increment: f64, random: &[i16]) {
+        self.offset += increment;
+        if self.offset > SMP_NUM as f64 {
+            self.offset -= SMP_NUM as f64;
+            if self.offset >= SMP_NUM as f64 {
+                self.offset = 0.0;
+            }
+
+            if self.random != RandomType::None {
+                self.rdm_start = random[self.rdm_index].into();
+                self.rdm_index = (self.rdm_index + 1) % SMP_NUM_RAND;
+                self.rdm_margin = i32::from(random[self.rdm_index]) - self.rdm_start;
+            }
+        }
+    }
+}
+
+struct Unit {
+    pan: [f64; 2],
+    /// Sample each point is reached at, and its magnitude
+    envelope: Vec<(i32, f64)>,
+    enve_index: usize,
+    enve_mag_start: f64,
+    enve_mag_margin: f64,
+    enve_count: i32,
+
+    main: Oscillator,
+    freq: Oscillator,
+    volu: Oscillator,
+}
+
+impl Unit {
+    fn new(unit: &RPxTonePTNUnit, tables: &'static Tables) -> Self {
+        let pan = i32::from(unit.pan).clamp(-100, 100);
+        let pan = match pan {
+            0 => [1.0, 1.0],
+            p if p < 0 => [1.0, f64::from(100 + p) / 100.0],
+            p => [f64::from(100 - p) / 100.0, 1.0],
+        };
+
+        let envelope = unit
+            .envelope
+            .iter()
+            .map(|p| {
+                let x = p.x.min(LIMIT_ENVELOPE_X) as i32;
+                let y = p.y.min(LIMIT_ENVELOPE_Y);
+                (BASIC_SPS as i32 * x / 1000, f64::from(y) / 100.0)
+            })
+            .collect();
+
+        let mut unit = Self {
+            pan,
+            envelope,
+            enve_index: 0,
+            enve_mag_start: 0.0,
+            enve_mag_margin: 0.0,
+            enve_count: 0,
+            main: Oscillator::new(&unit.osc_main, tables),
+            freq: Oscillator::new(&unit.osc_frequency, tables),
+            volu: Oscillator::new(&unit.osc_volume, tables),
+        };
+        unit.skip_instant_points();
+        unit
+    }
+
+    /// Move on to the next envelope point that takes any time to reach
+    fn skip_instant_points(&mut self) {
+        while let Some(&(smp, mag)) = self.envelope.get(self.enve_index) {
+            self.enve_mag_margin = mag - self.enve_mag_start;
+            if smp != 0 {
+                break;
+            }
+            self.enve_mag_start = mag;
+            self.enve_index += 1;
+        }
+    }
+
+    fn envelope(&self) -> f64 {
+        match self.envelope.get(self.enve_index) {
+            Some(&(smp, _)) => {
+                self.enve_mag_start
+                    + self.enve_mag_margin * f64::from(self.enve_count) / f64::from(smp)
+            },
+            None => self.enve_mag_start,
+        }
+    }
+
+    fn sample(&self, channel: usize) -> f64 {
+        let work = self.main.value(|v| v);
+        let vol = self.volu.value(|v| v);
+
+        let work = work * (vol + f64::from(SAMPLING_TOP)) / f64::from(SAMPLING_TOP * 2);
+        work * self.pan[channel] * self.envelope()
+    }
+
+    fn increment(&mut self, tables: &Tables) {
+        let random = &tables.waves[PTNWaveType::Random as usize];
+
+        let fre = self.freq.value(|v| KEY_TOP * v / SAMPLING_TOP);
+        let main_increment = self.main.increment * f64::from(tables.frequency(fre as i32));
+        self.main.increment(main_increment, random);
+        self.freq.increment(self.freq.increment, random);
+        self.volu.increment(self.volu.increment, random);
+
+        if let Some(&(smp, mag)) = self.envelope.get(self.enve_index) {
+            self.enve_count += 1;
+            if self.enve_count >= smp {
+                self.enve_count = 0;
+                self.enve_mag_start = mag;
+                self.enve_mag_margin = 0.0;
+                self.enve_index += 1;
+                self.skip_instant_points();
+            }
+        }
+    }
+}
+
+/// Build a noise design into interleaved stereo samples at 44100Hz
+///
+/// Samples are scaled the same way as decoded PCM woices, to within `-0.5..=0.5`.
+#[allow(clippy::cast_precision_loss)]
+pub(crate) fn build(sample_num: u32, units: &[RPxTonePTNUnit]) -> Vec<f32> {
+    let tables = Tables::get();
+    let mut units: Vec<_> = units
+        .iter()
+        .filter(|u| u.enabled)
+        .map(|u| Unit::new(u, tables))
+        .collect();
+
+    let sample_num = sample_num.min(LIMIT_SMP_NUM) as usize;
+    let mut samples = Vec::with_capacity(sample_num * 2);
+    for _ in 0..sample_num {
+        for channel in 0..2 {
+            let store: f64 = units.iter().map(|u| u.sample(channel)).sum();
+            let v = (store as i32).clamp(-SAMPLING_TOP, SAMPLING_TOP);
+            samples.push(v as f32 / f32::from(i16::MAX) / 2.0);
+        }
+
+        for unit in &mut units {
+            unit.increment(tables);
+        }
+    }
+    samples
+}
@@ -0,0 +1,44 @@
+use crate::{interface::event::Key, util::ZeroToOneF32};
+
+/// Where in the output a notification happened
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MooPosition {
+    /// Frame within the buffer passed to [`Moo::sample`](crate::interface::moo::Moo::sample)
+    pub frame: usize,
+    /// Position in the song, in samples
+    pub sample: u32,
+    /// Position in the song, in ticks
+    pub clock: u32,
+}
+
+/// Gets called by [`RPxToneMoo`](super::moo::RPxToneMoo) as things happen while sampling.
+///
+/// Every method does nothing by default. The observer is called from inside `sample`, so anything
+/// slow here holds up rendering.
+///
+/// `()` is the "no observer" observer, which compiles down to nothing.
+#[allow(unused_variables)]
+pub trait MooObserver {
+    /// `false` skips all of the bookkeeping needed to call the observer
+    const ENABLED: bool = true;
+
+    /// A note started on `unit_no`, from a song event or [`MooLive`](crate::interface::moo::MooLive)
+    fn note_on(&mut self, pos: MooPosition, unit_no: u8, key: Key, velocity: ZeroToOneF32) {}
+
+    /// A note on `unit_no` ended, either because its length is over (its release may still be
+    /// ringing) or because it got cut off
+    fn note_off(&mut self, pos: MooPosition, unit_no: u8, key: Key) {}
+
+    /// Playback reached a new beat. `beat` is `0` at the start of each measure
+    fn beat(&mut self, pos: MooPosition, measure: u32, beat: u32) {}
+
+    /// Playback jumped back to the repeat measure
+    fn loop_wrap(&mut self, pos: MooPosition) {}
+
+    /// The song finished (reached the end without looping, or faded out)
+    fn end(&mut self, pos: MooPosition) {}
+}
+
+impl MooObserver for () {
+    const ENABLED: bool = false;
+}
@@ -0,0 +1,95 @@
+use crate::{
+    interface::overdrive::{
+        AddOverDriveError, HasOverDrives, OverDAmp, OverDCut, OverDrive, OverDrives, OverDrivesMut,
+    },
+    util::{BoxOrMut, BoxOrRef},
+};
+
+use super::service::RPxTone;
+
+/// Maximum number of overdrives in a project (`pxtnMAX_TUNEOVERDRIVESTRUCT`)
+pub const MAX_OVERDRIVES: usize = 2;
+
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct RPxToneOverDrive {
+    pub(crate) group: u8,
+    /// Percent, kept as project files store it so they save back exactly
+    pub(crate) cut: f32,
+    pub(crate) amp: OverDAmp,
+}
+
+impl OverDrive for RPxToneOverDrive {
+    fn group(&self) -> u8 {
+        self.group
+    }
+
+    fn set_group(&mut self, group: u8) {
+        self.group = group;
+    }
+
+    fn cut(&self) -> OverDCut {
+        OverDCut::new(self.cut / 100.0)
+    }
+
+    fn set_cut(&mut self, cut: OverDCut) {
+        self.cut = *cut * 100.0;
+    }
+
+    fn amp(&self) -> OverDAmp {
+        self.amp
+    }
+
+    fn set_amp(&mut self, amp: OverDAmp) {
+        self.amp = amp;
+    }
+}
+
+impl OverDrives for RPxTone {
+    type O = RPxToneOverDrive;
+
+    fn iter<'a>(&'a self) -> Box<dyn Iterator<Item = BoxOrRef<'a, Self::O>> + 'a> {
+        Box::new(self.overdrives.iter().map(BoxOrRef::Ref))
+    }
+}
+
+impl OverDrivesMut for RPxTone {
+    fn iter_mut<'a>(&'a mut self) -> Box<dyn Iterator<Item = BoxOrMut<'a, Self::O>> + 'a> {
+        Box::new(self.overdrives.iter_mut().map(BoxOrMut::Ref))
+    }
+
+    fn add(
+        &mut self,
+        group: u8,
+        cut: OverDCut,
+        amp: OverDAmp,
+    ) -> Result<BoxOrMut<'_, Self::O>, AddOverDriveError> {
+        if self.overdrives.len() >= MAX_OVERDRIVES {
+            return Err(AddOverDriveError { group, cut, amp });
+        }
+
+        self.overdrives
+            .push(RPxToneOverDrive { group, cut: *cut * 100.0, amp });
+        Ok(self.overdrives.last_mut().unwrap().into())
+    }
+
+    fn remove(&mut self, index: usize) -> bool {
+        if index >= self.overdrives.len() {
+            return false;
+        }
+        self.overdrives.remove(index);
+        true
+    }
+}
+
+impl HasOverDrives for RPxTone {
+    type OverDrives = Self;
+    type OverDrivesMut = Self;
+
+    fn overdrives(&self) -> BoxOrRef<'_, Self::OverDrives> {
+        BoxOrRef::Ref(self)
+    }
+
+    fn overdrives_mut(&mut self) -> BoxOrMut<'_, Self::OverDrivesMut> {
+        BoxOrMut::Ref(self)
+    }
+}
@@ -0,0 +1,222 @@
+//! The ptNoise format, used both for standalone `.ptnoise` files and inside `matePTN ` blocks
+
+use std::io::{self, Cursor, Read};
+
+use byteorder::{LittleEndian, ReadBytesExt};
+
+use crate::interface::woice::PTNWaveType;
+
+use super::{
+    io::{v_r, v_w},
+    woice::{RPxTonePTNEnvelopePoint, RPxTonePTNOscillator, RPxTonePTNUnit, RPxToneVoicePTN},
+};
+
+const CODE: &[u8; 8] = b"PTNOISE-";
+const VERSION: u32 = 20_120_418;
+
+const MAX_UNITS: u8 = 4;
+const MAX_ENVELOPE_POINTS: u32 = 3;
+
+const FLAG_ENVELOPE: u32 = 0x0004;
+const FLAG_PAN: u32 = 0x0008;
+const FLAG_OSC_MAIN: u32 = 0x0010;
+const FLAG_OSC_FREQ: u32 = 0x0020;
+const FLAG_OSC_VOLU: u32 = 0x0040;
+const FLAG_UNCOVERED: u32 = 0xffff_ff83;
+
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum PTNoiseError {
+    IncorrectHeader([u8; 8]),
+    /// Written by a newer version of ptNoise than this reader knows about
+    NewerVersion(u32),
+    /// Valid, but uses something og pxtone doesn't support either
+    Unsupported(&'static str),
+    UnexpectedEnd,
+}
+
+impl From<io::Error> for PTNoiseError {
+    fn from(_: io::Error) -> Self {
+        // reading from memory can only fail by running out of data
+        Self::UnexpectedEnd
+    }
+}
+
+/// Read a standalone `.ptnoise` file
+///
+/// The file doesn't store a key, tuning or flags, so those get the same defaults as og pxtone.
+pub fn read(bytes: &[u8]) -> Result<RPxToneVoicePTN, PTNoiseError> {
+    read_from(&mut Cursor::new(bytes))
+}
+
+pub(crate) fn read_from(c: &mut Cursor<&[u8]>) -> Result<RPxToneVoicePTN, PTNoiseError> {
+    let mut code = [0_u8; 8];
+    c.read_exact(&mut code)?;
+    if &code != CODE {
+        return Err(PTNoiseError::IncorrectHeader(code));
+    }
+
+    let version = c.read_u32::<LittleEndian>()?;
+    if version > VERSION {
+        return Err(PTNoiseError::NewerVersion(version));
+    }
+
+    let ptn_sample_num = v_r(c)?;
+
+    let unit_num = c.read_u8()?;
+    if unit_num > MAX_UNITS {
+        return Err(PTNoiseError::Unsupported("unit count"));
+    }
+
+    let ptn_units = (0..unit_num)
+        .map(|_| read_unit(c))
+        .collect::<Result<_, _>>()?;
+
+    Ok(RPxToneVoicePTN::new(
+        0x4500,
+        128,
+        64,
+        1.0,
+        ptn_sample_num,
+        ptn_units,
+        false,
+        true,
+        false,
+    ))
+}
+
+fn read_unit(c: &mut Cursor<&[u8]>) -> Result<RPxTonePTNUnit, PTNoiseError> {
+    let flags = v_r(c)?;
+    if flags & FLAG_UNCOVERED != 0 {
+        return Err(PTNoiseError::Unsupported("unit flags"));
+    }
+
+    let mut envelope = Vec::new();
+    if flags & FLAG_ENVELOPE != 0 {
+        let num = v_r(c)?;
+        if num > MAX_ENVELOPE_POINTS {
+            return Err(PTNoiseError::Unsupported("envelope point count"));
+        }
+        for _ in 0..num {
+            let x = v_r(c)?;
+            let y = v_r(c)?;
+            envelope.push(RPxTonePTNEnvelopePoint { x, y: y as _ });
+        }
+    }
+
+    let pan = if flags & FLAG_PAN == 0 {
+        0
+    } else {
+        c.read_i8()?
+    };
+
+    let mut osc = |flag| {
+        if flags & flag == 0 {
+            Ok(silent_oscillator())
+        } else {
+            read_oscillator(c)
+        }
+    };
+    let osc_main = osc(FLAG_OSC_MAIN)?;
+    let osc_frequency = osc(FLAG_OSC_FREQ)?;
+    let osc_volume = osc(FLAG_OSC_VOLU)?;
+
+    Ok(RPxTonePTNUnit {
+        enabled: true,
+        pan,
+        envelope,
+        osc_main,
+        osc_frequency,
+        osc_volume,
+    })
+}
+
+#[allow(clippy::cast_precision_loss)]
+fn read_oscillator(c: &mut Cursor<&[u8]>) -> Result<RPxTonePTNOscillator, PTNoiseError> {
+    let shape = v_r(c)?;
+    if shape > PTNWaveType::Saw8 as u32 {
+        return Err(PTNoiseError::Unsupported("wave type"));
+    }
+    let reverse = v_r(c)? != 0;
+    // stored as tenths
+    let frequency = v_r(c)? as i32 as f32 / 10.0;
+    let volume = v_r(c)? as i32 as f32 / 10.0;
+    let offset = v_r(c)? as i32 as f32 / 10.0;
+
+    Ok(RPxTonePTNOscillator {
+        shape: (shape as u8).into(),
+        frequency,
+        volume,
+        offset,
+        reverse,
+    })
+}
+
+fn silent_oscillator() -> RPxTonePTNOscillator {
+    RPxTonePTNOscillator {
+        shape: PTNWaveType::None,
+        frequency: 0.0,
+        volume: 0.0,
+        offset: 0.0,
+        reverse: false,
+    }
+}
+
+/// Write a standalone `.ptnoise` file, in the same layout ptNoise itself does
+///
+/// Disabled units are left out, as are oscillators with no shape.
+pub fn write<W: io::Write>(voice: &RPxToneVoicePTN, mut writer: W) -> io::Result<()> {
+    let units: Vec<_> = voice.ptn_units.iter().filter(|u| u.enabled).collect();
+
+    let mut out = Vec::new();
+    out.extend_from_slice(CODE);
+    out.extend_from_slice(&VERSION.to_le_bytes());
+    v_w(&mut out, voice.ptn_sample_num);
+    out.push(units.len() as u8);
+
+    for unit in units {
+        let mut flags = FLAG_ENVELOPE;
+        if unit.pan != 0 {
+            flags |= FLAG_PAN;
+        }
+        if unit.osc_main.shape != PTNWaveType::None {
+            flags |= FLAG_OSC_MAIN;
+        }
+        if unit.osc_frequency.shape != PTNWaveType::None {
+            flags |= FLAG_OSC_FREQ;
+        }
+        if unit.osc_volume.shape != PTNWaveType::None {
+            flags |= FLAG_OSC_VOLU;
+        }
+        v_w(&mut out, flags);
+
+        v_w(&mut out, unit.envelope.len() as u32);
+        for p in &unit.envelope {
+            v_w(&mut out, p.x);
+            v_w(&mut out, p.y.into());
+        }
+
+        if flags & FLAG_PAN != 0 {
+            out.push(unit.pan as u8);
+        }
+        for (flag, osc) in [
+            (FLAG_OSC_MAIN, &unit.osc_main),
+            (FLAG_OSC_FREQ, &unit.osc_frequency),
+            (FLAG_OSC_VOLU, &unit.osc_volume),
+        ] {
+            if flags & flag != 0 {
+                write_oscillator(&mut out, osc);
+            }
+        }
+    }
+
+    writer.write_all(&out)
+}
+
+fn write_oscillator(out: &mut Vec<u8>, osc: &RPxTonePTNOscillator) {
+    v_w(out, osc.shape as u32);
+    v_w(out, osc.reverse.into());
+    v_w(out, (osc.frequency * 10.0) as i32 as u32);
+    v_w(out, (osc.volume * 10.0) as i32 as u32);
+    v_w(out, (osc.offset * 10.0) as i32 as u32);
+}
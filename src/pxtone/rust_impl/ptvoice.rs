@@ -0,0 +1,253 @@
+//! The ptVoice format, used both for standalone `.ptvoice` files and inside `matePTV ` blocks
+
+use std::io::{self, Cursor, Read};
+
+use byteorder::{LittleEndian, ReadBytesExt};
+
+use super::{
+    io::{v_r, v_w},
+    woice::{
+        RPXTonePTVEnvelope, RPxTonePTNEnvelopePoint, RPxTonePTVCoordinatePoint,
+        RPxTonePTVCoordinateWave, RPxTonePTVOvertoneWave, RPxTonePTVOvertoneWaveTone,
+        RPxTonePTVWaveType, RPxToneVoicePTV, RPxToneWoicePTV,
+    },
+};
+
+const CODE: &[u8; 8] = b"PTVOICE-";
+const VERSION: u32 = 20_060_111;
+
+const VOICEFLAG_WAVELOOP: u32 = 0x1;
+const VOICEFLAG_SMOOTH: u32 = 0x2;
+const VOICEFLAG_BEATFIT: u32 = 0x4;
+
+const DATAFLAG_WAVE: u32 = 0x1;
+const DATAFLAG_ENVELOPE: u32 = 0x2;
+
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum PTVoiceError {
+    IncorrectHeader([u8; 8]),
+    /// Written by a newer version of ptVoice than this reader knows about
+    NewerVersion(u32),
+    /// Valid, but uses something og pxtone doesn't support either, like sampled waves
+    Unsupported(&'static str),
+    UnexpectedEnd,
+}
+
+impl From<io::Error> for PTVoiceError {
+    fn from(_: io::Error) -> Self {
+        // reading from memory can only fail by running out of data
+        Self::UnexpectedEnd
+    }
+}
+
+/// Read a standalone `.ptvoice` file
+pub fn read(bytes: &[u8]) -> Result<RPxToneWoicePTV, PTVoiceError> {
+    read_from(&mut Cursor::new(bytes))
+}
+
+pub(crate) fn read_from(c: &mut Cursor<&[u8]>) -> Result<RPxToneWoicePTV, PTVoiceError> {
+    let mut code = [0_u8; 8];
+    c.read_exact(&mut code)?;
+    if &code != CODE {
+        return Err(PTVoiceError::IncorrectHeader(code));
+    }
+
+    let version = c.read_u32::<LittleEndian>()?;
+    if version > VERSION {
+        return Err(PTVoiceError::NewerVersion(version));
+    }
+
+    let _total = c.read_u32::<LittleEndian>()?;
+    let _x3x_basic_key = v_r(c)?;
+
+    let work1 = v_r(c)?;
+    let work2 = v_r(c)?;
+    if work1 != 0 || work2 != 0 {
+        return Err(PTVoiceError::Unsupported("reserved values"));
+    }
+
+    let voice_num = v_r(c)?;
+    let mut voices = Vec::new();
+    for _ in 0..voice_num {
+        let basic_key = v_r(c)?;
+        let volume = v_r(c)?;
+        let pan = v_r(c)?;
+        let tuning = f32::from_bits(v_r(c)?);
+        let voice_flags = v_r(c)?;
+        let data_flags = v_r(c)?;
+
+        if voice_flags & !(VOICEFLAG_WAVELOOP | VOICEFLAG_SMOOTH | VOICEFLAG_BEATFIT) != 0 {
+            return Err(PTVoiceError::Unsupported("voice flags"));
+        }
+        if data_flags & !(DATAFLAG_WAVE | DATAFLAG_ENVELOPE) != 0 {
+            return Err(PTVoiceError::Unsupported("data flags"));
+        }
+
+        let wave = if data_flags & DATAFLAG_WAVE == 0 {
+            None
+        } else {
+            Some(read_wave(c)?)
+        };
+
+        let envelope = if data_flags & DATAFLAG_ENVELOPE == 0 {
+            RPXTonePTVEnvelope::default()
+        } else {
+            read_envelope(c)?
+        };
+
+        // a voice without a wave is silent
+        if let Some(wave) = wave {
+            voices.push(RPxToneVoicePTV::new(
+                basic_key as _,
+                volume as _,
+                pan as _,
+                tuning,
+                wave,
+                envelope,
+                voice_flags & VOICEFLAG_WAVELOOP != 0,
+                voice_flags & VOICEFLAG_SMOOTH != 0,
+                voice_flags & VOICEFLAG_BEATFIT != 0,
+            ));
+        }
+    }
+
+    Ok(RPxToneWoicePTV { voices })
+}
+
+fn read_wave(c: &mut Cursor<&[u8]>) -> Result<RPxTonePTVWaveType, PTVoiceError> {
+    match v_r(c)? {
+        0 => {
+            let num_points = v_r(c)?;
+            let resolution = v_r(c)?;
+
+            let points = (0..num_points)
+                .map(|_| {
+                    let x = c.read_u8()?;
+                    let y = c.read_i8()?;
+                    Ok(RPxTonePTVCoordinatePoint::new(x.into(), y.into()))
+                })
+                .collect::<Result<_, PTVoiceError>>()?;
+
+            Ok(RPxTonePTVWaveType::Coordinate(RPxTonePTVCoordinateWave {
+                resolution,
+                points,
+            }))
+        },
+        1 => {
+            let num_tones = v_r(c)?;
+
+            let tones = (0..num_tones)
+                .map(|_| {
+                    let frequency = v_r(c)?;
+                    let amplitude = v_r(c)?;
+                    Ok(RPxTonePTVOvertoneWaveTone {
+                        frequency: frequency as _,
+                        amplitude: amplitude as _,
+                    })
+                })
+                .collect::<Result<_, PTVoiceError>>()?;
+
+            Ok(RPxTonePTVWaveType::Overtone(RPxTonePTVOvertoneWave {
+                tones,
+            }))
+        },
+        _ => Err(PTVoiceError::Unsupported("wave type")),
+    }
+}
+
+fn read_envelope(c: &mut Cursor<&[u8]>) -> Result<RPXTonePTVEnvelope, PTVoiceError> {
+    let fps = v_r(c)?;
+    let head_num = v_r(c)?;
+    let body_num = v_r(c)?;
+    let tail_num = v_r(c)?;
+
+    if body_num != 0 || tail_num != 1 {
+        return Err(PTVoiceError::Unsupported("envelope shape"));
+    }
+
+    let points = (0..head_num + body_num + tail_num)
+        .map(|_| {
+            let x = v_r(c)?;
+            let y = v_r(c)?;
+            Ok(RPxTonePTNEnvelopePoint { x, y: y as _ })
+        })
+        .collect::<Result<_, PTVoiceError>>()?;
+
+    Ok(RPXTonePTVEnvelope { fps, head_num, body_num, tail_num, points })
+}
+
+/// Write a standalone `.ptvoice` file, in the same layout ptVoice itself does
+pub fn write<W: io::Write>(woice: &RPxToneWoicePTV, mut writer: W) -> io::Result<()> {
+    let mut body = Vec::new();
+
+    // unused basic key, then two reserved values
+    v_w(&mut body, 0);
+    v_w(&mut body, 0);
+    v_w(&mut body, 0);
+    v_w(&mut body, woice.voices.len() as u32);
+
+    for voice in &woice.voices {
+        let mut voice_flags = 0;
+        if voice.flag_loop {
+            voice_flags |= VOICEFLAG_WAVELOOP;
+        }
+        if voice.flag_smooth {
+            voice_flags |= VOICEFLAG_SMOOTH;
+        }
+        if voice.flag_beat_fit {
+            voice_flags |= VOICEFLAG_BEATFIT;
+        }
+
+        let envelope = &voice.envelope;
+        let has_envelope = !envelope.points.is_empty();
+        let data_flags = if has_envelope {
+            DATAFLAG_WAVE | DATAFLAG_ENVELOPE
+        } else {
+            DATAFLAG_WAVE
+        };
+
+        v_w(&mut body, voice.basic_key as u32);
+        v_w(&mut body, voice.volume as u32);
+        v_w(&mut body, voice.pan as u32);
+        v_w(&mut body, voice.tuning.to_bits());
+        v_w(&mut body, voice_flags);
+        v_w(&mut body, data_flags);
+
+        match &voice.wave {
+            RPxTonePTVWaveType::Coordinate(wave) => {
+                v_w(&mut body, 0);
+                v_w(&mut body, wave.points.len() as u32);
+                v_w(&mut body, wave.resolution);
+                for p in &wave.points {
+                    body.push(p.x as u8);
+                    body.push(p.y as u8);
+                }
+            },
+            RPxTonePTVWaveType::Overtone(wave) => {
+                v_w(&mut body, 1);
+                v_w(&mut body, wave.tones.len() as u32);
+                for t in &wave.tones {
+                    v_w(&mut body, t.frequency.into());
+                    v_w(&mut body, i32::from(t.amplitude) as u32);
+                }
+            },
+        }
+
+        if has_envelope {
+            v_w(&mut body, envelope.fps);
+            v_w(&mut body, envelope.head_num);
+            v_w(&mut body, envelope.body_num);
+            v_w(&mut body, envelope.tail_num);
+            for p in &envelope.points {
+                v_w(&mut body, p.x);
+                v_w(&mut body, p.y.into());
+            }
+        }
+    }
+
+    writer.write_all(CODE)?;
+    writer.write_all(&VERSION.to_le_bytes())?;
+    writer.write_all(&(body.len() as u32).to_le_bytes())?;
+    writer.write_all(&body)
+}
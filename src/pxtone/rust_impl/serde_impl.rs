@@ -0,0 +1,255 @@
+//! Serde support for [`RPxTone`](super::service::RPxTone), behind the `serde` feature
+//!
+//! Everything but the voices is derived. Voices keep their samples decoded for playback, so they
+//! go through plain descriptions of their settings instead and get rebuilt when deserialized.
+//! PCM and Ogg Vorbis data is stored as base64, so saving the project back to a `.ptcop` gives the
+//! same file.
+//!
+//! ```no_run
+//! use pxtone::{interface::io::PxToneServiceIO, rust_impl::service::RPxTone};
+//!
+//! let mut pxtone = RPxTone::new();
+//! pxtone.read_bytes(&std::fs::read("song.ptcop").unwrap()).unwrap();
+//! let text = serde_json::to_string_pretty(&pxtone).unwrap();
+//! let mut pxtone: RPxTone = serde_json::from_str(&text).unwrap();
+//! pxtone.write_file("song.ptcop").unwrap();
+//! ```
+
+use serde::{de::Error, Deserialize, Deserializer, Serialize, Serializer};
+
+use super::woice::{
+    RPXTonePTVEnvelope, RPxTonePTNUnit, RPxTonePTVWaveType, RPxToneVoiceOGGV, RPxToneVoicePCM,
+    RPxToneVoicePTN, RPxToneVoicePTV,
+};
+
+mod base64_data {
+    use base64::{engine::general_purpose::STANDARD, Engine};
+    use serde::{de::Error, Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(data: &[u8], serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&STANDARD.encode(data))
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<u8>, D::Error> {
+        let text = String::deserialize(deserializer)?;
+        STANDARD.decode(text).map_err(D::Error::custom)
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(rename = "RPxToneVoicePCM")]
+struct PCMSettings {
+    basic_key: i32,
+    volume: i32,
+    pan: i32,
+    tuning: f32,
+    flag_loop: bool,
+    flag_smooth: bool,
+    flag_beat_fit: bool,
+    channels: u8,
+    samples_per_second: u32,
+    bits_per_sample: u8,
+    #[serde(with = "base64_data")]
+    data: Vec<u8>,
+}
+
+impl Serialize for RPxToneVoicePCM {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        PCMSettings {
+            basic_key: self.basic_key,
+            volume: self.volume,
+            pan: self.pan,
+            tuning: self.tuning,
+            flag_loop: self.flag_loop,
+            flag_smooth: self.flag_smooth,
+            flag_beat_fit: self.flag_beat_fit,
+            channels: self.channels,
+            samples_per_second: self.samples_per_second,
+            bits_per_sample: self.bits_per_sample,
+            data: self.data(),
+        }
+        .serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for RPxToneVoicePCM {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let s = PCMSettings::deserialize(deserializer)?;
+        Self::new(
+            s.basic_key,
+            s.volume,
+            s.pan,
+            s.tuning,
+            s.channels,
+            s.samples_per_second,
+            s.bits_per_sample,
+            s.data,
+            s.flag_loop,
+            s.flag_smooth,
+            s.flag_beat_fit,
+        )
+        .map_err(|e| D::Error::custom(format!("{e:?}")))
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(rename = "RPxToneVoicePTV")]
+struct PTVSettings<W, E> {
+    basic_key: i32,
+    volume: i32,
+    pan: i32,
+    tuning: f32,
+    flag_loop: bool,
+    flag_smooth: bool,
+    flag_beat_fit: bool,
+    wave: W,
+    envelope: E,
+}
+
+impl Serialize for RPxToneVoicePTV {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        PTVSettings {
+            basic_key: self.basic_key,
+            volume: self.volume,
+            pan: self.pan,
+            tuning: self.tuning,
+            flag_loop: self.flag_loop,
+            flag_smooth: self.flag_smooth,
+            flag_beat_fit: self.flag_beat_fit,
+            wave: &self.wave,
+            envelope: &self.envelope,
+        }
+        .serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for RPxToneVoicePTV {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let s = PTVSettings::<RPxTonePTVWaveType, RPXTonePTVEnvelope>::deserialize(deserializer)?;
+        Ok(Self::new(
+            s.basic_key,
+            s.volume,
+            s.pan,
+            s.tuning,
+            s.wave,
+            s.envelope,
+            s.flag_loop,
+            s.flag_smooth,
+            s.flag_beat_fit,
+        ))
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(rename = "RPxToneVoicePTN")]
+struct PTNSettings<U> {
+    basic_key: i32,
+    volume: i32,
+    pan: i32,
+    tuning: f32,
+    flag_loop: bool,
+    flag_smooth: bool,
+    flag_beat_fit: bool,
+    channels: u8,
+    samples_per_second: u32,
+    bits_per_sample: u8,
+    ptn_sample_num: u32,
+    ptn_units: U,
+}
+
+impl Serialize for RPxToneVoicePTN {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        PTNSettings {
+            basic_key: self.basic_key,
+            volume: self.volume,
+            pan: self.pan,
+            tuning: self.tuning,
+            flag_loop: self.flag_loop,
+            flag_smooth: self.flag_smooth,
+            flag_beat_fit: self.flag_beat_fit,
+            channels: self.channels,
+            samples_per_second: self.samples_per_second,
+            bits_per_sample: self.bits_per_sample,
+            ptn_sample_num: self.ptn_sample_num,
+            ptn_units: &self.ptn_units,
+        }
+        .serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for RPxToneVoicePTN {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let s = PTNSettings::<Vec<RPxTonePTNUnit>>::deserialize(deserializer)?;
+        let mut voice = Self::new(
+            s.basic_key,
+            s.volume,
+            s.pan,
+            s.tuning,
+            s.ptn_sample_num,
+            s.ptn_units,
+            s.flag_loop,
+            s.flag_smooth,
+            s.flag_beat_fit,
+        );
+        voice.channels = s.channels;
+        voice.samples_per_second = s.samples_per_second;
+        voice.bits_per_sample = s.bits_per_sample;
+        Ok(voice)
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(rename = "RPxToneVoiceOGGV")]
+struct OGGVSettings {
+    basic_key: i32,
+    volume: i32,
+    pan: i32,
+    tuning: f32,
+    flag_loop: bool,
+    flag_smooth: bool,
+    flag_beat_fit: bool,
+    channels: u8,
+    samples_per_second: u32,
+    sample_num: u32,
+    #[serde(with = "base64_data")]
+    data: Vec<u8>,
+}
+
+impl Serialize for RPxToneVoiceOGGV {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        OGGVSettings {
+            basic_key: self.basic_key,
+            volume: self.volume,
+            pan: self.pan,
+            tuning: self.tuning,
+            flag_loop: self.flag_loop,
+            flag_smooth: self.flag_smooth,
+            flag_beat_fit: self.flag_beat_fit,
+            channels: self.channels,
+            samples_per_second: self.samples_per_second,
+            sample_num: self.ogg_sample_num,
+            data: self.ogg_data.clone(),
+        }
+        .serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for RPxToneVoiceOGGV {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let s = OGGVSettings::deserialize(deserializer)?;
+        Self::new(
+            s.basic_key,
+            s.volume,
+            s.pan,
+            s.tuning,
+            s.channels,
+            s.samples_per_second,
+            s.sample_num,
+            s.data,
+            s.flag_loop,
+            s.flag_smooth,
+            s.flag_beat_fit,
+        )
+        .map_err(|e| D::Error::custom(format!("{e:?}")))
+    }
+}
@@ -1,7 +1,11 @@
 use crate::interface::service::{InvalidText, PxTone};
 
-use super::{event::RPxToneEventList, unit::RPxToneUnit, woice::RPxToneWoice};
+use super::{
+    delay::RPxToneDelay, event::RPxToneEventList, overdrive::RPxToneOverDrive, unit::RPxToneUnit,
+    woice::RPxToneWoice,
+};
 
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct RPxTone {
     beat_num: i32,
     beat_tempo: f32,
@@ -15,6 +19,8 @@ pub struct RPxTone {
     pub(crate) event_list: RPxToneEventList,
     pub(crate) woices: Vec<RPxToneWoice>,
     pub(crate) units: Vec<RPxToneUnit>,
+    pub(crate) delays: Vec<RPxToneDelay>,
+    pub(crate) overdrives: Vec<RPxToneOverDrive>,
 }
 
 impl Default for RPxTone {
@@ -38,6 +44,8 @@ impl RPxTone {
             event_list: RPxToneEventList::default(),
             woices: Vec::new(),
             units: Vec::new(),
+            delays: Vec::new(),
+            overdrives: Vec::new(),
         }
     }
 }
@@ -5,6 +5,7 @@ use crate::{
 
 use super::service::RPxTone;
 
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct RPxToneUnit {
     pub(crate) selected: bool,
     pub(crate) muted: bool,
@@ -0,0 +1,298 @@
+//! RIFF WAVE reading for PCM woices
+//!
+//! PCM woices hold 8 or 16 bit samples in 1 or 2 channels, the same as og pxtone reads. Other
+//! sample formats can be converted to one of those on the way in with [`WavImportOptions`].
+
+use std::{
+    fmt,
+    io::{Cursor, Read},
+};
+
+use byteorder::{LittleEndian, ReadBytesExt};
+
+const WAVE_FORMAT_PCM: u16 = 1;
+const WAVE_FORMAT_IEEE_FLOAT: u16 = 3;
+const WAVE_FORMAT_EXTENSIBLE: u16 = 0xfffe;
+
+/// Rates kept as they are by [`WavImportOptions::convert_sample_rate`]
+const STANDARD_SAMPLE_RATES: [u32; 3] = [11025, 22050, 44100];
+const CONVERTED_SAMPLE_RATE: u32 = 44100;
+
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum WavError {
+    /// Not a RIFF WAVE file, or one whose format makes no sense, like a sample rate of 0
+    IncorrectHeader,
+    MissingChunk(&'static str),
+    /// A sample layout that isn't supported, or is only supported with conversion enabled
+    UnsupportedFormat {
+        format: u16,
+        channels: u16,
+        bits_per_sample: u16,
+    },
+    UnexpectedEnd,
+    /// The file couldn't be read at all
+    Io(std::io::Error),
+}
+
+impl fmt::Display for WavError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::IncorrectHeader => write!(f, "Not a valid RIFF WAVE file"),
+            Self::MissingChunk(id) => write!(f, "WAV file has no {:?} chunk", id.trim_end()),
+            Self::UnsupportedFormat { format, channels, bits_per_sample } => {
+                write!(
+                    f,
+                    "Unsupported WAV format {format} with {channels} channel(s) of \
+                     {bits_per_sample} bit samples"
+                )
+            },
+            Self::UnexpectedEnd => write!(f, "WAV file ended unexpectedly"),
+            Self::Io(e) => write!(f, "{e}"),
+        }
+    }
+}
+
+impl std::error::Error for WavError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Io(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<std::io::Error> for WavError {
+    fn from(_: std::io::Error) -> Self {
+        // reading from memory can only fail by running out of data
+        Self::UnexpectedEnd
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct WavImportOptions {
+    /// Convert 24 bit, 32 bit and floating point samples to 16 bit rather than rejecting them
+    pub convert_format: bool,
+    /// Resample anything not at 11025, 22050 or 44100Hz to 44100Hz rather than keeping its rate
+    pub convert_sample_rate: bool,
+}
+
+/// Sample data read from a WAV file, ready to pass to
+/// [`WoicesMut::add_pcm`](crate::interface::woice::WoicesMut::add_pcm)
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Wav {
+    /// 1 or 2
+    pub channels: u8,
+    pub samples_per_second: u32,
+    /// 8 or 16
+    pub bits_per_sample: u8,
+    /// Interleaved, unsigned if 8 bit and signed little endian if 16 bit
+    pub data: Vec<u8>,
+}
+
+#[derive(Clone, Copy)]
+enum SampleFormat {
+    U8,
+    I16,
+    I24,
+    I32,
+    F32,
+    F64,
+}
+
+impl SampleFormat {
+    fn bytes(self) -> usize {
+        match self {
+            Self::U8 => 1,
+            Self::I16 => 2,
+            Self::I24 => 3,
+            Self::I32 | Self::F32 => 4,
+            Self::F64 => 8,
+        }
+    }
+
+    #[allow(clippy::cast_precision_loss)]
+    fn decode(self, b: &[u8]) -> f32 {
+        match self {
+            Self::U8 => (f32::from(b[0]) - 128.0) / 128.0,
+            Self::I16 => f32::from(i16::from_le_bytes([b[0], b[1]])) / 32768.0,
+            Self::I24 => (i32::from_le_bytes([0, b[0], b[1], b[2]]) >> 8) as f32 / 8_388_608.0,
+            Self::I32 => i32::from_le_bytes([b[0], b[1], b[2], b[3]]) as f32 / 2_147_483_648.0,
+            Self::F32 => f32::from_le_bytes([b[0], b[1], b[2], b[3]]),
+            Self::F64 => f64::from_le_bytes(b[..8].try_into().unwrap()) as f32,
+        }
+    }
+}
+
+/// Read a WAV file's format and sample data
+///
+/// 8 and 16 bit data at a kept rate is passed through untouched. Anything converted comes out as
+/// 16 bit, or 8 bit if it was 8 bit to begin with.
+pub fn read(bytes: &[u8], options: &WavImportOptions) -> Result<Wav, WavError> {
+    let mut c = Cursor::new(bytes);
+
+    let mut riff = [0_u8; 12];
+    c.read_exact(&mut riff)?;
+    if &riff[0..4] != b"RIFF" || &riff[8..12] != b"WAVE" {
+        return Err(WavError::IncorrectHeader);
+    }
+
+    let mut fmt = None;
+    let mut data = None;
+    while fmt.is_none() || data.is_none() {
+        let mut id = [0_u8; 4];
+        if c.read(&mut id)? < id.len() {
+            break;
+        }
+        let size = c.read_u32::<LittleEndian>()? as usize;
+        let start = c.position() as usize;
+        let chunk = bytes
+            .get(start..start + size)
+            .ok_or(WavError::UnexpectedEnd)?;
+
+        match &id {
+            b"fmt " => fmt = Some(read_format(chunk)?),
+            b"data" => data = Some(chunk),
+            _ => {},
+        }
+
+        // chunks are padded to an even length
+        c.set_position((start + size + size % 2) as u64);
+    }
+
+    let (format, channels, samples_per_second, bits_per_sample, block_align) =
+        fmt.ok_or(WavError::MissingChunk("fmt "))?;
+    let data = data.ok_or(WavError::MissingChunk("data"))?;
+    if samples_per_second == 0 {
+        return Err(WavError::IncorrectHeader);
+    }
+
+    let unsupported = WavError::UnsupportedFormat { format, channels, bits_per_sample };
+    let sample_format = match (format, bits_per_sample) {
+        (WAVE_FORMAT_PCM, 8) => SampleFormat::U8,
+        (WAVE_FORMAT_PCM, 16) => SampleFormat::I16,
+        (WAVE_FORMAT_PCM, 24) => SampleFormat::I24,
+        (WAVE_FORMAT_PCM, 32) => SampleFormat::I32,
+        (WAVE_FORMAT_IEEE_FLOAT, 32) => SampleFormat::F32,
+        (WAVE_FORMAT_IEEE_FLOAT, 64) => SampleFormat::F64,
+        _ => return Err(unsupported),
+    };
+    if !matches!(channels, 1 | 2)
+        || usize::from(block_align) != sample_format.bytes() * usize::from(channels)
+    {
+        return Err(unsupported);
+    }
+
+    let frame_bytes = usize::from(block_align);
+    let data = &data[..data.len() - data.len() % frame_bytes];
+
+    let keep_format = matches!(sample_format, SampleFormat::U8 | SampleFormat::I16);
+    if !keep_format && !options.convert_format {
+        return Err(unsupported);
+    }
+
+    let keep_rate =
+        !options.convert_sample_rate || STANDARD_SAMPLE_RATES.contains(&samples_per_second);
+    let bits_per_sample = if matches!(sample_format, SampleFormat::U8) {
+        8
+    } else {
+        16
+    };
+
+    if keep_format && keep_rate {
+        return Ok(Wav {
+            channels: channels as u8,
+            samples_per_second,
+            bits_per_sample,
+            data: data.to_vec(),
+        });
+    }
+
+    let mut samples: Vec<f32> = data
+        .chunks_exact(sample_format.bytes())
+        .map(|b| sample_format.decode(b))
+        .collect();
+    let mut samples_per_second = samples_per_second;
+    if !keep_rate {
+        samples = resample(
+            &samples,
+            usize::from(channels),
+            samples_per_second,
+            CONVERTED_SAMPLE_RATE,
+        );
+        samples_per_second = CONVERTED_SAMPLE_RATE;
+    }
+
+    let data = if bits_per_sample == 8 {
+        samples
+            .iter()
+            .map(|s| (s * 128.0 + 128.0).round().clamp(0.0, 255.0) as u8)
+            .collect()
+    } else {
+        samples
+            .iter()
+            .flat_map(|s| ((s * 32768.0).round().clamp(-32768.0, 32767.0) as i16).to_le_bytes())
+            .collect()
+    };
+
+    Ok(Wav {
+        channels: channels as u8,
+        samples_per_second,
+        bits_per_sample,
+        data,
+    })
+}
+
+/// Returns the format tag, channels, sample rate, bits per sample and block align
+fn read_format(chunk: &[u8]) -> Result<(u16, u16, u32, u16, u16), WavError> {
+    let mut c = Cursor::new(chunk);
+    let mut format = c.read_u16::<LittleEndian>()?;
+    let channels = c.read_u16::<LittleEndian>()?;
+    let samples_per_second = c.read_u32::<LittleEndian>()?;
+    let _bytes_per_second = c.read_u32::<LittleEndian>()?;
+    let block_align = c.read_u16::<LittleEndian>()?;
+    let bits_per_sample = c.read_u16::<LittleEndian>()?;
+
+    if format == WAVE_FORMAT_EXTENSIBLE {
+        let _extension_size = c.read_u16::<LittleEndian>()?;
+        let _valid_bits = c.read_u16::<LittleEndian>()?;
+        let _channel_mask = c.read_u32::<LittleEndian>()?;
+        // the sub format GUID starts with the format tag it stands for
+        format = c.read_u16::<LittleEndian>()?;
+    }
+
+    Ok((
+        format,
+        channels,
+        samples_per_second,
+        bits_per_sample,
+        block_align,
+    ))
+}
+
+/// Linearly interpolate interleaved samples to a new rate
+#[allow(clippy::cast_precision_loss)]
+fn resample(samples: &[f32], channels: usize, from: u32, to: u32) -> Vec<f32> {
+    let frames = samples.len() / channels;
+    if frames == 0 {
+        return Vec::new();
+    }
+
+    // round up like og pxtone's conversion does
+    let new_frames = (frames as u64 * u64::from(to)).div_ceil(u64::from(from)) as usize;
+    let step = f64::from(from) / f64::from(to);
+
+    let mut out = Vec::with_capacity(new_frames * channels);
+    for i in 0..new_frames {
+        let pos = i as f64 * step;
+        let a = (pos as usize).min(frames - 1);
+        let b = (a + 1).min(frames - 1);
+        let t = (pos - a as f64) as f32;
+        for ch in 0..channels {
+            let sa = samples[a * channels + ch];
+            let sb = samples[b * channels + ch];
+            out.push(sa + (sb - sa) * t);
+        }
+    }
+    out
+}
@@ -1,24 +1,40 @@
-use std::{f32::consts::PI, io::Cursor};
+use std::{
+    f32::consts::PI,
+    fmt,
+    io::{self, Cursor},
+    time::Duration,
+};
 
-use lewton::{inside_ogg::OggStreamReader, VorbisError};
+use lewton::{header::HeaderReadError, inside_ogg::OggStreamReader, VorbisError};
 
 use crate::{
+    export::wav::write_pcm,
     interface::{
+        event::Key,
         service::InvalidText,
         woice::{
-            HasWoices, PTNEnvelopePoint, PTNOscillator, PTNUnit, PTNWaveType, PTVCoordinateWave, PTVCoordinateWavePoint, PTVEnvelope, PTVOvertoneWave, PTVOvertoneWaveTone, PTVWaveType, SingleVoice, Voice, VoiceOGGV, VoicePCM, VoicePTN, VoicePTV, Woice, WoiceOGGV, WoicePCM, WoicePTN, WoicePTV, WoiceTypeMut, WoiceTypeRef, Woices, WoicesMut
+            HasWoices, PTNEnvelopePoint, PTNOscillator, PTNUnit, PTNWaveType, PTVCoordinateWave,
+            PTVCoordinateWavePoint, PTVEnvelope, PTVOvertoneWave, PTVOvertoneWaveTone, PTVWaveType,
+            SingleVoice, Voice, VoiceOGGV, VoicePCM, VoicePTN, VoicePTV, Woice, WoiceOGGV,
+            WoicePCM, WoicePTN, WoicePTV, WoiceTypeMut, WoiceTypeRef, Woices, WoicesMut,
         },
     },
     util::{BoxOrMut, BoxOrRef},
 };
 
-use super::service::RPxTone;
+use super::{
+    moo, noise_builder, ptnoise, ptvoice,
+    service::RPxTone,
+    wav::{self, WavError, WavImportOptions},
+};
 
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct RPxToneWoice {
     pub(crate) name: String,
     pub(crate) woice_type: RPxToneWoiceType,
 }
 
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum RPxToneWoiceType {
     PCM(RPxToneWoicePCM),
     PTV(RPxToneWoicePTV),
@@ -26,6 +42,61 @@ pub enum RPxToneWoiceType {
     OGGV(RPxToneWoiceOGGV),
 }
 
+/// Options for [`RPxToneWoice::export_note_wav`]
+#[derive(Debug, Clone, PartialEq)]
+pub struct NoteExportOptions {
+    pub key: Key,
+    /// How long the note is held for, envelope releases play out after it
+    pub length: Duration,
+    /// 1 (mono) or 2 (stereo)
+    pub channels: u8,
+    pub sample_rate: u32,
+}
+
+impl Default for NoteExportOptions {
+    fn default() -> Self {
+        Self {
+            key: Key::DEFAULT,
+            length: Duration::from_secs(1),
+            channels: 2,
+            sample_rate: 44100,
+        }
+    }
+}
+
+impl RPxToneWoice {
+    /// Render a single note of this woice to a 16 bit `.wav` file
+    ///
+    /// This works for every woice type, so it can turn PTV and PTN woices into plain samples. The
+    /// note is played like a unit with no effects would play it, at a level where a PCM woice on
+    /// its own key comes out as it was recorded.
+    #[allow(clippy::cast_possible_truncation)]
+    pub fn export_note_wav<W: io::Write>(
+        &self,
+        writer: W,
+        options: &NoteExportOptions,
+    ) -> io::Result<()> {
+        if !(1..=2).contains(&options.channels) || options.sample_rate == 0 {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "channels must be 1 or 2 and sample_rate must not be 0",
+            ));
+        }
+
+        let length = (options.length.as_secs_f64() * f64::from(options.sample_rate)) as u32;
+        let samples = moo::render_note(
+            self,
+            *options.key,
+            length,
+            options.channels,
+            options.sample_rate,
+        );
+        let data: Vec<u8> = samples.iter().flat_map(|s| s.to_le_bytes()).collect();
+        write_pcm(writer, options.channels, options.sample_rate, 16, &data)
+    }
+}
+
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct RPxToneWoicePCM {
     pub(crate) voice: RPxToneVoicePCM,
 }
@@ -96,7 +167,7 @@ impl RPxToneVoicePCM {
                 .collect(),
             (16, 1) => data
                 .chunks_exact(2)
-                .map(|a| i16::from_ne_bytes([a[0], a[1]]) as f32 / i16::MAX as f32 / 2.0)
+                .map(|a| i16::from_le_bytes([a[0], a[1]]) as f32 / i16::MAX as f32 / 2.0)
                 .collect(),
             (8, 2) => data
                 .chunks_exact(2)
@@ -111,8 +182,8 @@ impl RPxToneVoicePCM {
                 .chunks_exact(4)
                 .flat_map(|a| {
                     [
-                        i16::from_ne_bytes([a[0], a[1]]) as f32 / i16::MAX as f32 / 2.0,
-                        i16::from_ne_bytes([a[2], a[3]]) as f32 / i16::MAX as f32 / 2.0,
+                        i16::from_le_bytes([a[0], a[1]]) as f32 / i16::MAX as f32 / 2.0,
+                        i16::from_le_bytes([a[2], a[3]]) as f32 / i16::MAX as f32 / 2.0,
                     ]
                 })
                 .collect(),
@@ -135,6 +206,58 @@ impl RPxToneVoicePCM {
             ratio_to_a,
         })
     }
+
+    /// Create a voice from the bytes of a WAV file, with the same defaults og pxtone gives one
+    ///
+    /// Like og, samples shorter than 5ms are looped.
+    #[allow(clippy::cast_precision_loss)]
+    pub fn from_wav(bytes: &[u8], options: &WavImportOptions) -> Result<Self, WavError> {
+        let wav = wav::read(bytes, options)?;
+        let frames =
+            wav.data.len() / (usize::from(wav.bits_per_sample / 8) * usize::from(wav.channels));
+        let looped = (frames as f32) / (wav.samples_per_second as f32) < 0.005;
+
+        Self::new(
+            0x4500,
+            128,
+            64,
+            1.0,
+            wav.channels,
+            wav.samples_per_second,
+            wav.bits_per_sample,
+            wav.data,
+            looped,
+            true,
+            false,
+        )
+        .map_err(
+            |RPxToneVoicePCMError::InvalidPCMConfig { bits_per_sample, channels }| {
+                WavError::UnsupportedFormat {
+                    format: 1,
+                    channels: channels.into(),
+                    bits_per_sample: bits_per_sample.into(),
+                }
+            },
+        )
+    }
+
+    /// The sample data in the same format [`RPxToneVoicePCM::new`] takes it
+    #[must_use]
+    #[allow(clippy::cast_precision_loss)]
+    pub fn data(&self) -> Vec<u8> {
+        match self.bits_per_sample {
+            8 => self
+                .samples
+                .iter()
+                .map(|s| ((s + 0.5) * u8::MAX as f32).round() as u8)
+                .collect(),
+            _ => self
+                .samples
+                .iter()
+                .flat_map(|s| ((s * 2.0 * i16::MAX as f32).round() as i16).to_le_bytes())
+                .collect(),
+        }
+    }
 }
 
 impl Voice for RPxToneVoicePCM {
@@ -169,6 +292,14 @@ impl Voice for RPxToneVoicePCM {
     fn set_tuning(&mut self, tuning: f32) {
         self.tuning = tuning;
     }
+
+    fn flag_loop(&self) -> bool {
+        self.flag_loop
+    }
+
+    fn flag_smooth(&self) -> bool {
+        self.flag_smooth
+    }
 }
 
 impl VoicePCM for RPxToneVoicePCM {
@@ -184,6 +315,20 @@ impl VoicePCM for RPxToneVoicePCM {
         self.bits_per_sample
     }
 
+    fn sample_num(&self) -> u32 {
+        self.sample_num
+    }
+
+    fn export_wav<W: io::Write>(&self, writer: W) -> io::Result<()> {
+        write_pcm(
+            writer,
+            self.channels,
+            self.samples_per_second,
+            self.bits_per_sample,
+            &self.data(),
+        )
+    }
+
     #[allow(clippy::cast_precision_loss)]
     #[allow(clippy::inline_always)]
     #[inline(always)] // this function is very hot
@@ -206,6 +351,21 @@ impl VoicePCM for RPxToneVoicePCM {
     }
 }
 
+/// Write samples decoded for playback as a 16 bit `.wav`
+fn write_samples<W: io::Write>(
+    writer: W,
+    channels: u8,
+    samples_per_second: u32,
+    samples: &[f32],
+) -> io::Result<()> {
+    let data: Vec<u8> = samples
+        .iter()
+        .flat_map(|s| ((s * 2.0 * f32::from(i16::MAX)).round() as i16).to_le_bytes())
+        .collect();
+    write_pcm(writer, channels, samples_per_second, 16, &data)
+}
+
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct RPxToneWoicePTV {
     pub(crate) voices: Vec<RPxToneVoicePTV>,
 }
@@ -221,6 +381,10 @@ impl WoicePTV<RPxToneVoicePTV> for RPxToneWoicePTV {
     fn voices(&self) -> Vec<&RPxToneVoicePTV> {
         self.voices.iter().collect()
     }
+
+    fn export_ptv<W: io::Write>(&self, writer: W) -> io::Result<()> {
+        ptvoice::write(self, writer)
+    }
 }
 
 pub struct RPxToneVoicePTV {
@@ -229,6 +393,10 @@ pub struct RPxToneVoicePTV {
     pub(crate) pan: i32,
     pub(crate) tuning: f32,
 
+    pub(crate) flag_loop: bool,
+    pub(crate) flag_smooth: bool,
+    pub(crate) flag_beat_fit: bool,
+
     pub(crate) wave: RPxTonePTVWaveType,
     pub(crate) envelope: RPXTonePTVEnvelope,
 
@@ -238,6 +406,7 @@ pub struct RPxToneVoicePTV {
 
 impl RPxToneVoicePTV {
     #[must_use]
+    #[allow(clippy::too_many_arguments)]
     pub fn new(
         basic_key: i32,
         volume: i32,
@@ -245,6 +414,9 @@ impl RPxToneVoicePTV {
         tuning: f32,
         wave: RPxTonePTVWaveType,
         envelope: RPXTonePTVEnvelope,
+        flag_loop: bool,
+        flag_smooth: bool,
+        flag_beat_fit: bool,
     ) -> Self {
         let sample_num = 400;
         let channels = 2;
@@ -277,6 +449,9 @@ impl RPxToneVoicePTV {
             volume,
             pan,
             tuning,
+            flag_loop,
+            flag_smooth,
+            flag_beat_fit,
             wave,
             envelope,
             samples,
@@ -285,6 +460,7 @@ impl RPxToneVoicePTV {
     }
 }
 
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum RPxTonePTVWaveType {
     Coordinate(RPxTonePTVCoordinateWave),
     Overtone(RPxTonePTVOvertoneWave),
@@ -322,6 +498,14 @@ impl Voice for RPxToneVoicePTV {
     fn set_tuning(&mut self, tuning: f32) {
         self.tuning = tuning;
     }
+
+    fn flag_loop(&self) -> bool {
+        self.flag_loop
+    }
+
+    fn flag_smooth(&self) -> bool {
+        self.flag_smooth
+    }
 }
 
 impl VoicePTV for RPxToneVoicePTV {
@@ -335,7 +519,7 @@ impl VoicePTV for RPxToneVoicePTV {
             RPxTonePTVWaveType::Overtone(o) => PTVWaveType::Overtone(o),
         }
     }
-    
+
     fn envelope(&self) -> &Self::Envelope {
         &self.envelope
     }
@@ -354,6 +538,17 @@ impl VoicePCM for RPxToneVoicePTV {
         16
     }
 
+    #[allow(clippy::cast_possible_truncation)]
+    fn sample_num(&self) -> u32 {
+        (self.samples.len() / 2) as u32
+    }
+
+    /// Writes the single cycle of the wave the voice loops, see
+    /// [`RPxToneWoice::export_note_wav`] for rendering an actual note of it
+    fn export_wav<W: io::Write>(&self, writer: W) -> io::Result<()> {
+        write_samples(writer, 2, 44100, &self.samples)
+    }
+
     #[allow(clippy::cast_precision_loss)]
     #[allow(clippy::inline_always)]
     #[inline(always)] // this function is very hot
@@ -367,6 +562,7 @@ impl VoicePCM for RPxToneVoicePTV {
     }
 }
 
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct RPxTonePTVCoordinateWave {
     pub(crate) resolution: u32,
     pub(crate) points: Vec<RPxTonePTVCoordinatePoint>,
@@ -422,6 +618,7 @@ impl PTVCoordinateWave for RPxTonePTVCoordinateWave {
     }
 }
 
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct RPxTonePTVCoordinatePoint {
     pub(crate) x: u32,
     pub(crate) y: i32,
@@ -443,6 +640,7 @@ impl PTVCoordinateWavePoint for RPxTonePTVCoordinatePoint {
     }
 }
 
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct RPxTonePTVOvertoneWave {
     pub(crate) tones: Vec<RPxTonePTVOvertoneWaveTone>,
 }
@@ -466,6 +664,7 @@ impl PTVOvertoneWave for RPxTonePTVOvertoneWave {
     }
 }
 
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct RPxTonePTVOvertoneWaveTone {
     pub(crate) frequency: u8,
     pub(crate) amplitude: i16,
@@ -482,6 +681,7 @@ impl PTVOvertoneWaveTone for RPxTonePTVOvertoneWaveTone {
 }
 
 #[derive(Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct RPXTonePTVEnvelope {
     pub(crate) fps: u32,
     pub(crate) head_num: u32,
@@ -514,25 +714,18 @@ impl PTVEnvelope for RPXTonePTVEnvelope {
     }
 }
 
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct RPxToneWoicePTN {
     pub(crate) voice: RPxToneVoicePTN,
 }
 
 impl Default for RPxToneWoicePTN {
     fn default() -> Self {
-        Self {
-            voice: RPxToneVoicePTN {
-                basic_key: 17664,
-                volume: 100,
-                pan: 64,
-                tuning: 1.0,
-                channels: 1,
-                samples_per_second: 44100,
-                bits_per_sample: 8,
-                ptn_sample_num: 0,
-                ptn_units: vec![],
-            },
-        }
+        let mut voice = RPxToneVoicePTN::new(17664, 100, 64, 1.0, 0, vec![], false, true, false);
+        voice.channels = 1;
+        voice.bits_per_sample = 8;
+
+        Self { voice }
     }
 }
 
@@ -546,7 +739,11 @@ impl SingleVoice<RPxToneVoicePTN> for RPxToneWoicePTN {
     }
 }
 
-impl WoicePTN<RPxToneVoicePTN> for RPxToneWoicePTN {}
+impl WoicePTN<RPxToneVoicePTN> for RPxToneWoicePTN {
+    fn export_ptn<W: io::Write>(&self, writer: W) -> io::Result<()> {
+        ptnoise::write(&self.voice, writer)
+    }
+}
 
 pub struct RPxToneVoicePTN {
     pub(crate) basic_key: i32,
@@ -554,12 +751,54 @@ pub struct RPxToneVoicePTN {
     pub(crate) pan: i32,
     pub(crate) tuning: f32,
 
+    pub(crate) flag_loop: bool,
+    pub(crate) flag_smooth: bool,
+    pub(crate) flag_beat_fit: bool,
+
     pub(crate) channels: u8,
     pub(crate) samples_per_second: u32,
     pub(crate) bits_per_sample: u8,
 
     pub(crate) ptn_sample_num: u32,
     pub(crate) ptn_units: Vec<RPxTonePTNUnit>,
+
+    /// Built from the design when the voice is made, as 44100Hz stereo
+    pub(crate) samples: Vec<f32>,
+}
+
+impl RPxToneVoicePTN {
+    /// Build a voice's noise from its design, which is too slow to do while playing
+    #[allow(clippy::too_many_arguments)]
+    #[must_use]
+    pub fn new(
+        basic_key: i32,
+        volume: i32,
+        pan: i32,
+        tuning: f32,
+        ptn_sample_num: u32,
+        ptn_units: Vec<RPxTonePTNUnit>,
+        flag_loop: bool,
+        flag_smooth: bool,
+        flag_beat_fit: bool,
+    ) -> Self {
+        let samples = noise_builder::build(ptn_sample_num, &ptn_units);
+
+        Self {
+            basic_key,
+            volume,
+            pan,
+            tuning,
+            flag_loop,
+            flag_smooth,
+            flag_beat_fit,
+            channels: 2,
+            samples_per_second: 44100,
+            bits_per_sample: 16,
+            ptn_sample_num,
+            ptn_units,
+            samples,
+        }
+    }
 }
 
 impl Voice for RPxToneVoicePTN {
@@ -594,6 +833,14 @@ impl Voice for RPxToneVoicePTN {
     fn set_tuning(&mut self, tuning: f32) {
         self.tuning = tuning;
     }
+
+    fn flag_loop(&self) -> bool {
+        self.flag_loop
+    }
+
+    fn flag_smooth(&self) -> bool {
+        self.flag_smooth
+    }
 }
 
 impl VoicePCM for RPxToneVoicePTN {
@@ -609,8 +856,42 @@ impl VoicePCM for RPxToneVoicePTN {
         self.bits_per_sample
     }
 
-    fn sample(&self, _cycle: f32, _channel: u8) -> f32 {
-        todo!()
+    /// Frames of the built noise, which is always stereo
+    #[allow(clippy::cast_possible_truncation)]
+    fn sample_num(&self) -> u32 {
+        (self.samples.len() / 2) as u32
+    }
+
+    /// Writes the built noise, which is always 16 bit stereo at 44100Hz
+    fn export_wav<W: io::Write>(&self, writer: W) -> io::Result<()> {
+        write_samples(writer, 2, 44100, &self.samples)
+    }
+
+    #[allow(clippy::cast_precision_loss)]
+    #[allow(clippy::inline_always)]
+    #[inline(always)] // this function is very hot
+    fn sample(&self, cycle: f32, channel: u8) -> f32 {
+        // always stereo, whatever `channels` says
+        let samples = &self.samples;
+        let frames = samples.len() / 2;
+        if frames == 0 {
+            return 0.0;
+        }
+
+        // played like a 44100Hz PCM woice, so 200 samples is A
+        let semitone_key_offset = (17664 - self.basic_key) as f32 / 256.0;
+        let ratio_to_a = frames as f32 / 200.0 / 2_f32.powf(semitone_key_offset / 12.0);
+        let idx = cycle / ratio_to_a * self.tuning;
+
+        let frame = (frames as f32 * idx) as usize;
+        let frame = if self.flag_loop {
+            frame % frames
+        } else if frame < frames {
+            frame
+        } else {
+            return 0.0;
+        };
+        samples[frame * 2 + usize::from(channel.min(1))]
     }
 }
 
@@ -626,6 +907,7 @@ impl VoicePTN for RPxToneVoicePTN {
     }
 }
 
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct RPxTonePTNUnit {
     pub(crate) enabled: bool,
     pub(crate) pan: i8,
@@ -665,6 +947,7 @@ impl PTNUnit for RPxTonePTNUnit {
 }
 
 #[derive(Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct RPxTonePTNEnvelopePoint {
     pub(crate) x: u32,
     pub(crate) y: u8,
@@ -680,6 +963,7 @@ impl PTNEnvelopePoint for RPxTonePTNEnvelopePoint {
     }
 }
 
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct RPxTonePTNOscillator {
     pub(crate) shape: PTNWaveType,
     pub(crate) frequency: f32,
@@ -730,6 +1014,7 @@ impl PTNOscillator for RPxTonePTNOscillator {
     }
 }
 
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct RPxToneWoiceOGGV {
     pub(crate) voice: RPxToneVoiceOGGV,
 }
@@ -776,6 +1061,47 @@ pub enum RPxToneVoiceOGGVError {
         channels: u8,
     },
     VorbisError(VorbisError),
+    /// An Ogg stream carrying something other than Vorbis, like Opus or FLAC
+    NotVorbis,
+    /// The file couldn't be read at all
+    Io(std::io::Error),
+}
+
+impl fmt::Display for RPxToneVoiceOGGVError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::InvalidOGGVConfig { samples_per_second, channels } => {
+                write!(
+                    f,
+                    "Invalid Ogg Vorbis config: {channels} channel(s) at {samples_per_second}Hz"
+                )
+            },
+            Self::VorbisError(e) => write!(f, "{e}"),
+            Self::NotVorbis => write!(f, "Ogg stream isn't Vorbis"),
+            Self::Io(e) => write!(f, "{e}"),
+        }
+    }
+}
+
+impl std::error::Error for RPxToneVoiceOGGVError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::VorbisError(e) => Some(e),
+            Self::Io(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<VorbisError> for RPxToneVoiceOGGVError {
+    fn from(e: VorbisError) -> Self {
+        match e {
+            VorbisError::BadHeader(
+                HeaderReadError::NotVorbisHeader | HeaderReadError::HeaderIsAudio,
+            ) => Self::NotVorbis,
+            e => Self::VorbisError(e),
+        }
+    }
 }
 
 impl RPxToneVoiceOGGV {
@@ -801,17 +1127,13 @@ impl RPxToneVoiceOGGV {
         let semitone_key_offset = (17664 - basic_key) as f32 / 256.0;
         let ratio_to_a = ratio_to_a / 2_f32.powf(semitone_key_offset / 12.0);
 
-        let mut ogg_reader = OggStreamReader::new(Cursor::new(&ogg_data))
-            .map_err(RPxToneVoiceOGGVError::VorbisError)?;
+        let mut ogg_reader = OggStreamReader::new(Cursor::new(&ogg_data))?;
         let ogg_samples_per_second = ogg_reader.ident_hdr.audio_sample_rate;
         let ogg_channels = ogg_reader.ident_hdr.audio_channels;
 
         let mut samples = vec![];
 
-        while let Some(raw_samples) = ogg_reader
-            .read_dec_packet_itl()
-            .map_err(RPxToneVoiceOGGVError::VorbisError)?
-        {
+        while let Some(raw_samples) = ogg_reader.read_dec_packet_itl()? {
             if ogg_channels == 2 {
                 samples.extend(raw_samples.chunks_exact(2).flat_map(|a| {
                     [
@@ -847,6 +1169,40 @@ impl RPxToneVoiceOGGV {
             ogg_data,
         })
     }
+
+    /// Create a voice from the bytes of an Ogg Vorbis file, with the same defaults og pxtone gives
+    /// one
+    ///
+    /// The channel count and rate come from the Vorbis headers, and the sample count from the
+    /// stream's last granule position like og's `ov_pcm_total`.
+    pub fn from_ogg(data: Vec<u8>) -> Result<Self, RPxToneVoiceOGGVError> {
+        let ogg_reader = OggStreamReader::new(Cursor::new(&data))?;
+        let channels = ogg_reader.ident_hdr.audio_channels;
+        let samples_per_second = ogg_reader.ident_hdr.audio_sample_rate;
+        let serial = ogg_reader.stream_serial();
+
+        let mut packets = ogg_reader.into_inner();
+        let mut sample_num = 0;
+        while let Some(packet) = packets.read_packet().map_err(VorbisError::from)? {
+            if packet.stream_serial() == serial {
+                sample_num = packet.absgp_page();
+            }
+        }
+
+        Self::new(
+            0x4500,
+            128,
+            64,
+            1.0,
+            channels,
+            samples_per_second,
+            sample_num as u32,
+            data,
+            false,
+            true,
+            false,
+        )
+    }
 }
 
 impl Voice for RPxToneVoiceOGGV {
@@ -881,6 +1237,14 @@ impl Voice for RPxToneVoiceOGGV {
     fn set_tuning(&mut self, tuning: f32) {
         self.tuning = tuning;
     }
+
+    fn flag_loop(&self) -> bool {
+        self.flag_loop
+    }
+
+    fn flag_smooth(&self) -> bool {
+        self.flag_smooth
+    }
 }
 
 impl VoicePCM for RPxToneVoiceOGGV {
@@ -896,6 +1260,20 @@ impl VoicePCM for RPxToneVoiceOGGV {
         8 // TODO: does ogg actually have this?
     }
 
+    fn sample_num(&self) -> u32 {
+        self.sample_num
+    }
+
+    /// Writes the decoded stream as 16 bit
+    fn export_wav<W: io::Write>(&self, writer: W) -> io::Result<()> {
+        write_samples(
+            writer,
+            self.channels,
+            self.samples_per_second,
+            &self.samples,
+        )
+    }
+
     #[allow(clippy::cast_precision_loss)]
     #[allow(clippy::inline_always)]
     #[inline(always)] // this function is very hot
@@ -1006,6 +1384,9 @@ impl Woices for RPxTone {
 }
 
 impl WoicesMut for RPxTone {
+    type WavError = WavError;
+    type OggError = RPxToneVoiceOGGVError;
+
     fn iter_mut<'a>(&'a mut self) -> Box<dyn Iterator<Item = BoxOrMut<Self::W>> + 'a> {
         Box::new(self.woices.iter_mut().map(BoxOrMut::Ref))
     }
@@ -1036,32 +1417,117 @@ impl WoicesMut for RPxTone {
         })
     }
 
+    fn add_pcm(
+        &mut self,
+        channels: u8,
+        samples_per_second: u32,
+        bits_per_sample: u8,
+        data: Vec<u8>,
+        basic_key: i32,
+        looped: bool,
+    ) -> Option<BoxOrMut<'_, <Self::W as Woice>::PCM>> {
+        let voice = RPxToneVoicePCM::new(
+            basic_key,
+            128,
+            64,
+            1.0,
+            channels,
+            samples_per_second,
+            bits_per_sample,
+            data,
+            looped,
+            true,
+            false,
+        )
+        .ok()?;
+        self.woices.push(RPxToneWoice {
+            name: String::new(),
+            woice_type: RPxToneWoiceType::PCM(RPxToneWoicePCM { voice }),
+        });
+        self.woices.last_mut().map(|r| {
+            BoxOrMut::Ref(match &mut r.woice_type {
+                RPxToneWoiceType::PCM(w) => w,
+                _ => unreachable!(),
+            })
+        })
+    }
+
     fn add_pcm_from_file<P: AsRef<std::path::Path>>(
         &mut self,
-        _path: P,
-    ) -> Option<BoxOrMut<<Self::W as Woice>::PCM>> {
-        todo!()
+        path: P,
+    ) -> Result<BoxOrMut<'_, <Self::W as Woice>::PCM>, WavError> {
+        self.add_pcm_from_bytes(&std::fs::read(path).map_err(WavError::Io)?)
+    }
+
+    fn add_pcm_from_bytes(
+        &mut self,
+        bytes: &[u8],
+    ) -> Result<BoxOrMut<'_, <Self::W as Woice>::PCM>, WavError> {
+        let voice = RPxToneVoicePCM::from_wav(bytes, &WavImportOptions::default())?;
+        self.woices.push(RPxToneWoice {
+            name: String::new(),
+            woice_type: RPxToneWoiceType::PCM(RPxToneWoicePCM { voice }),
+        });
+        match &mut self.woices.last_mut().unwrap().woice_type {
+            RPxToneWoiceType::PCM(w) => Ok(BoxOrMut::Ref(w)),
+            _ => unreachable!(),
+        }
     }
 
     fn add_ptv_from_file<P: AsRef<std::path::Path>>(
         &mut self,
-        _path: P,
+        path: P,
     ) -> Option<BoxOrMut<<Self::W as Woice>::PTV>> {
-        todo!()
+        let woice = ptvoice::read(&std::fs::read(path).ok()?).ok()?;
+        self.woices.push(RPxToneWoice {
+            name: String::new(),
+            woice_type: RPxToneWoiceType::PTV(woice),
+        });
+        self.woices.last_mut().map(|r| {
+            BoxOrMut::Ref(match &mut r.woice_type {
+                RPxToneWoiceType::PTV(w) => w,
+                _ => unreachable!(),
+            })
+        })
     }
 
     fn add_ptn_from_file<P: AsRef<std::path::Path>>(
         &mut self,
-        _path: P,
+        path: P,
     ) -> Option<BoxOrMut<<Self::W as Woice>::PTN>> {
-        todo!()
+        let voice = ptnoise::read(&std::fs::read(path).ok()?).ok()?;
+        self.woices.push(RPxToneWoice {
+            name: String::new(),
+            woice_type: RPxToneWoiceType::PTN(RPxToneWoicePTN { voice }),
+        });
+        self.woices.last_mut().map(|r| {
+            BoxOrMut::Ref(match &mut r.woice_type {
+                RPxToneWoiceType::PTN(w) => w,
+                _ => unreachable!(),
+            })
+        })
     }
 
     fn add_oggv_from_file<P: AsRef<std::path::Path>>(
         &mut self,
-        _path: P,
-    ) -> Option<BoxOrMut<<Self::W as Woice>::OGGV>> {
-        todo!()
+        path: P,
+    ) -> Result<BoxOrMut<'_, <Self::W as Woice>::OGGV>, RPxToneVoiceOGGVError> {
+        self.add_oggv_from_bytes(&std::fs::read(path).map_err(RPxToneVoiceOGGVError::Io)?)
+    }
+
+    fn add_oggv_from_bytes(
+        &mut self,
+        bytes: &[u8],
+    ) -> Result<BoxOrMut<'_, <Self::W as Woice>::OGGV>, RPxToneVoiceOGGVError> {
+        let voice = RPxToneVoiceOGGV::from_ogg(bytes.to_vec())?;
+        self.woices.push(RPxToneWoice {
+            name: String::new(),
+            woice_type: RPxToneWoiceType::OGGV(RPxToneWoiceOGGV { voice }),
+        });
+        match &mut self.woices.last_mut().unwrap().woice_type {
+            RPxToneWoiceType::OGGV(w) => Ok(BoxOrMut::Ref(w)),
+            _ => unreachable!(),
+        }
     }
 
     fn remove(&mut self, index: usize) -> bool {
@@ -0,0 +1,370 @@
+//! Conversions between clock ticks, seconds, samples and measures/beats
+//!
+//! ```
+//! use pxtone::{
+//!     interface::{event::HasEventList, service::PxTone},
+//!     time::{HasTempoMap, MusicalTime},
+//! };
+//!
+//! # fn f(pxtone: &(impl PxTone + HasEventList)) {
+//! let tempo_map = pxtone.tempo_map();
+//! let clock = tempo_map.musical_to_clock(MusicalTime { measure: 2, beat: 1, tick: 0 });
+//! println!("measure 3, beat 2 is at {}s", tempo_map.clock_to_secs(clock));
+//! # }
+//! ```
+
+use std::time::Duration;
+
+use crate::interface::{
+    event::{BaseEvent, EventKind, EventList, GenericEvent, HasEventList},
+    service::PxTone,
+};
+
+/// The values that decide how fast a song plays
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Tempo {
+    /// Beats per measure
+    pub beat_num: i32,
+    /// Beats per minute
+    pub beat_tempo: f32,
+    /// Ticks per beat
+    pub beat_clock: i32,
+}
+
+impl Tempo {
+    pub fn of<P: PxTone + ?Sized>(pxtone: &P) -> Self {
+        Self {
+            beat_num: pxtone.beat_num(),
+            beat_tempo: pxtone.beat_tempo(),
+            beat_clock: pxtone.beat_clock(),
+        }
+    }
+
+    #[must_use]
+    pub fn ticks_per_sec(&self) -> f64 {
+        f64::from(self.beat_clock) * f64::from(self.beat_tempo) / 60.0
+    }
+
+    #[must_use]
+    pub fn clocks_per_measure(&self) -> u32 {
+        self.beat_clock.max(1) as u32 * self.beat_num.max(1) as u32
+    }
+
+    fn apply(&mut self, change: TempoChange) {
+        match change {
+            TempoChange::BeatClock(beat_clock) => self.beat_clock = beat_clock,
+            TempoChange::BeatTempo(beat_tempo) => self.beat_tempo = beat_tempo,
+            TempoChange::BeatNum(beat_num) => self.beat_num = beat_num,
+        }
+    }
+}
+
+/// A change to one of the [`Tempo`] values partway through a song
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TempoChange {
+    BeatClock(i32),
+    BeatTempo(f32),
+    BeatNum(i32),
+}
+
+impl TempoChange {
+    /// Read a change from a raw event, as stored in project files.
+    ///
+    /// Returns `None` for every other kind of event.
+    #[must_use]
+    pub fn from_raw(kind: EventKind, value: u32) -> Option<Self> {
+        match kind {
+            EventKind::BeatClock => Some(Self::BeatClock(value as i32)),
+            // stored as the bits of the float
+            EventKind::BeatTempo => Some(Self::BeatTempo(f32::from_bits(value))),
+            EventKind::BeatNum => Some(Self::BeatNum(value as i32)),
+            _ => None,
+        }
+    }
+}
+
+/// Position in a song counted in measures, beats and ticks, all starting at `0`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Default)]
+pub struct MusicalTime {
+    pub measure: u32,
+    pub beat: u32,
+    pub tick: u32,
+}
+
+#[derive(Debug, Clone)]
+struct Segment {
+    clock: u32,
+    secs: f64,
+    measure: u32,
+    tempo: Tempo,
+}
+
+impl Segment {
+    /// Beats from the start of the segment to `clock`
+    fn beats_until(&self, clock: u32) -> f64 {
+        f64::from(clock - self.clock) / f64::from(self.tempo.beat_clock.max(1))
+    }
+
+    fn secs_at(&self, clock: u32) -> f64 {
+        self.secs + self.beats_until(clock) * 60.0 / f64::from(self.tempo.beat_tempo)
+    }
+}
+
+/// Converts between the different ways of measuring time in a song.
+///
+/// Songs play at the tempo of the project, changed partway through by any tempo events (see
+/// [`HasTempoMap::tempo_map`] and [`TempoMap::with_changes`]). Measures are counted from the start of the song, so changing the
+/// beat length or number of beats is best done at the start of a measure; a measure that gets
+/// cut short by a change counts as a whole one.
+#[derive(Debug, Clone)]
+pub struct TempoMap {
+    segments: Vec<Segment>,
+    repeat_measure: u32,
+    end_measure: u32,
+}
+
+impl TempoMap {
+    pub fn new<P: PxTone + ?Sized>(pxtone: &P) -> Self {
+        let last_measure = pxtone.last_measure();
+        let end_measure = if last_measure > 0 {
+            last_measure
+        } else {
+            pxtone.num_measures()
+        };
+
+        Self {
+            segments: vec![Segment {
+                clock: 0,
+                secs: 0.0,
+                measure: 0,
+                tempo: Tempo::of(pxtone),
+            }],
+            repeat_measure: pxtone.repeat_measure().clamp(0, end_measure.max(0)) as u32,
+            end_measure: end_measure.max(0) as u32,
+        }
+    }
+
+    /// Apply tempo changes at the given clocks, in any order.
+    #[must_use]
+    pub fn with_changes(mut self, changes: impl IntoIterator<Item = (u32, TempoChange)>) -> Self {
+        let mut changes: Vec<_> = changes.into_iter().collect();
+        changes.sort_by_key(|(clock, _)| *clock);
+
+        self.segments.truncate(1);
+        for (clock, change) in changes {
+            let last_index = self.segments.len() - 1;
+            let last = &mut self.segments[last_index];
+            if last.clock == clock {
+                last.tempo.apply(change);
+                continue;
+            }
+
+            let cpm = last.tempo.clocks_per_measure();
+            let mut tempo = last.tempo;
+            tempo.apply(change);
+            let segment = Segment {
+                clock,
+                secs: last.secs_at(clock),
+                measure: last.measure + (clock - last.clock).div_ceil(cpm),
+                tempo,
+            };
+            self.segments.push(segment);
+        }
+
+        self
+    }
+
+    fn segment_at_clock(&self, clock: u32) -> &Segment {
+        let i = self.segments.partition_point(|s| s.clock <= clock);
+        &self.segments[i.saturating_sub(1)]
+    }
+
+    fn segment_at_measure(&self, measure: u32) -> &Segment {
+        let i = self.segments.partition_point(|s| s.measure <= measure);
+        &self.segments[i.saturating_sub(1)]
+    }
+
+    /// Tempo in effect at `clock`
+    #[must_use]
+    pub fn tempo_at(&self, clock: u32) -> Tempo {
+        self.segment_at_clock(clock).tempo
+    }
+
+    #[must_use]
+    pub fn clock_to_secs(&self, clock: u32) -> f64 {
+        self.segment_at_clock(clock).secs_at(clock)
+    }
+
+    #[must_use]
+    pub fn secs_to_clock(&self, secs: f64) -> u32 {
+        self.secs_to_ticks(secs) as u32
+    }
+
+    /// Like [`TempoMap::secs_to_clock`], but keeping the fraction of a tick
+    #[must_use]
+    pub fn secs_to_ticks(&self, secs: f64) -> f64 {
+        let i = self.segments.partition_point(|s| s.secs <= secs);
+        let segment = &self.segments[i.saturating_sub(1)];
+        f64::from(segment.clock) + (secs - segment.secs).max(0.0) * segment.tempo.ticks_per_sec()
+    }
+
+    /// Sample at `clock`, rounded down the same way og pxtone does
+    #[must_use]
+    pub fn clock_to_sample(&self, clock: u32, sample_rate: u32) -> u64 {
+        let segment = self.segment_at_clock(clock);
+        let sample_rate = f64::from(sample_rate);
+        let beats = segment.beats_until(clock);
+        (segment.secs * sample_rate
+            + sample_rate * 60.0 * beats / f64::from(segment.tempo.beat_tempo)) as u64
+    }
+
+    #[allow(clippy::cast_precision_loss)]
+    #[must_use]
+    pub fn sample_to_clock(&self, sample: u64, sample_rate: u32) -> u32 {
+        self.secs_to_clock(sample as f64 / f64::from(sample_rate))
+    }
+
+    #[must_use]
+    pub fn clock_to_musical(&self, clock: u32) -> MusicalTime {
+        let segment = self.segment_at_clock(clock);
+        let offset = clock - segment.clock;
+        let beat_clock = segment.tempo.beat_clock.max(1) as u32;
+        let cpm = segment.tempo.clocks_per_measure();
+
+        MusicalTime {
+            measure: segment.measure + offset / cpm,
+            beat: offset % cpm / beat_clock,
+            tick: offset % beat_clock,
+        }
+    }
+
+    #[must_use]
+    pub fn musical_to_clock(&self, time: MusicalTime) -> u32 {
+        let segment = self.segment_at_measure(time.measure);
+        segment.clock
+            + (time.measure - segment.measure) * segment.tempo.clocks_per_measure()
+            + time.beat * segment.tempo.beat_clock.max(1) as u32
+            + time.tick
+    }
+
+    /// Clock at the start of `measure`
+    #[must_use]
+    pub fn measure_to_clock(&self, measure: u32) -> u32 {
+        self.musical_to_clock(MusicalTime { measure, ..MusicalTime::default() })
+    }
+
+    /// Clock that playback jumps back to when looping
+    #[must_use]
+    pub fn repeat_clock(&self) -> u32 {
+        self.measure_to_clock(self.repeat_measure)
+    }
+
+    /// Clock at which the song ends (or loops)
+    #[must_use]
+    pub fn end_clock(&self) -> u32 {
+        self.measure_to_clock(self.end_measure)
+    }
+
+    /// Length of the song in samples when played `loops` times, every play after the first
+    /// starting from the repeat measure. `0` loops is as long as `1`.
+    #[must_use]
+    pub fn song_samples(&self, sample_rate: u32, loops: u32) -> u64 {
+        let end = self.clock_to_sample(self.end_clock(), sample_rate);
+        let repeat = self.clock_to_sample(self.repeat_clock(), sample_rate);
+        end + u64::from(loops.saturating_sub(1)) * (end - repeat)
+    }
+
+    /// Length of the song when played `loops` times, see [`TempoMap::song_samples`]
+    #[must_use]
+    pub fn song_duration(&self, loops: u32) -> Duration {
+        let end = self.clock_to_secs(self.end_clock());
+        let repeat = self.clock_to_secs(self.repeat_clock());
+        Duration::from_secs_f64(end + f64::from(loops.saturating_sub(1)) * (end - repeat))
+    }
+}
+
+/// Adds [`HasTempoMap::tempo_map`] as a method on every backend
+pub trait HasTempoMap {
+    /// The project's tempo, with its tempo events applied
+    fn tempo_map(&self) -> TempoMap;
+}
+
+impl<T: PxTone + HasEventList + ?Sized> HasTempoMap for T {
+    fn tempo_map(&self) -> TempoMap {
+        let events = self.event_list();
+        let changes = [
+            EventKind::BeatClock,
+            EventKind::BeatTempo,
+            EventKind::BeatNum,
+        ]
+        .into_iter()
+        .flat_map(|kind| events.events_of_kind(kind))
+        .filter_map(|e| Some((e.clock(), e.tempo_change()?)));
+        TempoMap::new(self).with_changes(changes)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{MusicalTime, Tempo, TempoChange, TempoMap};
+
+    fn tempo_map(changes: &[(u32, TempoChange)]) -> TempoMap {
+        TempoMap {
+            segments: vec![super::Segment {
+                clock: 0,
+                secs: 0.0,
+                measure: 0,
+                tempo: Tempo { beat_num: 4, beat_tempo: 120.0, beat_clock: 480 },
+            }],
+            repeat_measure: 1,
+            end_measure: 4,
+        }
+        .with_changes(changes.iter().copied())
+    }
+
+    #[test]
+    fn converts_at_a_constant_tempo() {
+        let map = tempo_map(&[]);
+        let time = MusicalTime { measure: 1, beat: 2, tick: 10 };
+
+        assert_eq!(map.musical_to_clock(time), 1920 + 960 + 10);
+        assert_eq!(map.clock_to_musical(1920 + 960 + 10), time);
+        assert!((map.clock_to_secs(960) - 1.0).abs() < 1e-9);
+        assert_eq!(map.secs_to_clock(1.0), 960);
+        assert_eq!(map.clock_to_sample(960, 44100), 44100);
+        assert_eq!(map.sample_to_clock(44100, 44100), 960);
+        assert_eq!(map.song_samples(44100, 1), 4 * 2 * 44100);
+        assert_eq!(map.song_samples(44100, 3), (4 + 3 + 3) * 2 * 44100);
+    }
+
+    #[test]
+    fn follows_tempo_changes() {
+        // double speed and 3/4 from measure 2
+        let map = tempo_map(&[
+            (3840, TempoChange::BeatTempo(240.0)),
+            (3840, TempoChange::BeatNum(3)),
+        ]);
+
+        assert!((map.clock_to_secs(3840) - 4.0).abs() < 1e-9);
+        assert!((map.clock_to_secs(3840 + 480) - 4.25).abs() < 1e-9);
+        assert_eq!(map.secs_to_clock(4.25), 3840 + 480);
+        assert_eq!(map.measure_to_clock(3), 3840 + 1440);
+        assert_eq!(
+            map.clock_to_musical(3840 + 1440 + 480),
+            MusicalTime { measure: 3, beat: 1, tick: 0 }
+        );
+        // 4 measures: 2 of 2s and 2 of 0.75s
+        assert_eq!(map.song_duration(1).as_secs_f64(), 5.5);
+    }
+
+    #[test]
+    fn reads_raw_tempo_events() {
+        use crate::interface::event::EventKind;
+
+        assert_eq!(
+            TempoChange::from_raw(EventKind::BeatTempo, 150.0_f32.to_bits()),
+            Some(TempoChange::BeatTempo(150.0))
+        );
+        assert_eq!(TempoChange::from_raw(EventKind::Key, 0), None);
+    }
+}
@@ -95,8 +95,13 @@ impl<'a, T: 'a + ?Sized> From<&'a mut T> for BoxOrMut<'a, T> {
 }
 
 /// Wrapper for an f32 representing a value from 0.0 to 1.0 (inclusive).
-#[derive(Clone, Copy, Debug)]
+#[derive(Clone, Copy, Debug, PartialEq)]
 #[repr(transparent)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(transparent)
+)]
 pub struct ZeroToOneF32(f32);
 
 impl ZeroToOneF32 {
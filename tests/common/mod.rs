@@ -0,0 +1,4 @@
+//! Helpers shared between test files, each only uses some of them
+#![allow(dead_code)]
+
+pub mod vorbis;
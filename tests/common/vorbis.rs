@@ -0,0 +1,164 @@
+//! Hand built Ogg Vorbis streams, since there's no encoder to make test files with
+
+/// Short blocks are 256 samples, and each one after the first adds half of that
+const SAMPLES_PER_PACKET: u64 = 128;
+
+/// Pages can't hold more than 255 segments, and every audio packet here is a single segment
+const PACKETS_PER_PAGE: usize = 255;
+
+/// Packs bits least significant first, the way Vorbis headers are laid out
+#[derive(Default)]
+pub struct Bits {
+    pub bytes: Vec<u8>,
+    len: usize,
+}
+
+impl Bits {
+    pub fn put(&mut self, value: u32, bits: usize) -> &mut Self {
+        for i in 0..bits {
+            if self.len.is_multiple_of(8) {
+                self.bytes.push(0);
+            }
+            self.bytes[self.len / 8] |= (((value >> i) & 1) as u8) << (self.len % 8);
+            self.len += 1;
+        }
+        self
+    }
+}
+
+fn ogg_crc(data: &[u8]) -> u32 {
+    let mut crc = 0_u32;
+    for &b in data {
+        crc ^= u32::from(b) << 24;
+        for _ in 0..8 {
+            crc = if crc & 0x8000_0000 == 0 {
+                crc << 1
+            } else {
+                (crc << 1) ^ 0x04c1_1db7
+            };
+        }
+    }
+    crc
+}
+
+pub fn ogg_page(packets: &[Vec<u8>], flags: u8, granule: u64, sequence: u32) -> Vec<u8> {
+    let mut lacing = Vec::new();
+    for p in packets {
+        lacing.extend(std::iter::repeat_n(255, p.len() / 255));
+        lacing.push((p.len() % 255) as u8);
+    }
+
+    let mut page = b"OggS\0".to_vec();
+    page.push(flags);
+    page.extend_from_slice(&granule.to_le_bytes());
+    page.extend_from_slice(&0x5054_4f4e_u32.to_le_bytes());
+    page.extend_from_slice(&sequence.to_le_bytes());
+    page.extend_from_slice(&[0; 4]);
+    page.push(lacing.len() as u8);
+    page.extend_from_slice(&lacing);
+    for p in packets {
+        page.extend_from_slice(p);
+    }
+
+    let crc = ogg_crc(&page);
+    page[22..26].copy_from_slice(&crc.to_le_bytes());
+    page
+}
+
+fn header(kind: u8, body: &[u8]) -> Vec<u8> {
+    let mut packet = vec![kind];
+    packet.extend_from_slice(b"vorbis");
+    packet.extend_from_slice(body);
+    packet
+}
+
+/// The smallest stream lewton accepts that can make a sound: one codebook, floor, residue,
+/// mapping and short block mode, then the `audio` packets over as many pages as they need, with
+/// the last page's granule set to `granule`.
+fn vorbis(channels: u8, rate: u32, audio: &[Vec<u8>], granule: u64) -> Vec<u8> {
+    let mut ident = vec![0, 0, 0, 0, channels];
+    ident.extend_from_slice(&rate.to_le_bytes());
+    ident.extend_from_slice(&[0; 12]);
+    // block sizes of 256 and 2048, then the framing bit
+    ident.extend_from_slice(&[0xb8, 1]);
+
+    let mut comment = 4_u32.to_le_bytes().to_vec();
+    comment.extend_from_slice(b"test");
+    comment.extend_from_slice(&[0, 0, 0, 0, 1]);
+
+    let mut setup = Bits::default();
+    // a codebook of two 1 bit entries, which are 0.0 and 0.5 as vectors
+    setup.put(0, 8).put(0x56_4342, 24).put(1, 16).put(2, 24);
+    setup.put(0, 1).put(0, 1).put(0, 5).put(0, 5).put(1, 4);
+    setup.put(0, 32).put(787 << 21 | 1, 32).put(0, 4).put(0, 1);
+    setup.put(0, 1).put(1, 1);
+    // a placeholder time domain transform
+    setup.put(0, 6).put(0, 16);
+    // a floor 1 with one partition of one point
+    setup.put(0, 6).put(1, 16).put(1, 5).put(0, 4);
+    setup.put(0, 3).put(0, 2).put(0, 8);
+    setup.put(0, 2).put(8, 4).put(128, 8);
+    // a type 1 residue covering one frequency bin with two classes that both read the codebook
+    setup.put(0, 6).put(1, 16);
+    setup.put(10, 24).put(11, 24).put(0, 24);
+    setup.put(1, 6).put(0, 8);
+    setup.put(1, 3).put(0, 1).put(1, 3).put(0, 1);
+    setup.put(0, 8).put(0, 8);
+    // one mapping of every channel to them
+    setup.put(0, 6).put(0, 16).put(0, 1).put(0, 1).put(0, 2);
+    setup.put(0, 8).put(0, 8).put(0, 8);
+    // one short block mode, then the framing bit
+    setup.put(0, 6).put(0, 1).put(0, 16).put(0, 16).put(0, 8);
+    setup.put(1, 1);
+
+    let mut stream = ogg_page(&[header(1, &ident)], 0x02, 0, 0);
+    stream.extend(ogg_page(
+        &[header(3, &comment), header(5, &setup.bytes)],
+        0,
+        0,
+        1,
+    ));
+
+    let pages = audio.chunks(PACKETS_PER_PAGE).count();
+    let mut decoded = 0;
+    for (i, packets) in audio.chunks(PACKETS_PER_PAGE).enumerate() {
+        decoded += packets.len() as u64 * SAMPLES_PER_PACKET;
+        let (flags, granule) = if i + 1 == pages {
+            (0x04, granule)
+        } else {
+            (0, decoded - SAMPLES_PER_PACKET)
+        };
+        stream.extend(ogg_page(packets, flags, granule, i as u32 + 2));
+    }
+    stream
+}
+
+/// A stream whose `packets` audio packets have every channel's floor unused, so it decodes to
+/// silence
+pub fn silent_vorbis(channels: u8, rate: u32, packets: usize, granule: u64) -> Vec<u8> {
+    // an audio packet flag and an unused floor flag for each channel
+    let audio: Vec<_> = (0..packets).map(|_| vec![0]).collect();
+    vorbis(channels, rate, &audio, granule)
+}
+
+/// A stream that plays a steady tone around `10.5 / 256` of `rate` for `samples` samples on every
+/// channel
+pub fn tone_vorbis(channels: u8, rate: u32, samples: u64) -> Vec<u8> {
+    let mut packet = Bits::default();
+    packet.put(0, 1);
+    for _ in 0..channels {
+        // a flat floor at full volume
+        packet.put(1, 1).put(255, 8).put(255, 8);
+    }
+    for _ in 0..channels {
+        // the second class
+        packet.put(1, 1);
+    }
+    for _ in 0..channels {
+        // and 0.5 in the one bin the residue covers, which peaks at about half of full scale
+        packet.put(1, 1);
+    }
+
+    let packets = samples.div_ceil(SAMPLES_PER_PACKET) as usize + 1;
+    vorbis(channels, rate, &vec![packet.bytes; packets], samples)
+}
@@ -0,0 +1,96 @@
+#![cfg(feature = "rust-impl")]
+
+use std::sync::Arc;
+
+use pxtone::{
+    control::{ControlledMoo, MooHandle},
+    interface::{io::PxToneServiceIO, moo::Moo, service::PxTone, unit::Units},
+    rust_impl::{moo::RPxTonePlayer, service::RPxTone},
+};
+
+const SAMPLE: &[u8] = include_bytes!("../examples/sample.ptcop");
+const SAMPLE_RATE: u32 = 8000;
+
+fn uncontrolled() -> RPxTonePlayer {
+    let mut pxtone = RPxTone::new();
+    pxtone.read_bytes(SAMPLE).unwrap();
+
+    let mut moo = RPxTonePlayer::new(Arc::new(pxtone));
+    moo.set_audio_format(1, SAMPLE_RATE).unwrap();
+    moo.prepare_sample().unwrap();
+    moo
+}
+
+fn player() -> (ControlledMoo<RPxTonePlayer>, MooHandle) {
+    ControlledMoo::new(uncontrolled())
+}
+
+fn frames_per_measure(pxtone: &RPxTone) -> u32 {
+    let beats = f64::from(pxtone.beat_num());
+    (f64::from(SAMPLE_RATE) * 60.0 * beats / f64::from(pxtone.beat_tempo())) as u32
+}
+
+fn peak(moo: &mut ControlledMoo<RPxTonePlayer>, frames: usize) -> u16 {
+    let mut buf = vec![0; frames];
+    moo.sample(&mut buf).unwrap();
+    buf.iter().map(|s| s.unsigned_abs()).max().unwrap()
+}
+
+#[test]
+fn commands_apply_on_next_sample() {
+    let (mut moo, handle) = player();
+    assert!(peak(&mut moo, 8000) > 0);
+
+    std::thread::spawn(move || handle.set_master_volume(0.0).unwrap())
+        .join()
+        .unwrap();
+    assert_eq!(peak(&mut moo, 8000), 0);
+}
+
+#[test]
+fn float_samples_match_the_wrapped_moo() {
+    let (mut moo, handle) = player();
+    let mut controlled = vec![0.0; 8000];
+    moo.sample_f32(&mut controlled).unwrap();
+
+    let mut expected = vec![0.0; 8000];
+    uncontrolled().sample_f32(&mut expected).unwrap();
+    assert!(controlled.iter().any(|&s| s != 0.0));
+    assert_eq!(controlled, expected);
+
+    handle.set_master_volume(0.0).unwrap();
+    moo.sample_f32(&mut controlled).unwrap();
+    assert!(controlled.iter().all(|&s| s == 0.0));
+}
+
+#[test]
+fn muting_every_unit_is_silent() {
+    let (mut moo, handle) = player();
+    let units = Units::iter(&**moo).count() as u8;
+    for unit_no in 0..units {
+        handle.set_unit_muted(unit_no, true).unwrap();
+    }
+    assert_eq!(peak(&mut moo, 8000), 0);
+
+    for unit_no in 0..units {
+        handle.set_unit_muted(unit_no, false).unwrap();
+    }
+    handle.jump_to_measure(0).unwrap();
+    assert!(peak(&mut moo, 8000) > 0);
+}
+
+#[test]
+fn jumps_and_loops_measures() {
+    let (mut moo, handle) = player();
+    let measure = frames_per_measure(&moo);
+
+    handle.jump_to_measure(2).unwrap();
+    handle.set_loop_measures(Some(2..3)).unwrap();
+    peak(&mut moo, 1);
+    assert_eq!(moo.sampling_offset(), measure * 2 + 1);
+    assert_eq!(moo.sampling_end(), measure * 3);
+
+    // wraps back to the start of the section
+    peak(&mut moo, measure as usize);
+    assert_eq!(moo.sampling_offset(), measure * 2 + 1);
+}
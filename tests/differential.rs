@@ -0,0 +1,443 @@
+//! Differential tests between `og_impl` and `rust_impl`
+//!
+//! Every project in the corpus is loaded into both backends and rendered for a few seconds,
+//! once per woice type (with every other woice type silenced).
+//! The renders are compared per-sample and by their average spectrum, and each case has
+//! its own threshold since some parts of the Rust renderer are further along than others.
+//!
+//! The corpus is `examples/sample.ptcop` plus any `.ptcop` files in `tests/corpus/`, and
+//! `examples/sample.ptcop` again with every unit switched to `examples/sample.ptvoice` or to a
+//! generated Ogg Vorbis tone, since it has no PTV or OGGV woices of its own.
+//! Run with `--nocapture` to see the full report. A case fails if nothing in the corpus covers it.
+//!
+//! Effects are removed before rendering and have no cases, since `rust_impl` doesn't render
+//! delays or overdrives yet.
+
+#![cfg(all(feature = "og-impl", feature = "rust-impl"))]
+
+mod common;
+
+use std::{collections::HashMap, f64::consts::PI, fmt, fs, path::PathBuf};
+
+use common::vorbis::tone_vorbis;
+use pxtone::{
+    interface::{
+        delay::{DelaysMut, HasDelays},
+        event::{
+            BaseEvent, EventListMut, EventOn, EventVoiceNo, GenericEvent, GenericEventKind,
+            HasEventList,
+        },
+        event_impl::EventImpl,
+        io::PxToneServiceIO,
+        moo::{AsMoo, Moo},
+        overdrive::{HasOverDrives, OverDrivesMut},
+        unit::{HasUnits, Units},
+        woice::{HasWoices, Woice, WoiceType, Woices, WoicesMut},
+    },
+    og_impl::service::PxToneService,
+    rust_impl::service::RPxTone,
+};
+
+const CHANNELS: u8 = 2;
+const SAMPLE_RATE: u32 = 44100;
+const RENDER_SECS: u32 = 10;
+
+const FFT_SIZE: usize = 2048;
+/// Bins this far below the loudest bin are ignored by the spectral comparison
+const SPECTRUM_FLOOR_DB: f64 = -60.0;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum WoiceKind {
+    None,
+    Pcm,
+    Ptv,
+    Ptn,
+    Oggv,
+}
+
+fn woice_kinds<P: HasWoices>(pxtone: &P) -> Vec<WoiceKind> {
+    pxtone
+        .woices()
+        .iter()
+        .map(|w| match w.woice_type() {
+            WoiceType::PCM(_) => WoiceKind::Pcm,
+            WoiceType::PTV(_) => WoiceKind::Ptv,
+            WoiceType::PTN(_) => WoiceKind::Ptn,
+            WoiceType::OGGV(_) => WoiceKind::Oggv,
+            _ => WoiceKind::None,
+        })
+        .collect()
+}
+
+/// Silence every note that isn't played with a woice of type `kind`.
+///
+/// `kinds` comes from the reference project so both backends silence the same notes even if
+/// one of them loads a woice differently.
+/// Notes are silenced by setting their length to 0, which both backends treat the same way.
+/// Returns the number of notes left playing.
+fn isolate_woice_kind<P: HasEventList>(
+    pxtone: &mut P,
+    kinds: &[WoiceKind],
+    kind: WoiceKind,
+) -> usize {
+    let mut unit_woices = HashMap::new();
+    let mut kept = 0;
+
+    let mut event_list = pxtone.event_list_mut();
+    for event in event_list.iter_mut() {
+        let unit_no = event.unit_no();
+        match event.kind_mut() {
+            GenericEventKind::VoiceNo(v) => {
+                unit_woices.insert(unit_no, v.voice_no());
+            },
+            GenericEventKind::On(mut on) => {
+                let woice = *unit_woices.get(&unit_no).unwrap_or(&0);
+                if kinds.get(woice as usize) == Some(&kind) {
+                    kept += 1;
+                } else {
+                    on.set_length(0);
+                }
+            },
+            _ => {},
+        }
+    }
+
+    kept
+}
+
+/// Switch every unit to the project's last woice for the whole song
+fn play_last_woice<P: HasWoices + HasUnits + HasEventList>(pxtone: &mut P) {
+    // the backends are their own woice and unit lists, so the traits have to be named
+    let voice_no = (Woices::iter(&*pxtone.woices()).count() - 1) as u8;
+    let units = Units::iter(&*pxtone.units()).count() as u8;
+
+    let mut event_list = pxtone.event_list_mut();
+    for event in event_list.iter_mut() {
+        if let GenericEventKind::VoiceNo(mut v) = event.kind_mut() {
+            v.set_voice_no(voice_no);
+        }
+    }
+    for unit_no in 0..units {
+        event_list
+            .add(&EventImpl::voice_no(0, unit_no, voice_no))
+            .unwrap();
+    }
+}
+
+fn remove_delays<P: HasDelays>(pxtone: &mut P) {
+    while pxtone.delays_mut().remove(0) {}
+}
+
+fn remove_overdrives<P: HasOverDrives>(pxtone: &mut P) {
+    while pxtone.overdrives_mut().remove(0) {}
+}
+
+fn render<P: AsMoo>(pxtone: &mut P) -> Vec<i16> {
+    let mut moo = pxtone.as_moo();
+    moo.set_audio_format(CHANNELS, SAMPLE_RATE).unwrap();
+    moo.prepare_sample().unwrap();
+
+    let mut buf = vec![0; (SAMPLE_RATE * RENDER_SECS) as usize * CHANNELS as usize];
+    moo.sample(&mut buf).unwrap();
+    buf
+}
+
+/// Comparison between a reference render and a test render
+struct Diff {
+    /// Largest per-sample error
+    max_error: i32,
+    /// Root mean square of the per-sample error
+    rms_error: f64,
+    /// Signal to error ratio, relative to the reference
+    snr_db: f64,
+    /// Mean absolute difference between the average spectra, in dB
+    spectral_db: f64,
+}
+
+impl Diff {
+    fn new(reference: &[i16], test: &[i16]) -> Self {
+        assert_eq!(reference.len(), test.len());
+
+        let mut max_error = 0;
+        let mut signal = 0.0;
+        let mut error = 0.0;
+        for (&r, &t) in reference.iter().zip(test) {
+            let e = i32::from(r) - i32::from(t);
+            max_error = max_error.max(e.abs());
+            signal += f64::from(r) * f64::from(r);
+            error += f64::from(e) * f64::from(e);
+        }
+
+        let snr_db = if error == 0.0 {
+            f64::INFINITY
+        } else {
+            10.0 * (signal / error).log10()
+        };
+
+        Self {
+            max_error,
+            rms_error: (error / reference.len() as f64).sqrt(),
+            snr_db,
+            spectral_db: spectral_distance(&spectrum(reference), &spectrum(test)),
+        }
+    }
+}
+
+impl fmt::Display for Diff {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "max error {}, rms error {:.2}, snr {:.2}dB, spectral difference {:.2}dB",
+            self.max_error, self.rms_error, self.snr_db, self.spectral_db
+        )
+    }
+}
+
+/// Threshold a [`Diff`] has to meet
+struct Threshold {
+    min_snr_db: f64,
+    max_spectral_db: f64,
+}
+
+impl Threshold {
+    fn check(&self, name: &str, diff: &Diff) {
+        assert!(
+            diff.snr_db >= self.min_snr_db,
+            "{name}: snr {:.2}dB is below {:.2}dB",
+            diff.snr_db,
+            self.min_snr_db
+        );
+        assert!(
+            diff.spectral_db <= self.max_spectral_db,
+            "{name}: spectral difference {:.2}dB is above {:.2}dB",
+            diff.spectral_db,
+            self.max_spectral_db
+        );
+    }
+}
+
+/// Average power spectrum (in dB) of the downmixed signal
+fn spectrum(samples: &[i16]) -> Vec<f64> {
+    let mono: Vec<f64> = samples
+        .chunks(CHANNELS as usize)
+        .map(|c| c.iter().map(|&s| f64::from(s)).sum::<f64>() / c.len() as f64)
+        .collect();
+
+    let window: Vec<f64> = (0..FFT_SIZE)
+        .map(|i| 0.5 - 0.5 * (2.0 * PI * i as f64 / FFT_SIZE as f64).cos())
+        .collect();
+
+    let mut power = vec![0.0; FFT_SIZE / 2];
+    let mut frames = 0;
+    for frame in mono.windows(FFT_SIZE).step_by(FFT_SIZE / 2) {
+        let mut re: Vec<f64> = frame.iter().zip(&window).map(|(s, w)| s * w).collect();
+        let mut im = vec![0.0; FFT_SIZE];
+        fft(&mut re, &mut im);
+
+        for (i, p) in power.iter_mut().enumerate() {
+            *p += re[i] * re[i] + im[i] * im[i];
+        }
+        frames += 1;
+    }
+
+    power
+        .into_iter()
+        .map(|p| 10.0 * (p / f64::from(frames.max(1)) + 1e-9).log10())
+        .collect()
+}
+
+fn spectral_distance(a: &[f64], b: &[f64]) -> f64 {
+    let peak = a.iter().chain(b).copied().fold(f64::NEG_INFINITY, f64::max);
+    let floor = peak + SPECTRUM_FLOOR_DB;
+
+    let (sum, count) = a
+        .iter()
+        .zip(b)
+        .filter(|(a, b)| **a > floor || **b > floor)
+        .fold((0.0, 0_u32), |(sum, count), (a, b)| {
+            (sum + (a.max(floor) - b.max(floor)).abs(), count + 1)
+        });
+
+    if count == 0 {
+        0.0
+    } else {
+        sum / f64::from(count)
+    }
+}
+
+/// In-place iterative radix-2 FFT, `re.len()` must be a power of two
+fn fft(re: &mut [f64], im: &mut [f64]) {
+    let n = re.len();
+
+    let mut j = 0;
+    for i in 1..n {
+        let mut bit = n >> 1;
+        while j & bit != 0 {
+            j ^= bit;
+            bit >>= 1;
+        }
+        j |= bit;
+        if i < j {
+            re.swap(i, j);
+            im.swap(i, j);
+        }
+    }
+
+    let mut len = 2;
+    while len <= n {
+        let angle = -2.0 * PI / len as f64;
+        for start in (0..n).step_by(len) {
+            for k in 0..len / 2 {
+                let (sin, cos) = (angle * k as f64).sin_cos();
+                let (a, b) = (start + k, start + k + len / 2);
+                let tr = re[b] * cos - im[b] * sin;
+                let ti = re[b] * sin + im[b] * cos;
+                re[b] = re[a] - tr;
+                im[b] = im[a] - ti;
+                re[a] += tr;
+                im[a] += ti;
+            }
+        }
+        len <<= 1;
+    }
+}
+
+fn corpus() -> Vec<(String, Vec<u8>)> {
+    let root = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+    let mut paths = vec![root.join("examples/sample.ptcop")];
+
+    if let Ok(dir) = fs::read_dir(root.join("tests/corpus")) {
+        let mut extra: Vec<_> = dir
+            .filter_map(|e| e.ok().map(|e| e.path()))
+            .filter(|p| p.extension().is_some_and(|e| e == "ptcop"))
+            .collect();
+        extra.sort();
+        paths.extend(extra);
+    }
+
+    paths
+        .into_iter()
+        .map(|p| (p.display().to_string(), fs::read(&p).unwrap()))
+        .collect()
+}
+
+fn load(bytes: &[u8]) -> (PxToneService<'static>, RPxTone) {
+    let mut og = PxToneService::new().unwrap();
+    og.read_bytes(bytes).unwrap();
+
+    let mut rust = RPxTone::new();
+    rust.read_bytes(bytes).unwrap();
+
+    (og, rust)
+}
+
+/// Every corpus project loaded into both backends, plus the projects made for woice types the
+/// corpus doesn't have
+fn cases() -> Vec<(String, PxToneService<'static>, RPxTone)> {
+    let mut cases: Vec<_> = corpus()
+        .into_iter()
+        .map(|(name, bytes)| {
+            let (og, rust) = load(&bytes);
+            (name, og, rust)
+        })
+        .collect();
+
+    let root = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+    let sample = fs::read(root.join("examples/sample.ptcop")).unwrap();
+
+    let ptv = root.join("examples/sample.ptvoice");
+    let (mut og, mut rust) = load(&sample);
+    og.woices_mut().add_ptv_from_file(&ptv).unwrap();
+    rust.woices_mut().add_ptv_from_file(&ptv).unwrap();
+    play_last_woice(&mut og);
+    play_last_woice(&mut rust);
+    cases.push(("examples/sample.ptcop on sample.ptvoice".into(), og, rust));
+
+    // about 2 seconds, as a whole number of the stream's 128 sample blocks
+    let ogg = tone_vorbis(CHANNELS, SAMPLE_RATE, 128 * 700);
+    let (mut og, mut rust) = load(&sample);
+    og.woices_mut().add_oggv_from_bytes(&ogg).unwrap();
+    rust.woices_mut().add_oggv_from_bytes(&ogg).unwrap();
+    play_last_woice(&mut og);
+    play_last_woice(&mut rust);
+    cases.push(("examples/sample.ptcop on a Vorbis tone".into(), og, rust));
+
+    cases
+}
+
+/// Compare the backends on every case that uses woices of type `kind`, with all effects removed.
+fn check_woice_kind(kind: WoiceKind, threshold: &Threshold) {
+    let mut covered = false;
+
+    for (name, mut og, mut rust) in cases() {
+        remove_delays(&mut og);
+        remove_overdrives(&mut og);
+
+        let kinds = woice_kinds(&og);
+        if isolate_woice_kind(&mut og, &kinds, kind) == 0 {
+            continue;
+        }
+        isolate_woice_kind(&mut rust, &kinds, kind);
+        covered = true;
+
+        let diff = Diff::new(&render(&mut og), &render(&mut rust));
+        println!("{name} [{kind:?}]: {diff}");
+        threshold.check(&name, &diff);
+    }
+
+    assert!(
+        covered,
+        "no corpus project uses {kind:?} woices, add one to tests/corpus/"
+    );
+}
+
+#[test]
+fn pcm() {
+    check_woice_kind(
+        WoiceKind::Pcm,
+        &Threshold { min_snr_db: 20.0, max_spectral_db: 3.0 },
+    );
+}
+
+#[test]
+fn ptv() {
+    check_woice_kind(
+        WoiceKind::Ptv,
+        &Threshold { min_snr_db: 10.0, max_spectral_db: 6.0 },
+    );
+}
+
+#[test]
+fn ptn() {
+    check_woice_kind(
+        WoiceKind::Ptn,
+        &Threshold { min_snr_db: 20.0, max_spectral_db: 3.0 },
+    );
+}
+
+#[test]
+fn oggv() {
+    // vorbis decoders are allowed to differ slightly
+    check_woice_kind(
+        WoiceKind::Oggv,
+        &Threshold { min_snr_db: 15.0, max_spectral_db: 4.0 },
+    );
+}
+
+#[test]
+fn fft_matches_dft() {
+    let mut re: Vec<f64> = (0..16).map(|i| f64::from(i * i % 7) - 3.0).collect();
+    let mut im = vec![0.0; 16];
+    let input = re.clone();
+    fft(&mut re, &mut im);
+
+    for k in 0..16 {
+        let (mut dre, mut dim) = (0.0, 0.0);
+        for (n, x) in input.iter().enumerate() {
+            let a = -2.0 * PI * (k * n) as f64 / 16.0;
+            dre += x * a.cos();
+            dim += x * a.sin();
+        }
+        assert!((re[k] - dre).abs() < 1e-9 && (im[k] - dim).abs() < 1e-9);
+    }
+}
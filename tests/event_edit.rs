@@ -0,0 +1,117 @@
+#![cfg(feature = "rust-impl")]
+
+use pxtone::{
+    interface::{
+        event::{BaseEvent, EventKind, EventList, EventListMut, HasEventList, Key},
+        event_impl::EventImpl,
+    },
+    rust_impl::service::RPxTone,
+};
+
+fn summary(pxtone: &RPxTone) -> Vec<(u32, u8, EventKind)> {
+    pxtone
+        .event_list()
+        .iter()
+        .map(|e| (e.clock(), e.unit_no(), EventKind::of(e)))
+        .collect()
+}
+
+fn project() -> RPxTone {
+    let mut pxtone = RPxTone::new();
+    let mut events = pxtone.event_list_mut();
+    for e in [
+        EventImpl::key(0, 0, Key::from_midi(60)),
+        EventImpl::on(0, 0, 960),
+        EventImpl::on(480, 1, 480),
+        EventImpl::key(960, 0, Key::from_midi(64)),
+        EventImpl::on(960, 0, 480),
+        EventImpl::on(1920, 1, 480),
+    ] {
+        events.add(&e).unwrap();
+    }
+    pxtone
+}
+
+#[test]
+fn remove_and_retain() {
+    let mut pxtone = project();
+
+    assert_eq!(pxtone.event_list_mut().remove(960, 0, EventKind::Key), 1);
+    assert_eq!(pxtone.event_list_mut().remove(960, 0, EventKind::Key), 0);
+
+    pxtone.event_list_mut().retain(|e| e.unit_no() == 1);
+    assert_eq!(
+        summary(&pxtone),
+        [(480, 1, EventKind::On), (1920, 1, EventKind::On)]
+    );
+
+    pxtone.event_list_mut().clear();
+    assert!(summary(&pxtone).is_empty());
+}
+
+#[test]
+fn remove_range_shortens_overlapping_notes() {
+    let mut pxtone = project();
+
+    assert_eq!(pxtone.event_list_mut().remove_range(720..1920, Some(0)), 3);
+    let events = pxtone.event_list();
+    let notes = events.notes();
+    assert_eq!(notes.len(), 3);
+    assert_eq!((notes[0].unit_no, notes[0].length), (0, 720));
+    // the other unit is untouched
+    assert_eq!((notes[1].unit_no, notes[1].length), (1, 480));
+}
+
+#[test]
+fn shift_clocks_keeps_the_list_sorted() {
+    let mut pxtone = project();
+
+    assert_eq!(
+        pxtone.event_list_mut().shift_clocks(960..1921, None, -960),
+        3
+    );
+    let mut shifted = summary(&pxtone);
+    assert!(shifted.windows(2).all(|w| w[0].0 <= w[1].0));
+    shifted.sort_by_key(|&(clock, unit_no, kind)| (clock, unit_no, kind as u8));
+    assert_eq!(
+        shifted,
+        [
+            (0, 0, EventKind::On),
+            (0, 0, EventKind::On),
+            (0, 0, EventKind::Key),
+            (0, 0, EventKind::Key),
+            (480, 1, EventKind::On),
+            (960, 1, EventKind::On),
+        ]
+    );
+
+    // clocks can't go below zero
+    assert_eq!(pxtone.event_list_mut().shift_clocks(0..1, Some(0), -100), 4);
+    assert_eq!(summary(&pxtone)[0].0, 0);
+}
+
+#[test]
+fn add_keeps_same_clock_events_in_order() {
+    let mut pxtone = project();
+    let mut events = pxtone.event_list_mut();
+    for e in [
+        EventImpl::on(480, 0, 480),
+        EventImpl::key(0, 1, Key::from_midi(62)),
+    ] {
+        events.add(&e).unwrap();
+    }
+
+    assert_eq!(
+        summary(&pxtone),
+        [
+            (0, 0, EventKind::Key),
+            (0, 0, EventKind::On),
+            (0, 1, EventKind::Key),
+            (480, 1, EventKind::On),
+            (480, 0, EventKind::On),
+            (960, 0, EventKind::Key),
+            (960, 0, EventKind::On),
+            (1920, 1, EventKind::On),
+        ]
+    );
+}
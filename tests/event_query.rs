@@ -0,0 +1,94 @@
+#![cfg(feature = "rust-impl")]
+
+use pxtone::{
+    interface::{
+        event::{
+            BaseEvent, EventKind, EventList, EventListMut, HasEventList, Key, PanValue, UnitState,
+        },
+        event_impl::EventImpl,
+        io::PxToneServiceIO,
+        note::Note,
+    },
+    rust_impl::service::RPxTone,
+    util::ZeroToOneF32,
+};
+
+const SAMPLE: &[u8] = include_bytes!("../examples/sample.ptcop");
+
+#[test]
+fn range_queries_match_a_full_scan() {
+    let mut pxtone = RPxTone::new();
+    pxtone.read_bytes(SAMPLE).unwrap();
+    let events = pxtone.event_list();
+
+    for clocks in [0..1920, 1000..5000, 5000..5000, 100_000..200_000] {
+        let scanned: Vec<_> = events
+            .iter()
+            .filter(|e| clocks.contains(&e.clock()))
+            .map(|e| (e.clock(), e.unit_no(), EventKind::of(e)))
+            .collect();
+        let queried: Vec<_> = events
+            .events_in(clocks)
+            .map(|e| (e.clock(), e.unit_no(), EventKind::of(e)))
+            .collect();
+        assert_eq!(scanned, queried);
+    }
+
+    let ons = events.events_of_kind(EventKind::On).count();
+    assert!(ons > 0);
+    assert_eq!(events.notes().len(), ons);
+    assert!(events.events_for_unit(0).all(|e| e.unit_no() == 0));
+}
+
+#[test]
+fn notes_and_unit_state() {
+    let mut pxtone = RPxTone::new();
+    let mut events = pxtone.event_list_mut();
+    for e in [
+        EventImpl::key(0, 1, Key::from_midi(60)),
+        EventImpl::on(0, 1, 480),
+        EventImpl::velocity(0, 1, ZeroToOneF32::new(0.5)),
+        EventImpl::pan_volume(240, 1, PanValue::left()),
+        EventImpl::on(480, 1, 240),
+        EventImpl::key(480, 1, Key::from_midi(62)),
+        EventImpl::on(480, 2, 960),
+    ] {
+        events.add(&e).unwrap();
+    }
+
+    let events = pxtone.event_list();
+    let velocity = ZeroToOneF32::new(0.5);
+    assert_eq!(
+        events.notes_in(0..481),
+        [
+            Note {
+                unit_no: 1,
+                clock: 0,
+                length: 480,
+                key: Key::from_midi(60),
+                velocity
+            },
+            Note {
+                unit_no: 1,
+                clock: 480,
+                length: 240,
+                key: Key::from_midi(62),
+                velocity
+            },
+            Note {
+                unit_no: 2,
+                clock: 480,
+                length: 960,
+                key: Key::DEFAULT,
+                velocity: UnitState::default().velocity,
+            },
+        ]
+    );
+    assert_eq!(events.notes_in(720..1000).len(), 1);
+
+    let state = events.unit_state_at(1, 240);
+    assert_eq!(state.key, Key::from_midi(60));
+    assert_eq!(state.pan_volume, PanValue::left());
+    assert_eq!(events.unit_state_at(1, 239).pan_volume, PanValue::center());
+    assert_eq!(events.unit_state_at(3, 1000), UnitState::default());
+}
@@ -0,0 +1,70 @@
+#![cfg(feature = "rust-impl")]
+
+use std::sync::Arc;
+
+use pxtone::{
+    interface::{io::PxToneServiceIO, moo::Moo, unit::Units},
+    rust_impl::{meter::Level, moo::RPxTonePlayer, service::RPxTone},
+};
+
+const SAMPLE: &[u8] = include_bytes!("../examples/sample.ptcop");
+
+fn player() -> RPxTonePlayer {
+    let mut pxtone = RPxTone::new();
+    pxtone.read_bytes(SAMPLE).unwrap();
+
+    let mut moo = RPxTonePlayer::new(Arc::new(pxtone));
+    moo.set_audio_format(2, 8000).unwrap();
+    moo.prepare_sample().unwrap();
+    moo
+}
+
+fn render(moo: &mut RPxTonePlayer, frames: usize) {
+    let mut buf = vec![0; frames * 2];
+    moo.sample(&mut buf).unwrap();
+}
+
+#[test]
+fn measures_units_and_groups() {
+    let mut moo = player();
+    let meters = moo.meter_handle();
+    assert!(meters.snapshot().units.is_empty());
+
+    render(&mut moo, 8000);
+
+    let snapshot = std::thread::spawn(move || meters.snapshot())
+        .join()
+        .unwrap();
+    assert_eq!(snapshot.units.len(), Units::iter(&*moo).count());
+    assert!(snapshot.units.iter().any(|l| l.peak > 0.0));
+    assert!(snapshot.groups.iter().any(|l| l.peak > 0.0));
+    for level in snapshot.units.iter().chain(&snapshot.groups) {
+        assert!(level.rms <= level.peak);
+        assert!(level.peak <= 1.0);
+    }
+}
+
+#[test]
+fn muted_units_read_silent() {
+    let mut moo = player();
+    let meters = moo.meter_handle();
+    moo.set_unit_mute_enabled(true).unwrap();
+    moo.set_unit_muted(0, true).unwrap();
+
+    render(&mut moo, 8000);
+    assert_eq!(meters.unit(0), Level::default());
+    assert_eq!(meters.group(7), None);
+}
+
+#[test]
+fn metering_does_not_change_output() {
+    let mut plain = player();
+    let mut metered = player();
+    let _meters = metered.meter_handle();
+
+    let mut a = vec![0; 16000];
+    let mut b = vec![0; 16000];
+    plain.sample(&mut a).unwrap();
+    metered.sample(&mut b).unwrap();
+    assert_eq!(a, b);
+}
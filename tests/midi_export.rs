@@ -0,0 +1,159 @@
+#![cfg(feature = "rust-impl")]
+
+use pxtone::{
+    export::midi::{MidiExport, MidiExportError, MidiExportOptions},
+    interface::{
+        event::{EventKind, EventList, EventListMut, HasEventList, Key},
+        event_impl::EventImpl,
+        io::PxToneServiceIO,
+        note::Note,
+        service::PxTone,
+        unit::{HasUnits, Units, UnitsMut},
+    },
+    rust_impl::service::RPxTone,
+    util::ZeroToOneF32,
+};
+
+const SAMPLE: &[u8] = include_bytes!("../examples/sample.ptcop");
+
+/// `(clock, message)` of every event in each track
+fn parse(bytes: &[u8]) -> (u16, u16, Vec<Vec<(u32, Vec<u8>)>>) {
+    fn vlq(bytes: &[u8], at: &mut usize) -> u32 {
+        let mut value = 0;
+        loop {
+            let b = bytes[*at];
+            *at += 1;
+            value = (value << 7) | u32::from(b & 0x7F);
+            if b & 0x80 == 0 {
+                return value;
+            }
+        }
+    }
+
+    assert_eq!(&bytes[..8], b"MThd\0\0\0\x06");
+    let format = u16::from_be_bytes([bytes[8], bytes[9]]);
+    let division = u16::from_be_bytes([bytes[12], bytes[13]]);
+
+    let mut tracks = Vec::new();
+    let mut at = 14;
+    while at < bytes.len() {
+        assert_eq!(&bytes[at..at + 4], b"MTrk");
+        let len = u32::from_be_bytes(bytes[at + 4..at + 8].try_into().unwrap()) as usize;
+        let end = at + 8 + len;
+        at += 8;
+
+        let mut events = Vec::new();
+        let mut clock = 0;
+        while at < end {
+            clock += vlq(bytes, &mut at);
+            let start = at;
+            if bytes[at] == 0xFF {
+                at += 2;
+                let len = vlq(bytes, &mut at) as usize;
+                at += len;
+            } else {
+                at += 3;
+            }
+            events.push((clock, bytes[start..at].to_vec()));
+        }
+        assert_eq!(events.last().unwrap().1, [0xFF, 0x2F, 0]);
+        tracks.push(events);
+    }
+
+    (format, division, tracks)
+}
+
+fn export(pxtone: &RPxTone) -> Vec<u8> {
+    let mut bytes = Vec::new();
+    pxtone
+        .export_midi(&mut bytes, &MidiExportOptions::default())
+        .unwrap();
+    bytes
+}
+
+fn messages(track: &[(u32, Vec<u8>)], status: u8) -> Vec<(u32, Vec<u8>)> {
+    track
+        .iter()
+        .filter(|(_, m)| m[0] & 0xF0 == status)
+        .cloned()
+        .collect()
+}
+
+#[test]
+fn one_track_per_unit() {
+    let mut pxtone = RPxTone::new();
+    pxtone.read_bytes(SAMPLE).unwrap();
+
+    let (format, division, tracks) = parse(&export(&pxtone));
+    assert_eq!(format, 1);
+    assert_eq!(i32::from(division), pxtone.beat_clock());
+    assert_eq!(tracks.len(), Units::iter(&*pxtone.units()).count() + 1);
+
+    let us_per_beat = (60_000_000.0 / pxtone.beat_tempo()).round() as u32;
+    assert!(tracks[0].contains(&(
+        0,
+        [&[0xFF, 0x51, 3][..], &us_per_beat.to_be_bytes()[1..]].concat()
+    )));
+    assert!(tracks[0].contains(&(0, vec![0xFF, 0x58, 4, pxtone.beat_num() as u8, 2, 24, 8])));
+
+    // every note that's in MIDI's range is there, and gets turned off
+    let ons: usize = tracks.iter().map(|t| messages(t, 0x90).len()).sum();
+    let offs: usize = tracks.iter().map(|t| messages(t, 0x80).len()).sum();
+    let notes = pxtone.event_list().events_of_kind(EventKind::On).count();
+    assert!(ons > 0 && ons <= notes);
+    assert_eq!(ons, offs);
+}
+
+#[test]
+fn microtuning_and_porta_bend() {
+    let mut pxtone = RPxTone::new();
+    pxtone.units_mut().add_new().unwrap();
+    let mut events = pxtone.event_list_mut();
+    events
+        .insert_note(&Note {
+            unit_no: 0,
+            clock: 0,
+            length: 480,
+            key: Key::from_midi_cents(60, 25.0),
+            velocity: ZeroToOneF32::new(0.5),
+        })
+        .unwrap();
+    events.add(&EventImpl::porta(0, 0, 100)).unwrap();
+    events
+        .add(&EventImpl::key(200, 0, Key::from_midi(62)))
+        .unwrap();
+    events
+        .add(&EventImpl::volume(240, 0, ZeroToOneF32::new(0.25)))
+        .unwrap();
+
+    let (_, _, tracks) = parse(&export(&pxtone));
+    let track = &tracks[1];
+
+    assert_eq!(messages(track, 0x90), [(0, vec![0x90, 60, 64])]);
+    assert_eq!(messages(track, 0x80), [(480, vec![0x80, 60, 0])]);
+    assert!(track.contains(&(240, vec![0xB0, 7, 32])));
+
+    // a quarter semitone up with a range of 12 is 8192 + 8192 / 48
+    let bends: Vec<_> = messages(track, 0xE0)
+        .into_iter()
+        .map(|(clock, m)| (clock, u16::from(m[1]) | u16::from(m[2]) << 7))
+        .collect();
+    assert_eq!(bends[0], (0, 8192 + 171));
+    // then slides up to 2 semitones over 100 clocks
+    assert_eq!(bends.last(), Some(&(300, 8192 + 1365)));
+    assert!(bends.len() > 5);
+    assert!(bends.windows(2).all(|w| w[0].0 < w[1].0 && w[0].1 < w[1].1));
+}
+
+#[test]
+fn invalid_options() {
+    let pxtone = RPxTone::new();
+    let options = MidiExportOptions {
+        pitch_bend_range: 0,
+        ..MidiExportOptions::default()
+    };
+    assert!(matches!(
+        pxtone.export_midi(Vec::new(), &options),
+        Err(MidiExportError::InvalidOptions(_))
+    ));
+}
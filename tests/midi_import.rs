@@ -0,0 +1,156 @@
+#![cfg(feature = "rust-impl")]
+
+use pxtone::{
+    export::midi::{MidiExport, MidiExportOptions},
+    import::midi::{MidiImport, MidiImportError, MidiImportWarning},
+    interface::{
+        event::{EventKind, EventList, EventListMut, HasEventList, Key},
+        note::Note,
+        service::PxTone,
+        unit::{HasUnits, Unit, Units, UnitsMut},
+    },
+    rust_impl::service::RPxTone,
+    util::ZeroToOneF32,
+};
+
+fn unit_names(pxtone: &RPxTone) -> Vec<String> {
+    Units::iter(&*pxtone.units()).map(|u| u.name()).collect()
+}
+
+#[test]
+fn round_trip_through_export() {
+    let mut pxtone = RPxTone::new();
+    pxtone.set_beat_num(3);
+    pxtone.set_beat_tempo(150.0);
+    for name in ["lead", "bass"] {
+        pxtone
+            .units_mut()
+            .add_new()
+            .unwrap()
+            .set_name(name.into())
+            .unwrap();
+    }
+    let mut events = pxtone.event_list_mut();
+    for (unit_no, clock, midi, velocity) in [(0, 0, 72, 0.5), (0, 480, 74, 0.75), (1, 0, 48, 1.0)] {
+        events
+            .insert_note(&Note {
+                unit_no,
+                clock,
+                length: 240,
+                key: Key::from_midi(midi),
+                velocity: ZeroToOneF32::new(velocity * 127.0 / 128.0),
+            })
+            .unwrap();
+    }
+
+    let mut bytes = Vec::new();
+    pxtone
+        .export_midi(&mut bytes, &MidiExportOptions::default())
+        .unwrap();
+
+    let mut imported = RPxTone::new();
+    imported.units_mut().add_new().unwrap();
+    let warnings = imported.import_midi(&bytes).unwrap();
+
+    assert_eq!(warnings, []);
+    assert_eq!(unit_names(&imported), ["lead", "bass"]);
+    assert_eq!(imported.beat_num(), 3);
+    assert!((imported.beat_tempo() - 150.0).abs() < 0.01);
+    assert_eq!(imported.event_list().notes(), pxtone.event_list().notes());
+}
+
+/// Format 0, one track with two channels
+fn handcrafted() -> Vec<u8> {
+    let track: &[u8] = &[
+        0x00, 0xFF, 0x03, 4, b's', b'o', b'n', b'g', // track name
+        0x00, 0xFF, 0x51, 3, 0x07, 0xA1, 0x20, // 120 bpm
+        0x00, 0xFF, 0x58, 4, 6, 3, 24, 8, // 6/8
+        0x00, 0xC0, 5, // program change
+        0x00, 0x90, 60, 100, // overlapping notes on channel 1
+        0x30, 0x90, 64, 100, //
+        0x30, 0xE0, 0x00, 0x60, // bend up a semitone
+        0x30, 0x80, 60, 0, //
+        0x00, 64, 0, // running status note off
+        0x00, 0x91, 50, 127, // channel 2
+        0x60, 0x81, 50, 0, //
+        0x00, 0xFF, 0x51, 3, 0x0F, 0x42, 0x40, // 60 bpm
+        0x00, 0xFF, 0x2F, 0,
+    ];
+
+    let mut bytes = b"MThd\0\0\0\x06\0\0\0\x01\0\x60".to_vec();
+    bytes.extend_from_slice(b"MTrk");
+    bytes.extend_from_slice(&(track.len() as u32).to_be_bytes());
+    bytes.extend_from_slice(track);
+    bytes
+}
+
+#[test]
+fn channels_become_units_with_warnings() {
+    let mut pxtone = RPxTone::new();
+    let warnings = pxtone.import_midi(&handcrafted()).unwrap();
+
+    assert_eq!(
+        warnings,
+        [
+            MidiImportWarning::TempoChanges { ignored: 1 },
+            MidiImportWarning::Polyphony { unit_no: 0, overlaps: 1 },
+            MidiImportWarning::Unmapped { message: "program change", count: 1 },
+        ]
+    );
+    assert_eq!(unit_names(&pxtone), ["song ch1", "song ch2"]);
+    assert!((pxtone.beat_tempo() - 120.0).abs() < 0.01);
+    // 6/8 is three quarter notes
+    assert_eq!(pxtone.beat_num(), 3);
+
+    // 0x60 ticks per beat, scaled to the project's beat clock
+    let beat = pxtone.beat_clock() as u32;
+    let events = pxtone.event_list();
+    let notes = events.notes();
+    assert_eq!(notes.len(), 3);
+    assert_eq!((notes[0].clock, notes[0].length), (0, beat * 3 / 2));
+    assert_eq!((notes[1].clock, notes[1].length), (beat / 2, beat));
+    assert_eq!(notes[2].unit_no, 1);
+    assert_eq!(notes[2].velocity, ZeroToOneF32::new(127.0 / 128.0));
+
+    // the bend moves the playing note up
+    let bend = beat;
+    assert_eq!(events.unit_state_at(0, bend).key, Key::from_midi(65));
+    assert!(events
+        .events_in(bend..=bend)
+        .any(|e| EventKind::of(e) == EventKind::Key));
+}
+
+#[test]
+fn warns_about_percussion() {
+    // move the second channel to channel 10, these bytes are only its note statuses
+    let bytes: Vec<u8> = handcrafted()
+        .into_iter()
+        .map(|b| match b {
+            0x91 => 0x99,
+            0x81 => 0x89,
+            b => b,
+        })
+        .collect();
+    let mut pxtone = RPxTone::new();
+    let warnings = pxtone.import_midi(&bytes).unwrap();
+
+    assert!(warnings.contains(&MidiImportWarning::Percussion { unit_no: 1 }));
+    assert!(!warnings.contains(&MidiImportWarning::Percussion { unit_no: 0 }));
+    assert_eq!(unit_names(&pxtone), ["song ch1", "song ch10"]);
+}
+
+#[test]
+fn rejects_bad_files() {
+    let mut pxtone = RPxTone::new();
+    assert!(matches!(
+        pxtone.import_midi(b"RIFF"),
+        Err(MidiImportError::InvalidFile(_))
+    ));
+
+    let mut smpte = handcrafted();
+    smpte[12] = 0xE7;
+    assert!(matches!(
+        pxtone.import_midi(&smpte),
+        Err(MidiImportError::SmpteTiming)
+    ));
+}
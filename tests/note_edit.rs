@@ -0,0 +1,105 @@
+#![cfg(feature = "rust-impl")]
+
+use pxtone::{
+    interface::{
+        event::{BaseEvent, EventKind, EventList, EventListMut, HasEventList, Key},
+        note::{Note, NoteEditError},
+    },
+    rust_impl::service::RPxTone,
+    util::ZeroToOneF32,
+};
+
+fn note(unit_no: u8, clock: u32, length: u32, midi: u8) -> Note {
+    Note {
+        unit_no,
+        clock,
+        length,
+        key: Key::from_midi(midi),
+        velocity: ZeroToOneF32::new(0.5),
+    }
+}
+
+fn project(notes: &[Note]) -> RPxTone {
+    let mut pxtone = RPxTone::new();
+    let mut events = pxtone.event_list_mut();
+    for n in notes {
+        events.insert_note(n).unwrap();
+    }
+    pxtone
+}
+
+#[test]
+fn inserting_keeps_later_notes() {
+    let notes = [
+        note(0, 0, 480, 60),
+        note(1, 0, 480, 72),
+        note(0, 960, 480, 60),
+    ];
+    let mut pxtone = project(&notes);
+    assert_eq!(pxtone.event_list().notes(), notes);
+
+    let inserted = note(0, 480, 240, 67);
+    pxtone.event_list_mut().insert_note(&inserted).unwrap();
+    assert_eq!(
+        pxtone.event_list().notes(),
+        [notes[0], notes[1], inserted, notes[2]]
+    );
+
+    // nothing to change, so only the On is added
+    let events = pxtone.event_list().iter().count();
+    pxtone
+        .event_list_mut()
+        .insert_note(&note(0, 1440, 240, 60))
+        .unwrap();
+    assert_eq!(pxtone.event_list().iter().count(), events + 1);
+}
+
+#[test]
+fn delete_and_resize() {
+    let notes = [note(0, 0, 480, 60), note(0, 960, 480, 64)];
+    let mut pxtone = project(&notes);
+
+    let resized = pxtone.event_list_mut().resize_note(&notes[0], 120).unwrap();
+    assert_eq!(resized.length, 120);
+    assert_eq!(pxtone.event_list().notes(), [resized, notes[1]]);
+
+    pxtone.event_list_mut().delete_note(&resized).unwrap();
+    assert_eq!(pxtone.event_list().notes(), [notes[1]]);
+    assert!(matches!(
+        pxtone.event_list_mut().delete_note(&resized),
+        Err(NoteEditError::NotFound)
+    ));
+}
+
+#[test]
+fn transpose_quantize_and_split() {
+    let notes = [note(0, 10, 480, 60), note(0, 960, 480, 60)];
+    let mut pxtone = project(&notes);
+
+    let up = pxtone
+        .event_list_mut()
+        .transpose_note(&notes[0], 7)
+        .unwrap();
+    assert_eq!(up.key, Key::from_midi(67));
+    let moved = pxtone.event_list_mut().quantize_note(&up, 120).unwrap();
+    assert_eq!(moved.clock, 0);
+    assert_eq!(pxtone.event_list().notes(), [moved, notes[1]]);
+
+    let (first, second) = pxtone.event_list_mut().split_note(&notes[1], 1200).unwrap();
+    assert_eq!((first.clock, first.length), (960, 240));
+    assert_eq!(
+        (second.clock, second.length, second.key),
+        (1200, 240, notes[1].key)
+    );
+    assert_eq!(pxtone.event_list().notes(), [moved, first, second]);
+    assert!(matches!(
+        pxtone.event_list_mut().split_note(&first, 960),
+        Err(NoteEditError::SplitOutside { clock: 960 })
+    ));
+
+    // edits never leave the list unsorted
+    let events = pxtone.event_list();
+    let clocks: Vec<_> = events.iter().map(BaseEvent::clock).collect();
+    assert!(clocks.windows(2).all(|w| w[0] <= w[1]));
+    assert_eq!(events.events_of_kind(EventKind::On).count(), 3);
+}
@@ -0,0 +1,111 @@
+#![cfg(feature = "rust-impl")]
+
+mod common;
+
+use common::vorbis::{ogg_page, silent_vorbis, tone_vorbis};
+use pxtone::{
+    interface::woice::{HasWoices, SingleVoice, Voice, VoiceOGGV, VoicePCM, WoicesMut},
+    rust_impl::{
+        service::RPxTone,
+        wav::{self, WavImportOptions},
+        woice::{RPxToneVoiceOGGV, RPxToneVoiceOGGVError},
+    },
+};
+
+#[test]
+fn reads_headers_and_sample_count() {
+    for channels in [1, 2] {
+        // each short block after the first adds 128 samples, and the granule trims the end
+        let voice = RPxToneVoiceOGGV::from_ogg(silent_vorbis(channels, 22050, 101, 12345)).unwrap();
+        assert_eq!(voice.ogg_channels(), channels);
+        assert_eq!(voice.ogg_samples_per_second(), 22050);
+        assert_eq!(voice.ogg_sample_num(), 12345);
+        assert_eq!(
+            (voice.channels(), voice.samples_per_second()),
+            (channels, 22050)
+        );
+        assert_eq!(voice.basic_key(), 0x4500);
+    }
+}
+
+#[test]
+fn add_from_bytes_and_file() {
+    let ogg = silent_vorbis(2, 44100, 11, 1280);
+
+    let mut pxtone = RPxTone::new();
+    let mut woices = pxtone.woices_mut();
+    let woice = woices.add_oggv_from_bytes(&ogg).unwrap();
+    assert_eq!(woice.voice().ogg_data(), ogg);
+    assert_eq!(woice.voice().ogg_sample_num(), 1280);
+
+    let path = std::env::temp_dir().join(format!("pxtone-oggv-import-{}.ogg", std::process::id()));
+    std::fs::write(&path, &ogg).unwrap();
+    let added = woices
+        .add_oggv_from_file(&path)
+        .map(|w| w.voice().ogg_data().to_vec());
+    std::fs::remove_file(&path).unwrap();
+    assert_eq!(added.unwrap(), ogg);
+
+    assert!(matches!(
+        woices.add_oggv_from_file(&path),
+        Err(RPxToneVoiceOGGVError::Io(e)) if e.kind() == std::io::ErrorKind::NotFound
+    ));
+    assert!(matches!(
+        woices.add_oggv_from_bytes(b"not an ogg"),
+        Err(RPxToneVoiceOGGVError::VorbisError(_))
+    ));
+}
+
+#[test]
+fn exports_ogg_and_wav() {
+    let ogg = silent_vorbis(2, 22050, 11, 1280);
+    let voice = RPxToneVoiceOGGV::from_ogg(ogg.clone()).unwrap();
+
+    let mut out = Vec::new();
+    voice.export_ogg(&mut out).unwrap();
+    assert_eq!(out, ogg);
+
+    let mut out = Vec::new();
+    voice.export_wav(&mut out).unwrap();
+    let wav = wav::read(&out, &WavImportOptions::default()).unwrap();
+    assert_eq!(
+        (wav.channels, wav.samples_per_second, wav.bits_per_sample),
+        (2, 22050, 16)
+    );
+    assert_eq!(wav.data.len(), 1280 * 4);
+    assert!(wav.data.iter().all(|&b| b == 0));
+}
+
+#[test]
+fn rejects_other_codecs() {
+    let mut opus = b"OpusHead".to_vec();
+    opus.extend_from_slice(&[1, 2, 0, 0, 0x80, 0xbb, 0, 0, 0, 0, 0]);
+    let ogg = ogg_page(&[opus], 0x02, 0, 0);
+    assert!(matches!(
+        RPxToneVoiceOGGV::from_ogg(ogg),
+        Err(RPxToneVoiceOGGVError::NotVorbis)
+    ));
+
+    assert!(matches!(
+        RPxToneVoiceOGGV::from_ogg(b"RIFF".to_vec()),
+        Err(RPxToneVoiceOGGVError::VorbisError(_))
+    ));
+}
+
+#[test]
+fn decodes_a_tone() {
+    let voice = RPxToneVoiceOGGV::from_ogg(tone_vorbis(2, 44100, 4480)).unwrap();
+    assert_eq!(voice.ogg_sample_num(), 4480);
+
+    let mut out = Vec::new();
+    voice.export_wav(&mut out).unwrap();
+    let wav = wav::read(&out, &WavImportOptions::default()).unwrap();
+    let samples: Vec<i16> = wav
+        .data
+        .chunks(2)
+        .map(|b| i16::from_le_bytes([b[0], b[1]]))
+        .collect();
+    assert_eq!(samples.len(), 4480 * 2);
+    let peak = samples.iter().map(|s| s.unsigned_abs()).max().unwrap();
+    assert!((16000..16500).contains(&peak), "{peak}");
+}
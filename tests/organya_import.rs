@@ -0,0 +1,193 @@
+#![cfg(feature = "rust-impl")]
+
+use pxtone::{
+    export::wav::{WavExport, WavExportOptions, WavSampleFormat},
+    import::organya::{
+        OrganyaImport, OrganyaImportError, OrganyaImportWarning, OrganyaSamples, WAVE_LEN, WAVE_NUM,
+    },
+    interface::{
+        event::{BaseEvent, EventKind, EventList, HasEventList, Key, PanValue},
+        service::PxTone,
+        unit::{HasUnits, Unit, Units},
+        woice::{HasWoices, Woices},
+    },
+    rust_impl::service::RPxTone,
+};
+
+/// `(x, y, length, volume, pan)`
+type OrgNote = (u32, u8, u8, u8, u8);
+
+fn org(tracks: &[(usize, u16, u8, &[OrgNote])]) -> Vec<u8> {
+    // 100ms steps, 4 beats of 4 steps, looping the second measure
+    let mut bytes = b"Org-02".to_vec();
+    bytes.extend_from_slice(&100_u16.to_le_bytes());
+    bytes.extend_from_slice(&[4, 4]);
+    bytes.extend_from_slice(&16_u32.to_le_bytes());
+    bytes.extend_from_slice(&32_u32.to_le_bytes());
+
+    let notes_of = |i| tracks.iter().find(|t| t.0 == i);
+    for i in 0..16 {
+        let (freq, wave_no, notes) = notes_of(i).map_or((1000, 0, &[][..]), |t| (t.1, t.2, t.3));
+        bytes.extend_from_slice(&freq.to_le_bytes());
+        bytes.extend_from_slice(&[wave_no, 0]);
+        bytes.extend_from_slice(&(notes.len() as u16).to_le_bytes());
+    }
+    for i in 0..16 {
+        let notes = notes_of(i).map_or(&[][..], |t| t.3);
+        for n in notes {
+            bytes.extend_from_slice(&n.0.to_le_bytes());
+        }
+        bytes.extend(notes.iter().map(|n| n.1));
+        bytes.extend(notes.iter().map(|n| n.2));
+        bytes.extend(notes.iter().map(|n| n.3));
+        bytes.extend(notes.iter().map(|n| n.4));
+    }
+    bytes
+}
+
+fn song() -> Vec<u8> {
+    org(&[
+        // A4 cut off by the next note, then a volume and pan change
+        (
+            0,
+            1000,
+            3,
+            &[
+                (0, 45, 4, 255, 6),
+                (2, 47, 2, 200, 255),
+                (8, 255, 0, 128, 0),
+            ],
+        ),
+        (1, 990, 50, &[(4, 36, 1, 255, 255)]),
+        (8, 1000, 2, &[(0, 27, 1, 255, 12)]),
+    ])
+}
+
+struct Sounds {
+    waves: Vec<u8>,
+    drums: Vec<Vec<u8>>,
+}
+
+impl Sounds {
+    fn new() -> Self {
+        let waves = (0..WAVE_NUM * WAVE_LEN)
+            .map(|i| {
+                if i % WAVE_LEN < WAVE_LEN / 2 {
+                    0x40
+                } else {
+                    0xC0
+                }
+            })
+            .collect();
+        // a tenth of a second of noise-ish drum
+        let drums = vec![
+            vec![],
+            vec![],
+            (0..2205).map(|i| (i * 37 % 256) as u8).collect(),
+        ];
+        Self { waves, drums }
+    }
+
+    fn import(
+        &self,
+        pxtone: &mut RPxTone,
+        bytes: &[u8],
+    ) -> Result<Vec<OrganyaImportWarning>, OrganyaImportError> {
+        let drums: Vec<&[u8]> = self.drums.iter().map(Vec::as_slice).collect();
+        let samples = OrganyaSamples { waves: &self.waves, drums: &drums };
+        pxtone.import_organya(bytes, &samples)
+    }
+}
+
+#[test]
+fn tracks_become_units_and_events() {
+    let mut pxtone = RPxTone::new();
+    let warnings = Sounds::new().import(&mut pxtone, &song()).unwrap();
+
+    assert_eq!(
+        warnings,
+        [OrganyaImportWarning::FineTune { track: 1, freq: 990 }]
+    );
+    let names: Vec<_> = Units::iter(&*pxtone.units()).map(|u| u.name()).collect();
+    assert_eq!(names.len(), 16);
+    assert_eq!((&*names[0], &*names[8]), ("melody 1", "drum 1"));
+    // only tracks with notes get a woice
+    assert_eq!(Woices::iter(&*pxtone.woices()).count(), 3);
+
+    assert!((pxtone.beat_tempo() - 150.0).abs() < 0.01);
+    assert_eq!(pxtone.beat_num(), 4);
+    assert_eq!((pxtone.repeat_measure(), pxtone.last_measure()), (1, 2));
+
+    let step = pxtone.beat_clock() as u32 / 4;
+    let events = pxtone.event_list();
+    let notes: Vec<_> = events
+        .notes()
+        .into_iter()
+        .filter(|n| n.unit_no == 0)
+        .collect();
+    assert_eq!(notes.len(), 2);
+    assert_eq!((notes[0].clock, notes[0].length), (0, step * 2));
+    assert_eq!(notes[0].key, Key::from_midi(69));
+    assert_eq!((notes[1].clock, notes[1].length), (step * 2, step * 2));
+    assert_eq!(
+        events.unit_state_at(0, step * 8).pan_volume,
+        PanValue::left()
+    );
+    let volumes: Vec<_> = events
+        .events_of_kind(EventKind::Volume)
+        .filter(|e| e.unit_no() == 0)
+        .map(|e| e.clock())
+        .collect();
+    assert_eq!(volumes, [step * 2, step * 8]);
+
+    // drums play their whole sample, at a rate set by the key
+    let drum = events.notes().into_iter().find(|n| n.unit_no == 8).unwrap();
+    let secs = 2205.0 / (27.0 * 800.0 + 100.0);
+    let clocks_per_sec = f64::from(pxtone.beat_clock()) * 150.0 / 60.0;
+    assert_eq!(drum.length, (secs * clocks_per_sec).ceil() as u32);
+    assert!(drum.key < Key::DEFAULT);
+    assert_eq!(events.unit_state_at(8, 0).pan_volume, PanValue::right());
+}
+
+#[test]
+fn renders() {
+    let mut pxtone = RPxTone::new();
+    Sounds::new().import(&mut pxtone, &song()).unwrap();
+    let options = WavExportOptions {
+        channels: 1,
+        sample_rate: 8000,
+        format: WavSampleFormat::Int16,
+        loops: 1,
+        fade_out: None,
+    };
+    let summary = pxtone.export_wav(Vec::new(), &options).unwrap();
+    assert!(summary.peak > 0.0);
+}
+
+#[test]
+fn rejects_bad_input() {
+    let mut pxtone = RPxTone::new();
+    let sounds = Sounds::new();
+    assert!(matches!(
+        sounds.import(&mut pxtone, b"Org-01"),
+        Err(OrganyaImportError::InvalidFile(_))
+    ));
+    let mut truncated = song();
+    truncated.pop();
+    assert!(matches!(
+        sounds.import(&mut pxtone, &truncated),
+        Err(OrganyaImportError::InvalidFile(_))
+    ));
+
+    let missing = org(&[(9, 1000, 1, &[(0, 30, 1, 255, 255)])]);
+    assert!(matches!(
+        sounds.import(&mut pxtone, &missing),
+        Err(OrganyaImportError::MissingDrum { wave_no: 1 })
+    ));
+
+    let short = Sounds { waves: vec![0; 256], ..Sounds::new() };
+    assert!(matches!(
+        short.import(&mut pxtone, &song()),
+        Err(OrganyaImportError::InvalidWaves { len: 256 })
+    ));
+}
@@ -0,0 +1,94 @@
+#![cfg(feature = "rust-impl")]
+
+use pxtone::{
+    diff::diff,
+    interface::{
+        delay::{DelayUnit, DelaysMut, HasDelays},
+        io::PxToneServiceIO,
+        overdrive::{HasOverDrives, OverDAmp, OverDCut, OverDrivesMut},
+        unit::{HasUnits, Unit, UnitsMut},
+        woice::{HasWoices, WoicesMut},
+    },
+    rust_impl::service::RPxTone,
+    util::ZeroToOneF32,
+};
+
+const SAMPLE: &[u8] = include_bytes!("../examples/sample.ptcop");
+
+fn write(pxtone: &mut RPxTone, name: &str) -> Vec<u8> {
+    let path =
+        std::env::temp_dir().join(format!("pxtone-write-{name}-{}.ptcop", std::process::id()));
+    let bytes = pxtone.write_file(&path).unwrap();
+    assert_eq!(std::fs::read(&path).unwrap(), bytes);
+    std::fs::remove_file(path).unwrap();
+    bytes
+}
+
+fn read(bytes: &[u8]) -> RPxTone {
+    let mut pxtone = RPxTone::new();
+    pxtone.read_bytes(bytes).unwrap();
+    pxtone
+}
+
+#[test]
+fn sample_saves_back_byte_for_byte() {
+    let written = write(&mut read(SAMPLE), "sample");
+
+    // og pxtone saves these the same way: the exe version as 0, and unit names padded with NULs
+    // rather than whatever was left in the buffer after them
+    let mut expected = SAMPLE.to_vec();
+    expected[16..18].fill(0);
+    let mut at = 0;
+    while let Some(found) = expected[at..].windows(8).position(|w| w == b"assiUNIT") {
+        let name = at + found + 16;
+        let name = &mut expected[name..name + 16];
+        let end = name.iter().position(|&b| b == 0).unwrap_or(16);
+        name[end..].fill(0);
+        at += found + 8;
+    }
+
+    assert_eq!(written.len(), expected.len());
+    assert!(written == expected);
+}
+
+#[test]
+fn edited_project_round_trips() {
+    let mut pxtone = read(SAMPLE);
+    // RPxTone is its own delay/overdrive list, so the trait has to be named to pick the method
+    DelaysMut::add(
+        &mut *pxtone.delays_mut(),
+        3,
+        DelayUnit::Second(0.5),
+        ZeroToOneF32::new(0.15),
+    )
+    .unwrap();
+    OverDrivesMut::add(
+        &mut *pxtone.overdrives_mut(),
+        1,
+        OverDCut::new(0.503),
+        OverDAmp::new(2.0),
+    )
+    .unwrap();
+    pxtone
+        .woices_mut()
+        .add_ptv_from_file("examples/sample.ptvoice")
+        .unwrap();
+    pxtone
+        .woices_mut()
+        .add_pcm(2, 22050, 16, (0..=255).collect(), 0x4500, true)
+        .unwrap();
+    // units without a name don't get an assiUNIT block
+    pxtone
+        .units_mut()
+        .add_new()
+        .unwrap()
+        .set_name(String::new())
+        .unwrap();
+
+    let written = write(&mut pxtone, "edited");
+    let mut read_back = read(&written);
+
+    let d = diff(&pxtone, &read_back);
+    assert!(d.is_empty(), "{d}");
+    assert!(write(&mut read_back, "edited-again") == written);
+}
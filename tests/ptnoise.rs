@@ -0,0 +1,120 @@
+#![cfg(feature = "rust-impl")]
+
+use pxtone::{
+    interface::{
+        io::PxToneServiceIO,
+        woice::{
+            HasWoices, PTNOscillator, PTNUnit, PTNWaveType, SingleVoice, VoicePTN, Woice, WoicePTN,
+            WoiceType, Woices, WoicesMut,
+        },
+    },
+    rust_impl::{
+        ptnoise::{self, PTNoiseError},
+        service::RPxTone,
+    },
+};
+
+const SAMPLE: &[u8] = include_bytes!("../examples/sample.ptcop");
+const BLANK: &[u8] = include_bytes!("../src/pxtone/og_impl/blank.ptnoise");
+
+/// The ptNoise data of every `matePTN ` block in the sample project
+fn sample_noises() -> Vec<&'static [u8]> {
+    let mut noises = Vec::new();
+    let mut rest = SAMPLE;
+    while let Some(i) = rest.windows(8).position(|w| w == b"matePTN ") {
+        let size = u32::from_le_bytes(rest[i + 8..i + 12].try_into().unwrap()) as usize;
+        // skip the block size and the 16 byte material header
+        noises.push(&rest[i + 12 + 16..i + 12 + size]);
+        rest = &rest[i + 12 + size..];
+    }
+    noises
+}
+
+#[test]
+fn round_trips_exactly() {
+    let noises = sample_noises();
+    assert_eq!(noises.len(), 3);
+
+    for bytes in noises.into_iter().chain([BLANK]) {
+        let voice = ptnoise::read(bytes).unwrap();
+        let mut written = Vec::new();
+        ptnoise::write(&voice, &mut written).unwrap();
+        assert_eq!(written, bytes);
+    }
+}
+
+#[test]
+fn project_ptn_woices_are_decoded() {
+    let mut pxtone = RPxTone::new();
+    pxtone.read_bytes(SAMPLE).unwrap();
+
+    let mut exported = Vec::new();
+    for woice in Woices::iter(&*pxtone.woices()) {
+        if let WoiceType::PTN(ptn) = woice.woice_type() {
+            ptn.export_ptn(&mut exported).unwrap();
+        }
+    }
+    assert_eq!(exported, sample_noises().concat());
+}
+
+#[test]
+fn add_from_file() {
+    let mut pxtone = RPxTone::new();
+    let mut woices = pxtone.woices_mut();
+    let woice = woices
+        .add_ptn_from_file("src/pxtone/og_impl/blank.ptnoise")
+        .unwrap();
+
+    let voice = woice.voice();
+    assert_eq!(voice.ptn_sample_num(), 44100);
+    let units = voice.units();
+    assert_eq!(units.len(), 1);
+    assert_eq!(units[0].envelope().len(), 3);
+    assert_eq!(units[0].osc_main().shape(), PTNWaveType::None);
+
+    let mut written = Vec::new();
+    woice.export_ptn(&mut written).unwrap();
+    assert_eq!(written, BLANK);
+
+    assert!(woices.add_ptn_from_file("missing.ptnoise").is_none());
+}
+
+#[test]
+fn rejects_bad_input() {
+    assert!(matches!(
+        ptnoise::read(b"PTVOICE-"),
+        Err(PTNoiseError::IncorrectHeader(_))
+    ));
+    assert!(matches!(
+        ptnoise::read(&BLANK[..BLANK.len() - 1]),
+        Err(PTNoiseError::UnexpectedEnd)
+    ));
+
+    let mut newer = BLANK.to_vec();
+    newer[8..12].copy_from_slice(&20_991_231_u32.to_le_bytes());
+    assert!(matches!(
+        ptnoise::read(&newer),
+        Err(PTNoiseError::NewerVersion(20_991_231))
+    ));
+}
+
+#[cfg(feature = "og-impl")]
+#[test]
+fn matches_og_impl() {
+    use pxtone::og_impl::service::PxToneService;
+
+    let mut og = PxToneService::new().unwrap();
+    og.read_bytes(SAMPLE).unwrap();
+
+    for (noise, woice) in sample_noises()
+        .into_iter()
+        .zip(Woices::iter(&*og.woices()).filter(|w| matches!(w.woice_type(), WoiceType::PTN(_))))
+    {
+        let WoiceType::PTN(ptn) = woice.woice_type() else {
+            unreachable!()
+        };
+        let mut written = Vec::new();
+        ptn.export_ptn(&mut written).unwrap();
+        assert_eq!(written, noise);
+    }
+}
@@ -0,0 +1,72 @@
+#![cfg(feature = "rust-impl")]
+
+use pxtone::{
+    interface::woice::{
+        HasWoices, PTVEnvelope, PTVWaveType, Voice, VoicePTV, Woice, WoicePTV, WoiceType, Woices,
+        WoicesMut,
+    },
+    rust_impl::{
+        ptvoice::{self, PTVoiceError},
+        service::RPxTone,
+    },
+};
+
+const SAMPLE: &[u8] = include_bytes!("../examples/sample.ptvoice");
+const BLANK: &[u8] = include_bytes!("../src/pxtone/og_impl/blank.ptvoice");
+
+#[test]
+fn round_trips_exactly() {
+    for bytes in [SAMPLE, BLANK] {
+        let woice = ptvoice::read(bytes).unwrap();
+        let mut written = Vec::new();
+        woice.export_ptv(&mut written).unwrap();
+        assert_eq!(written, bytes);
+    }
+}
+
+#[test]
+fn add_from_file() {
+    let mut pxtone = RPxTone::new();
+    let mut woices = pxtone.woices_mut();
+    let woice = woices.add_ptv_from_file("examples/sample.ptvoice").unwrap();
+
+    let voices = woice.voices();
+    assert_eq!(voices.len(), 1);
+    assert_eq!((voices[0].volume(), voices[0].pan()), (64, 64));
+    assert!(matches!(voices[0].wave(), PTVWaveType::Coordinate(_)));
+    assert_eq!(voices[0].envelope().tail_num(), 1);
+    drop(woices);
+
+    let woices = pxtone.woices();
+    let woice = Woices::iter(&*woices).next().unwrap();
+    let WoiceType::PTV(ptv) = woice.woice_type() else {
+        panic!("not a ptvoice");
+    };
+    let mut written = Vec::new();
+    ptv.export_ptv(&mut written).unwrap();
+    assert_eq!(written, SAMPLE);
+
+    assert!(pxtone
+        .woices_mut()
+        .add_ptv_from_file("missing.ptvoice")
+        .is_none());
+}
+
+#[test]
+fn rejects_bad_input() {
+    assert!(matches!(
+        ptvoice::read(b"PTNOISE-"),
+        Err(PTVoiceError::IncorrectHeader(_))
+    ));
+    assert!(matches!(
+        ptvoice::read(&SAMPLE[..SAMPLE.len() - 1]),
+        Err(PTVoiceError::UnexpectedEnd)
+    ));
+
+    let mut newer = SAMPLE.to_vec();
+    newer[8..12].copy_from_slice(&20_991_231_u32.to_le_bytes());
+    assert!(matches!(
+        ptvoice::read(&newer),
+        Err(PTVoiceError::NewerVersion(20_991_231))
+    ));
+}
@@ -0,0 +1,148 @@
+#![cfg(feature = "serde")]
+
+use pxtone::{
+    diff::diff,
+    export::wav::{WavExport, WavExportOptions, WavSampleFormat},
+    interface::{
+        delay::{DelayUnit, DelaysMut, HasDelays},
+        io::PxToneServiceIO,
+        woice::{HasWoices, WoicesMut},
+    },
+    rust_impl::service::RPxTone,
+    util::ZeroToOneF32,
+};
+use serde_json::{json, Value};
+
+const SAMPLE: &[u8] = include_bytes!("../examples/sample.ptcop");
+
+fn load() -> RPxTone {
+    let mut pxtone = RPxTone::new();
+    pxtone.read_bytes(SAMPLE).unwrap();
+    pxtone
+}
+
+fn render(pxtone: &mut RPxTone) -> Vec<u8> {
+    let options = WavExportOptions {
+        channels: 1,
+        sample_rate: 8000,
+        format: WavSampleFormat::Int16,
+        loops: 1,
+        fade_out: None,
+    };
+    let mut wav = Vec::new();
+    pxtone.export_wav(&mut wav, &options).unwrap();
+    wav
+}
+
+#[test]
+fn text_round_trip_is_exact() {
+    let mut pxtone = load();
+    pxtone
+        .delays_mut()
+        .add(3, DelayUnit::Beat(0.5), ZeroToOneF32::new(0.25))
+        .unwrap();
+    pxtone
+        .woices_mut()
+        .add_pcm(2, 22050, 16, (0..=255).collect(), 0x4500, true)
+        .unwrap();
+
+    let text = serde_json::to_string_pretty(&pxtone).unwrap();
+    let mut read: RPxTone = serde_json::from_str(&text).unwrap();
+
+    let d = diff(&pxtone, &read);
+    assert!(d.is_empty(), "{d}");
+    assert_eq!(serde_json::to_string_pretty(&read).unwrap(), text);
+    assert_eq!(render(&mut read), render(&mut pxtone));
+
+    // PCM data is kept as base64 rather than a huge array of numbers
+    let value: Value = serde_json::from_str(&text).unwrap();
+    let pcm = &value["woices"].as_array().unwrap().last().unwrap()["woice_type"]["PCM"];
+    assert_eq!(pcm["voice"]["data"].as_str().unwrap().len(), 344);
+}
+
+#[test]
+fn synthesized_woices() {
+    let mut value = serde_json::to_value(load()).unwrap();
+    let envelope = json!([{ "x": 0, "y": 100 }, { "x": 100, "y": 0 }]);
+    let osc = |shape, frequency| {
+        json!({
+            "shape": shape,
+            "frequency": frequency,
+            "volume": 50.0,
+            "offset": 0.0,
+            "reverse": false,
+        })
+    };
+    let woices = value["woices"].as_array_mut().unwrap();
+    woices.push(json!({
+        "name": "ptv",
+        "woice_type": { "PTV": { "voices": [{
+            "basic_key": 0x4500,
+            "volume": 64,
+            "pan": 64,
+            "tuning": 1.0,
+            "flag_loop": true,
+            "flag_smooth": true,
+            "flag_beat_fit": false,
+            "wave": { "Coordinate": {
+                "resolution": 200,
+                "points": [{ "x": 0, "y": 0 }, { "x": 50, "y": 64 }, { "x": 150, "y": -64 }],
+            } },
+            "envelope": {
+                "fps": 1000,
+                "head_num": 1,
+                "body_num": 0,
+                "tail_num": 1,
+                "points": envelope,
+            },
+        }] } },
+    }));
+    woices.push(json!({
+        "name": "ptn",
+        "woice_type": { "PTN": { "voice": {
+            "basic_key": 0x4500,
+            "volume": 100,
+            "pan": 64,
+            "tuning": 1.0,
+            "flag_loop": false,
+            "flag_smooth": true,
+            "flag_beat_fit": false,
+            "channels": 1,
+            "samples_per_second": 44100,
+            "bits_per_sample": 16,
+            "ptn_sample_num": 4410,
+            "ptn_units": [{
+                "enabled": true,
+                "pan": 0,
+                "envelope": envelope,
+                "osc_main": osc("Random", 440.0),
+                "osc_frequency": osc("Sine", 2.0),
+                "osc_volume": osc("None", 0.0),
+            }],
+        } } },
+    }));
+
+    let pxtone: RPxTone = serde_json::from_value(value.clone()).unwrap();
+    assert_eq!(serde_json::to_value(&pxtone).unwrap(), value);
+}
+
+fn first_pcm(value: &mut Value) -> &mut Value {
+    value["woices"]
+        .as_array_mut()
+        .unwrap()
+        .iter_mut()
+        .find_map(|w| w["woice_type"].get_mut("PCM"))
+        .unwrap()
+}
+
+#[test]
+fn rejects_bad_payloads() {
+    let mut value = serde_json::to_value(load()).unwrap();
+
+    first_pcm(&mut value)["voice"]["data"] = json!("not base64!");
+    assert!(serde_json::from_value::<RPxTone>(value.clone()).is_err());
+
+    first_pcm(&mut value)["voice"]["data"] = json!("AAAA");
+    first_pcm(&mut value)["voice"]["bits_per_sample"] = json!(24);
+    assert!(serde_json::from_value::<RPxTone>(value).is_err());
+}
@@ -0,0 +1,182 @@
+#![cfg(feature = "rust-impl")]
+
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use pxtone::{
+    export::sfz::{SfzExport, SfzExportError, SfzExportOptions},
+    interface::{
+        io::PxToneServiceIO,
+        woice::{HasWoices, SingleVoice, Voice, WoicesMut},
+    },
+    rust_impl::{
+        service::RPxTone,
+        wav::{self, WavImportOptions},
+    },
+};
+
+const SAMPLE: &[u8] = include_bytes!("../examples/sample.ptcop");
+
+/// A fresh folder in the temp dir, removed when dropped
+struct TempDir(PathBuf);
+
+impl TempDir {
+    fn new(name: &str) -> Self {
+        let path = std::env::temp_dir().join(format!("pxtone-sfz-{name}-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&path);
+        Self(path)
+    }
+}
+
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.0);
+    }
+}
+
+/// Each `<region>`'s opcodes, in order
+fn regions(sfz: &Path) -> Vec<Vec<(String, String)>> {
+    let text = fs::read_to_string(sfz).unwrap();
+    text.split("<region>")
+        .skip(1)
+        .map(|region| {
+            region
+                .lines()
+                .filter_map(|l| l.split_once('='))
+                .map(|(k, v)| (k.to_owned(), v.to_owned()))
+                .collect()
+        })
+        .collect()
+}
+
+fn opcode<'a>(region: &'a [(String, String)], name: &str) -> Option<&'a str> {
+    region
+        .iter()
+        .find(|(k, _)| k == name)
+        .map(|(_, v)| v.as_str())
+}
+
+#[test]
+fn exports_sample_and_rendered_woices() {
+    let dir = TempDir::new("project");
+    let mut pxtone = RPxTone::new();
+    pxtone.read_bytes(SAMPLE).unwrap();
+
+    let options = SfzExportOptions {
+        render_notes: vec![72, 48, 60],
+        note_length: Duration::from_millis(100),
+        ..SfzExportOptions::default()
+    };
+    let written = pxtone.export_sfz(&dir.0, &options).unwrap();
+    assert_eq!(written.len(), 8);
+
+    for (i, path) in written.iter().enumerate() {
+        assert!(path
+            .file_name()
+            .unwrap()
+            .to_str()
+            .unwrap()
+            .starts_with(&format!("{i:02} ")));
+        let regions = regions(path);
+
+        if i < 5 {
+            // PCM woices keep their sample as is, and play at the same pitch as in pxtone
+            assert_eq!(regions.len(), 1);
+            let region = &regions[0];
+            assert_eq!(opcode(region, "pitch_keycenter"), Some("57"));
+            assert_eq!(opcode(region, "tune"), None);
+            assert!(opcode(region, "lokey").is_none());
+
+            let sample = fs::read(dir.0.join(opcode(region, "sample").unwrap())).unwrap();
+            let sample = wav::read(&sample, &WavImportOptions::default()).unwrap();
+            assert_eq!((sample.channels, sample.bits_per_sample), (1, 8));
+        } else {
+            // PTN woices are rendered into a multisample covering every key
+            let keys: Vec<_> = regions
+                .iter()
+                .map(|r| {
+                    (
+                        opcode(r, "lokey").unwrap(),
+                        opcode(r, "pitch_keycenter").unwrap(),
+                        opcode(r, "hikey").unwrap(),
+                    )
+                })
+                .collect();
+            assert_eq!(
+                keys,
+                [("0", "48", "54"), ("55", "60", "66"), ("67", "72", "127")]
+            );
+
+            for region in &regions {
+                let sample = fs::read(dir.0.join(opcode(region, "sample").unwrap())).unwrap();
+                let sample = wav::read(&sample, &WavImportOptions::default()).unwrap();
+                assert_eq!((sample.channels, sample.samples_per_second), (2, 44100));
+                assert!(sample.data.len() >= 4410 * 4);
+            }
+        }
+    }
+}
+
+#[test]
+fn maps_key_tuning_and_flags() {
+    let dir = TempDir::new("mapping");
+    let mut pxtone = RPxTone::new();
+    {
+        let mut woices = pxtone.woices_mut();
+        // a minor third up
+        woices
+            .add_pcm(1, 44100, 16, vec![0; 2000], 0x4500 + 3 * 256, true)
+            .unwrap();
+        // a quarter tone sharp, which sounds at its recorded rate a quarter tone down
+        let mut woice = woices
+            .add_pcm(2, 22050, 8, vec![128; 2000], 0x4500, false)
+            .unwrap();
+        woice.voice_mut().set_tuning(2_f32.powf(0.5 / 12.0));
+    }
+
+    let written = pxtone
+        .export_sfz(&dir.0, &SfzExportOptions::default())
+        .unwrap();
+
+    let looped = &regions(&written[0])[0];
+    assert_eq!(opcode(looped, "pitch_keycenter"), Some("60"));
+    assert_eq!(opcode(looped, "loop_mode"), Some("loop_continuous"));
+    assert_eq!(opcode(looped, "loop_start"), Some("0"));
+    assert_eq!(opcode(looped, "loop_end"), Some("999"));
+
+    let tuned = &regions(&written[1])[0];
+    assert_eq!(opcode(tuned, "pitch_keycenter"), Some("57"));
+    assert_eq!(opcode(tuned, "tune"), Some("50"));
+    assert_eq!(opcode(tuned, "loop_mode"), Some("no_loop"));
+}
+
+#[test]
+fn rejects_bad_options() {
+    let dir = TempDir::new("options");
+    let pxtone = RPxTone::new();
+
+    for options in [
+        SfzExportOptions {
+            render_notes: vec![],
+            ..SfzExportOptions::default()
+        },
+        SfzExportOptions {
+            render_notes: vec![60, 128],
+            ..SfzExportOptions::default()
+        },
+        SfzExportOptions {
+            note_length: Duration::ZERO,
+            ..SfzExportOptions::default()
+        },
+        SfzExportOptions { sample_rate: 0, ..SfzExportOptions::default() },
+    ] {
+        assert!(matches!(
+            pxtone.export_sfz(&dir.0, &options),
+            Err(SfzExportError::InvalidOptions(_))
+        ));
+    }
+    assert!(!dir.0.exists());
+}
@@ -0,0 +1,107 @@
+#![cfg(feature = "rust-impl")]
+
+use pxtone::{
+    diff::diff,
+    interface::{
+        delay::{DelayUnit, DelaysMut, HasDelays},
+        event::{EventListMut, HasEventList, Key},
+        event_impl::EventImpl,
+        io::PxToneServiceIO,
+        service::PxTone,
+        unit::{HasUnits, Unit, UnitsMut},
+        woice::{HasWoices, WoicesMut},
+    },
+    rust_impl::service::RPxTone,
+    util::ZeroToOneF32,
+};
+
+const SAMPLE: &[u8] = include_bytes!("../examples/sample.ptcop");
+
+fn load_rust() -> RPxTone {
+    let mut pxtone = RPxTone::new();
+    pxtone.read_bytes(SAMPLE).unwrap();
+    pxtone
+}
+
+#[test]
+fn same_project_has_no_differences() {
+    let d = diff(&load_rust(), &load_rust());
+    assert!(d.is_empty(), "{d}");
+}
+
+#[test]
+fn compares_ptv_woices() {
+    let mut a = load_rust();
+    a.woices_mut()
+        .add_ptv_from_file("examples/sample.ptvoice")
+        .unwrap();
+    let d = diff(&a, &a);
+    assert!(d.is_empty(), "{d}");
+
+    let d = diff(&a, &load_rust());
+    let paths: Vec<_> = d.differences.iter().map(|d| d.path.as_str()).collect();
+    assert!(paths.contains(&"woices.len"), "{d}");
+}
+
+#[test]
+fn reports_changes() {
+    let a = load_rust();
+    let mut b = load_rust();
+
+    b.set_beat_tempo(a.beat_tempo() + 1.0);
+    // RPxTone is its own unit/delay list, so the trait has to be named to pick the right method
+    UnitsMut::iter_mut(&mut *b.units_mut())
+        .next()
+        .unwrap()
+        .set_name("renamed".into())
+        .unwrap();
+    b.event_list_mut()
+        .add(&EventImpl::key(12345, 0, Key::new(0)))
+        .unwrap();
+    DelaysMut::add(
+        &mut *b.delays_mut(),
+        0,
+        DelayUnit::Beat(1.0),
+        ZeroToOneF32::new(0.5),
+    )
+    .unwrap();
+
+    let d = diff(&a, &b);
+    let paths: Vec<_> = d.differences.iter().map(|d| d.path.as_str()).collect();
+    assert!(paths.contains(&"master.beat_tempo"), "{d}");
+    assert!(paths.contains(&"units[0].name"), "{d}");
+    assert!(paths.contains(&"events.len"), "{d}");
+    assert!(paths.contains(&"delays.len"), "{d}");
+}
+
+#[cfg(feature = "og-impl")]
+#[test]
+fn rust_impl_matches_og_impl() {
+    use pxtone::og_impl::service::PxToneService;
+
+    let mut og = PxToneService::new().unwrap();
+    og.read_bytes(SAMPLE).unwrap();
+
+    let d = diff(&og, &load_rust());
+    assert!(d.is_empty(), "{d}");
+}
+
+#[cfg(feature = "og-impl")]
+#[test]
+fn rust_impl_matches_og_impl_with_ptv() {
+    use pxtone::og_impl::service::PxToneService;
+
+    let mut og = PxToneService::new().unwrap();
+    og.read_bytes(SAMPLE).unwrap();
+    og.woices_mut()
+        .add_ptv_from_file("examples/sample.ptvoice")
+        .unwrap();
+    let mut rust = load_rust();
+    rust.woices_mut()
+        .add_ptv_from_file("examples/sample.ptvoice")
+        .unwrap();
+
+    // compares the PTV's waves and envelopes, which og has to expose too
+    let d = diff(&og, &rust);
+    assert!(d.is_empty(), "{d}");
+}
@@ -0,0 +1,142 @@
+#![cfg(feature = "rust-impl")]
+
+use std::time::Duration;
+
+use pxtone::{
+    export::wav::{WavExport, WavExportError, WavExportOptions, WavSampleFormat},
+    interface::{io::PxToneServiceIO, service::PxTone},
+    rust_impl::service::RPxTone,
+};
+
+const SAMPLE: &[u8] = include_bytes!("../examples/sample.ptcop");
+
+// low rate & mono to keep the tests fast
+const OPTIONS: WavExportOptions = WavExportOptions {
+    channels: 1,
+    sample_rate: 8000,
+    format: WavSampleFormat::Int16,
+    loops: 1,
+    fade_out: None,
+};
+
+fn load() -> RPxTone {
+    let mut pxtone = RPxTone::new();
+    pxtone.read_bytes(SAMPLE).unwrap();
+    // keep the song short, with a one measure loop
+    pxtone.set_num_measures(4);
+    pxtone.set_last_measure(4);
+    pxtone.set_repeat_measure(3);
+    pxtone
+}
+
+fn frames_per_measure(pxtone: &RPxTone) -> u64 {
+    let beats = f64::from(pxtone.beat_num());
+    (f64::from(OPTIONS.sample_rate) * 60.0 * beats / f64::from(pxtone.beat_tempo())) as u64
+}
+
+fn u16_at(bytes: &[u8], at: usize) -> u16 {
+    u16::from_le_bytes(bytes[at..at + 2].try_into().unwrap())
+}
+
+fn u32_at(bytes: &[u8], at: usize) -> u32 {
+    u32::from_le_bytes(bytes[at..at + 4].try_into().unwrap())
+}
+
+#[test]
+fn writes_pcm16() {
+    let mut pxtone = load();
+    let mut wav = Vec::new();
+    let summary = pxtone.export_wav(&mut wav, &OPTIONS).unwrap();
+
+    assert_eq!(summary.frames, frames_per_measure(&pxtone) * 4);
+    assert!(summary.peak > 0.0);
+
+    assert_eq!(&wav[0..4], b"RIFF");
+    assert_eq!(u32_at(&wav, 4) as usize, wav.len() - 8);
+    assert_eq!(&wav[8..16], b"WAVEfmt ");
+    assert_eq!(u16_at(&wav, 20), 1);
+    assert_eq!(u16_at(&wav, 22), 1);
+    assert_eq!(u32_at(&wav, 24), 8000);
+    assert_eq!(u16_at(&wav, 34), 16);
+    assert_eq!(&wav[36..40], b"data");
+    assert_eq!(u32_at(&wav, 40) as u64, summary.frames * 2);
+    assert_eq!(wav.len(), 44 + summary.frames as usize * 2);
+}
+
+#[test]
+fn writes_float32() {
+    let mut pxtone = load();
+    let mut wav = Vec::new();
+    let options = WavExportOptions {
+        channels: 2,
+        format: WavSampleFormat::Float32,
+        ..OPTIONS
+    };
+    let summary = pxtone.export_wav(&mut wav, &options).unwrap();
+
+    assert_eq!(u16_at(&wav, 20), 3);
+    assert_eq!(u16_at(&wav, 22), 2);
+    assert_eq!(u16_at(&wav, 34), 32);
+    assert_eq!(&wav[38..42], b"fact");
+    assert_eq!(u32_at(&wav, 46) as u64, summary.frames);
+    assert_eq!(&wav[50..54], b"data");
+    assert_eq!(wav.len(), 58 + summary.frames as usize * 8);
+}
+
+#[test]
+fn float32_is_rendered_unclipped() {
+    let mut pxtone = load();
+    let mut int16 = Vec::new();
+    pxtone.export_wav(&mut int16, &OPTIONS).unwrap();
+    let mut float32 = Vec::new();
+    let options = WavExportOptions { format: WavSampleFormat::Float32, ..OPTIONS };
+    let summary = pxtone.export_wav(&mut float32, &options).unwrap();
+
+    let ints = int16[44..]
+        .chunks_exact(2)
+        .map(|b| i16::from_le_bytes([b[0], b[1]]));
+    let floats = float32[58..]
+        .chunks_exact(4)
+        .map(|b| f32::from_le_bytes([b[0], b[1], b[2], b[3]]));
+    let mut fractional = false;
+    for (i, f) in ints.zip(floats) {
+        let scaled = f * 32768.0;
+        fractional |= scaled.fract() != 0.0;
+        if i != i16::MIN && i != i16::MAX {
+            assert!((scaled - f32::from(i)).abs() <= 1.0, "{i} {f}");
+        }
+    }
+    // not just the 16-bit render converted
+    assert!(fractional);
+    assert!(summary.peak > 0.0);
+}
+
+#[test]
+fn loops_and_fades() {
+    let mut pxtone = load();
+    let measure = frames_per_measure(&pxtone);
+    let options = WavExportOptions {
+        loops: 3,
+        fade_out: Some(Duration::from_secs(1)),
+        ..OPTIONS
+    };
+    let mut wav = Vec::new();
+    let summary = pxtone.export_wav(&mut wav, &options).unwrap();
+
+    // 4 measures, 2 more plays of the last measure, then a second of fade
+    assert_eq!(summary.frames, measure * 4 + measure * 2 + 8000);
+
+    // fully faded out by the end
+    let last = u16_at(&wav, wav.len() - 2) as i16;
+    assert!(last.unsigned_abs() < 16, "{last}");
+}
+
+#[test]
+fn rejects_invalid_options() {
+    let mut pxtone = load();
+    let options = WavExportOptions { loops: 0, ..OPTIONS };
+    assert!(matches!(
+        pxtone.export_wav(Vec::new(), &options),
+        Err(WavExportError::InvalidOptions(_))
+    ));
+}
@@ -0,0 +1,227 @@
+#![cfg(feature = "rust-impl")]
+
+use pxtone::{
+    export::wav::{WavExport, WavExportOptions, WavSampleFormat},
+    interface::{
+        io::PxToneServiceIO,
+        woice::{HasWoices, SingleVoice, VoicePCM, WoicesMut},
+    },
+    rust_impl::{
+        service::RPxTone,
+        wav::{self, Wav, WavError, WavImportOptions},
+        woice::RPxToneVoicePCM,
+    },
+};
+
+const CONVERT: WavImportOptions =
+    WavImportOptions { convert_format: true, convert_sample_rate: true };
+
+fn chunk(id: &[u8; 4], body: &[u8]) -> Vec<u8> {
+    let mut out = id.to_vec();
+    out.extend_from_slice(&(body.len() as u32).to_le_bytes());
+    out.extend_from_slice(body);
+    if body.len() % 2 == 1 {
+        out.push(0);
+    }
+    out
+}
+
+fn fmt(format: u16, channels: u16, rate: u32, bits: u16) -> Vec<u8> {
+    let block_align = channels * bits / 8;
+    let mut body = Vec::new();
+    body.extend_from_slice(&format.to_le_bytes());
+    body.extend_from_slice(&channels.to_le_bytes());
+    body.extend_from_slice(&rate.to_le_bytes());
+    body.extend_from_slice(&(rate * u32::from(block_align)).to_le_bytes());
+    body.extend_from_slice(&block_align.to_le_bytes());
+    body.extend_from_slice(&bits.to_le_bytes());
+    body
+}
+
+fn riff(chunks: &[Vec<u8>]) -> Vec<u8> {
+    let body = chunks.concat();
+    let mut out = b"RIFF".to_vec();
+    out.extend_from_slice(&(body.len() as u32 + 4).to_le_bytes());
+    out.extend_from_slice(b"WAVE");
+    out.extend_from_slice(&body);
+    out
+}
+
+fn wav(format: u16, channels: u16, rate: u32, bits: u16, data: &[u8]) -> Vec<u8> {
+    riff(&[
+        chunk(b"fmt ", &fmt(format, channels, rate, bits)),
+        chunk(b"data", data),
+    ])
+}
+
+#[test]
+fn passes_pcm_through() {
+    let data: Vec<u8> = (0..=255).collect();
+    for (channels, bits) in [(1, 8), (2, 8), (1, 16), (2, 16)] {
+        let read = wav::read(&wav(1, channels, 22050, bits, &data), &CONVERT).unwrap();
+        assert_eq!(
+            read,
+            Wav {
+                channels: channels as u8,
+                samples_per_second: 22050,
+                bits_per_sample: bits as u8,
+                data: data.clone(),
+            }
+        );
+    }
+
+    // other chunks are skipped, odd sized ones with their padding, and a trailing partial frame
+    // is dropped
+    let bytes = riff(&[
+        chunk(b"LIST", b"odd"),
+        chunk(b"data", &[1, 2, 3, 4, 5]),
+        chunk(b"fmt ", &fmt(1, 2, 44100, 16)),
+    ]);
+    let read = wav::read(&bytes, &WavImportOptions::default()).unwrap();
+    assert_eq!(read.data, [1, 2, 3, 4]);
+}
+
+#[test]
+fn converts_formats_when_asked() {
+    // -1.0, 0.0, 0.5 in each format
+    let i24 = [0x00, 0x00, 0x80, 0, 0, 0, 0x00, 0x00, 0x40];
+    let i32 = [0, 0, 0, 0x80, 0, 0, 0, 0, 0, 0, 0, 0x40];
+    let f32: Vec<u8> = [-1.0_f32, 0.0, 0.5]
+        .iter()
+        .flat_map(|s| s.to_le_bytes())
+        .collect();
+    let expected: Vec<u8> = [-32768_i16, 0, 16384]
+        .iter()
+        .flat_map(|s| s.to_le_bytes())
+        .collect();
+
+    for (format, bits, data) in [(1, 24, &i24[..]), (1, 32, &i32[..]), (3, 32, &f32[..])] {
+        let bytes = wav(format, 1, 44100, bits, data);
+        assert!(matches!(
+            wav::read(&bytes, &WavImportOptions::default()),
+            Err(WavError::UnsupportedFormat { .. })
+        ));
+        let read = wav::read(&bytes, &CONVERT).unwrap();
+        assert_eq!((read.bits_per_sample, &read.data), (16, &expected));
+    }
+
+    // WAVE_FORMAT_EXTENSIBLE, with float as the sub format
+    let mut extensible = fmt(0xfffe, 1, 44100, 32);
+    extensible.extend_from_slice(&22_u16.to_le_bytes());
+    extensible.extend_from_slice(&32_u16.to_le_bytes());
+    extensible.extend_from_slice(&4_u32.to_le_bytes());
+    extensible.extend_from_slice(&3_u16.to_le_bytes());
+    extensible.extend_from_slice(&[0; 14]);
+    let bytes = riff(&[chunk(b"fmt ", &extensible), chunk(b"data", &f32)]);
+    assert_eq!(wav::read(&bytes, &CONVERT).unwrap().data, expected);
+}
+
+#[test]
+fn resamples_unusual_rates() {
+    let data: Vec<u8> = (0..32000_u32).map(|i| (i % 256) as u8).collect();
+    let bytes = wav(1, 1, 32000, 8, &data);
+
+    let kept = wav::read(&bytes, &WavImportOptions::default()).unwrap();
+    assert_eq!((kept.samples_per_second, kept.data.len()), (32000, 32000));
+
+    let read = wav::read(&bytes, &CONVERT).unwrap();
+    assert_eq!(read.samples_per_second, 44100);
+    assert_eq!(read.bits_per_sample, 8);
+    assert_eq!(read.data.len(), 44100);
+    assert_eq!(&read.data[..2], &data[..2]);
+}
+
+#[test]
+fn reads_exported_projects() {
+    let mut pxtone = RPxTone::new();
+    pxtone
+        .read_bytes(include_bytes!("../examples/sample.ptcop"))
+        .unwrap();
+
+    let mut int16 = Vec::new();
+    let options = WavExportOptions { sample_rate: 22050, ..WavExportOptions::default() };
+    pxtone.export_wav(&mut int16, &options).unwrap();
+    let mut float32 = Vec::new();
+    let options = WavExportOptions { format: WavSampleFormat::Float32, ..options };
+    pxtone.export_wav(&mut float32, &options).unwrap();
+
+    let mut woices = pxtone.woices_mut();
+    let woice = woices.add_pcm_from_bytes(&int16).unwrap();
+    let voice = woice.voice();
+    assert_eq!(
+        (
+            voice.channels(),
+            voice.samples_per_second(),
+            voice.bits_per_sample()
+        ),
+        (2, 22050, 16)
+    );
+    assert_eq!(voice.data(), int16[44..]);
+
+    let converted = RPxToneVoicePCM::from_wav(&float32, &CONVERT).unwrap();
+    assert_eq!(converted.data().len(), int16.len() - 44);
+    assert!(converted
+        .data()
+        .chunks_exact(2)
+        .zip(int16[44..].chunks_exact(2))
+        .all(|(a, b)| {
+            let a = i16::from_le_bytes([a[0], a[1]]);
+            let b = i16::from_le_bytes([b[0], b[1]]);
+            (a - b).abs() <= 1
+        }));
+}
+
+#[test]
+fn add_from_file() {
+    let path = std::env::temp_dir().join(format!("pxtone-wav-import-{}.wav", std::process::id()));
+    std::fs::write(&path, wav(1, 1, 11025, 8, &[128; 40])).unwrap();
+
+    let mut pxtone = RPxTone::new();
+    let mut woices = pxtone.woices_mut();
+    let added = woices
+        .add_pcm_from_file(&path)
+        .map(|w| (w.voice().samples_per_second(), w.voice().data()));
+    std::fs::remove_file(&path).unwrap();
+    assert_eq!(added.unwrap(), (11025, vec![128; 40]));
+
+    assert!(matches!(
+        woices.add_pcm_from_file(&path),
+        Err(WavError::Io(e)) if e.kind() == std::io::ErrorKind::NotFound
+    ));
+    assert!(matches!(
+        woices.add_pcm_from_bytes(&wav(1, 1, 44100, 24, &[0; 3])),
+        Err(WavError::UnsupportedFormat { bits_per_sample: 24, .. })
+    ));
+}
+
+#[test]
+fn rejects_bad_input() {
+    assert!(matches!(
+        wav::read(b"RIFF\0\0\0\0AVI LIST", &CONVERT),
+        Err(WavError::IncorrectHeader)
+    ));
+    assert!(matches!(
+        wav::read(&riff(&[chunk(b"fmt ", &fmt(1, 1, 44100, 16))]), &CONVERT),
+        Err(WavError::MissingChunk("data"))
+    ));
+    assert!(matches!(
+        wav::read(&wav(1, 3, 44100, 16, &[0; 6]), &CONVERT),
+        Err(WavError::UnsupportedFormat { channels: 3, .. })
+    ));
+
+    let bytes = wav(1, 1, 44100, 16, &[0; 8]);
+    assert!(matches!(
+        wav::read(&bytes[..bytes.len() - 1], &CONVERT),
+        Err(WavError::UnexpectedEnd)
+    ));
+
+    // resampling from 0Hz would divide by zero
+    assert!(matches!(
+        wav::read(&wav(1, 1, 0, 16, &[0; 8]), &CONVERT),
+        Err(WavError::IncorrectHeader)
+    ));
+    assert_eq!(
+        WavError::UnsupportedFormat { format: 1, channels: 3, bits_per_sample: 16 }.to_string(),
+        "Unsupported WAV format 1 with 3 channel(s) of 16 bit samples"
+    );
+}
@@ -0,0 +1,183 @@
+#![cfg(feature = "rust-impl")]
+
+use std::{io, time::Duration};
+
+use pxtone::{
+    interface::{
+        event::Key,
+        io::PxToneServiceIO,
+        woice::{HasWoices, SingleVoice, VoicePCM, Woice, WoiceType, Woices, WoicesMut},
+    },
+    rust_impl::{
+        service::RPxTone,
+        wav::{self, Wav, WavImportOptions},
+        woice::{NoteExportOptions, RPxToneWoice},
+    },
+    util::BoxOrRef,
+};
+
+const SAMPLE: &[u8] = include_bytes!("../examples/sample.ptcop");
+
+fn checksum(bytes: &[u8]) -> u32 {
+    bytes
+        .iter()
+        .fold(0, |h, &b| h.wrapping_mul(31).wrapping_add(b.into()))
+}
+
+fn last_woice(pxtone: &RPxTone) -> BoxOrRef<'_, RPxToneWoice> {
+    Woices::iter(pxtone).last().unwrap()
+}
+
+fn note(woice: &RPxToneWoice, options: &NoteExportOptions) -> Wav {
+    let mut out = Vec::new();
+    woice.export_note_wav(&mut out, options).unwrap();
+    wav::read(&out, &WavImportOptions::default()).unwrap()
+}
+
+fn samples(wav: &Wav) -> Vec<i16> {
+    wav.data
+        .chunks_exact(2)
+        .map(|b| i16::from_le_bytes([b[0], b[1]]))
+        .collect()
+}
+
+#[test]
+fn pcm_exports_its_data() {
+    let mut pxtone = RPxTone::new();
+    let mut woices = pxtone.woices_mut();
+    for (channels, rate, bits, data) in [
+        (2, 22050, 16, (0..=255).collect::<Vec<u8>>()),
+        // odd sized data gets a pad byte
+        (1, 11025, 8, vec![0, 128, 255]),
+    ] {
+        let woice = woices
+            .add_pcm(channels, rate, bits, data.clone(), 0x4500, false)
+            .unwrap();
+        let mut out = Vec::new();
+        woice.voice().export_wav(&mut out).unwrap();
+        assert_eq!(out.len() % 2, 0);
+        assert_eq!(
+            u32::from_le_bytes(out[4..8].try_into().unwrap()) as usize,
+            out.len() - 8
+        );
+
+        let read = wav::read(&out, &WavImportOptions::default()).unwrap();
+        assert_eq!(
+            read,
+            Wav {
+                channels,
+                samples_per_second: rate,
+                bits_per_sample: bits,
+                data: data.clone(),
+            }
+        );
+        assert_eq!(
+            woices.add_pcm_from_bytes(&out).unwrap().voice().data(),
+            data
+        );
+    }
+}
+
+#[test]
+fn ptn_noise_is_built_like_og() {
+    let mut pxtone = RPxTone::new();
+    pxtone.read_bytes(SAMPLE).unwrap();
+
+    // checksums of what og pxtone's noise builder makes of the sample project's noises
+    let og = [
+        (4444, 0xc7d5_8e76),
+        (8600, 0x6cf0_5118),
+        (12140, 0x00fa_8bda),
+    ];
+
+    let mut built = Vec::new();
+    for woice in Woices::iter(&pxtone) {
+        if let WoiceType::PTN(ptn) = woice.woice_type() {
+            let mut out = Vec::new();
+            ptn.voice().export_wav(&mut out).unwrap();
+            let read = wav::read(&out, &WavImportOptions::default()).unwrap();
+            assert_eq!((read.channels, read.samples_per_second), (2, 44100));
+            built.push((read.data.len() / 4, checksum(&read.data)));
+        }
+    }
+    assert_eq!(built, og);
+}
+
+#[test]
+#[allow(clippy::cast_precision_loss)]
+fn pcm_note_plays_back_as_recorded() {
+    // a slow sine, so being a sample off either way barely matters
+    let sine: Vec<i16> = (0..4410)
+        .map(|i| ((i as f32 / 4410.0 * std::f32::consts::TAU).sin() * 16000.0) as i16)
+        .collect();
+    let data = sine.iter().flat_map(|s| s.to_ne_bytes()).collect();
+
+    let mut pxtone = RPxTone::new();
+    pxtone
+        .woices_mut()
+        .add_pcm(1, 44100, 16, data, 0x4500, false)
+        .unwrap();
+
+    let options = NoteExportOptions {
+        length: Duration::from_millis(100),
+        channels: 1,
+        ..NoteExportOptions::default()
+    };
+    let rendered = samples(&note(&last_woice(&pxtone), &options));
+    assert!((4410..4420).contains(&rendered.len()));
+    // skip the fade in smoothing does. pxtone's pitch is only accurate to a fraction of a percent,
+    // so allow for drifting that far from the recording
+    for (i, &r) in rendered.iter().enumerate().take(sine.len()).skip(200) {
+        let near = &sine[i - i / 100 - 2..(i + i / 100 + 2).min(sine.len())];
+        assert!(near.iter().any(|&s| (r - s).abs() < 64), "sample {i}: {r}");
+    }
+
+    // an octave up runs through the sample twice as fast
+    let options = NoteExportOptions {
+        key: Key::new(*Key::DEFAULT + 12 * Key::SEMITONE),
+        ..options
+    };
+    let rendered = samples(&note(&last_woice(&pxtone), &options));
+    assert!(rendered[2250..].iter().all(|&s| s == 0));
+    assert!(rendered[1000..2205].iter().any(|&s| s != 0));
+}
+
+#[test]
+fn any_woice_renders_a_note() {
+    let mut pxtone = RPxTone::new();
+    pxtone.read_bytes(SAMPLE).unwrap();
+    pxtone
+        .woices_mut()
+        .add_ptv_from_file("examples/sample.ptvoice")
+        .unwrap();
+
+    let options = NoteExportOptions {
+        length: Duration::from_millis(250),
+        sample_rate: 22050,
+        ..NoteExportOptions::default()
+    };
+    for woice in Woices::iter(&pxtone) {
+        let wav = note(&woice, &options);
+        assert_eq!((wav.channels, wav.samples_per_second), (2, 22050));
+
+        let rendered = samples(&wav);
+        assert!(rendered.len() >= 2 * 5512, "{}", rendered.len());
+        assert!(rendered.iter().any(|&s| s.abs() > 1000));
+    }
+}
+
+#[test]
+fn rejects_bad_note_options() {
+    let mut pxtone = RPxTone::new();
+    pxtone.woices_mut().add_blank_ptn().unwrap();
+
+    for options in [
+        NoteExportOptions { channels: 3, ..NoteExportOptions::default() },
+        NoteExportOptions { sample_rate: 0, ..NoteExportOptions::default() },
+    ] {
+        let err = last_woice(&pxtone)
+            .export_note_wav(io::sink(), &options)
+            .unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+    }
+}